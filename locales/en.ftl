@@ -0,0 +1,161 @@
+# Console messages. Keep ids in sync with sv.ftl; missing ones fall back to English.
+
+processing = Processing {$path}...
+detecting = Detecting {$path}...
+retrying = Retrying {$path}...
+cropping = Cropping {$path}...
+recropping = Re-cropping {$path}...
+measuring-skew = Measuring how the photos lean...
+systematic-skew = {"  "}Photos lean {$degrees}° alike; straightening all of them ({$count ->
+    [one] 1 photo
+   *[other] {$count} photos
+} measured)
+no-systematic-skew = {"  "}No shared lean found ({$count ->
+    [one] 1 photo
+   *[other] {$count} photos
+} measured)
+finished = Finished {$path}:
+failed = {"  "}Failed: {$error}
+skipping = Skipping {$path}: {$error}
+unchanged-skipped = {"  "}Unchanged since the last run, skipped
+no-photos = {"  "}No photos found
+found-photos = {"  "}Found {$count ->
+    [one] 1 photo
+   *[other] {$count} photos
+}
+no-more-photos = {"  "}No additional photos found
+found-more-photos = {"  "}Found {$count ->
+    [one] 1 more photo
+   *[other] {$count} more photos
+}
+saved-photos = {"  "}Saved {$count ->
+    [one] 1 cropped photo
+   *[other] {$count} cropped photos
+}
+sheet-photos = {$path}: {$count ->
+    [one] 1 photo
+   *[other] {$count} photos
+}
+not-in-manifest = {$path} is not listed in the manifest
+stem-disambiguated = Another sheet's crops would have the same names as those of {$path}; naming them {$stem}_1, {$stem}_2 …
+advice-heading = Suggestions for the next run:
+advice-min-area-empty = {"  "}Lower --min-area to {$suggested}: {$count ->
+    [one] 1 sheet
+   *[other] {$count} sheets
+} came out empty and likely have missed small prints
+advice-min-area-borderline = {"  "}Lower --min-area to {$suggested}: {$count ->
+    [one] 1 sheet has
+   *[other] {$count} sheets have
+} photos close to the limit, so smaller prints were likely missed
+advice-size-tolerance = {"  "}Raise --size-tolerance to {$suggested} or keep the odd ones with --off-size flag: {$count ->
+    [one] 1 candidate
+   *[other] {$count} candidates
+} matched no known print size
+advice-max-photos = {"  "}Raise --max-photos above {$max}: {$count ->
+    [one] 1 sheet
+   *[other] {$count} sheets
+} had more photos than it let through
+advice-shapes = {"  "}Add the missing outline to --shapes (e.g. rect,ellipse): {$count ->
+    [one] 1 candidate was
+   *[other] {$count} candidates were
+} turned down for its shape
+advice-robust-corners = {"  "}Try --robust-corners or --print-shape rounded: photos fill their rectangles poorly (median confidence {$confidence}), as torn or rounded corners do
+fusing-scans = Fusing with second scan {$path}
+glare-found = Photo {$index}: {$percent}% glare
+animated-skipped = Skipped: animated image with {$frames} frames, not a scanned sheet
+first-page-only = The file has {$pages} pages; only the first is used
+detection-cached = Reusing the cached detection
+batch-title = Review batch {$number} of {$total}
+batch-intro = {$count} sheets. Check each sheet's outlines and crops, then run the command below it that fits; the marks go to the run's manifest.
+sheet-photos-found = {$count} photos found
+batches-written = Wrote {$count} review batches to {$path}
+selecting-pages = Looking for still pages in {$path}...
+page-taken = Took page {$path}
+input-deferred = {$path} is empty or still being written; reading it last
+unfinished-skipped = Skipped: {$empty ->
+    [true] the file is still empty
+   *[other] the file is still being written
+}; run again to pick it up
+caption-not-in-manifest = No photo {$photo} on a sheet {$sheet} in the manifest; caption skipped
+era-found = Photo {$index}: looks {$era ->
+    [black-white] black and white
+    [faded-color] like faded colour
+   *[other] like glossy colour
+}
+duplicate-skipped = Skipping {$path}: same content as {$original}
+duplicates-summary = {$count ->
+    [one] 1 input was skipped as a duplicate of another
+   *[other] {$count} inputs were skipped as duplicates of others
+}
+renaming = {$from} → {$to}
+review-state = Reviewed: {$approved} approved, {$needs_fix} need fixing, {$unreviewed} not yet reviewed
+marked-reviewed = Marked {$count ->
+    [one] 1 sheet
+   *[other] {$count} sheets
+} as {$status}
+marked-missing = {$count ->
+    [one] 1 sheet was
+   *[other] {$count} sheets were
+} not found in the manifest
+plan-file = {$path}: {$size}, {$width}x{$height}
+plan-file-unknown = {$path}: {$size}, resolution unknown
+plan-unsupported = {$path}: looks like an image, but .{$extension} files are not supported and will be skipped
+plan-total = {$count ->
+    [one] 1 file
+   *[other] {$count} files
+}, {$size}, {$megapixels} megapixels; {$unsupported} unsupported
+plan-estimate = Estimated time: {$serial} on one core, about {$parallel} with {$workers} workers (reading and detection, timed on {$samples ->
+    [one] 1 sheet
+   *[other] {$samples} sheets
+})
+montage-page = {$path}: {$count ->
+    [one] 1 photo
+   *[other] {$count} photos
+}
+sheet-turned = {"  "}Sheet turned {$degrees}°
+ruler-found = {"  "}Ruler: {$dpi} dpi
+text-rejected = {"  "}Skipped {$count ->
+    [one] 1 region
+   *[other] {$count} regions
+} that look like text
+no-ruler = {"  "}No ruler found
+cmyk-converted = {"  "}CMYK source converted to sRGB through its ICC profile; colours outside sRGB may be clipped
+cmyk-approximate = {"  "}CMYK source converted to sRGB without a colour profile; colours are approximate
+profile-converted = {"  "}Converted from {$profile} to sRGB; colours outside sRGB may be clipped
+no-exif = {"  "}{$format} crops carry no EXIF; source link only kept in the manifest
+photo-too-small = {"  "}Photo {$index} is only {$width}x{$height} pixels (below --warn-min-side {$min})
+sharpened = {"  "}Sharpened photo {$index}
+needs-review = {"  "}Photo {$index} needs a second look (low confidence or soft), labelled for review
+copied-identical = {"  "}Photo {$index} is the whole sheet; copying the original file instead of re-encoding
+date-estimated = {"  "}Photo {$index} was probably taken {$from}–{$to} ({$confidence}% sure)
+exported = Exported {$count ->
+    [one] 1 photo
+   *[other] {$count} photos
+} to {$path}
+exported-parts = Exported {$count ->
+    [one] 1 photo
+   *[other] {$count} photos
+} to {$path} in {$parts} parts
+copy-failed = Failed to copy {$path}: {$error}
+create-failed = Failed to create {$path}: {$error}
+write-failed = Failed to write {$path}: {$error}
+verify-modified = MODIFIED {$path}
+verify-missing = MISSING  {$path}
+verify-unreadable = UNREADABLE {$path}: {$error}
+verify-untracked = UNTRACKED {$path}
+verified = {$ok} crops verified, {$problems} problems, {$unhashed} without a recorded hash
+preset-written = Preset written to {$path}
+bench-start = Benchmarking {$combinations} combinations on {$sheets} sheets
+bench-row = min_area {$min_area}  canny {$low}/{$high}  {$crops} crops  {$ms} ms{$accuracy}
+bench-accuracy = {"  "}precision {$precision}  recall {$recall}  F1 {$f1}
+bench-best = Best: min_area {$min_area}, canny_low {$low}, canny_high {$high}
+tune-cancelled = Tuning cancelled; nothing written
+space-warning = Warning: {$message}
+space-short = {$path} has {$free} free but the crops are estimated to need about {$needed}
+server-listening = Listening on {$url}
+server-open = Warning: anyone who can reach this address can use the server; pass --token-file
+response-failed = Could not send response: {$error}
+cache-write-failed = Could not write cache entry {$path}
+remove-failed = Could not remove {$path}: {$error}
+traces-flush-failed = Failed to flush traces: {$error}
+disk-full-paused = {"  "}Disk full while writing {$path}; paused until {$size} is free
@@ -0,0 +1,161 @@
+# Konsolmeddelanden. Håll id:na i takt med en.ftl; saknade visas på engelska.
+
+processing = Bearbetar {$path}...
+detecting = Letar foton i {$path}...
+retrying = Försöker igen med {$path}...
+cropping = Beskär {$path}...
+recropping = Beskär om {$path}...
+measuring-skew = Mäter hur fotona lutar...
+systematic-skew = {"  "}Fotona lutar {$degrees}° lika; rätar upp alla ({$count ->
+    [one] 1 foto
+   *[other] {$count} foton
+} uppmätta)
+no-systematic-skew = {"  "}Ingen gemensam lutning hittades ({$count ->
+    [one] 1 foto
+   *[other] {$count} foton
+} uppmätta)
+finished = Klar med {$path}:
+failed = {"  "}Misslyckades: {$error}
+skipping = Hoppar över {$path}: {$error}
+unchanged-skipped = {"  "}Oförändrad sedan förra körningen, hoppas över
+no-photos = {"  "}Inga foton hittades
+found-photos = {"  "}Hittade {$count ->
+    [one] 1 foto
+   *[other] {$count} foton
+}
+no-more-photos = {"  "}Inga fler foton hittades
+found-more-photos = {"  "}Hittade {$count ->
+    [one] 1 foto till
+   *[other] {$count} foton till
+}
+saved-photos = {"  "}Sparade {$count ->
+    [one] 1 beskuret foto
+   *[other] {$count} beskurna foton
+}
+sheet-photos = {$path}: {$count ->
+    [one] 1 foto
+   *[other] {$count} foton
+}
+not-in-manifest = {$path} finns inte i manifestet
+stem-disambiguated = Ett annat arks beskärningar skulle få samma namn som de från {$path}; de heter i stället {$stem}_1, {$stem}_2 …
+advice-heading = Förslag inför nästa körning:
+advice-min-area-empty = {"  "}Sänk --min-area till {$suggested}: {$count ->
+    [one] 1 ark
+   *[other] {$count} ark
+} blev tomma och har troligen missade små kort
+advice-min-area-borderline = {"  "}Sänk --min-area till {$suggested}: {$count ->
+    [one] 1 ark har
+   *[other] {$count} ark har
+} foton nära gränsen, så mindre kort har troligen missats
+advice-size-tolerance = {"  "}Höj --size-tolerance till {$suggested} eller behåll avvikarna med --off-size flag: {$count ->
+    [one] 1 kandidat
+   *[other] {$count} kandidater
+} passade ingen känd kortstorlek
+advice-max-photos = {"  "}Höj --max-photos över {$max}: {$count ->
+    [one] 1 ark
+   *[other] {$count} ark
+} hade fler foton än gränsen släppte igenom
+advice-shapes = {"  "}Lägg till den saknade formen i --shapes (t.ex. rect,ellipse): {$count ->
+    [one] 1 kandidat
+   *[other] {$count} kandidater
+} valdes bort för sin form
+advice-robust-corners = {"  "}Prova --robust-corners eller --print-shape rounded: fotona fyller sina rektanglar dåligt (mediankonfidens {$confidence}), som när hörn är rivna eller rundade
+fusing-scans = Slår ihop med andra skanningen {$path}
+glare-found = Foto {$index}: {$percent} % reflexer
+animated-skipped = Hoppade över: animerad bild med {$frames} bildrutor, inte ett skannat ark
+first-page-only = Filen har {$pages} sidor; bara den första används
+detection-cached = Återanvänder sparad detektering
+batch-title = Granskningsomgång {$number} av {$total}
+batch-intro = {$count} ark. Kontrollera varje arks ramar och beskurna foton och kör sedan kommandot under det som passar; markeringarna hamnar i körningens manifest.
+sheet-photos-found = {$count} foton hittade
+batches-written = Skrev {$count} granskningsomgångar till {$path}
+selecting-pages = Letar efter stillastående sidor i {$path}...
+page-taken = Tog sidan {$path}
+input-deferred = {$path} är tom eller skrivs fortfarande; läses sist
+unfinished-skipped = Hoppade över: {$empty ->
+    [true] filen är fortfarande tom
+   *[other] filen skrivs fortfarande
+}; kör igen för att ta med den
+caption-not-in-manifest = Inget foto {$photo} på ett ark {$sheet} i manifestet; bildtexten hoppas över
+era-found = Foto {$index}: ser ut som {$era ->
+    [black-white] svartvitt
+    [faded-color] blekt färgfoto
+   *[other] blankt färgfoto
+}
+duplicate-skipped = Hoppar över {$path}: samma innehåll som {$original}
+duplicates-summary = {$count ->
+    [one] 1 indatafil hoppades över som dubblett av en annan
+   *[other] {$count} indatafiler hoppades över som dubbletter av andra
+}
+renaming = {$from} → {$to}
+review-state = Granskning: {$approved} godkända, {$needs_fix} behöver åtgärdas, {$unreviewed} ej granskade
+marked-reviewed = Markerade {$count ->
+    [one] 1 ark
+   *[other] {$count} ark
+} som {$status}
+marked-missing = {$count ->
+    [one] 1 ark
+   *[other] {$count} ark
+} fanns inte i manifestet
+plan-file = {$path}: {$size}, {$width}x{$height}
+plan-file-unknown = {$path}: {$size}, okänd upplösning
+plan-unsupported = {$path}: ser ut som en bild, men .{$extension}-filer stöds inte och hoppas över
+plan-total = {$count ->
+    [one] 1 fil
+   *[other] {$count} filer
+}, {$size}, {$megapixels} megapixel; {$unsupported} stöds inte
+plan-estimate = Beräknad tid: {$serial} på en kärna, ungefär {$parallel} med {$workers} trådar (läsning och detektering, mätt på {$samples ->
+    [one] 1 ark
+   *[other] {$samples} ark
+})
+montage-page = {$path}: {$count ->
+    [one] 1 foto
+   *[other] {$count} foton
+}
+sheet-turned = {"  "}Arket vändes {$degrees}°
+ruler-found = {"  "}Linjal: {$dpi} dpi
+text-rejected = {"  "}Hoppade över {$count ->
+    [one] 1 område
+   *[other] {$count} områden
+} som ser ut som text
+no-ruler = {"  "}Ingen linjal hittades
+cmyk-converted = {"  "}CMYK-källan konverterades till sRGB via sin ICC-profil; färger utanför sRGB kan ha klippts
+cmyk-approximate = {"  "}CMYK-källan konverterades till sRGB utan färgprofil; färgerna är ungefärliga
+profile-converted = {"  "}Konverterad från {$profile} till sRGB; färger utanför sRGB kan ha klippts
+no-exif = {"  "}{$format}-filer saknar EXIF; kopplingen till källfilen finns bara i manifestet
+photo-too-small = {"  "}Foto {$index} är bara {$width}x{$height} pixlar (under --warn-min-side {$min})
+sharpened = {"  "}Skärpte foto {$index}
+needs-review = {"  "}Foto {$index} behöver granskas (låg konfidens eller oskarpt) och märks för granskning
+copied-identical = {"  "}Foto {$index} är hela arket; originalfilen kopieras i stället för att kodas om
+date-estimated = {"  "}Foto {$index} är troligen taget {$from}–{$to} ({$confidence} % säkert)
+exported = Exporterade {$count ->
+    [one] 1 foto
+   *[other] {$count} foton
+} till {$path}
+exported-parts = Exporterade {$count ->
+    [one] 1 foto
+   *[other] {$count} foton
+} till {$path} i {$parts} delar
+copy-failed = Kunde inte kopiera {$path}: {$error}
+create-failed = Kunde inte skapa {$path}: {$error}
+write-failed = Kunde inte skriva {$path}: {$error}
+verify-modified = ÄNDRAD {$path}
+verify-missing = SAKNAS {$path}
+verify-unreadable = OLÄSBAR {$path}: {$error}
+verify-untracked = EJ I MANIFESTET {$path}
+verified = {$ok} foton kontrollerade, {$problems} problem, {$unhashed} utan sparad hash
+preset-written = Förinställning sparad i {$path}
+bench-start = Provar {$combinations} kombinationer på {$sheets} ark
+bench-row = min_area {$min_area}  canny {$low}/{$high}  {$crops} foton  {$ms} ms{$accuracy}
+bench-accuracy = {"  "}precision {$precision}  recall {$recall}  F1 {$f1}
+bench-best = Bäst: min_area {$min_area}, canny_low {$low}, canny_high {$high}
+tune-cancelled = Justeringen avbröts; inget sparades
+space-warning = Varning: {$message}
+space-short = {$path} har {$free} ledigt men fotona beräknas behöva ungefär {$needed}
+server-listening = Lyssnar på {$url}
+server-open = Varning: alla som når den här adressen kan använda servern; ange --token-file
+response-failed = Kunde inte skicka svaret: {$error}
+cache-write-failed = Kunde inte skriva cachefilen {$path}
+remove-failed = Kunde inte ta bort {$path}: {$error}
+traces-flush-failed = Kunde inte skicka spårningsdata: {$error}
+disk-full-paused = {"  "}Disken blev full vid skrivning av {$path}; väntar tills {$size} är ledigt
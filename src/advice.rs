@@ -0,0 +1,139 @@
+use crate::detect::{PrintShape, RejectReason};
+use crate::manifest::{Manifest, Photo, Sheet};
+
+/// Photos smaller than this many times their sheet's minimum area are
+/// taken to be close to the limit.
+const BORDERLINE_AREA: f64 = 1.5;
+/// Share of the smallest photo found the suggested minimum area is set to,
+/// leaving room for prints a little smaller still.
+const SUGGESTED_AREA_SHARE: f64 = 0.6;
+/// Median confidence below which corners are likely torn or rounded.
+const LOW_CONFIDENCE: f32 = 0.85;
+/// How much `--size-tolerance` is suggested to grow by.
+const TOLERANCE_STEP: f64 = 0.04;
+
+/// A parameter change the results of a batch point to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Advice {
+    /// Sheets came out empty, or photos only just cleared the minimum area,
+    /// so smaller prints were probably missed.
+    LowerMinArea {
+        suggested: f64,
+        empty_sheets: usize,
+        borderline_sheets: usize,
+    },
+    /// Candidates matched none of the known print sizes.
+    LoosenSizes { rejected: usize, suggested: f64 },
+    /// Sheets held more photos than `--max-photos` let through.
+    RaiseMaxPhotos { max_photos: usize, sheets: usize },
+    /// Candidates had an outline the batch did not ask for.
+    AddShapes { rejected: usize },
+    /// Photos fill their rectangles poorly, as torn or rounded corners do.
+    RobustCorners { median_confidence: f32 },
+}
+
+/// Reads the confidence, rejections and empty sheets of a finished batch
+/// for parameters worth changing before the next one, most telling first.
+///
+/// Sheets split by a grid or layout are left out, as their photos are
+/// placed by geometry rather than found.
+pub fn recommend(manifest: &Manifest) -> Vec<Advice> {
+    let sheets: Vec<&Sheet> = manifest
+        .sheets
+        .iter()
+        .filter(|s| s.params.grid.is_none() && s.params.layout.is_none())
+        .collect();
+    let mut advice = Vec::new();
+
+    // A minimum area given as a share of the sheet cannot be compared
+    // without the sheet's size, which the manifest does not keep.
+    let absolute = || sheets.iter().filter(|s| s.params.min_area_frac.is_none());
+    let empty_sheets = absolute().filter(|s| s.photos.is_empty()).count();
+    let borderline_sheets = absolute()
+        .filter(|s| {
+            s.photos
+                .iter()
+                .any(|p| area(p) < s.params.min_area * BORDERLINE_AREA)
+        })
+        .count();
+    if empty_sheets + borderline_sheets > 0 {
+        let current = absolute()
+            .map(|s| s.params.min_area)
+            .fold(f64::INFINITY, f64::min);
+        let smallest = absolute()
+            .flat_map(|s| &s.photos)
+            .map(area)
+            .fold(f64::INFINITY, f64::min);
+        let suggested = (smallest.min(current) * SUGGESTED_AREA_SHARE / 1000.0).floor() * 1000.0;
+        if suggested >= 1000.0 {
+            advice.push(Advice::LowerMinArea {
+                suggested,
+                empty_sheets,
+                borderline_sheets,
+            });
+        }
+    }
+
+    let off_size = rejected(&sheets, RejectReason::OffSize);
+    if off_size > 0 {
+        let tolerance = sheets
+            .iter()
+            .map(|s| s.params.size_tolerance)
+            .fold(0.0, f64::max);
+        advice.push(Advice::LoosenSizes {
+            rejected: off_size,
+            suggested: ((tolerance + TOLERANCE_STEP) * 100.0).round() / 100.0,
+        });
+    }
+
+    let limited: Vec<&&Sheet> = sheets
+        .iter()
+        .filter(|s| s.rejected.iter().any(|r| r.reason == RejectReason::Limit))
+        .collect();
+    if let Some(max_photos) = limited.iter().filter_map(|s| s.params.max_photos).max() {
+        advice.push(Advice::RaiseMaxPhotos {
+            max_photos,
+            sheets: limited.len(),
+        });
+    }
+
+    let shape = rejected(&sheets, RejectReason::Shape);
+    if shape > 0 {
+        advice.push(Advice::AddShapes { rejected: shape });
+    }
+
+    let mut confidences: Vec<f32> = sheets
+        .iter()
+        .filter(|s| !s.params.robust_corners && s.params.print_shape == PrintShape::Rect)
+        .flat_map(|s| &s.photos)
+        .map(|p| p.confidence)
+        .collect();
+    if !confidences.is_empty() {
+        confidences.sort_by(f32::total_cmp);
+        let median_confidence = confidences[confidences.len() / 2];
+        if median_confidence < LOW_CONFIDENCE {
+            advice.push(Advice::RobustCorners { median_confidence });
+        }
+    }
+    advice
+}
+
+fn rejected(sheets: &[&Sheet], reason: RejectReason) -> usize {
+    sheets
+        .iter()
+        .flat_map(|s| &s.rejected)
+        .filter(|r| r.reason == reason)
+        .count()
+}
+
+/// Area of the photo's quad in source pixels.
+fn area(photo: &Photo) -> f64 {
+    let q = photo.quad;
+    let twice: f32 = (0..4)
+        .map(|i| {
+            let (a, b) = (q[i], q[(i + 1) % 4]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum();
+    (twice.abs() / 2.0) as f64
+}
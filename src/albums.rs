@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Maps source sheets to albums for `export --albums`, so crops land in
+/// `Year/Album/` folders with XMP sidecars that digiKam and Lightroom pick up
+/// as collections and colour labels.
+///
+/// ```toml
+/// [[album]]
+/// source = "scans/mormor"
+/// year = 1962
+/// name = "Sommar på Öland"
+/// collections = ["Familj", "Semester"]
+/// label = "Green"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlbumMapping {
+    #[serde(default, rename = "album")]
+    pub albums: Vec<Album>,
+}
+
+/// One mapping entry, applied to every sheet whose source path starts with
+/// `source`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Album {
+    pub source: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<u16>,
+    /// Album folder name; defaults to the sheet's folder name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collections: Vec<String>,
+    /// Colour label as Lightroom spells it (`Red`, `Yellow`, `Green`, ...).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// Where a crop goes and what its sidecar says.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Placement {
+    pub year: Option<u16>,
+    pub album: String,
+    pub collections: Vec<String>,
+    pub label: Option<String>,
+}
+
+impl AlbumMapping {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read album mapping {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid album mapping {}", path.display()))
+    }
+
+    /// Placement of crops from `source`. The entry with the longest matching
+    /// `source` wins; sheets no entry matches go to an album named after
+    /// their folder, without a year.
+    pub fn place(&self, source: &Path) -> Placement {
+        let folder = source
+            .parent()
+            .and_then(Path::file_name)
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Unsorted".to_string());
+        let Some(album) = self
+            .albums
+            .iter()
+            .filter(|a| source.starts_with(&a.source))
+            .max_by_key(|a| a.source.components().count())
+        else {
+            return Placement {
+                year: None,
+                album: folder,
+                collections: Vec::new(),
+                label: None,
+            };
+        };
+        Placement {
+            year: album.year,
+            album: album.name.clone().unwrap_or(folder),
+            collections: album.collections.clone(),
+            label: album.label.clone(),
+        }
+    }
+}
+
+impl Placement {
+    /// Directory under the export root: `Year/Album`, or just `Album`.
+    pub fn dir(&self) -> PathBuf {
+        let mut dir = PathBuf::new();
+        if let Some(year) = self.year {
+            dir.push(year.to_string());
+        }
+        dir.push(sanitize(&self.album));
+        dir
+    }
+
+    /// XMP sidecar with the album and collections as keywords (flat and
+    /// hierarchical), the colour label and, when known, the year taken.
+    pub fn xmp(&self) -> String {
+        let mut subjects = vec![self.album.clone()];
+        subjects.extend(self.collections.iter().cloned());
+        let mut hierarchy = vec![("Albums", self.album.as_str())];
+        hierarchy.extend(self.collections.iter().map(|c| ("Collections", c.as_str())));
+
+        let mut xmp = String::from(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             <rdf:Description rdf:about=\"\"\n \
+             xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n \
+             xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n \
+             xmlns:photoshop=\"http://ns.adobe.com/photoshop/1.0/\"\n \
+             xmlns:lr=\"http://ns.adobe.com/lightroom/1.0/\"\n \
+             xmlns:digiKam=\"http://www.digikam.org/ns/1.0/\"",
+        );
+        if let Some(label) = &self.label {
+            xmp.push_str(&format!("\n xmp:Label=\"{}\"", escape(label)));
+        }
+        if let Some(year) = self.year {
+            xmp.push_str(&format!("\n photoshop:DateCreated=\"{year}\""));
+        }
+        xmp.push_str(">\n");
+        push_list(
+            &mut xmp,
+            "dc:subject",
+            "rdf:Bag",
+            subjects.iter().map(String::as_str),
+        );
+        let lightroom: Vec<String> = hierarchy.iter().map(|(p, t)| format!("{p}|{t}")).collect();
+        push_list(
+            &mut xmp,
+            "lr:hierarchicalSubject",
+            "rdf:Bag",
+            lightroom.iter().map(String::as_str),
+        );
+        let digikam: Vec<String> = hierarchy.iter().map(|(p, t)| format!("{p}/{t}")).collect();
+        push_list(
+            &mut xmp,
+            "digiKam:TagsList",
+            "rdf:Seq",
+            digikam.iter().map(String::as_str),
+        );
+        xmp.push_str("</rdf:Description>\n</rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>\n");
+        xmp
+    }
+}
+
+/// Sidecar path for `photo`: the full file name plus `.xmp`, as digiKam
+/// writes and reads them by default.
+pub fn sidecar_path(photo: &Path) -> PathBuf {
+    let mut name = photo.as_os_str().to_owned();
+    name.push(".xmp");
+    PathBuf::from(name)
+}
+
+fn push_list<'a>(
+    xmp: &mut String,
+    property: &str,
+    kind: &str,
+    items: impl Iterator<Item = &'a str>,
+) {
+    xmp.push_str(&format!(" <{property}>\n  <{kind}>\n"));
+    for item in items {
+        xmp.push_str(&format!("   <rdf:li>{}</rdf:li>\n", escape(item)));
+    }
+    xmp.push_str(&format!("  </{kind}>\n </{property}>\n"));
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Keeps album names usable as folder names on every platform.
+fn sanitize(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let trimmed = cleaned.trim().trim_end_matches('.');
+    if trimmed.is_empty() {
+        "Unsorted".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
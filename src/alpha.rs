@@ -0,0 +1,110 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use opencv::core::{self, AlgorithmHint, Mat, Scalar, Vector};
+use opencv::imgcodecs;
+use opencv::imgproc;
+use opencv::prelude::*;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// Offset of the colour type in a PNG file: signature, IHDR length and
+/// type, width, height and bit depth come first.
+const PNG_COLOR_TYPE_OFFSET: usize = 25;
+
+/// Whether an encoded image starting with `header` carries an alpha channel.
+/// Only PNG is recognized: grey or RGB with alpha (colour types 4 and 6).
+pub fn has_alpha(header: &[u8]) -> bool {
+    header.starts_with(PNG_SIGNATURE)
+        && matches!(header.get(PNG_COLOR_TYPE_OFFSET), Some(4) | Some(6))
+}
+
+/// The alpha channel of the image at `path` as 8-bit grey, or `None` when it
+/// has none.
+pub fn read_alpha(path: &Path) -> Result<Option<Mat>> {
+    let mut header = [0u8; PNG_COLOR_TYPE_OFFSET + 1];
+    let read = fs::File::open(path)
+        .and_then(|mut file| file.read(&mut header))
+        .with_context(|| format!("Could not read image {}", path.display()))?;
+    if !has_alpha(&header[..read]) {
+        return Ok(None);
+    }
+    let bytes =
+        fs::read(path).with_context(|| format!("Could not read image {}", path.display()))?;
+    decode_alpha(&bytes)
+}
+
+/// The alpha channel of an encoded image as 8-bit grey, or `None` when it
+/// has none.
+pub fn decode_alpha(bytes: &[u8]) -> Result<Option<Mat>> {
+    if !has_alpha(bytes) {
+        return Ok(None);
+    }
+    let image = imgcodecs::imdecode(
+        &Vector::<u8>::from_slice(bytes),
+        imgcodecs::IMREAD_UNCHANGED,
+    )
+    .context("Could not decode image")?;
+    let channels = image.channels();
+    if image.empty() || (channels != 2 && channels != 4) {
+        return Ok(None);
+    }
+    let mut alpha = Mat::default();
+    core::extract_channel(&image, &mut alpha, channels - 1)?;
+    if alpha.depth() != core::CV_8U {
+        let mut narrow = Mat::default();
+        alpha.convert_to(&mut narrow, core::CV_8U, 1.0 / 257.0, 0.0)?;
+        alpha = narrow;
+    }
+    Ok(Some(alpha))
+}
+
+/// `image` (BGR) laid over a solid RGB `background` through `alpha`, so
+/// transparent parts read as the background instead of as black.
+pub fn composite(image: &Mat, alpha: &Mat, background: [u8; 3]) -> Result<Mat> {
+    let [r, g, b] = background.map(f64::from);
+    let mut weight = Mat::default();
+    alpha.convert_to(&mut weight, core::CV_32F, 1.0 / 255.0, 0.0)?;
+    let mut inverse = Mat::default();
+    weight.convert_to(&mut inverse, core::CV_32F, -1.0, 1.0)?;
+    let backdrop = Mat::new_rows_cols_with_default(
+        image.rows(),
+        image.cols(),
+        image.typ(),
+        Scalar::new(b, g, r, 0.0),
+    )?;
+    let mut blended = Mat::default();
+    imgproc::blend_linear(image, &backdrop, &weight, &inverse, &mut blended)?;
+    Ok(blended)
+}
+
+/// `image` (8-bit grey, BGR or BGRA) with `alpha` as its alpha channel.
+/// An image that already has alpha keeps the more transparent of the two.
+pub fn attach(image: &Mat, alpha: &Mat) -> Result<Mat> {
+    let (mut bgra, alpha) = match image.channels() {
+        4 => {
+            let mut existing = Mat::default();
+            core::extract_channel(image, &mut existing, 3)?;
+            let mut combined = Mat::default();
+            core::min(&existing, alpha, &mut combined)?;
+            (image.clone(), combined)
+        }
+        1 => (convert(image, imgproc::COLOR_GRAY2BGRA)?, alpha.clone()),
+        _ => (convert(image, imgproc::COLOR_BGR2BGRA)?, alpha.clone()),
+    };
+    core::insert_channel(&alpha, &mut bgra, 3)?;
+    Ok(bgra)
+}
+
+fn convert(image: &Mat, code: i32) -> Result<Mat> {
+    let mut converted = Mat::default();
+    imgproc::cvt_color(
+        image,
+        &mut converted,
+        code,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    Ok(converted)
+}
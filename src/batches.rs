@@ -0,0 +1,166 @@
+use std::fmt::Write;
+use std::path::{self, Component, Path, PathBuf};
+
+use crate::manifest::Sheet;
+use crate::tr;
+
+/// Folder below the output directory the review batches are written to.
+pub const BATCHES_DIR: &str = "review-batches";
+
+/// Folder of batch `number`, counted from 1 and padded so they sort in order.
+pub fn dir_name(number: usize) -> String {
+    format!("batch-{number:03}")
+}
+
+/// A sheet of a batch and the file name of its overlay beside the page,
+/// when it could be drawn.
+pub struct BatchSheet<'a> {
+    pub sheet: &'a Sheet,
+    pub overlay: Option<String>,
+}
+
+/// HTML page for reviewing batch `number` of `total` in `batch_dir`: each
+/// sheet's overlay and crops, with the commands that mark it.
+///
+/// The commands mark the sheets in `manifest`, the run's own manifest rather
+/// than the batch's, so every batch's verdicts end up in one place; its lock
+/// file lets several reviewers mark at the same time.
+pub fn page(
+    number: usize,
+    total: usize,
+    sheets: &[BatchSheet],
+    manifest: &Path,
+    batch_dir: &Path,
+) -> String {
+    let title = tr!("batch-title", number = number, total = total);
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>\n\
+         body {{ font-family: sans-serif; margin: 2em; }}\n\
+         section {{ border-top: 1px solid #ccc; padding: 1em 0; }}\n\
+         .overlay {{ max-width: 100%; max-height: 80vh; }}\n\
+         .crops img {{ height: 160px; margin: 4px; border: 1px solid #ccc; }}\n\
+         code {{ display: block; background: #f4f4f4; padding: 4px; margin: 4px 0; }}\n\
+         </style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>{intro}</p>\n",
+        title = escape(&title),
+        intro = escape(&tr!("batch-intro", count = sheets.len())),
+    );
+    let manifest = quote(manifest);
+    for batch_sheet in sheets {
+        let sheet = batch_sheet.sheet;
+        let _ = write!(
+            html,
+            "<section>\n<h2>{}</h2>\n<p>{}</p>\n",
+            escape(&sheet.source.display().to_string()),
+            escape(&tr!("sheet-photos-found", count = sheet.photos.len()))
+        );
+        if let Some(overlay) = &batch_sheet.overlay {
+            let _ = writeln!(
+                html,
+                "<a href=\"{0}\"><img class=\"overlay\" src=\"{0}\" alt=\"\"></a>",
+                escape(&encode(overlay))
+            );
+        }
+        html.push_str("<div class=\"crops\">\n");
+        for output in sheet.photos.iter().filter_map(|p| p.output.as_deref()) {
+            let _ = writeln!(
+                html,
+                "<a href=\"{0}\"><img src=\"{0}\" alt=\"\"></a>",
+                escape(&href(output, batch_dir))
+            );
+        }
+        html.push_str("</div>\n");
+        let source = quote(&sheet.source);
+        for status in ["approved", "needs-fix"] {
+            let _ = writeln!(
+                html,
+                "<code>photo-cropper review {manifest} --mark {status} --sheets {source}</code>",
+                manifest = escape(&manifest),
+                source = escape(&source),
+            );
+        }
+        html.push_str("</section>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Link from a page in `dir` to the file at `target`: a relative one where
+/// both lie on the same drive, else a `file://` URL.
+fn href(target: &Path, dir: &Path) -> String {
+    let (Ok(target), Ok(dir)) = (path::absolute(target), path::absolute(dir)) else {
+        return encode(&target.to_string_lossy());
+    };
+    let (target, dir) = (normalize(&target), normalize(&dir));
+    let common = target
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return format!("file:///{}", encode(&target.to_string_lossy()));
+    }
+    let up = dir.components().count() - common;
+    let parts: Vec<String> = std::iter::repeat_n("..".to_string(), up)
+        .chain(
+            target
+                .components()
+                .skip(common)
+                .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+        )
+        .collect();
+    encode(&parts.join("/"))
+}
+
+/// `path` with `.` and `..` resolved without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            other => normal.push(other),
+        }
+    }
+    normal
+}
+
+/// `path` quoted for a shell when it holds anything but plain characters.
+fn quote(path: &Path) -> String {
+    let text = path.display().to_string();
+    if text
+        .chars()
+        .all(|c| c.is_alphanumeric() || "/._-:\\".contains(c))
+    {
+        text
+    } else {
+        format!("\"{text}\"")
+    }
+}
+
+/// Percent-encodes what a URL path cannot hold as it is.
+fn encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '\\' => encoded.push('/'),
+            ' ' | '#' | '?' | '%' | '"' => {
+                let _ = write!(encoded, "%{:02X}", c as u32);
+            }
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// `text` with the characters HTML gives meaning escaped.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
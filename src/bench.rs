@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use opencv::core::Mat;
+
+use crate::config::ParamOverrides;
+use crate::detect::{self, DetectParams, Quad};
+
+/// One sheet of the benchmark sample, loaded once and reused for every combination.
+pub struct Sample {
+    pub image: Mat,
+    /// Parameters resolved for the sheet before the grid values are applied.
+    pub params: DetectParams,
+    /// Photos known to be on the sheet, if ground truth was supplied.
+    pub truth: Option<Vec<Quad>>,
+    /// Factor `image` was downscaled by; `params` and `truth` are at full size.
+    pub scale: f64,
+}
+
+/// Result of running one parameter combination over the whole sample.
+#[derive(Clone, Debug)]
+pub struct Score {
+    pub overrides: ParamOverrides,
+    pub crops: usize,
+    pub elapsed: Duration,
+    pub accuracy: Option<Accuracy>,
+}
+
+/// Detection accuracy against the ground truth.
+#[derive(Clone, Copy, Debug)]
+pub struct Accuracy {
+    pub precision: f64,
+    pub recall: f64,
+}
+
+impl Accuracy {
+    pub fn f1(&self) -> f64 {
+        if self.precision + self.recall == 0.0 {
+            return 0.0;
+        }
+        2.0 * self.precision * self.recall / (self.precision + self.recall)
+    }
+}
+
+/// Every combination of the given values; an empty list leaves that parameter
+/// at the value resolved for each sheet.
+pub fn grid(min_areas: &[f64], canny_lows: &[f64], canny_highs: &[f64]) -> Vec<ParamOverrides> {
+    fn values<T: Copy>(list: &[T]) -> Vec<Option<T>> {
+        if list.is_empty() {
+            vec![None]
+        } else {
+            list.iter().copied().map(Some).collect()
+        }
+    }
+
+    let mut combinations = Vec::new();
+    for min_area in values(min_areas) {
+        for canny_low in values(canny_lows) {
+            for canny_high in values(canny_highs) {
+                combinations.push(ParamOverrides {
+                    min_area,
+                    canny_low,
+                    canny_high,
+                    ..ParamOverrides::default()
+                });
+            }
+        }
+    }
+    combinations
+}
+
+/// Runs detection with `overrides` on every sample and scores the result.
+///
+/// Accuracy is only reported when every sample has ground truth; a detected
+/// photo counts as correct when it overlaps an unmatched true photo.
+pub fn evaluate(samples: &[Sample], overrides: &ParamOverrides) -> Result<Score> {
+    let mut crops = 0;
+    let mut elapsed = Duration::ZERO;
+    let (mut correct, mut truth_total) = (0, 0);
+    let has_truth = !samples.is_empty() && samples.iter().all(|s| s.truth.is_some());
+
+    for sample in samples {
+        let mut params = sample.params.clone();
+        overrides.apply_to(&mut params);
+        if sample.scale != 1.0 {
+            params = params.scaled(sample.scale);
+        }
+
+        let started = Instant::now();
+        let mut candidates = detect::detect_quads(&sample.image, &params)?;
+        elapsed += started.elapsed();
+        let up = (1.0 / sample.scale) as f32;
+        for p in candidates.iter_mut().flat_map(|c| c.quad.iter_mut()) {
+            p.x = (p.x + 0.5) * up - 0.5;
+            p.y = (p.y + 0.5) * up - 0.5;
+        }
+        crops += candidates.len();
+
+        if let Some(truth) = &sample.truth {
+            truth_total += truth.len();
+            let mut unmatched: Vec<&Quad> = truth.iter().collect();
+            for candidate in &candidates {
+                if let Some(pos) = unmatched
+                    .iter()
+                    .position(|t| detect::quads_overlap(t, &candidate.quad))
+                {
+                    unmatched.swap_remove(pos);
+                    correct += 1;
+                }
+            }
+        }
+    }
+
+    let ratio = |n: usize, d: usize| if d == 0 { 1.0 } else { n as f64 / d as f64 };
+    let accuracy = has_truth.then(|| Accuracy {
+        precision: ratio(correct, crops),
+        recall: ratio(correct, truth_total),
+    });
+
+    Ok(Score {
+        overrides: overrides.clone(),
+        crops,
+        elapsed,
+        accuracy,
+    })
+}
+
+/// The best combination: highest F1 score when accuracy is known, otherwise
+/// the most crops found; ties go to the faster one.
+pub fn best(scores: &[Score]) -> Option<&Score> {
+    scores.iter().max_by(|a, b| {
+        let key = |s: &Score| s.accuracy.map_or(s.crops as f64, |acc| acc.f1());
+        key(a)
+            .total_cmp(&key(b))
+            .then_with(|| b.elapsed.cmp(&a.elapsed))
+    })
+}
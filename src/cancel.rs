@@ -0,0 +1,77 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use crossbeam_channel::RecvTimeoutError;
+
+/// How often [`abandonable`] looks at its token while the work runs.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Lets another thread, e.g. a UI whose user navigated away, abandon a
+/// detection in progress. Clones share the same flag.
+///
+/// Detection checks the token between its steps; a single OpenCV call on a
+/// huge sheet still runs to its end, which [`abandonable`] does not wait for.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the work holding this token to stop at its next check.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails with [`Cancelled`] once the token has been cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        Ok(())
+    }
+}
+
+/// Error of work abandoned through a [`CancelToken`]; tell it apart from
+/// real failures with `err.is::<Cancelled>()`.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Runs `work` on a thread of its own and returns its result, or
+/// [`Cancelled`] as soon as `cancel` fires without waiting for the work to
+/// get there. The abandoned thread stops at its next check of the token.
+pub fn abandonable<T, F>(cancel: &CancelToken, work: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    thread::spawn(move || {
+        // Nobody listens any more once the caller has given up.
+        let _ = sender.send(work());
+    });
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(result) => return result,
+            Err(RecvTimeoutError::Timeout) => cancel.check()?,
+            Err(RecvTimeoutError::Disconnected) => anyhow::bail!("Detection thread panicked"),
+        }
+    }
+}
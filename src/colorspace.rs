@@ -0,0 +1,337 @@
+use anyhow::{Context, Result, bail, ensure};
+use img_parts::jpeg::{Jpeg, markers};
+use img_parts::{Bytes, DynImage, ImageEXIF, ImageICC};
+use moxcms::{ColorProfile, DataColorSpace, Layout, ProfileText, TransformOptions};
+use opencv::core::{self, AlgorithmHint, Mat, Scalar, Vector};
+use opencv::imgcodecs;
+use opencv::imgproc;
+use opencv::prelude::*;
+
+/// TIFF tag holding the photometric interpretation; 5 means CMYK.
+const TIFF_PHOTOMETRIC: u16 = 262;
+const TIFF_PHOTOMETRIC_SEPARATED: u32 = 5;
+/// TIFF (and EXIF) tag holding an embedded ICC profile.
+const TIFF_ICC_PROFILE: u16 = 34675;
+const EXIF_ORIENTATION: u16 = 0x0112;
+
+/// How a source's colours were brought into sRGB, the working colour space.
+/// Every conversion may clip colours sRGB cannot show.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Conversion {
+    /// CMYK through the file's embedded ICC profile.
+    CmykProfile,
+    /// CMYK by the plain formula, without a profile; colours are approximate.
+    CmykApproximate,
+    /// RGB from the named embedded profile, e.g. Adobe RGB or ProPhoto RGB.
+    Profile(String),
+}
+
+/// A source decoded to BGR in sRGB.
+pub struct Decoded {
+    pub image: Mat,
+    /// What it took to get there, when the file was not plain RGB or sRGB.
+    pub conversion: Option<Conversion>,
+}
+
+/// Decodes an image file's `bytes` to BGR in sRGB.
+///
+/// CMYK JPEGs are decoded from their raw ink values (Adobe's inverted
+/// storage included) and converted through their ICC profile when they have
+/// one. CMYK TIFFs are converted by the plain formula. RGB sources tagged with
+/// another profile than sRGB (JPEG, PNG or TIFF) are converted from it.
+/// Everything else is decoded as OpenCV does.
+#[tracing::instrument(level = "debug", skip_all, err)]
+pub fn decode(bytes: &[u8]) -> Result<Decoded> {
+    let jpeg = if bytes.starts_with(&[markers::P, markers::SOI]) {
+        Some(Jpeg::from_bytes(Bytes::copy_from_slice(bytes))?)
+    } else {
+        None
+    };
+    if let Some(jpeg) = &jpeg
+        && let Some(adobe) = jpeg_cmyk(jpeg)
+    {
+        return decode_cmyk_jpeg(bytes, jpeg, adobe);
+    }
+
+    let image = imgcodecs::imdecode(&Vector::<u8>::from_slice(bytes), imgcodecs::IMREAD_COLOR)
+        .context("Could not decode image")?;
+    ensure!(!image.empty(), "Could not decode image");
+    let tiff = Ifd::first(bytes);
+    if tiff.as_ref().and_then(|t| t.value(TIFF_PHOTOMETRIC)) == Some(TIFF_PHOTOMETRIC_SEPARATED) {
+        // libtiff already turned the inks into RGB, by the plain formula.
+        return Ok(Decoded {
+            image,
+            conversion: Some(Conversion::CmykApproximate),
+        });
+    }
+    let icc = match (&jpeg, &tiff) {
+        (Some(jpeg), _) => jpeg.icc_profile(),
+        (_, Some(tiff)) => tiff.data(TIFF_ICC_PROFILE).map(Bytes::copy_from_slice),
+        _ => DynImage::from_bytes(Bytes::copy_from_slice(bytes))
+            .ok()
+            .flatten()
+            .and_then(|image| image.icc_profile()),
+    };
+
+    let Some(profile) = icc
+        .and_then(|icc| ColorProfile::new_from_slice(&icc).ok())
+        .filter(|p| p.color_space == DataColorSpace::Rgb)
+    else {
+        return Ok(Decoded {
+            image,
+            conversion: None,
+        });
+    };
+    let name = profile_name(&profile);
+    if name.to_lowercase().contains("srgb") {
+        return Ok(Decoded {
+            image,
+            conversion: None,
+        });
+    }
+    let transform = profile.create_transform_8bit(
+        Layout::Rgb,
+        &ColorProfile::new_srgb(),
+        Layout::Rgb,
+        TransformOptions::default(),
+    )?;
+    let rgb = convert(&image, imgproc::COLOR_BGR2RGB)?;
+    let mut srgb = Mat::new_rows_cols_with_default(
+        image.rows(),
+        image.cols(),
+        core::CV_8UC3,
+        Scalar::all(0.0),
+    )?;
+    transform.transform(rgb.data_bytes()?, srgb.data_bytes_mut()?)?;
+    Ok(Decoded {
+        image: convert(&srgb, imgproc::COLOR_RGB2BGR)?,
+        conversion: Some(Conversion::Profile(name)),
+    })
+}
+
+/// Decodes an image file's `bytes` to BGR at their native 16 bits per
+/// channel, for crops written at that depth.
+///
+/// Fails for 8-bit sources, whose crops would only claim the extra
+/// precision, and for those [`decode`] has to convert to sRGB, as the
+/// conversion is only done at 8 bits and the colours would not match.
+pub fn decode_deep(bytes: &[u8]) -> Result<Mat> {
+    let image = imgcodecs::imdecode(
+        &Vector::<u8>::from_slice(bytes),
+        imgcodecs::IMREAD_ANYDEPTH | imgcodecs::IMREAD_COLOR,
+    )
+    .context("Could not decode image")?;
+    ensure!(!image.empty(), "Could not decode image");
+    ensure!(
+        image.depth() == core::CV_16U,
+        "The image has 8 bits per channel; --bit-depth 16 needs a 16-bit source"
+    );
+    ensure!(
+        decode(bytes)?.conversion.is_none(),
+        "--bit-depth 16 needs an RGB or grey source in sRGB; this one is converted at 8 bits"
+    );
+    Ok(image)
+}
+
+/// Whether the JPEG holds CMYK, and if so whether it carries Adobe's marker,
+/// which means the inks are stored inverted.
+fn jpeg_cmyk(jpeg: &Jpeg) -> Option<bool> {
+    let frame = jpeg.segments().iter().find(|s| {
+        matches!(s.marker(), markers::SOF0..=markers::SOF15)
+            && !matches!(s.marker(), markers::DHT | markers::JPG | markers::DAC)
+    })?;
+    // Precision, height and width come before the component count.
+    if frame.contents().get(5) != Some(&4) {
+        return None;
+    }
+    Some(
+        jpeg.segments_by_marker(markers::APP14)
+            .any(|s| s.contents().starts_with(b"Adobe")),
+    )
+}
+
+fn decode_cmyk_jpeg(bytes: &[u8], jpeg: &Jpeg, adobe: bool) -> Result<Decoded> {
+    let mut decoder = jpeg_decoder::Decoder::new(bytes);
+    let mut inks = decoder.decode().context("Could not decode CMYK JPEG")?;
+    let info = decoder.info().context("CMYK JPEG has no frame")?;
+    if info.pixel_format != jpeg_decoder::PixelFormat::CMYK32 {
+        bail!("Expected CMYK pixels, got {:?}", info.pixel_format);
+    }
+    // The decoder assumes Adobe's inverted storage; other writers store the inks as they are.
+    if !adobe {
+        for v in &mut inks {
+            *v = 255 - *v;
+        }
+    }
+
+    let mut image = Mat::new_rows_cols_with_default(
+        info.height as i32,
+        info.width as i32,
+        core::CV_8UC3,
+        Scalar::all(0.0),
+    )?;
+    let profile = jpeg
+        .icc_profile()
+        .and_then(|icc| ColorProfile::new_from_slice(&icc).ok())
+        .filter(|p| p.color_space == DataColorSpace::Cmyk);
+    let transform = profile.and_then(|profile| {
+        profile
+            .create_transform_8bit(
+                Layout::Rgba,
+                &ColorProfile::new_srgb(),
+                Layout::Rgb,
+                TransformOptions::default(),
+            )
+            .ok()
+    });
+    let rgb = image.data_bytes_mut()?;
+    let conversion = match transform {
+        Some(transform) => {
+            transform.transform(&inks, rgb)?;
+            Conversion::CmykProfile
+        }
+        None => {
+            for (out, ink) in rgb.chunks_exact_mut(3).zip(inks.chunks_exact(4)) {
+                let white = 255 - ink[3] as u32;
+                for (o, i) in out.iter_mut().zip(ink) {
+                    *o = ((255 - *i as u32) * white / 255) as u8;
+                }
+            }
+            Conversion::CmykApproximate
+        }
+    };
+
+    let image = convert(&image, imgproc::COLOR_RGB2BGR)?;
+    let orientation = jpeg
+        .exif()
+        .and_then(|exif| Ifd::first(&exif)?.value(EXIF_ORIENTATION));
+    Ok(Decoded {
+        image: apply_orientation(image, orientation.unwrap_or(1))?,
+        conversion: Some(conversion),
+    })
+}
+
+/// Turns `image` upright per an EXIF orientation value, as OpenCV does for
+/// the images it decodes itself.
+fn apply_orientation(image: Mat, orientation: u32) -> Result<Mat> {
+    let mut out = Mat::default();
+    match orientation {
+        2 => core::flip(&image, &mut out, 1)?,
+        3 => core::rotate(&image, &mut out, core::ROTATE_180)?,
+        4 => core::flip(&image, &mut out, 0)?,
+        5 => core::transpose(&image, &mut out)?,
+        6 => core::rotate(&image, &mut out, core::ROTATE_90_CLOCKWISE)?,
+        7 => {
+            let mut transposed = Mat::default();
+            core::transpose(&image, &mut transposed)?;
+            core::flip(&transposed, &mut out, -1)?;
+        }
+        8 => core::rotate(&image, &mut out, core::ROTATE_90_COUNTERCLOCKWISE)?,
+        _ => return Ok(image),
+    }
+    Ok(out)
+}
+
+fn profile_name(profile: &ColorProfile) -> String {
+    let name = match &profile.description {
+        Some(ProfileText::PlainString(text)) => text.clone(),
+        Some(ProfileText::Localizable(texts)) => {
+            texts.first().map(|t| t.value.clone()).unwrap_or_default()
+        }
+        Some(ProfileText::Description(text)) => text.ascii_string.clone(),
+        None => String::new(),
+    };
+    let name = name.trim_matches(char::from(0)).trim();
+    if name.is_empty() {
+        "an unnamed ICC profile".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+fn convert(image: &Mat, code: i32) -> Result<Mat> {
+    let mut converted = Mat::default();
+    imgproc::cvt_color(
+        image,
+        &mut converted,
+        code,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    Ok(converted)
+}
+
+/// The first image file directory of a TIFF structure: a TIFF file or the
+/// body of an EXIF block.
+struct Ifd<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+    start: usize,
+    count: usize,
+}
+
+impl<'a> Ifd<'a> {
+    fn first(bytes: &'a [u8]) -> Option<Self> {
+        let big_endian = match bytes.get(..4)? {
+            b"MM\0*" => true,
+            b"II*\0" => false,
+            _ => return None,
+        };
+        let mut ifd = Self {
+            bytes,
+            big_endian,
+            start: 0,
+            count: 0,
+        };
+        ifd.start = ifd.u32_at(4)? as usize;
+        ifd.count = ifd.u16_at(ifd.start)? as usize;
+        Some(ifd)
+    }
+
+    fn u16_at(&self, at: usize) -> Option<u16> {
+        let b: [u8; 2] = self.bytes.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+
+    fn u32_at(&self, at: usize) -> Option<u32> {
+        let b: [u8; 4] = self.bytes.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    /// Offset of the 12-byte entry for `tag`.
+    fn entry(&self, tag: u16) -> Option<usize> {
+        (0..self.count)
+            .map(|i| self.start + 2 + i * 12)
+            .find(|&entry| self.u16_at(entry) == Some(tag))
+    }
+
+    /// First value of a SHORT or LONG tag.
+    fn value(&self, tag: u16) -> Option<u32> {
+        let entry = self.entry(tag)?;
+        match self.u16_at(entry + 2)? {
+            3 => self.u16_at(entry + 8).map(u32::from),
+            4 => self.u32_at(entry + 8),
+            _ => None,
+        }
+    }
+
+    /// Raw bytes of a BYTE or UNDEFINED tag.
+    fn data(&self, tag: u16) -> Option<&'a [u8]> {
+        let entry = self.entry(tag)?;
+        let len = self.u32_at(entry + 4)? as usize;
+        let at = if len <= 4 {
+            entry + 8
+        } else {
+            self.u32_at(entry + 8)? as usize
+        };
+        self.bytes.get(at..at.checked_add(len)?)
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::detect::{CannyMode, ContourMode, DetectParams, PhotoShape, PrintShape};
+use crate::layout::{Grid, Layout, Region};
+use crate::lens::LensProfile;
+use crate::reject::RejectList;
+use crate::sizes::{PrintSize, SizeAction};
+
+/// Per-directory config file picked up while walking the input tree.
+pub const DIR_CONFIG_FILE: &str = ".photo-cropper.toml";
+/// Suffix of per-image config files: `scan_017.jpg` is tuned by `scan_017.params.toml`.
+pub const SIDECAR_SUFFIX: &str = ".params.toml";
+
+/// Detection parameters as written in a config file; unset fields are inherited.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParamOverrides {
+    pub min_area: Option<f64>,
+    pub min_area_frac: Option<f64>,
+    pub pad: Option<i32>,
+    pub canny_low: Option<f64>,
+    pub canny_high: Option<f64>,
+    pub canny: Option<CannyMode>,
+    pub blur_kernel: Option<i32>,
+    pub blur_sigma: Option<f64>,
+    pub threshold_block: Option<i32>,
+    pub robust_corners: Option<bool>,
+    pub print_shape: Option<PrintShape>,
+    pub shapes: Option<Vec<PhotoShape>>,
+    pub contours: Option<ContourMode>,
+    pub known_sizes: Option<Vec<PrintSize>>,
+    pub dpi: Option<f64>,
+    pub size_tolerance: Option<f64>,
+    pub off_size: Option<SizeAction>,
+    pub grid: Option<Grid>,
+    pub cell_margin: Option<i32>,
+    pub compensate_shadows: Option<bool>,
+    pub merge_fragments: Option<bool>,
+    pub keep_corner_mounts: Option<bool>,
+    pub keep_text_regions: Option<bool>,
+    pub recover_edge_photos: Option<bool>,
+    pub split_pairs: Option<bool>,
+    pub pyramid_levels: Option<u32>,
+    pub tile: Option<u32>,
+    pub rectify_page: Option<bool>,
+    pub deskew_sheet: Option<bool>,
+    pub max_photos: Option<usize>,
+    pub layout: Option<Layout>,
+    pub ruler_tick_mm: Option<f64>,
+    pub roi: Option<Region>,
+    pub lens: Option<LensProfile>,
+    pub alpha_background: Option<[u8; 3]>,
+    pub second_scan: Option<String>,
+}
+
+impl ParamOverrides {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    /// Layers `other` on top of `self`; fields set in `other` win.
+    pub fn merge(&mut self, other: &ParamOverrides) {
+        if other.min_area.is_some() {
+            self.min_area = other.min_area;
+        }
+        if other.min_area_frac.is_some() {
+            self.min_area_frac = other.min_area_frac;
+        }
+        if other.pad.is_some() {
+            self.pad = other.pad;
+        }
+        if other.canny_low.is_some() {
+            self.canny_low = other.canny_low;
+        }
+        if other.canny_high.is_some() {
+            self.canny_high = other.canny_high;
+        }
+        if other.canny.is_some() {
+            self.canny = other.canny;
+        }
+        if other.blur_kernel.is_some() {
+            self.blur_kernel = other.blur_kernel;
+        }
+        if other.blur_sigma.is_some() {
+            self.blur_sigma = other.blur_sigma;
+        }
+        if other.threshold_block.is_some() {
+            self.threshold_block = other.threshold_block;
+        }
+        if other.robust_corners.is_some() {
+            self.robust_corners = other.robust_corners;
+        }
+        if other.print_shape.is_some() {
+            self.print_shape = other.print_shape;
+        }
+        if other.shapes.is_some() {
+            self.shapes = other.shapes.clone();
+        }
+        if other.contours.is_some() {
+            self.contours = other.contours;
+        }
+        if other.known_sizes.is_some() {
+            self.known_sizes = other.known_sizes.clone();
+        }
+        if other.dpi.is_some() {
+            self.dpi = other.dpi;
+        }
+        if other.size_tolerance.is_some() {
+            self.size_tolerance = other.size_tolerance;
+        }
+        if other.off_size.is_some() {
+            self.off_size = other.off_size;
+        }
+        if other.grid.is_some() {
+            self.grid = other.grid;
+        }
+        if other.cell_margin.is_some() {
+            self.cell_margin = other.cell_margin;
+        }
+        if other.compensate_shadows.is_some() {
+            self.compensate_shadows = other.compensate_shadows;
+        }
+        if other.merge_fragments.is_some() {
+            self.merge_fragments = other.merge_fragments;
+        }
+        if other.keep_corner_mounts.is_some() {
+            self.keep_corner_mounts = other.keep_corner_mounts;
+        }
+        if other.keep_text_regions.is_some() {
+            self.keep_text_regions = other.keep_text_regions;
+        }
+        if other.recover_edge_photos.is_some() {
+            self.recover_edge_photos = other.recover_edge_photos;
+        }
+        if other.split_pairs.is_some() {
+            self.split_pairs = other.split_pairs;
+        }
+        if other.pyramid_levels.is_some() {
+            self.pyramid_levels = other.pyramid_levels;
+        }
+        if other.tile.is_some() {
+            self.tile = other.tile;
+        }
+        if other.rectify_page.is_some() {
+            self.rectify_page = other.rectify_page;
+        }
+        if other.deskew_sheet.is_some() {
+            self.deskew_sheet = other.deskew_sheet;
+        }
+        if other.max_photos.is_some() {
+            self.max_photos = other.max_photos;
+        }
+        if other.layout.is_some() {
+            self.layout = other.layout.clone();
+        }
+        if other.ruler_tick_mm.is_some() {
+            self.ruler_tick_mm = other.ruler_tick_mm;
+        }
+        if other.roi.is_some() {
+            self.roi = other.roi;
+        }
+        if other.lens.is_some() {
+            self.lens = other.lens.clone();
+        }
+        if other.alpha_background.is_some() {
+            self.alpha_background = other.alpha_background;
+        }
+        if other.second_scan.is_some() {
+            self.second_scan = other.second_scan.clone();
+        }
+    }
+
+    pub fn apply_to(&self, params: &mut DetectParams) {
+        if let Some(min_area) = self.min_area {
+            params.min_area = min_area;
+        }
+        if let Some(frac) = self.min_area_frac {
+            params.min_area_frac = Some(frac);
+        }
+        if let Some(pad) = self.pad {
+            params.pad = pad;
+        }
+        if let Some(canny_low) = self.canny_low {
+            params.canny_low = canny_low;
+        }
+        if let Some(canny_high) = self.canny_high {
+            params.canny_high = canny_high;
+        }
+        if let Some(canny) = self.canny {
+            params.canny = canny;
+        }
+        if let Some(blur_kernel) = self.blur_kernel {
+            params.blur_kernel = blur_kernel;
+        }
+        if let Some(blur_sigma) = self.blur_sigma {
+            params.blur_sigma = blur_sigma;
+        }
+        if let Some(threshold_block) = self.threshold_block {
+            params.threshold_block = threshold_block;
+        }
+        if let Some(robust_corners) = self.robust_corners {
+            params.robust_corners = robust_corners;
+        }
+        if let Some(print_shape) = self.print_shape {
+            params.print_shape = print_shape;
+        }
+        if let Some(shapes) = &self.shapes {
+            params.shapes = shapes.clone();
+        }
+        if let Some(contours) = self.contours {
+            params.contours = contours;
+        }
+        if let Some(known_sizes) = &self.known_sizes {
+            params.known_sizes = known_sizes.clone();
+        }
+        if self.dpi.is_some() {
+            params.dpi = self.dpi;
+        }
+        if let Some(size_tolerance) = self.size_tolerance {
+            params.size_tolerance = size_tolerance;
+        }
+        if let Some(off_size) = self.off_size {
+            params.off_size = off_size;
+        }
+        if self.grid.is_some() {
+            params.grid = self.grid;
+        }
+        if let Some(cell_margin) = self.cell_margin {
+            params.cell_margin = cell_margin;
+        }
+        if let Some(compensate_shadows) = self.compensate_shadows {
+            params.compensate_shadows = compensate_shadows;
+        }
+        if let Some(merge_fragments) = self.merge_fragments {
+            params.merge_fragments = merge_fragments;
+        }
+        if let Some(keep_corner_mounts) = self.keep_corner_mounts {
+            params.keep_corner_mounts = keep_corner_mounts;
+        }
+        if let Some(keep_text_regions) = self.keep_text_regions {
+            params.keep_text_regions = keep_text_regions;
+        }
+        if let Some(recover_edge_photos) = self.recover_edge_photos {
+            params.recover_edge_photos = recover_edge_photos;
+        }
+        if let Some(split_pairs) = self.split_pairs {
+            params.split_pairs = split_pairs;
+        }
+        if let Some(pyramid_levels) = self.pyramid_levels {
+            params.pyramid_levels = pyramid_levels;
+        }
+        if let Some(tile) = self.tile {
+            params.tile = Some(tile);
+        }
+        if let Some(rectify_page) = self.rectify_page {
+            params.rectify_page = rectify_page;
+        }
+        if let Some(deskew_sheet) = self.deskew_sheet {
+            params.deskew_sheet = deskew_sheet;
+        }
+        if let Some(max_photos) = self.max_photos {
+            params.max_photos = Some(max_photos);
+        }
+        if let Some(layout) = &self.layout {
+            params.layout = Some(layout.clone());
+        }
+        if let Some(tick_mm) = self.ruler_tick_mm {
+            params.ruler_tick_mm = Some(tick_mm);
+        }
+        if let Some(roi) = self.roi {
+            params.roi = Some(roi);
+        }
+        if let Some(lens) = &self.lens {
+            params.lens = Some(lens.clone());
+        }
+        if let Some(alpha_background) = self.alpha_background {
+            params.alpha_background = alpha_background;
+        }
+        if let Some(second_scan) = &self.second_scan {
+            params.second_scan = Some(second_scan.clone());
+        }
+    }
+}
+
+/// Resolves the parameters for each image from the config layers, lowest first:
+/// built-in defaults, the global config file, `.photo-cropper.toml` files from
+/// the input root down to the image's directory, the image's own
+/// `<stem>.params.toml` sidecar, and finally command-line flags.
+pub struct ParamResolver {
+    root: PathBuf,
+    global: ParamOverrides,
+    cli: ParamOverrides,
+    reject_list: Option<RejectList>,
+    dirs: HashMap<PathBuf, ParamOverrides>,
+}
+
+impl ParamResolver {
+    pub fn new(root: &Path, global: ParamOverrides, cli: ParamOverrides) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            global,
+            cli,
+            reject_list: None,
+            dirs: HashMap::new(),
+        }
+    }
+
+    /// Excludes the regions listed for each image from its detection.
+    pub fn with_reject_list(mut self, reject_list: Option<RejectList>) -> Self {
+        self.reject_list = reject_list;
+        self
+    }
+
+    pub fn params_for(&mut self, image: &Path) -> Result<DetectParams> {
+        let mut layered = self.global.clone();
+        if let Some(dir) = image.parent() {
+            layered.merge(&self.dir_overrides(dir)?);
+        }
+        let sidecar = sidecar_path(image);
+        if sidecar.is_file() {
+            layered.merge(&ParamOverrides::load(&sidecar)?);
+        }
+        layered.merge(&self.cli);
+
+        let mut params = DetectParams::default();
+        layered.apply_to(&mut params);
+        if let Some(list) = &self.reject_list {
+            params.exclude = list.for_source(image);
+        }
+        Ok(params)
+    }
+
+    /// Merged overrides of every directory config from the root down to `dir`.
+    fn dir_overrides(&mut self, dir: &Path) -> Result<ParamOverrides> {
+        if let Some(cached) = self.dirs.get(dir) {
+            return Ok(cached.clone());
+        }
+
+        let mut overrides = match dir.parent() {
+            Some(parent) if dir != self.root && dir.starts_with(&self.root) => {
+                self.dir_overrides(parent)?
+            }
+            _ => ParamOverrides::default(),
+        };
+
+        let config_path = dir.join(DIR_CONFIG_FILE);
+        if config_path.is_file() {
+            overrides.merge(&ParamOverrides::load(&config_path)?);
+        }
+
+        self.dirs.insert(dir.to_path_buf(), overrides.clone());
+        Ok(overrides)
+    }
+}
+
+/// Path of the sidecar config that overrides parameters for `image` alone.
+pub fn sidecar_path(image: &Path) -> PathBuf {
+    let stem = image.file_stem().unwrap_or_default().to_string_lossy();
+    image.with_file_name(format!("{stem}{SIDECAR_SUFFIX}"))
+}
@@ -0,0 +1,133 @@
+use anyhow::Result;
+use opencv::core::{Mat, Point2f, Size};
+
+use crate::cancel::CancelToken;
+use crate::detect::{self, Candidate, DetectParams, Interpolation, PhotoShape, Quad, Rejection};
+
+/// Detects photos on scanned sheets with a fixed set of parameters.
+#[derive(Clone, Debug)]
+pub struct PhotoCropper {
+    params: DetectParams,
+    cancel: CancelToken,
+}
+
+/// A detected photo whose straightened pixels are only computed on request.
+pub struct DetectedPhoto<'a> {
+    pub quad: Quad,
+    pub confidence: f32,
+    pub shape: PhotoShape,
+    /// Matches none of the known print sizes.
+    pub off_size: bool,
+    /// Outline of an oval or non-rectangular print, empty for plain rectangles.
+    pub outline: Vec<Point2f>,
+    pub warped: LazyWarp<'a>,
+}
+
+/// Deferred perspective warp of one photo out of its sheet.
+pub struct LazyWarp<'a> {
+    image: &'a Mat,
+    quad: Quad,
+}
+
+/// Iterator over the photos of one sheet, largest first.
+pub struct DetectIter<'a> {
+    image: &'a Mat,
+    candidates: std::vec::IntoIter<Candidate>,
+    rejected: Vec<Rejection>,
+    contours: Vec<Vec<Point2f>>,
+    proposals: Vec<Quad>,
+}
+
+impl PhotoCropper {
+    pub fn new(params: DetectParams) -> Self {
+        Self {
+            params,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Makes detection give up with [`Cancelled`](crate::Cancelled) soon
+    /// after `cancel` fires, e.g. when a UI no longer needs the result.
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn params(&self) -> &DetectParams {
+        &self.params
+    }
+
+    /// Runs detection on `image` and yields each photo without warping it.
+    ///
+    /// Contour analysis happens up front; the cost of each warp is only paid
+    /// when [`LazyWarp::warp`] is called.
+    pub fn detect_iter<'a>(&self, image: &'a Mat) -> Result<DetectIter<'a>> {
+        let detection = detect::detect_cancellable(image, &self.params, &self.cancel)?;
+        Ok(DetectIter {
+            image,
+            candidates: detection.photos.into_iter(),
+            rejected: detection.rejected,
+            contours: detection.contours,
+            proposals: detection.candidates,
+        })
+    }
+}
+
+impl DetectIter<'_> {
+    /// Regions that were found but not taken for photos, e.g. captions.
+    pub fn rejected(&self) -> &[Rejection] {
+        &self.rejected
+    }
+
+    /// Simplified outlines of the contours considered, in source coordinates.
+    pub fn contours(&self) -> &[Vec<Point2f>] {
+        &self.contours
+    }
+
+    /// Rectangles of the accepted contours before overlap suppression.
+    pub fn candidates(&self) -> &[Quad] {
+        &self.proposals
+    }
+}
+
+impl<'a> Iterator for DetectIter<'a> {
+    type Item = DetectedPhoto<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let candidate = self.candidates.next()?;
+        Some(DetectedPhoto {
+            quad: candidate.quad,
+            confidence: candidate.confidence,
+            shape: candidate.shape,
+            off_size: candidate.off_size,
+            outline: candidate.outline,
+            warped: LazyWarp {
+                image: self.image,
+                quad: candidate.quad,
+            },
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.candidates.size_hint()
+    }
+}
+
+impl ExactSizeIterator for DetectIter<'_> {}
+
+impl LazyWarp<'_> {
+    /// Dimensions the warped image will have.
+    pub fn size(&self) -> Size {
+        detect::warp_size(&self.quad)
+    }
+
+    /// Performs the perspective warp with bicubic interpolation.
+    pub fn warp(&self) -> Result<Mat> {
+        self.warp_with(Interpolation::default())
+    }
+
+    /// Performs the perspective warp with the given resampling.
+    pub fn warp_with(&self, interpolation: Interpolation) -> Result<Mat> {
+        detect::warp_quad(self.image, &self.quad, interpolation)
+    }
+}
@@ -0,0 +1,172 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, ensure};
+use opencv::core::Mat;
+use serde::{Deserialize, Serialize};
+
+/// Share of the model's belief the reported range must gather; the range
+/// grows from the likeliest period until it does.
+const RANGE_MASS: f32 = 0.6;
+
+/// When a photo was probably taken, as judged from how it looks.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DateEstimate {
+    /// First year of the range.
+    pub from: u16,
+    /// Last year of the range.
+    pub to: u16,
+    /// How much of the model's belief falls inside the range, 0 to 1.
+    pub confidence: f32,
+}
+
+/// An ONNX classifier that sorts photos into periods such as decades.
+///
+/// The model takes one 224×224 RGB image scaled to 0–1 (NCHW) and gives
+/// one score per period. The periods are listed in a labels file next to
+/// the model (`dating.onnx.labels` for `dating.onnx`), one per line in the
+/// model's output order, as `1950-1959` or `1950s`.
+pub struct DateModel {
+    net: Net,
+    periods: Vec<(u16, u16)>,
+}
+
+impl DateModel {
+    pub fn load(model: &Path) -> Result<Self> {
+        let labels = labels_path(model);
+        let text = fs::read_to_string(&labels)
+            .with_context(|| format!("Could not read the model's labels {}", labels.display()))?;
+        let periods = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                parse_period(line)
+                    .with_context(|| format!("Invalid period {line:?} in {}", labels.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        ensure!(!periods.is_empty(), "{} lists no periods", labels.display());
+        Ok(Self {
+            net: load_net(model)?,
+            periods,
+        })
+    }
+
+    /// Estimates when the upright 8-bit BGR `photo` was taken.
+    pub fn estimate(&mut self, photo: &Mat) -> Result<DateEstimate> {
+        let scores = scores(&mut self.net, photo)?;
+        ensure!(
+            scores.len() == self.periods.len(),
+            "The model gives {} scores but its labels list {} periods",
+            scores.len(),
+            self.periods.len()
+        );
+        Ok(range(&self.periods, &probabilities(scores)))
+    }
+}
+
+fn labels_path(model: &Path) -> PathBuf {
+    let mut path = model.as_os_str().to_owned();
+    path.push(".labels");
+    PathBuf::from(path)
+}
+
+/// `1950-1959`, or `1950s` for the decade.
+fn parse_period(text: &str) -> Option<(u16, u16)> {
+    if let Some(decade) = text.strip_suffix('s') {
+        let from: u16 = decade.parse().ok()?;
+        return Some((from, from + 9));
+    }
+    let (from, to) = text.split_once('-')?;
+    let (from, to): (u16, u16) = (from.trim().parse().ok()?, to.trim().parse().ok()?);
+    (from <= to).then_some((from, to))
+}
+
+/// `scores` as probabilities: kept when they already are, else softmaxed.
+fn probabilities(scores: Vec<f32>) -> Vec<f32> {
+    let sum: f32 = scores.iter().sum();
+    if scores.iter().all(|s| (0.0..=1.0).contains(s)) && (sum - 1.0).abs() < 0.01 {
+        return scores;
+    }
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exp: Vec<f32> = scores.iter().map(|s| (s - max).exp()).collect();
+    let sum: f32 = exp.iter().sum();
+    exp.into_iter().map(|e| e / sum).collect()
+}
+
+/// The run of neighbouring periods around the likeliest one that gathers
+/// [`RANGE_MASS`], growing towards the likelier side each step.
+fn range(periods: &[(u16, u16)], probabilities: &[f32]) -> DateEstimate {
+    let best = probabilities
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map_or(0, |(i, _)| i);
+    let (mut first, mut last) = (best, best);
+    let mut mass = probabilities[best];
+    while mass < RANGE_MASS && (first > 0 || last + 1 < periods.len()) {
+        let before = first.checked_sub(1).map(|i| probabilities[i]);
+        let after = probabilities.get(last + 1).copied();
+        if before.unwrap_or(-1.0) >= after.unwrap_or(-1.0) {
+            first -= 1;
+            mass += probabilities[first];
+        } else {
+            last += 1;
+            mass += probabilities[last];
+        }
+    }
+    DateEstimate {
+        from: periods[first..=last]
+            .iter()
+            .map(|p| p.0)
+            .min()
+            .unwrap_or_default(),
+        to: periods[first..=last]
+            .iter()
+            .map(|p| p.1)
+            .max()
+            .unwrap_or_default(),
+        confidence: mass.min(1.0),
+    }
+}
+
+#[cfg(feature = "date-model")]
+type Net = opencv::dnn::Net;
+
+#[cfg(feature = "date-model")]
+fn load_net(model: &Path) -> Result<Net> {
+    opencv::dnn::read_net_from_onnx(&model.to_string_lossy())
+        .with_context(|| format!("Could not load the model {}", model.display()))
+}
+
+#[cfg(feature = "date-model")]
+fn scores(net: &mut Net, photo: &Mat) -> Result<Vec<f32>> {
+    use opencv::core::{CV_32F, Scalar, Size};
+    use opencv::prelude::*;
+
+    let blob = opencv::dnn::blob_from_image(
+        photo,
+        1.0 / 255.0,
+        Size::new(224, 224),
+        Scalar::default(),
+        true,
+        false,
+        CV_32F,
+    )?;
+    net.set_input(&blob, "", 1.0, Scalar::default())?;
+    let output = net.forward_single("")?;
+    Ok(output.data_typed::<f32>()?.to_vec())
+}
+
+#[cfg(not(feature = "date-model"))]
+type Net = ();
+
+#[cfg(not(feature = "date-model"))]
+fn load_net(_model: &Path) -> Result<Net> {
+    anyhow::bail!("--date-model needs a build with the `date-model` feature")
+}
+
+#[cfg(not(feature = "date-model"))]
+fn scores(_net: &mut Net, _photo: &Mat) -> Result<Vec<f32>> {
+    anyhow::bail!("--date-model needs a build with the `date-model` feature")
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::hash;
+use crate::settle::{self, Readiness};
+
+/// An input with the same content as one listed before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Duplicate {
+    pub path: PathBuf,
+    /// The earlier file it repeats, which is the one processed.
+    pub original: PathBuf,
+}
+
+/// `files` without exact duplicates, such as a scan saved twice in
+/// different folders of a merged archive, and the duplicates left out. The
+/// first of each set of equal files, in the order given, is kept.
+///
+/// Only files sharing a size are hashed, so a batch of distinct scans costs
+/// no more than listing it. Files that cannot be read, or are empty or still
+/// being written, are always kept, to be dealt with when they are read.
+pub fn split(files: Vec<PathBuf>) -> (Vec<PathBuf>, Vec<Duplicate>) {
+    let mut by_size: HashMap<u64, usize> = HashMap::new();
+    let sizes: Vec<Option<u64>> = files
+        .iter()
+        .map(|file| {
+            let size = fs::metadata(file).ok()?.len();
+            (settle::check(file).ok()? == Readiness::Ready).then_some(size)
+        })
+        .collect();
+    for size in sizes.iter().flatten() {
+        *by_size.entry(*size).or_default() += 1;
+    }
+
+    let mut seen: HashMap<String, PathBuf> = HashMap::new();
+    let mut unique = Vec::with_capacity(files.len());
+    let mut duplicates = Vec::new();
+    for (file, size) in files.into_iter().zip(sizes) {
+        let shared = size.is_some_and(|size| by_size[&size] > 1);
+        let digest = shared.then(|| hash::sha256_file(&file).ok()).flatten();
+        let Some(digest) = digest else {
+            unique.push(file);
+            continue;
+        };
+        match seen.get(&digest) {
+            Some(original) => duplicates.push(Duplicate {
+                path: file,
+                original: original.clone(),
+            }),
+            None => {
+                seen.insert(digest, file.clone());
+                unique.push(file);
+            }
+        }
+    }
+    (unique, duplicates)
+}
@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::detect::DetectParams;
+use crate::hash;
+use crate::manifest::Sheet;
+use crate::orientation::Rotation;
+
+/// Detected geometry kept on disk between commands, one JSON file per
+/// source content and parameter set, so going back and forth between
+/// `review`, `recrop` and `run` detects a sheet again only when the scan or
+/// its parameters changed.
+pub struct DetectionCache {
+    dir: PathBuf,
+}
+
+impl DetectionCache {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Key of detecting the file with `source_sha256` turned by `rotation`
+    /// with `params`. The program version is part of it, as detection
+    /// changes between releases.
+    pub fn key(source_sha256: &str, params: &DetectParams, rotation: Rotation) -> Result<String> {
+        let params = serde_json::to_string(params)?;
+        Ok(hash::sha256_hex(
+            format!(
+                "{source_sha256}\n{params}\n{rotation:?}\n{}",
+                env!("CARGO_PKG_VERSION")
+            )
+            .as_bytes(),
+        ))
+    }
+
+    /// The sheet detected under `key`, if any. An entry that cannot be read
+    /// counts as missing, to be detected and written anew.
+    pub fn get(&self, key: &str) -> Option<Sheet> {
+        let text = fs::read_to_string(self.path(key)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    /// Stores `sheet` under `key`, through a temporary file so that parallel
+    /// workers never read half an entry.
+    pub fn put(&self, key: &str, sheet: &Sheet) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Could not create {}", self.dir.display()))?;
+        let path = self.path(key);
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string(sheet)?)
+            .with_context(|| format!("Could not write {}", temp.display()))?;
+        fs::rename(&temp, &path).with_context(|| format!("Could not write {}", path.display()))
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+}
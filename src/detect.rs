@@ -0,0 +1,2072 @@
+use anyhow::Result;
+use opencv::core::{
+    self, AlgorithmHint, BORDER_REPLICATE, Mat, Point, Point2f, Scalar, Size, Vector,
+};
+use opencv::imgproc::{self, InterpolationFlags};
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cancel::CancelToken;
+use crate::layout::{Grid, Layout, Region};
+use crate::lens::LensProfile;
+use crate::pairs;
+use crate::quadfit;
+use crate::sizes::{PrintSize, SizeAction};
+use crate::skew;
+use crate::textlike;
+
+/// Corner points of a detected photo in source image coordinates, ordered
+/// top-left, top-right, bottom-right, bottom-left.
+pub type Quad = [Point2f; 4];
+
+/// Parameters controlling photo detection on a single sheet.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DetectParams {
+    pub min_area: f64,
+    /// Minimum area as a share of the sheet; replaces `min_area` when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_area_frac: Option<f64>,
+    pub pad: i32,
+    pub canny_low: f64,
+    pub canny_high: f64,
+    /// How the Canny thresholds are chosen; `auto` replaces the two above.
+    #[serde(default)]
+    pub canny: CannyMode,
+    /// Side of the Gaussian blur kernel applied before thresholding (odd; 0 turns it off).
+    #[serde(default = "default_blur_kernel")]
+    pub blur_kernel: i32,
+    /// Standard deviation of the blur in pixels; 0 derives it from the kernel side.
+    #[serde(default)]
+    pub blur_sigma: f64,
+    /// Side of the neighbourhood the adaptive threshold compares each pixel with (odd, at least 3).
+    #[serde(default = "default_threshold_block")]
+    pub threshold_block: i32,
+    /// Fit corners from the straight edges instead of the minimum-area rectangle.
+    #[serde(default)]
+    pub robust_corners: bool,
+    /// Edge style of the prints; non-rectangular shapes are fitted from their edges.
+    #[serde(default)]
+    pub print_shape: PrintShape,
+    /// Photo outlines to look for; contours matching none of them are skipped.
+    #[serde(default = "default_shapes")]
+    pub shapes: Vec<PhotoShape>,
+    /// Which of nested outlines are taken for photos.
+    #[serde(default)]
+    pub contours: ContourMode,
+    /// Print sizes expected in the batch; empty accepts any size.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub known_sizes: Vec<PrintSize>,
+    /// Scan resolution, needed to compare candidates against `known_sizes`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dpi: Option<f64>,
+    /// Share each side may deviate from a known size.
+    #[serde(default = "default_size_tolerance")]
+    pub size_tolerance: f64,
+    #[serde(default)]
+    pub off_size: SizeAction,
+    /// Split the sheet into this many equal cells instead of detecting photos.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid: Option<Grid>,
+    /// Pixels trimmed from every side of each grid cell.
+    #[serde(default)]
+    pub cell_margin: i32,
+    /// Flatten soft shadows around the prints before thresholding.
+    #[serde(default)]
+    pub compensate_shadows: bool,
+    /// Join partial contours of one photo (e.g. split by a strong horizon line).
+    #[serde(default)]
+    pub merge_fragments: bool,
+    /// Keep adhesive corner mounts inside the quad instead of fitting the
+    /// photo between them.
+    #[serde(default)]
+    pub keep_corner_mounts: bool,
+    /// Keep regions that look like captions or handwritten notes.
+    #[serde(default)]
+    pub keep_text_regions: bool,
+    /// Pad the sheet with its background colour rather than its border
+    /// pixels, and cut photos flush against the bed edge off at the edge.
+    #[serde(default)]
+    pub recover_edge_photos: bool,
+    /// Split candidates of about 2:1 with a seam down the middle into the
+    /// two photos lying edge to edge.
+    #[serde(default)]
+    pub split_pairs: bool,
+    /// Number of image scales searched, each half the size of the previous.
+    #[serde(default = "default_pyramid_levels")]
+    pub pyramid_levels: u32,
+    /// Search very large sheets in overlapping square tiles of this many
+    /// pixels a side, so working memory follows the tile rather than the sheet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tile: Option<u32>,
+    /// Straighten a photographed page before detecting the photos on it.
+    #[serde(default)]
+    pub rectify_page: bool,
+    /// Turn the whole sheet straight by its dominant edge angle before detection.
+    #[serde(default)]
+    pub deskew_sheet: bool,
+    /// Keep at most this many photos per sheet, the most confident ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_photos: Option<usize>,
+    /// Page template whose slots are refined locally instead of searching the whole sheet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<Layout>,
+    /// Measure the scan resolution from a ruler on the bed whose ticks are
+    /// this many millimetres apart; a found ruler overrides `dpi`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ruler_tick_mm: Option<f64>,
+    /// Part of the sheet searched for photos; the rest is ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roi: Option<Region>,
+    /// Regions of the sheet, as `[x, y]` source pixel quads, where no photo may be found.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<[[f32; 2]; 4]>,
+    /// Lens distortion removed from the source before detection and cropping.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lens: Option<LensProfile>,
+    /// RGB colour transparent parts of a source with alpha are laid over.
+    #[serde(default = "default_alpha_background")]
+    pub alpha_background: [u8; 3],
+    /// Suffix naming a second scan of each sheet (`_b` for `scan_b.tif` beside
+    /// `scan.tif`), fused with it to fill in sleeve glare.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub second_scan: Option<String>,
+}
+
+/// Outline of a photo as a whole, independent of its edge style.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PhotoShape {
+    /// Rectangular photos
+    #[default]
+    Rect,
+    /// Oval or circular photos, found by ellipse fitting
+    Ellipse,
+}
+
+impl PhotoShape {
+    pub fn is_rect(&self) -> bool {
+        *self == PhotoShape::Rect
+    }
+}
+
+/// Where the Canny thresholds come from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CannyMode {
+    /// The configured low and high thresholds
+    #[default]
+    Fixed,
+    /// Derived per sheet from its median gray value, and applied to the
+    /// blurred gray sheet rather than its adaptive threshold
+    Auto,
+}
+
+/// Which outlines are searched when they lie inside one another, as a photo
+/// in a decorative frame on a page.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ContourMode {
+    /// Outermost outlines only; anything inside them is not looked at
+    #[default]
+    External,
+    /// The innermost outline larger than the minimum area in each nest
+    Innermost,
+}
+
+fn default_shapes() -> Vec<PhotoShape> {
+    vec![PhotoShape::Rect]
+}
+
+fn default_size_tolerance() -> f64 {
+    0.08
+}
+
+fn default_blur_kernel() -> i32 {
+    5
+}
+
+fn default_threshold_block() -> i32 {
+    25
+}
+
+fn default_pyramid_levels() -> u32 {
+    1
+}
+
+fn default_alpha_background() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+/// Physical edge style of the prints on a sheet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PrintShape {
+    /// Straight edges and square corners
+    #[default]
+    Rect,
+    /// Straight edges with rounded corners
+    Rounded,
+    /// Scalloped (deckle) edges
+    Deckle,
+}
+
+/// Resampling used when straightening a photo.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Interpolation {
+    /// Fastest, blocky; fine for previews
+    Nearest,
+    /// Fast and smooth; fine for previews
+    Linear,
+    /// Good general quality
+    #[default]
+    Cubic,
+    /// Sharpest, slowest; for archival copies
+    Lanczos4,
+}
+
+impl Interpolation {
+    fn flag(self) -> i32 {
+        let flag = match self {
+            Interpolation::Nearest => InterpolationFlags::INTER_NEAREST,
+            Interpolation::Linear => InterpolationFlags::INTER_LINEAR,
+            Interpolation::Cubic => InterpolationFlags::INTER_CUBIC,
+            Interpolation::Lanczos4 => InterpolationFlags::INTER_LANCZOS4,
+        };
+        flag as i32
+    }
+}
+
+impl Default for DetectParams {
+    fn default() -> Self {
+        Self {
+            min_area: 20_000.0,
+            min_area_frac: None,
+            pad: 12,
+            canny_low: 50.0,
+            canny_high: 150.0,
+            canny: CannyMode::Fixed,
+            blur_kernel: default_blur_kernel(),
+            blur_sigma: 0.0,
+            threshold_block: default_threshold_block(),
+            robust_corners: false,
+            print_shape: PrintShape::Rect,
+            shapes: default_shapes(),
+            contours: ContourMode::External,
+            known_sizes: Vec::new(),
+            dpi: None,
+            size_tolerance: default_size_tolerance(),
+            off_size: SizeAction::Reject,
+            grid: None,
+            cell_margin: 0,
+            compensate_shadows: false,
+            merge_fragments: false,
+            keep_corner_mounts: false,
+            keep_text_regions: false,
+            recover_edge_photos: false,
+            split_pairs: false,
+            pyramid_levels: default_pyramid_levels(),
+            tile: None,
+            rectify_page: false,
+            deskew_sheet: false,
+            max_photos: None,
+            layout: None,
+            ruler_tick_mm: None,
+            roi: None,
+            exclude: Vec::new(),
+            lens: None,
+            alpha_background: default_alpha_background(),
+            second_scan: None,
+        }
+    }
+}
+
+impl DetectParams {
+    /// Looser variant used to retry sheets that produced too few photos:
+    /// smaller contours and fainter edges are accepted, and corners are fitted
+    /// from edges so partly merged or damaged prints still square up.
+    pub fn relaxed(&self) -> Self {
+        Self {
+            min_area: self.min_area * 0.5,
+            min_area_frac: self.min_area_frac.map(|frac| frac * 0.5),
+            canny_low: self.canny_low * 0.6,
+            canny_high: self.canny_high * 0.6,
+            robust_corners: true,
+            ..self.clone()
+        }
+    }
+
+    /// Parameters for a copy of the sheet resized by `scale`: pixel sizes,
+    /// resolution and excluded regions follow the image.
+    pub fn scaled(&self, scale: f64) -> Self {
+        Self {
+            min_area: self.min_area * scale * scale,
+            pad: ((self.pad as f64 * scale).round() as i32).max(1),
+            dpi: self.dpi.map(|dpi| dpi * scale),
+            roi: self.roi.map(|roi| roi.scaled(scale as f32)),
+            exclude: self
+                .exclude
+                .iter()
+                .map(|region| region.map(|[x, y]| [x * scale as f32, y * scale as f32]))
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Smallest contour area accepted on a sheet of `size` pixels.
+    pub fn min_area_for(&self, size: Size) -> f64 {
+        match self.min_area_frac {
+            Some(frac) => frac * size.width as f64 * size.height as f64,
+            None => self.min_area,
+        }
+    }
+}
+
+/// A photo found on a sheet, before any pixels are warped.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub quad: Quad,
+    /// How well the contour fills its rectangle (or ellipse), from 0 to 1.
+    pub confidence: f32,
+    pub shape: PhotoShape,
+    /// Matches none of the known print sizes (only kept when flagging).
+    pub off_size: bool,
+    /// Simplified outline of the print in source coordinates, kept for
+    /// non-rectangular prints and ellipses so the output can be masked to it.
+    pub outline: Vec<Point2f>,
+}
+
+struct RectCandidate {
+    rect: core::RotatedRect,
+    area: f64,
+    shape: PhotoShape,
+    /// Contour points, kept only when fitting corners from edges.
+    contour: Option<Vec<Point2f>>,
+    outline: Vec<Point2f>,
+}
+
+/// Why a candidate was not kept as a photo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RejectReason {
+    /// Its outline matches none of the requested shapes.
+    Shape,
+    /// Overlaps a larger candidate, which was kept instead.
+    Overlap,
+    /// Matches none of the known print sizes.
+    OffSize,
+    /// Less confident than the photos kept under `max_photos`.
+    Limit,
+    /// Looks like a caption, label or handwritten note.
+    Text,
+    /// Shows the same photo as a larger candidate over it.
+    Duplicate,
+}
+
+/// A region that was found but turned down.
+#[derive(Clone, Debug)]
+pub struct Rejection {
+    pub quad: Quad,
+    pub reason: RejectReason,
+}
+
+/// Photos found on a sheet, largest first, with the intermediate results
+/// that led to them. Everything is in source image coordinates.
+#[derive(Clone, Debug, Default)]
+pub struct Detection {
+    pub photos: Vec<Candidate>,
+    pub rejected: Vec<Rejection>,
+    /// Simplified outlines of the contours large enough to be considered.
+    pub contours: Vec<Vec<Point2f>>,
+    /// Minimum-area rectangles of the accepted contours, before overlapping
+    /// ones are suppressed.
+    pub candidates: Vec<Quad>,
+    /// Checked between the steps of the search.
+    cancel: CancelToken,
+}
+
+/// Lengths of a [`Detection`]'s lists at some point, so the entries added
+/// by a nested search can be mapped back to the caller's coordinates.
+struct Mark {
+    contours: usize,
+    candidates: usize,
+    rejected: usize,
+}
+
+impl Detection {
+    fn mark(&self) -> Mark {
+        Mark {
+            contours: self.contours.len(),
+            candidates: self.candidates.len(),
+            rejected: self.rejected.len(),
+        }
+    }
+
+    /// Every point recorded since `mark`.
+    fn points_since(&mut self, mark: &Mark) -> impl Iterator<Item = &mut Point2f> {
+        self.contours[mark.contours..]
+            .iter_mut()
+            .flatten()
+            .chain(self.candidates[mark.candidates..].iter_mut().flatten())
+            .chain(
+                self.rejected[mark.rejected..]
+                    .iter_mut()
+                    .flat_map(|r| r.quad.iter_mut()),
+            )
+    }
+
+    fn reject(&mut self, quad: Quad, reason: RejectReason) {
+        self.rejected.push(Rejection { quad, reason });
+    }
+}
+
+/// Contours below this share of the minimum area are left out of
+/// [`Detection::contours`]; smaller ones are mostly dust and texture.
+const TRACE_MIN_AREA_SHARE: f64 = 0.1;
+/// Largest deviation, in pixels, of a recorded contour from the real one.
+const TRACE_EPSILON: f64 = 2.0;
+/// Most of the 64 bits of two crops' difference hashes that may differ for
+/// them to count as the same photo.
+const DUPLICATE_MAX_DISTANCE: u32 = 10;
+
+/// Finds photos on a scanned sheet and returns their corners, largest first.
+pub fn detect_quads(image: &Mat, params: &DetectParams) -> Result<Vec<Candidate>> {
+    Ok(detect(image, params)?.photos)
+}
+
+/// Like [`detect_quads`], also reporting the contours, candidates and
+/// rejections behind the result.
+pub fn detect(image: &Mat, params: &DetectParams) -> Result<Detection> {
+    detect_cancellable(image, params, &CancelToken::default())
+}
+
+/// Like [`detect`], giving up with [`Cancelled`](crate::cancel::Cancelled)
+/// at the next step after `cancel` fires.
+#[tracing::instrument(skip_all, err)]
+pub fn detect_cancellable(
+    image: &Mat,
+    params: &DetectParams,
+    cancel: &CancelToken,
+) -> Result<Detection> {
+    let mut detection = Detection {
+        cancel: cancel.clone(),
+        ..Detection::default()
+    };
+    if params.keep_text_regions {
+        let photos = find_candidates(image, params, &mut detection)?;
+        detection.photos = drop_duplicates(image, photos, &mut detection)?;
+        return Ok(detection);
+    }
+    // The per-sheet limit only counts what survives the text check.
+    let unlimited = DetectParams {
+        max_photos: None,
+        ..params.clone()
+    };
+    let mut photos = Vec::new();
+    for candidate in find_candidates(image, &unlimited, &mut detection)? {
+        detection.cancel.check()?;
+        if textlike::is_text_like(image, &candidate.quad)? {
+            detection.reject(candidate.quad, RejectReason::Text);
+        } else {
+            photos.push(candidate);
+        }
+    }
+    let photos = drop_duplicates(image, photos, &mut detection)?;
+    detection.photos = finish(photos, params, &mut detection);
+    Ok(detection)
+}
+
+/// Drops candidates that show the same photo as a larger one they mostly
+/// overlap, as a last guard when the passes and regions that found them
+/// each let one through the overlap filter.
+///
+/// Overlap alone is not enough: a small print lying on a larger one is a
+/// photo of its own, and it looks nothing like the larger one's crop.
+fn drop_duplicates(
+    image: &Mat,
+    mut candidates: Vec<Candidate>,
+    trace: &mut Detection,
+) -> Result<Vec<Candidate>> {
+    candidates.sort_by_key(|c| {
+        let size = warp_size(&c.quad);
+        std::cmp::Reverse(size.width * size.height)
+    });
+    let mut kept: Vec<(Candidate, Option<u64>)> = Vec::new();
+    'outer: for candidate in candidates {
+        let mut hash = None;
+        for (other, other_hash) in &mut kept {
+            if !quads_overlap(&other.quad, &candidate.quad) {
+                continue;
+            }
+            // Hashed only when needed, as that means straightening the crop.
+            let a = *hash.get_or_insert(difference_hash(image, &candidate.quad)?);
+            let b = *other_hash.get_or_insert(difference_hash(image, &other.quad)?);
+            if (a ^ b).count_ones() <= DUPLICATE_MAX_DISTANCE {
+                tracing::debug!("candidate dropped as a duplicate of a larger one");
+                trace.reject(candidate.quad, RejectReason::Duplicate);
+                continue 'outer;
+            }
+        }
+        kept.push((candidate, hash));
+    }
+    Ok(kept.into_iter().map(|(candidate, _)| candidate).collect())
+}
+
+/// 64-bit difference hash of the straightened `quad`: whether each pixel of
+/// a 9×8 grey thumbnail is brighter than its right neighbour. Crops of the
+/// same photo hash alike despite slightly different edges.
+fn difference_hash(image: &Mat, quad: &Quad) -> Result<u64> {
+    let warped = warp_quad(image, quad, Interpolation::Linear)?;
+    let gray = if warped.channels() == 1 {
+        warped
+    } else {
+        let mut gray = Mat::default();
+        imgproc::cvt_color(
+            &warped,
+            &mut gray,
+            imgproc::COLOR_BGR2GRAY,
+            0,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        gray
+    };
+    let mut thumbnail = Mat::default();
+    imgproc::resize(
+        &gray,
+        &mut thumbnail,
+        Size::new(9, 8),
+        0.0,
+        0.0,
+        imgproc::INTER_AREA,
+    )?;
+    if thumbnail.depth() != core::CV_8U {
+        let mut narrow = Mat::default();
+        thumbnail.convert_to(&mut narrow, core::CV_8U, 1.0 / 257.0, 0.0)?;
+        thumbnail = narrow;
+    }
+    let mut hash = 0u64;
+    for row in 0..8 {
+        for col in 0..8 {
+            let left = *thumbnail.at_2d::<u8>(row, col)?;
+            let right = *thumbnail.at_2d::<u8>(row, col + 1)?;
+            hash = hash << 1 | u64::from(left > right);
+        }
+    }
+    Ok(hash)
+}
+
+fn find_candidates(
+    image: &Mat,
+    params: &DetectParams,
+    trace: &mut Detection,
+) -> Result<Vec<Candidate>> {
+    trace.cancel.check()?;
+    if let Some(roi) = params.roi {
+        let size = image.size()?;
+        let local = DetectParams {
+            roi: None,
+            min_area: params.min_area_for(size),
+            min_area_frac: None,
+            ..params.clone()
+        };
+        return detect_in_region(image, &local, roi.rect(size), trace);
+    }
+    if params.rectify_page {
+        return detect_on_page(image, params, trace);
+    }
+    if params.deskew_sheet {
+        return detect_deskewed(image, params, trace);
+    }
+    // Sheets from a fixed jig are split by geometry alone.
+    if let Some(grid) = params.grid {
+        let cells = grid.cells(image.size()?, params.cell_margin);
+        return Ok(cells
+            .into_iter()
+            .map(|quad| Candidate {
+                quad,
+                confidence: 1.0,
+                shape: PhotoShape::Rect,
+                off_size: false,
+                outline: Vec::new(),
+            })
+            .collect());
+    }
+    if let Some(layout) = &params.layout {
+        return refine_slots(image, params, layout, trace);
+    }
+
+    if let Some(tile) = params.tile {
+        let size = image.size()?;
+        if size.width.max(size.height) as u32 > tile {
+            return detect_tiled(image, params, tile as i32, trace);
+        }
+    }
+    if params.pyramid_levels > 1 {
+        return detect_pyramid(image, params, trace);
+    }
+
+    let dpi = match params.dpi {
+        Some(dpi) if dpi > 0.0 => dpi,
+        Some(dpi) => anyhow::bail!("DPI must be positive, got {dpi}"),
+        None if params.known_sizes.is_empty() => 0.0,
+        None => anyhow::bail!("Known print sizes need the scan DPI (--dpi)"),
+    };
+    let min_area = params.min_area_for(image.size()?);
+    let pad = params.pad.max(0);
+    let fit_corners = params.robust_corners || params.print_shape != PrintShape::Rect;
+    // Corner mounts are found from the outline, so it is kept for them too.
+    let fit_edges = fit_corners || !params.keep_corner_mounts;
+    // Replicated border pixels smear a photo touching the edge into the
+    // padding; the bed's own colour gives it an edge to be found by.
+    let (border_type, fill) = if params.recover_edge_photos {
+        (core::BORDER_CONSTANT, background_colour(image)?)
+    } else {
+        (BORDER_REPLICATE, Scalar::all(0.0))
+    };
+    let mut padded = Mat::default();
+    core::copy_make_border(image, &mut padded, pad, pad, pad, pad, border_type, fill)?;
+
+    let mut gray = Mat::default();
+    imgproc::cvt_color(
+        &padded,
+        &mut gray,
+        imgproc::COLOR_BGR2GRAY,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    if params.compensate_shadows {
+        gray = flatten_shadows(&gray)?;
+    }
+    trace.cancel.check()?;
+
+    let blurred = if params.blur_kernel > 1 {
+        // Kernel sides must be odd.
+        let side = params.blur_kernel | 1;
+        let mut blurred = Mat::default();
+        imgproc::gaussian_blur(
+            &gray,
+            &mut blurred,
+            Size::new(side, side),
+            params.blur_sigma,
+            params.blur_sigma,
+            core::BORDER_DEFAULT,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        blurred
+    } else {
+        gray
+    };
+
+    // Adaptive threshold handles both light and dark backgrounds.
+    let mut binary = Mat::default();
+    imgproc::adaptive_threshold(
+        &blurred,
+        &mut binary,
+        255.0,
+        imgproc::ADAPTIVE_THRESH_GAUSSIAN_C,
+        imgproc::THRESH_BINARY,
+        params.threshold_block.max(3) | 1,
+        10.0,
+    )?;
+    trace.cancel.check()?;
+
+    let mut inverted = Mat::default();
+    core::bitwise_not(&binary, &mut inverted, &core::no_array())?;
+
+    let (low, high) = if params.canny == CannyMode::Auto {
+        let (low, high) = auto_canny(&blurred)?;
+        tracing::debug!(low, high, "derived Canny thresholds");
+        (low, high)
+    } else if params.canny_high <= params.canny_low {
+        let high = (params.canny_low * 3.0).max(params.canny_low + 1.0);
+        (params.canny_low, high)
+    } else {
+        (params.canny_low, params.canny_high)
+    };
+
+    // Thresholds derived from the gray values only mean something on the gray
+    // image; on the binary one every threshold finds the same edges.
+    let edge_source = if params.canny == CannyMode::Auto {
+        &blurred
+    } else {
+        &inverted
+    };
+    let mut edges = Mat::default();
+    imgproc::canny(edge_source, &mut edges, low, high, 3, false)?;
+    trace.cancel.check()?;
+
+    let kernel =
+        imgproc::get_structuring_element(imgproc::MORPH_RECT, Size::new(5, 5), Point::new(-1, -1))?;
+    let mut dilated = Mat::default();
+    imgproc::dilate(
+        &edges,
+        &mut dilated,
+        &kernel,
+        Point::new(-1, -1),
+        2,
+        core::BORDER_CONSTANT,
+        Scalar::all(0.0),
+    )?;
+    edges = dilated;
+    mask_excluded(&mut edges, &params.exclude, pad as f32)?;
+
+    // Edge fitting needs every contour point, not just the polygon vertices.
+    let method = if fit_edges {
+        imgproc::CHAIN_APPROX_NONE
+    } else {
+        imgproc::CHAIN_APPROX_SIMPLE
+    };
+    let contours = match params.contours {
+        ContourMode::External => {
+            let mut contours: Vector<Vector<Point>> = Vector::new();
+            imgproc::find_contours(
+                &edges,
+                &mut contours,
+                imgproc::RETR_EXTERNAL,
+                method,
+                Point::new(0, 0),
+            )?;
+            contours
+        }
+        ContourMode::Innermost => innermost_contours(&edges, method, min_area)?,
+    };
+
+    let mut rects = Vec::new();
+    let unpad = |p: Point| Point2f::new((p.x - pad) as f32, (p.y - pad) as f32);
+
+    for contour in contours {
+        trace.cancel.check()?;
+        let area = imgproc::contour_area(&contour, false)?;
+        if area < min_area * TRACE_MIN_AREA_SHARE {
+            continue;
+        }
+        let mut approx: Vector<Point> = Vector::new();
+        imgproc::approx_poly_dp(&contour, &mut approx, TRACE_EPSILON, true)?;
+        trace.contours.push(approx.iter().map(unpad).collect());
+        if area < min_area {
+            continue;
+        }
+
+        let rect = imgproc::min_area_rect(&contour)?;
+        let size = rect.size;
+        if size.width <= 1.0 || size.height <= 1.0 {
+            continue;
+        }
+
+        let ellipse = if params.shapes.contains(&PhotoShape::Ellipse) {
+            fit_ellipse(&contour, &rect, area)?
+        } else {
+            None
+        };
+        let shape = match ellipse {
+            Some(_) => PhotoShape::Ellipse,
+            None => PhotoShape::Rect,
+        };
+        // With only ellipses requested, plain rectangles are not photos.
+        if shape.is_rect() && !params.shapes.contains(&PhotoShape::Rect) {
+            trace.reject(unpadded_corners(&rect, pad)?, RejectReason::Shape);
+            continue;
+        }
+
+        if let Some(ellipse) = ellipse {
+            rects.push(RectCandidate {
+                rect: ellipse,
+                area,
+                shape,
+                contour: None,
+                outline: ellipse_outline(&ellipse, pad as f32),
+            });
+            continue;
+        }
+
+        let outline = if params.print_shape == PrintShape::Rect {
+            Vec::new()
+        } else {
+            let mut approx: Vector<Point> = Vector::new();
+            imgproc::approx_poly_dp(&contour, &mut approx, 1.5, true)?;
+            approx.iter().map(unpad).collect()
+        };
+        let contour = fit_edges.then(|| {
+            contour
+                .iter()
+                .map(|p| Point2f::new(p.x as f32, p.y as f32))
+                .collect()
+        });
+        rects.push(RectCandidate {
+            rect,
+            area,
+            shape,
+            contour,
+            outline,
+        });
+    }
+
+    if params.merge_fragments {
+        rects = merge_fragments(rects, &edges)?;
+    }
+
+    for r in &rects {
+        trace.candidates.push(unpadded_corners(&r.rect, pad)?);
+    }
+
+    // Keep only the largest rectangle when overlapping occurs (nested or partial overlap).
+    rects.sort_by(|a, b| b.area.partial_cmp(&a.area).unwrap());
+    let mut filtered: Vec<RectCandidate> = Vec::new();
+    'outer: for candidate in rects {
+        for kept in &filtered {
+            if rects_overlap(&kept.rect, &candidate.rect)? {
+                trace.reject(
+                    unpadded_corners(&candidate.rect, pad)?,
+                    RejectReason::Overlap,
+                );
+                continue 'outer;
+            }
+        }
+        filtered.push(candidate);
+    }
+
+    let mut candidates = Vec::new();
+    for r in filtered {
+        let mut box_points = [Point2f::default(); 4];
+        r.rect.points(&mut box_points)?;
+        let rect_quad = order_points(&box_points);
+        let fitted = r.contour.as_deref().and_then(|contour| {
+            let fitted = fit_corners
+                .then(|| quadfit::fit_quad(contour, &rect_quad, params.print_shape))
+                .flatten();
+            if params.keep_corner_mounts {
+                return fitted;
+            }
+            quadfit::fit_inside_corner_mounts(contour, &fitted.unwrap_or(rect_quad)).or(fitted)
+        });
+        // Detection ran on the padded image; report corners in source coordinates.
+        let mut quad = fitted.unwrap_or(rect_quad);
+        for p in &mut quad {
+            p.x -= pad as f32;
+            p.y -= pad as f32;
+        }
+        if params.recover_edge_photos {
+            quad = snap_to_bounds(&quad, image.size()?);
+        }
+        let mut rect_area = (r.rect.size.width * r.rect.size.height) as f64;
+        if r.shape == PhotoShape::Ellipse {
+            rect_area *= std::f64::consts::FRAC_PI_4;
+        }
+        let confidence = (r.area / rect_area).clamp(0.0, 1.0) as f32;
+        let halves = if params.split_pairs && r.shape.is_rect() {
+            pairs::split(image, &quad)?
+        } else {
+            None
+        };
+        let (quads, outline) = match halves {
+            Some(halves) => {
+                tracing::debug!("candidate split into two photos lying edge to edge");
+                (halves.to_vec(), Vec::new())
+            }
+            None => (vec![quad], r.outline),
+        };
+        for quad in quads {
+            let size = warp_size(&quad);
+            let off_size = !params.known_sizes.is_empty()
+                && !params
+                    .known_sizes
+                    .iter()
+                    .any(|known| known.matches(size, dpi, params.size_tolerance));
+            if off_size && params.off_size == SizeAction::Reject {
+                trace.reject(quad, RejectReason::OffSize);
+                continue;
+            }
+            candidates.push(Candidate {
+                quad,
+                confidence,
+                shape: r.shape,
+                off_size,
+                outline: outline.clone(),
+            });
+        }
+    }
+
+    Ok(finish(candidates, params, trace))
+}
+
+/// Per-channel median of the outermost pixels of `image`, which mostly show
+/// the scanner bed even where a photo touches the edge. Works for any
+/// number of channels and depth, in the image's own value range.
+fn background_colour(image: &Mat) -> Result<Scalar> {
+    let (w, h) = (image.cols(), image.rows());
+    let channels = image.channels().clamp(1, 4) as usize;
+    let edges = [
+        image.row(0)?,
+        image.row(h - 1)?,
+        image.col(0)?,
+        image.col(w - 1)?,
+    ];
+    let mut ring: Vec<Vec<f32>> = vec![Vec::with_capacity(2 * (w + h) as usize); channels];
+    for edge in &edges {
+        // A continuous copy in one depth, whatever the image's was.
+        let mut values = Mat::default();
+        edge.convert_to(&mut values, core::CV_32F, 1.0, 0.0)?;
+        for pixel in values.data_typed::<f32>()?.chunks_exact(channels) {
+            for (channel, value) in ring.iter_mut().zip(pixel) {
+                channel.push(*value);
+            }
+        }
+    }
+    let mut colour = Scalar::all(0.0);
+    for (channel, mut values) in ring.into_iter().enumerate() {
+        let mid = values.len() / 2;
+        colour[channel] = *values.select_nth_unstable_by(mid, f32::total_cmp).1 as f64;
+    }
+    if channels == 1 {
+        colour = Scalar::all(colour[0]);
+    }
+    Ok(colour)
+}
+
+/// Cuts a photo that runs off the sheet back to the image edge: a corner
+/// beyond an edge is moved along the photo side that crosses it, so the
+/// quad stops where the scan does instead of warping replicated pixels.
+///
+/// Corners sticking out on their own, as of a tilted photo with one corner
+/// off the bed, are left where they are.
+fn snap_to_bounds(quad: &Quad, size: Size) -> Quad {
+    let (max_x, max_y) = ((size.width - 1) as f32, (size.height - 1) as f32);
+    // Each edge as the axis it bounds, which way is off the sheet, and where
+    // it lies measured that way.
+    let edges = [
+        (false, -1.0, 0.0),
+        (true, -1.0, 0.0),
+        (false, 1.0, max_x),
+        (true, 1.0, max_y),
+    ];
+    let mut snapped = *quad;
+    for (vertical, sign, limit) in edges {
+        let outward = |p: &Point2f| sign * if vertical { p.y } else { p.x };
+        // Half a pixel of slack for corners fitted right on the edge.
+        let beyond = snapped.map(|p| outward(&p) > limit + 0.5);
+        if beyond.iter().filter(|&&b| b).count() != 2 {
+            continue;
+        }
+        let before = snapped;
+        for i in 0..4 {
+            if !beyond[i] {
+                continue;
+            }
+            let (prev, next) = ((i + 3) % 4, (i + 1) % 4);
+            // The other corner of the side that stays on the sheet.
+            let inside = if beyond[prev] { next } else { prev };
+            let (from, to) = (before[inside], before[i]);
+            let t = (limit - outward(&from)) / (outward(&to) - outward(&from));
+            snapped[i] = Point2f::new(from.x + t * (to.x - from.x), from.y + t * (to.y - from.y));
+        }
+        tracing::debug!("photo touches the sheet edge, cut back to it");
+    }
+    snapped
+}
+
+/// Corners of `rect`, found on the image padded by `pad`, in source coordinates.
+fn unpadded_corners(rect: &core::RotatedRect, pad: i32) -> Result<Quad> {
+    let mut points = [Point2f::default(); 4];
+    rect.points(&mut points)?;
+    let mut quad = order_points(&points);
+    for p in &mut quad {
+        p.x -= pad as f32;
+        p.y -= pad as f32;
+    }
+    Ok(quad)
+}
+
+/// Clears the edges inside excluded regions so they can never form a photo.
+/// `offset` is the padding added around the source image.
+fn mask_excluded(edges: &mut Mat, exclude: &[[[f32; 2]; 4]], offset: f32) -> Result<()> {
+    if exclude.is_empty() {
+        return Ok(());
+    }
+    let polygons: Vector<Vector<Point>> = exclude
+        .iter()
+        .map(|region| {
+            region
+                .iter()
+                .map(|[x, y]| Point::new((x + offset).round() as i32, (y + offset).round() as i32))
+                .collect()
+        })
+        .collect();
+    imgproc::fill_poly(
+        edges,
+        &polygons,
+        Scalar::all(0.0),
+        imgproc::LINE_8,
+        0,
+        Point::new(0, 0),
+    )?;
+    Ok(())
+}
+
+/// Applies the per-sheet limit and orders candidates largest first.
+fn finish(
+    mut candidates: Vec<Candidate>,
+    params: &DetectParams,
+    trace: &mut Detection,
+) -> Vec<Candidate> {
+    if let Some(max) = params.max_photos
+        && candidates.len() > max
+    {
+        candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        for dropped in candidates.drain(max..) {
+            trace.reject(dropped.quad, RejectReason::Limit);
+        }
+    }
+    candidates.sort_by_key(|c| {
+        let size = warp_size(&c.quad);
+        std::cmp::Reverse(size.width * size.height)
+    });
+    candidates
+}
+
+/// Smallest share of the image a photographed page must cover.
+const PAGE_MIN_AREA: f64 = 0.25;
+
+/// Straightens the photographed page first and detects photos on the result,
+/// mapping everything back so quads stay in source image coordinates.
+///
+/// Falls back to the unrectified image when no page outline is found.
+fn detect_on_page(
+    image: &Mat,
+    params: &DetectParams,
+    trace: &mut Detection,
+) -> Result<Vec<Candidate>> {
+    let mut on_page = DetectParams {
+        rectify_page: false,
+        ..params.clone()
+    };
+    let Some(page) = find_page(image)? else {
+        tracing::warn!("no page outline found, detecting on the unrectified image");
+        return find_candidates(image, &on_page, trace);
+    };
+
+    let rectified = warp_quad(image, &page, Interpolation::Linear)?;
+    let size = warp_size(&page);
+    let (w, h) = ((size.width - 1) as f32, (size.height - 1) as f32);
+    let upright: Vector<Point2f> = Vector::from(vec![
+        Point2f::new(0.0, 0.0),
+        Point2f::new(w, 0.0),
+        Point2f::new(w, h),
+        Point2f::new(0.0, h),
+    ]);
+    let src: Vector<Point2f> = Vector::from(page.to_vec());
+    let back = imgproc::get_perspective_transform(&upright, &src, core::DECOMP_LU)?;
+    if !on_page.exclude.is_empty() {
+        let forward = imgproc::get_perspective_transform(&src, &upright, core::DECOMP_LU)?;
+        for region in &mut on_page.exclude {
+            let points = map_points(&region.map(|[x, y]| Point2f::new(x, y)), &forward)?;
+            for (corner, p) in region.iter_mut().zip(points) {
+                *corner = [p.x, p.y];
+            }
+        }
+    }
+    let mark = trace.mark();
+    let mut candidates = find_candidates(&rectified, &on_page, trace)?;
+    let mut traced: Vec<&mut Point2f> = trace.points_since(&mark).collect();
+    let mapped = map_points(&traced.iter().map(|p| **p).collect::<Vec<_>>(), &back)?;
+    for (p, q) in traced.iter_mut().zip(mapped) {
+        **p = q;
+    }
+    for candidate in &mut candidates {
+        candidate.quad = map_points(&candidate.quad, &back)?
+            .try_into()
+            .expect("four points map to four points");
+        candidate.outline = map_points(&candidate.outline, &back)?;
+    }
+    Ok(candidates)
+}
+
+/// Sheet tilts smaller than this, in degrees, are not worth resampling for.
+const SHEET_MIN_SKEW: f32 = 0.1;
+
+/// Measures the tilt most straight edges on the sheet share, as a scanner
+/// lid or jig leaves every print on it turned by, and detects on the sheet
+/// turned straight by it. Contours then run along the pixel grid, which
+/// makes for cleaner outlines and corners.
+///
+/// The canvas grows to hold the turned corners. Results are turned back
+/// into source coordinates, so the manifest refers to the source as usual.
+fn detect_deskewed(
+    image: &Mat,
+    params: &DetectParams,
+    trace: &mut Detection,
+) -> Result<Vec<Candidate>> {
+    let mut local = DetectParams {
+        deskew_sheet: false,
+        ..params.clone()
+    };
+    let angle = match skew::measure(image)? {
+        Some(angle) if angle.abs() >= SHEET_MIN_SKEW => angle,
+        _ => return find_candidates(image, &local, trace),
+    };
+    let _deskew = tracing::debug_span!("deskew_sheet", angle).entered();
+
+    let size = image.size()?;
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (w, h) = (size.width as f32, size.height as f32);
+    let turned = Size::new(
+        (w * cos.abs() + h * sin.abs()).ceil() as i32,
+        (w * sin.abs() + h * cos.abs()).ceil() as i32,
+    );
+    let from = Point2f::new((w - 1.0) / 2.0, (h - 1.0) / 2.0);
+    let to = Point2f::new(
+        (turned.width - 1) as f32 / 2.0,
+        (turned.height - 1) as f32 / 2.0,
+    );
+    // A clockwise tilt is undone by turning counter-clockwise, which is
+    // what OpenCV takes positive angles for.
+    let mut forward = imgproc::get_rotation_matrix_2d(from, angle as f64, 1.0)?;
+    *forward.at_2d_mut::<f64>(0, 2)? += (to.x - from.x) as f64;
+    *forward.at_2d_mut::<f64>(1, 2)? += (to.y - from.y) as f64;
+    let mut straight = Mat::default();
+    imgproc::warp_affine(
+        image,
+        &mut straight,
+        &forward,
+        turned,
+        imgproc::INTER_LINEAR,
+        BORDER_REPLICATE,
+        Scalar::all(0.0),
+    )?;
+    // Turns a point by `degrees` clockwise about `about`, moving it to `onto`.
+    let turn = |p: Point2f, degrees: f32, about: Point2f, onto: Point2f| {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (dx, dy) = (p.x - about.x, p.y - about.y);
+        Point2f::new(onto.x + dx * cos - dy * sin, onto.y + dx * sin + dy * cos)
+    };
+    for region in &mut local.exclude {
+        *region = region.map(|[x, y]| {
+            let p = turn(Point2f::new(x, y), -angle, from, to);
+            [p.x, p.y]
+        });
+    }
+    let back = |p: &mut Point2f| *p = turn(*p, angle, to, from);
+
+    let mark = trace.mark();
+    let mut candidates = find_candidates(&straight, &local, trace)?;
+    trace.points_since(&mark).for_each(back);
+    for candidate in &mut candidates {
+        candidate
+            .quad
+            .iter_mut()
+            .chain(candidate.outline.iter_mut())
+            .for_each(back);
+    }
+    Ok(candidates)
+}
+
+/// Outline of a page photographed at an angle: the largest four-cornered
+/// contour covering at least [`PAGE_MIN_AREA`] of the image.
+fn find_page(image: &Mat) -> Result<Option<Quad>> {
+    let mut gray = Mat::default();
+    imgproc::cvt_color(
+        image,
+        &mut gray,
+        imgproc::COLOR_BGR2GRAY,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    let mut blurred = Mat::default();
+    imgproc::gaussian_blur(
+        &gray,
+        &mut blurred,
+        Size::new(5, 5),
+        0.0,
+        0.0,
+        core::BORDER_DEFAULT,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    let mut edges = Mat::default();
+    imgproc::canny(&blurred, &mut edges, 50.0, 150.0, 3, false)?;
+    let kernel =
+        imgproc::get_structuring_element(imgproc::MORPH_RECT, Size::new(5, 5), Point::new(-1, -1))?;
+    let mut dilated = Mat::default();
+    imgproc::dilate(
+        &edges,
+        &mut dilated,
+        &kernel,
+        Point::new(-1, -1),
+        2,
+        core::BORDER_CONSTANT,
+        Scalar::all(0.0),
+    )?;
+
+    let mut contours: Vector<Vector<Point>> = Vector::new();
+    imgproc::find_contours(
+        &dilated,
+        &mut contours,
+        imgproc::RETR_EXTERNAL,
+        imgproc::CHAIN_APPROX_SIMPLE,
+        Point::new(0, 0),
+    )?;
+
+    let size = image.size()?;
+    let min_area = PAGE_MIN_AREA * size.width as f64 * size.height as f64;
+    let mut best: Option<(f64, Quad)> = None;
+    for contour in contours.iter() {
+        let area = imgproc::contour_area(&contour, false)?;
+        if area < min_area || best.is_some_and(|(a, _)| a >= area) {
+            continue;
+        }
+        let perimeter = imgproc::arc_length(&contour, true)?;
+        let mut approx: Vector<Point> = Vector::new();
+        imgproc::approx_poly_dp(&contour, &mut approx, 0.02 * perimeter, true)?;
+        if approx.len() != 4 || !imgproc::is_contour_convex(&approx)? {
+            continue;
+        }
+        let mut corners = [Point2f::default(); 4];
+        for (corner, p) in corners.iter_mut().zip(approx.iter()) {
+            *corner = Point2f::new(p.x as f32, p.y as f32);
+        }
+        best = Some((area, order_points(&corners)));
+    }
+    Ok(best.map(|(_, quad)| quad))
+}
+
+fn map_points(points: &[Point2f], m: &Mat) -> Result<Vec<Point2f>> {
+    if points.is_empty() {
+        return Ok(Vec::new());
+    }
+    let src: Vector<Point2f> = Vector::from(points.to_vec());
+    let mut dst: Vector<Point2f> = Vector::new();
+    core::perspective_transform(&src, &mut dst, m)?;
+    Ok(dst.to_vec())
+}
+
+/// Share of the median gray value below and above which the automatic
+/// Canny thresholds lie.
+const AUTO_CANNY_SIGMA: f64 = 0.33;
+
+/// Canny thresholds for the 8-bit `gray` image from its median value, the
+/// usual `(1 - σ)·median` and `(1 + σ)·median`, so dark and bright scans are
+/// both edged alike.
+fn auto_canny(gray: &Mat) -> Result<(f64, f64)> {
+    let mut histogram = [0usize; 256];
+    let pixels = gray.data_typed::<u8>()?;
+    for &v in pixels {
+        histogram[v as usize] += 1;
+    }
+    let pixels = pixels.len();
+    let mut seen = 0;
+    let median = histogram
+        .iter()
+        .position(|&count| {
+            seen += count;
+            seen * 2 >= pixels
+        })
+        .unwrap_or(127) as f64;
+    let low = ((1.0 - AUTO_CANNY_SIGMA) * median).max(0.0);
+    let high = ((1.0 + AUTO_CANNY_SIGMA) * median).min(255.0);
+    // A black sheet would leave no room between the two.
+    Ok((low, high.max(low + 1.0)))
+}
+
+/// Outlines on `edges` that enclose no other outline of at least `min_area`,
+/// found from the full contour tree.
+///
+/// Every dilated edge is a ring with an outer boundary and a hole, so the
+/// tree alternates between the two: outlines sit at even depths, holes at odd
+/// ones. Holes are skipped, as they trace the inside of the same edge.
+fn innermost_contours(edges: &Mat, method: i32, min_area: f64) -> Result<Vector<Vector<Point>>> {
+    let mut contours: Vector<Vector<Point>> = Vector::new();
+    let mut hierarchy: Vector<core::Vec4i> = Vector::new();
+    imgproc::find_contours_with_hierarchy(
+        edges,
+        &mut contours,
+        &mut hierarchy,
+        imgproc::RETR_TREE,
+        method,
+        Point::new(0, 0),
+    )?;
+    // Each entry is [next, previous, first child, parent].
+    let parents: Vec<i32> = hierarchy.iter().map(|h| h[3]).collect();
+    let ancestors = |i: usize| {
+        std::iter::successors(Some(parents[i]), |&p| Some(parents[p as usize]))
+            .take_while(|&p| p >= 0)
+            .map(|p| p as usize)
+    };
+    let outline: Vec<bool> = (0..parents.len())
+        .map(|i| ancestors(i).count() % 2 == 0)
+        .collect();
+    let mut encloses = vec![false; parents.len()];
+    for (i, contour) in contours.iter().enumerate() {
+        if outline[i] && imgproc::contour_area(&contour, false)? >= min_area {
+            for parent in ancestors(i) {
+                encloses[parent] = true;
+            }
+        }
+    }
+    Ok(contours
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| outline[i] && !encloses[i])
+        .map(|(_, contour)| contour)
+        .collect())
+}
+
+/// Smallest side of a pyramid level worth searching.
+const PYRAMID_MIN_SIDE: i32 = 256;
+
+/// Runs detection at full size and at successively halved sizes, so the
+/// fixed blur and threshold windows suit both small and large prints.
+///
+/// Finer levels win: a photo found at a coarser level is only added when no
+/// finer level already has it, keeping corners as precise as possible.
+fn detect_pyramid(
+    image: &Mat,
+    params: &DetectParams,
+    trace: &mut Detection,
+) -> Result<Vec<Candidate>> {
+    let single = DetectParams {
+        pyramid_levels: 1,
+        max_photos: None,
+        ..params.clone()
+    };
+    let mut found = find_candidates(image, &single, trace)?;
+
+    for level in 1..params.pyramid_levels {
+        let scale = 0.5f64.powi(level as i32);
+        let mut small = Mat::default();
+        imgproc::resize(
+            image,
+            &mut small,
+            Size::new(0, 0),
+            scale,
+            scale,
+            imgproc::INTER_AREA,
+        )?;
+        if small.cols().min(small.rows()) < PYRAMID_MIN_SIDE {
+            break;
+        }
+        let level_params = single.scaled(scale);
+        let _level = tracing::debug_span!("pyramid_level", level, scale).entered();
+        let up = (1.0 / scale) as f32;
+        let scale_up = |p: &mut Point2f| {
+            p.x = (p.x + 0.5) * up - 0.5;
+            p.y = (p.y + 0.5) * up - 0.5;
+        };
+        let mark = trace.mark();
+        let level_found = find_candidates(&small, &level_params, trace)?;
+        trace.points_since(&mark).for_each(scale_up);
+        for mut candidate in level_found {
+            candidate
+                .quad
+                .iter_mut()
+                .chain(candidate.outline.iter_mut())
+                .for_each(scale_up);
+            if found
+                .iter()
+                .any(|f| quads_overlap(&f.quad, &candidate.quad))
+            {
+                trace.reject(candidate.quad, RejectReason::Overlap);
+            } else {
+                found.push(candidate);
+            }
+        }
+    }
+
+    Ok(finish(found, params, trace))
+}
+
+/// Share of a tile's side it overlaps each neighbour by. Photos smaller than
+/// this always lie whole inside some tile.
+const TILE_OVERLAP: f32 = 0.25;
+/// Distance in pixels from an inner tile edge within which a candidate is
+/// taken to be cut off by it.
+const TILE_EDGE_MARGIN: f32 = 3.0;
+
+/// Runs detection tile by tile over a sheet too large to search at once,
+/// e.g. the stitched output of a map scanner with dozens of prints on it.
+///
+/// Only one tile is copied out and searched at a time, so the blurred,
+/// thresholded and edge images stay the size of a tile. A candidate running
+/// into a tile's inner edge is cut off by it: it is dropped when another
+/// tile holds the whole photo, and otherwise joined with the pieces the
+/// neighbouring tiles found of it into one rectangle across the seams.
+fn detect_tiled(
+    image: &Mat,
+    params: &DetectParams,
+    tile: i32,
+    trace: &mut Detection,
+) -> Result<Vec<Candidate>> {
+    let size = image.size()?;
+    let local = DetectParams {
+        tile: None,
+        max_photos: None,
+        // The share is of the whole sheet, not of each tile.
+        min_area: params.min_area_for(size),
+        min_area_frac: None,
+        ..params.clone()
+    };
+    let step = ((tile as f32 * (1.0 - TILE_OVERLAP)) as i32).max(1);
+    let starts = |length: i32| {
+        let mut starts: Vec<i32> = (0..(length - tile).max(0)).step_by(step as usize).collect();
+        starts.push((length - tile).max(0));
+        starts
+    };
+
+    let mut whole: Vec<Candidate> = Vec::new();
+    let mut pieces: Vec<Candidate> = Vec::new();
+    for top in starts(size.height) {
+        for left in starts(size.width) {
+            trace.cancel.check()?;
+            let region = core::Rect::new(
+                left,
+                top,
+                tile.min(size.width - left),
+                tile.min(size.height - top),
+            );
+            let _tile = tracing::debug_span!("tile", left, top).entered();
+            for candidate in detect_in_region(image, &local, region, trace)? {
+                if cut_by_tile(&candidate.quad, region, size) {
+                    pieces.push(candidate);
+                } else if whole
+                    .iter()
+                    .any(|w| quads_overlap(&w.quad, &candidate.quad))
+                {
+                    trace.reject(candidate.quad, RejectReason::Overlap);
+                } else {
+                    whole.push(candidate);
+                }
+            }
+        }
+    }
+
+    let mut rest = Vec::new();
+    for piece in pieces {
+        if whole.iter().any(|w| quads_overlap(&w.quad, &piece.quad)) {
+            trace.reject(piece.quad, RejectReason::Overlap);
+        } else {
+            rest.push(piece);
+        }
+    }
+    for group in touching_groups(rest) {
+        let confidence = group
+            .iter()
+            .map(|c| c.confidence)
+            .fold(f32::INFINITY, f32::min);
+        let corners: Vector<Point2f> = group.iter().flat_map(|c| c.quad).collect();
+        let rect = imgproc::min_area_rect(&corners)?;
+        let mut points = [Point2f::default(); 4];
+        rect.points(&mut points)?;
+        whole.push(Candidate {
+            quad: order_points(&points),
+            confidence,
+            shape: PhotoShape::Rect,
+            off_size: group.iter().any(|c| c.off_size),
+            outline: Vec::new(),
+        });
+    }
+
+    Ok(finish(whole, params, trace))
+}
+
+/// Whether `quad` runs into an edge of `tile` that lies inside the sheet.
+fn cut_by_tile(quad: &Quad, tile: core::Rect, sheet: Size) -> bool {
+    let (left, top) = (tile.x as f32, tile.y as f32);
+    let (right, bottom) = ((tile.x + tile.width) as f32, (tile.y + tile.height) as f32);
+    quad.iter().any(|p| {
+        (tile.x > 0 && p.x <= left + TILE_EDGE_MARGIN)
+            || (tile.y > 0 && p.y <= top + TILE_EDGE_MARGIN)
+            || (tile.x + tile.width < sheet.width && p.x >= right - 1.0 - TILE_EDGE_MARGIN)
+            || (tile.y + tile.height < sheet.height && p.y >= bottom - 1.0 - TILE_EDGE_MARGIN)
+    })
+}
+
+/// Splits `pieces` into groups whose bounding boxes touch or overlap, each
+/// one photo cut up by the tile seams.
+fn touching_groups(pieces: Vec<Candidate>) -> Vec<Vec<Candidate>> {
+    let bbox = |q: &Quad| {
+        let xs = q.iter().map(|p| p.x);
+        let ys = q.iter().map(|p| p.y);
+        (
+            xs.clone().fold(f32::INFINITY, f32::min) - TILE_EDGE_MARGIN,
+            ys.clone().fold(f32::INFINITY, f32::min) - TILE_EDGE_MARGIN,
+            xs.fold(f32::NEG_INFINITY, f32::max) + TILE_EDGE_MARGIN,
+            ys.fold(f32::NEG_INFINITY, f32::max) + TILE_EDGE_MARGIN,
+        )
+    };
+    let touch = |a: &Quad, b: &Quad| {
+        let ((ax1, ay1, ax2, ay2), (bx1, by1, bx2, by2)) = (bbox(a), bbox(b));
+        ax1 <= bx2 && bx1 <= ax2 && ay1 <= by2 && by1 <= ay2
+    };
+    let mut groups: Vec<Vec<Candidate>> = Vec::new();
+    for piece in pieces {
+        let (joined, mut apart): (Vec<_>, Vec<_>) = groups
+            .into_iter()
+            .partition(|group| group.iter().any(|c| touch(&c.quad, &piece.quad)));
+        let mut merged: Vec<Candidate> = joined.into_iter().flatten().collect();
+        merged.push(piece);
+        apart.push(merged);
+        groups = apart;
+    }
+    groups
+}
+
+/// Smallest share of a merged rectangle the fragments must cover between them.
+const MERGE_MIN_FILL: f64 = 0.9;
+/// Longest side over shortest side still accepted for a merged photo.
+const MERGE_MAX_ASPECT: f32 = 3.0;
+/// Widest gap between two fragments, as a share of the merged short side.
+const MERGE_MAX_GAP: f32 = 0.05;
+/// Degrees two fragments' sides may differ by and still count as one print.
+const MERGE_MAX_TILT: f32 = 2.0;
+/// Share of the longer facing side the two facing sides must share.
+const MERGE_MIN_SEAM: f32 = 0.9;
+/// Share of the seam between two fragments that must lie on an edge.
+const MERGE_MIN_SEAM_EDGES: f64 = 0.6;
+/// Share of its rectangle a fragment's contour fills when it is a whole print.
+const MERGE_PRINT_FILL: f64 = 0.97;
+/// Aspect ratios of common print formats: square, 10x13, 13x18, 10x15, 20x25.
+const PRINT_ASPECTS: [f32; 5] = [1.0, 4.0 / 3.0, 1.4, 1.5, 1.25];
+/// Relative difference from a print format still counted as that format.
+const PRINT_ASPECT_TOLERANCE: f32 = 0.02;
+
+/// How two fragments would join: the rectangle covering both and the line
+/// midway between their facing sides.
+struct FragmentJoin {
+    union: core::RotatedRect,
+    seam: [Point2f; 2],
+    fill: f64,
+}
+
+/// Repeatedly joins the pair of rectangles whose common bounding rectangle
+/// they fill best, as long as they lie side by side with at most a thin gap
+/// along an edge in `edges`, are not both whole prints on their own, and the
+/// result has a photo-like aspect ratio.
+fn merge_fragments(mut rects: Vec<RectCandidate>, edges: &Mat) -> Result<Vec<RectCandidate>> {
+    loop {
+        let mut best: Option<(usize, usize, FragmentJoin)> = None;
+        for i in 0..rects.len() {
+            for j in i + 1..rects.len() {
+                let Some(join) = fragment_join(&rects[i], &rects[j]) else {
+                    continue;
+                };
+                if best.as_ref().is_some_and(|(.., b)| b.fill >= join.fill) {
+                    continue;
+                }
+                if seam_edge_share(edges, &join.seam)? >= MERGE_MIN_SEAM_EDGES {
+                    best = Some((i, j, join));
+                }
+            }
+        }
+
+        let Some((i, j, join)) = best else {
+            return Ok(rects);
+        };
+        // j > i, so removing j leaves i in place.
+        let fragment = rects.swap_remove(j);
+        let merged = &mut rects[i];
+        merged.rect = join.union;
+        merged.area += fragment.area;
+        merged.contour = match (merged.contour.take(), fragment.contour) {
+            (Some(mut points), Some(more)) => {
+                points.extend(more);
+                Some(points)
+            }
+            _ => None,
+        };
+        merged.outline.clear();
+    }
+}
+
+/// The join of `a` and `b` when they could be two parts of one print: both
+/// rectangular, turned alike, side by side along most of their facing sides
+/// with at most a thin gap, and not both shaped like a whole print.
+fn fragment_join(a: &RectCandidate, b: &RectCandidate) -> Option<FragmentJoin> {
+    if a.shape != PhotoShape::Rect || b.shape != PhotoShape::Rect {
+        return None;
+    }
+    if looks_like_print(a) && looks_like_print(b) {
+        return None;
+    }
+    let turn = (b.rect.angle - a.rect.angle).rem_euclid(90.0);
+    if turn > MERGE_MAX_TILT && turn < 90.0 - MERGE_MAX_TILT {
+        return None;
+    }
+
+    // Work in the frame of `a`, where both rectangles are axis aligned.
+    let (sin, cos) = a.rect.angle.to_radians().sin_cos();
+    let (u, v) = ((cos, sin), (-sin, cos));
+    let (dx, dy) = (
+        b.rect.center.x - a.rect.center.x,
+        b.rect.center.y - a.rect.center.y,
+    );
+    let (bx, by) = (dx * u.0 + dy * u.1, dx * v.0 + dy * v.1);
+    let quarter_turns = ((b.rect.angle - a.rect.angle) / 90.0).round() as i32;
+    let (bw, bh) = if quarter_turns % 2 == 0 {
+        (b.rect.size.width, b.rect.size.height)
+    } else {
+        (b.rect.size.height, b.rect.size.width)
+    };
+    let (aw, ah) = (a.rect.size.width / 2.0, a.rect.size.height / 2.0);
+    let (bw, bh) = (bw / 2.0, bh / 2.0);
+    let spans = [
+        ((-aw, aw), (bx - bw, bx + bw)),
+        ((-ah, ah), (by - bh, by + bh)),
+    ];
+    let gap = |(a, b): ((f32, f32), (f32, f32))| (b.0 - a.1).max(a.0 - b.1);
+    // The pieces lie beside each other along the axis with the larger gap.
+    let across = if gap(spans[0]) >= gap(spans[1]) { 0 } else { 1 };
+    let (along_a, along_b) = spans[1 - across];
+    let shared = along_a.1.min(along_b.1) - along_a.0.max(along_b.0);
+    let longer = (along_a.1 - along_a.0).max(along_b.1 - along_b.0);
+    if shared < MERGE_MIN_SEAM * longer {
+        return None;
+    }
+
+    let (lo, hi) = (
+        (
+            spans[0].0.0.min(spans[0].1.0),
+            spans[1].0.0.min(spans[1].1.0),
+        ),
+        (
+            spans[0].0.1.max(spans[0].1.1),
+            spans[1].0.1.max(spans[1].1.1),
+        ),
+    );
+    let (w, h) = (hi.0 - lo.0, hi.1 - lo.1);
+    if w <= 1.0 || h <= 1.0 || w.max(h) / w.min(h) > MERGE_MAX_ASPECT {
+        return None;
+    }
+    if gap(spans[across]) > MERGE_MAX_GAP * w.min(h) {
+        return None;
+    }
+    let pieces =
+        (a.rect.size.width * a.rect.size.height + b.rect.size.width * b.rect.size.height) as f64;
+    let fill = pieces / (w * h) as f64;
+    // Above 1 the pieces overlap, which the overlap filter handles instead.
+    if !(MERGE_MIN_FILL..=1.0).contains(&fill) {
+        return None;
+    }
+
+    let to_image = |x: f32, y: f32| {
+        Point2f::new(
+            a.rect.center.x + x * u.0 + y * v.0,
+            a.rect.center.y + x * u.1 + y * v.1,
+        )
+    };
+    let (facing_a, facing_b) = spans[across];
+    let middle = if facing_b.0 >= facing_a.1 {
+        (facing_a.1 + facing_b.0) / 2.0
+    } else {
+        (facing_b.1 + facing_a.0) / 2.0
+    };
+    let (from, to) = (along_a.0.max(along_b.0), along_a.1.min(along_b.1));
+    let seam = if across == 0 {
+        [to_image(middle, from), to_image(middle, to)]
+    } else {
+        [to_image(from, middle), to_image(to, middle)]
+    };
+    let center = to_image((lo.0 + hi.0) / 2.0, (lo.1 + hi.1) / 2.0);
+    Some(FragmentJoin {
+        union: core::RotatedRect {
+            center,
+            size: core::Size2f::new(w, h),
+            angle: a.rect.angle,
+        },
+        seam,
+        fill,
+    })
+}
+
+/// Whether `r` is a clean rectangle in one of the common print formats, and
+/// so most likely a whole print rather than part of one.
+fn looks_like_print(r: &RectCandidate) -> bool {
+    let (w, h) = (r.rect.size.width, r.rect.size.height);
+    if r.area / ((w * h) as f64) < MERGE_PRINT_FILL {
+        return false;
+    }
+    let aspect = w.max(h) / w.min(h);
+    PRINT_ASPECTS
+        .iter()
+        .any(|&print| (aspect / print - 1.0).abs() <= PRINT_ASPECT_TOLERANCE)
+}
+
+/// Share of the pixels on the line between `seam`'s ends that are set in `edges`.
+fn seam_edge_share(edges: &Mat, seam: &[Point2f; 2]) -> Result<f64> {
+    let [from, to] = *seam;
+    let steps = (to.x - from.x).hypot(to.y - from.y).ceil().max(1.0) as i32;
+    let mut on_edge = 0;
+    for step in 0..=steps {
+        let t = step as f32 / steps as f32;
+        let x = (from.x + (to.x - from.x) * t).round() as i32;
+        let y = (from.y + (to.y - from.y) * t).round() as i32;
+        if x >= 0 && y >= 0 && x < edges.cols() && y < edges.rows() && *edges.at_2d::<u8>(y, x)? > 0
+        {
+            on_edge += 1;
+        }
+    }
+    Ok(on_edge as f64 / (steps + 1) as f64)
+}
+
+/// Share of a slot's size searched around it when refining a layout.
+const SLOT_SEARCH_MARGIN: f32 = 0.1;
+
+/// Detects photos inside `region` only and returns them in image coordinates.
+fn detect_in_region(
+    image: &Mat,
+    params: &DetectParams,
+    region: core::Rect,
+    trace: &mut Detection,
+) -> Result<Vec<Candidate>> {
+    let (left, top) = (region.x as f32, region.y as f32);
+    let mut local = params.clone();
+    local.exclude = params
+        .exclude
+        .iter()
+        .map(|r| r.map(|[x, y]| [x - left, y - top]))
+        .collect();
+    let roi = Mat::roi(image, region)?.try_clone()?;
+    let mark = trace.mark();
+    let mut found = find_candidates(&roi, &local, trace)?;
+    let shift = |p: &mut Point2f| {
+        p.x += left;
+        p.y += top;
+    };
+    trace.points_since(&mark).for_each(shift);
+    for c in &mut found {
+        c.quad
+            .iter_mut()
+            .chain(c.outline.iter_mut())
+            .for_each(shift);
+    }
+    Ok(found)
+}
+
+/// Detects each layout slot within its own neighbourhood of the sheet.
+///
+/// The best candidate overlapping a slot replaces it; a slot with no match is
+/// kept as drawn in the template, with zero confidence so it stands out.
+fn refine_slots(
+    image: &Mat,
+    params: &DetectParams,
+    layout: &Layout,
+    trace: &mut Detection,
+) -> Result<Vec<Candidate>> {
+    let size = image.size()?;
+    let mut candidates = Vec::new();
+    for slot in layout.slots(size) {
+        let xs = slot.iter().map(|p| p.x);
+        let ys = slot.iter().map(|p| p.y);
+        let (x0, x1) = (
+            xs.clone().fold(f32::INFINITY, f32::min),
+            xs.fold(0.0, f32::max),
+        );
+        let (y0, y1) = (
+            ys.clone().fold(f32::INFINITY, f32::min),
+            ys.fold(0.0, f32::max),
+        );
+        let (mx, my) = (
+            (x1 - x0) * SLOT_SEARCH_MARGIN,
+            (y1 - y0) * SLOT_SEARCH_MARGIN,
+        );
+        let left = ((x0 - mx).floor() as i32).clamp(0, size.width - 1);
+        let top = ((y0 - my).floor() as i32).clamp(0, size.height - 1);
+        let right = ((x1 + mx).ceil() as i32).clamp(left + 1, size.width);
+        let bottom = ((y1 + my).ceil() as i32).clamp(top + 1, size.height);
+        let region = core::Rect::new(left, top, right - left, bottom - top);
+
+        // Search the region alone, accepting photos down to a fraction of the slot.
+        let mut local = params.clone();
+        local.layout = None;
+        local.min_area = params
+            .min_area_for(size)
+            .min(((x1 - x0) * (y1 - y0) * 0.3) as f64);
+        local.min_area_frac = None;
+        let found = detect_in_region(image, &local, region, trace)?
+            .into_iter()
+            .find(|c| quads_overlap(&c.quad, &slot));
+
+        candidates.push(found.unwrap_or(Candidate {
+            quad: slot,
+            confidence: 0.0,
+            shape: PhotoShape::Rect,
+            off_size: false,
+            outline: Vec::new(),
+        }));
+    }
+    Ok(candidates)
+}
+
+/// Size of the straightened image produced by [`warp_quad`].
+pub fn warp_size(quad: &Quad) -> Size {
+    // Round down so a crop never has more pixels than the source spans along
+    // that side; rounding up would silently upscale by a fraction of a pixel.
+    let width_top = distance(&quad[0], &quad[1]);
+    let width_bottom = distance(&quad[3], &quad[2]);
+    let max_width = width_top.max(width_bottom).floor() as i32;
+
+    let height_left = distance(&quad[0], &quad[3]);
+    let height_right = distance(&quad[1], &quad[2]);
+    let max_height = height_left.max(height_right).floor() as i32;
+
+    Size::new(max_width.max(1), max_height.max(1))
+}
+
+/// Straightens the region inside `quad` into an upright image.
+///
+/// Corners may lie outside the image (photos touching the sheet edge); those
+/// pixels are filled by replicating the border, matching the detection padding.
+#[tracing::instrument(level = "debug", skip_all, err)]
+pub fn warp_quad(image: &Mat, quad: &Quad, interpolation: Interpolation) -> Result<Mat> {
+    let (m, size) = warp_transform(quad)?;
+
+    let mut warped = Mat::default();
+    imgproc::warp_perspective(
+        image,
+        &mut warped,
+        &m,
+        size,
+        interpolation.flag(),
+        core::BORDER_REPLICATE,
+        Scalar::all(0.0),
+    )?;
+
+    Ok(warped)
+}
+
+/// Grows (positive) or shrinks (negative) `quad` by `dx` and `dy` crop pixels
+/// on each side, measured in the straightened photo so perspective is respected.
+pub fn adjust_quad(quad: &Quad, dx: f32, dy: f32) -> Result<Quad> {
+    let size = warp_size(quad);
+    let (w, h) = ((size.width - 1) as f32, (size.height - 1) as f32);
+    // Keep at least a couple of pixels of photo.
+    let dx = dx.max(-(w / 2.0 - 1.0));
+    let dy = dy.max(-(h / 2.0 - 1.0));
+
+    let upright: Vector<Point2f> = Vector::from(vec![
+        Point2f::new(0.0, 0.0),
+        Point2f::new(w, 0.0),
+        Point2f::new(w, h),
+        Point2f::new(0.0, h),
+    ]);
+    let src: Vector<Point2f> = Vector::from(quad.to_vec());
+    let back = imgproc::get_perspective_transform(&upright, &src, core::DECOMP_LU)?;
+
+    let adjusted: Vector<Point2f> = Vector::from(vec![
+        Point2f::new(-dx, -dy),
+        Point2f::new(w + dx, -dy),
+        Point2f::new(w + dx, h + dy),
+        Point2f::new(-dx, h + dy),
+    ]);
+    let mut mapped: Vector<Point2f> = Vector::new();
+    core::perspective_transform(&adjusted, &mut mapped, &back)?;
+
+    let mut result = *quad;
+    for (corner, p) in result.iter_mut().zip(mapped.iter()) {
+        *corner = p;
+    }
+    Ok(result)
+}
+
+/// Perspective matrix mapping `quad` onto the upright crop, and the crop size.
+pub fn warp_transform(quad: &Quad) -> Result<(Mat, Size)> {
+    let size = warp_size(quad);
+    let (max_width, max_height) = (size.width, size.height);
+
+    let dst: Vector<Point2f> = Vector::from(vec![
+        Point2f::new(0.0, 0.0),
+        Point2f::new((max_width - 1) as f32, 0.0),
+        Point2f::new((max_width - 1) as f32, (max_height - 1) as f32),
+        Point2f::new(0.0, (max_height - 1) as f32),
+    ]);
+
+    let src: Vector<Point2f> = Vector::from(quad.to_vec());
+    let m = imgproc::get_perspective_transform(&src, &dst, core::DECOMP_LU)?;
+    Ok((m, size))
+}
+
+/// Removes soft shading, such as the shadow a lifted scanner lid casts
+/// around thick prints, while keeping sharp edges.
+///
+/// The background is estimated by a local maximum followed by a wide median,
+/// which follows gradual shading but not steps; subtracting it leaves the
+/// shadow as light as the lid so the outline hugs the print itself.
+fn flatten_shadows(gray: &Mat) -> Result<Mat> {
+    let kernel =
+        imgproc::get_structuring_element(imgproc::MORPH_RECT, Size::new(7, 7), Point::new(-1, -1))?;
+    let mut dilated = Mat::default();
+    imgproc::dilate(
+        gray,
+        &mut dilated,
+        &kernel,
+        Point::new(-1, -1),
+        1,
+        core::BORDER_REPLICATE,
+        Scalar::all(0.0),
+    )?;
+    let mut background = Mat::default();
+    imgproc::median_blur(&dilated, &mut background, 21)?;
+
+    let mut diff = Mat::default();
+    core::absdiff(gray, &background, &mut diff)?;
+    let mut flat = Mat::default();
+    core::bitwise_not(&diff, &mut flat, &core::no_array())?;
+    let mut normalized = Mat::default();
+    core::normalize(
+        &flat,
+        &mut normalized,
+        0.0,
+        255.0,
+        core::NORM_MINMAX,
+        -1,
+        &core::no_array(),
+    )?;
+    Ok(normalized)
+}
+
+/// Fits an ellipse to `contour` and returns its bounding box if the contour is
+/// clearly oval: it fills about pi/4 of its rectangle and matches the ellipse's area.
+fn fit_ellipse(
+    contour: &Vector<Point>,
+    rect: &core::RotatedRect,
+    area: f64,
+) -> Result<Option<core::RotatedRect>> {
+    if contour.len() < 5 {
+        return Ok(None);
+    }
+    let rect_fill = area / (rect.size.width * rect.size.height) as f64;
+    if rect_fill > 0.88 {
+        return Ok(None);
+    }
+    let ellipse = imgproc::fit_ellipse(contour)?;
+    let ellipse_area =
+        std::f64::consts::FRAC_PI_4 * (ellipse.size.width * ellipse.size.height) as f64;
+    if ellipse_area <= 0.0 || !(0.93..=1.07).contains(&(area / ellipse_area)) {
+        return Ok(None);
+    }
+    Ok(Some(ellipse))
+}
+
+/// Polygon approximating `ellipse`, shifted back by the detection padding.
+fn ellipse_outline(ellipse: &core::RotatedRect, pad: f32) -> Vec<Point2f> {
+    const STEPS: usize = 90;
+    let (sin, cos) = ellipse.angle.to_radians().sin_cos();
+    let (a, b) = (ellipse.size.width / 2.0, ellipse.size.height / 2.0);
+    (0..STEPS)
+        .map(|i| {
+            let t = i as f32 / STEPS as f32 * std::f32::consts::TAU;
+            let (x, y) = (a * t.cos(), b * t.sin());
+            Point2f::new(
+                ellipse.center.x + x * cos - y * sin - pad,
+                ellipse.center.y + x * sin + y * cos - pad,
+            )
+        })
+        .collect()
+}
+
+/// Whether two quads cover mostly the same area: their bounding boxes overlap
+/// by more than half of the smaller one.
+pub fn quads_overlap(a: &Quad, b: &Quad) -> bool {
+    let bbox = |q: &Quad| {
+        let xs = q.iter().map(|p| p.x);
+        let ys = q.iter().map(|p| p.y);
+        (
+            xs.clone().fold(f32::INFINITY, f32::min),
+            ys.clone().fold(f32::INFINITY, f32::min),
+            xs.fold(f32::NEG_INFINITY, f32::max),
+            ys.fold(f32::NEG_INFINITY, f32::max),
+        )
+    };
+    let (ax1, ay1, ax2, ay2) = bbox(a);
+    let (bx1, by1, bx2, by2) = bbox(b);
+    let intersection =
+        (ax2.min(bx2) - ax1.max(bx1)).max(0.0) * (ay2.min(by2) - ay1.max(by1)).max(0.0);
+    let smaller = ((ax2 - ax1) * (ay2 - ay1)).min((bx2 - bx1) * (by2 - by1));
+    smaller > 0.0 && intersection > smaller * 0.5
+}
+
+fn order_points(points: &[Point2f; 4]) -> [Point2f; 4] {
+    let mut ordered = [Point2f::default(); 4];
+
+    // top-left has smallest sum, bottom-right largest sum
+    ordered[0] = *points
+        .iter()
+        .min_by(|a, b| (a.x + a.y).partial_cmp(&(b.x + b.y)).unwrap())
+        .unwrap();
+    ordered[2] = *points
+        .iter()
+        .max_by(|a, b| (a.x + a.y).partial_cmp(&(b.x + b.y)).unwrap())
+        .unwrap();
+
+    // top-right has smallest difference, bottom-left largest difference
+    ordered[1] = *points
+        .iter()
+        .min_by(|a, b| (a.x - a.y).partial_cmp(&(b.x - b.y)).unwrap())
+        .unwrap();
+    ordered[3] = *points
+        .iter()
+        .max_by(|a, b| (a.x - a.y).partial_cmp(&(b.x - b.y)).unwrap())
+        .unwrap();
+
+    ordered
+}
+
+fn distance(a: &Point2f, b: &Point2f) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+fn rects_overlap(a: &core::RotatedRect, b: &core::RotatedRect) -> Result<bool> {
+    let (ax1, ay1, ax2, ay2) = rect_bbox(a)?;
+    let (bx1, by1, bx2, by2) = rect_bbox(b)?;
+
+    let intersect_w = (ax2.min(bx2) - ax1.max(bx1)).max(0.0);
+    let intersect_h = (ay2.min(by2) - ay1.max(by1)).max(0.0);
+
+    Ok(intersect_w > 0.0 && intersect_h > 0.0)
+}
+
+fn rect_bbox(rect: &core::RotatedRect) -> Result<(f32, f32, f32, f32)> {
+    let mut pts = [Point2f::default(); 4];
+    rect.points(&mut pts)?;
+    let min_x = pts.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+    let max_x = pts.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+    let min_y = pts.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+    let max_y = pts.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+    Ok((min_x, min_y, max_x, max_y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, w: f32, h: f32) -> RectCandidate {
+        RectCandidate {
+            rect: core::RotatedRect {
+                center: Point2f::new(x + w / 2.0, y + h / 2.0),
+                size: core::Size2f::new(w, h),
+                angle: 0.0,
+            },
+            area: (w * h) as f64,
+            shape: PhotoShape::Rect,
+            contour: None,
+            outline: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn abutting_prints_are_not_merged() {
+        let (left, right) = (rect(0.0, 0.0, 150.0, 100.0), rect(152.0, 0.0, 150.0, 100.0));
+        assert!(fragment_join(&left, &right).is_none());
+    }
+
+    #[test]
+    fn split_print_is_merged_across_its_seam() {
+        let (top, bottom) = (rect(0.0, 0.0, 150.0, 38.0), rect(0.0, 41.0, 150.0, 59.0));
+        let join = fragment_join(&top, &bottom).expect("the halves should join");
+        assert_eq!(join.union.size.width, 150.0);
+        assert_eq!(join.union.size.height, 100.0);
+        assert_eq!(join.seam[0].y, 39.5);
+        assert_eq!(join.seam[1].y, 39.5);
+    }
+
+    #[test]
+    fn far_apart_pieces_are_not_merged() {
+        let (top, bottom) = (rect(0.0, 0.0, 150.0, 30.0), rect(0.0, 60.0, 150.0, 40.0));
+        assert!(fragment_join(&top, &bottom).is_none());
+    }
+}
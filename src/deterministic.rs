@@ -0,0 +1,14 @@
+use anyhow::Result;
+use opencv::core;
+
+/// Makes OpenCV compute the same bits on every run of the same build on the
+/// same kind of machine, for golden-file tests: on one thread, as parallel
+/// sums add up their parts in whatever order the threads finish, and without
+/// the SIMD and IPP code paths, whose rounding depends on the CPU.
+///
+/// The settings are process-wide and stay in force; processing gets slower.
+pub fn enable() -> Result<()> {
+    core::set_num_threads(1)?;
+    core::set_use_optimized(false)?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::tr;
+
+/// Extra room kept on top of the estimate, as a share of it.
+const HEADROOM: f64 = 0.2;
+/// How often a run paused on a full disk checks for free space again.
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Typical bytes per pixel of a photo stored with the given extension.
+///
+/// JPEG is OpenCV's default quality 95, PNG and WebP compress photos to roughly
+/// two thirds of raw, TIFF and BMP are written uncompressed.
+fn bytes_per_pixel(extension: &str) -> f64 {
+    match extension.to_lowercase().as_str() {
+        "jpg" | "jpeg" => 0.5,
+        "png" | "webp" => 2.0,
+        _ => 3.0,
+    }
+}
+
+/// Rough size of the crops made from a source file of `source_bytes`.
+///
+/// The source's pixel count is inferred from its size and format, and the
+/// crops are assumed to cover the whole sheet, which errs on the safe side.
+pub fn estimate_output(source_bytes: u64, source_ext: &str, output_ext: &str) -> u64 {
+    let pixels = source_bytes as f64 / bytes_per_pixel(source_ext);
+    (pixels * bytes_per_pixel(output_ext)) as u64
+}
+
+/// Free bytes available to the current user on the volume holding `path`.
+///
+/// `path` need not exist yet; its nearest existing ancestor is measured.
+pub fn available(path: &Path) -> Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|p| p.exists())
+        .unwrap_or_else(|| Path::new("."));
+    available_at(existing)
+        .with_context(|| format!("Could not query free space at {}", existing.display()))
+}
+
+#[cfg(unix)]
+fn available_at(path: &Path) -> Result<u64> {
+    let stat = nix::sys::statvfs::statvfs(path)?;
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
+#[cfg(windows)]
+fn available_at(path: &Path) -> Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    let mut free: u64 = 0;
+    // SAFETY: `wide` is NUL terminated and the out pointers are valid or null.
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut free,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(free)
+}
+
+/// Checks that the volume of `output_dir` can hold `estimate` bytes plus headroom.
+///
+/// Returns `Ok(None)` when there is room, or a message describing the shortfall.
+pub fn preflight(output_dir: &Path, estimate: u64) -> Result<Option<String>> {
+    let needed = (estimate as f64 * (1.0 + HEADROOM)) as u64;
+    let free = available(output_dir)?;
+    if free >= needed {
+        return Ok(None);
+    }
+    Ok(Some(tr!(
+        "space-short",
+        path = output_dir.display().to_string(),
+        free = format_bytes(free),
+        needed = format_bytes(needed)
+    )))
+}
+
+/// Writes `bytes` to `path`, pausing instead of failing while the disk is full.
+///
+/// The write is retried once the volume has room for it again, so a long run
+/// can be resumed by freeing space without restarting it.
+pub fn write_waiting_for_space(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut warned = false;
+    loop {
+        match fs::write(path, bytes) {
+            Ok(()) => return Ok(()),
+            Err(err) if err.kind() == ErrorKind::StorageFull => {
+                // Don't leave a truncated file behind while waiting.
+                let _ = fs::remove_file(path);
+                if !warned {
+                    eprintln!(
+                        "{}",
+                        tr!(
+                            "disk-full-paused",
+                            path = path.display().to_string(),
+                            size = format_bytes(bytes.len() as u64)
+                        )
+                    );
+                    warned = true;
+                }
+                while available(path)? <= bytes.len() as u64 {
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to write {}", path.display()));
+            }
+        }
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
@@ -0,0 +1,380 @@
+use anyhow::{Result, ensure};
+use opencv::core::{self, AlgorithmHint, Mat, Point, Point2f, Scalar, Size, Vector};
+use opencv::imgproc;
+use opencv::prelude::*;
+
+use crate::detect::{self, Quad};
+
+/// Variance of the Laplacian of `image`; low values mean a soft, blurry crop.
+pub fn sharpness(image: &Mat) -> Result<f64> {
+    let gray = to_gray(image)?;
+
+    let mut laplacian = Mat::default();
+    imgproc::laplacian(
+        &gray,
+        &mut laplacian,
+        core::CV_64F,
+        1,
+        1.0,
+        0.0,
+        core::BORDER_DEFAULT,
+    )?;
+
+    let mut mean = Mat::default();
+    let mut stddev = Mat::default();
+    core::mean_std_dev(&laplacian, &mut mean, &mut stddev, &core::no_array())?;
+    let sd = *stddev.at_2d::<f64>(0, 0)?;
+    Ok(sd * sd)
+}
+
+/// Applies an unsharp mask if `image` is softer than `threshold`.
+///
+/// Returns `None` when the crop is already sharp enough. Radius and amount grow
+/// with resolution, since the same blur spans more pixels on a larger crop.
+pub fn auto_sharpen(image: &Mat, threshold: f64) -> Result<Option<Mat>> {
+    if sharpness(image)? >= threshold {
+        return Ok(None);
+    }
+
+    let long_side = image.cols().max(image.rows()) as f64;
+    let sigma = (long_side / 1000.0).clamp(0.8, 3.0);
+    let amount = 0.6 + 0.3 * sigma;
+
+    let mut blurred = Mat::default();
+    imgproc::gaussian_blur(
+        image,
+        &mut blurred,
+        Size::new(0, 0),
+        sigma,
+        sigma,
+        core::BORDER_DEFAULT,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+
+    let mut sharpened = Mat::default();
+    core::add_weighted(
+        image,
+        1.0 + amount,
+        &blurred,
+        -amount,
+        0.0,
+        &mut sharpened,
+        -1,
+    )?;
+    Ok(Some(sharpened))
+}
+
+/// JPEG quality range `--adaptive-quality` chooses from.
+const ADAPTIVE_QUALITY: (f64, f64) = (75.0, 95.0);
+/// Size at which a crop counts as large for [`adaptive_jpeg_quality`].
+const LARGE_MEGAPIXELS: f64 = 8.0;
+
+/// JPEG quality for `image` from its detail and size: a detailed, large
+/// photo gets close to the top of [`ADAPTIVE_QUALITY`], a small faded one
+/// the bottom, where stronger compression costs little that shows.
+///
+/// Detail is the [`sharpness`] on a log scale, from 10 (blank or blurred)
+/// to 1000 (fine texture); it weighs three times as much as the size.
+pub fn adaptive_jpeg_quality(image: &Mat) -> Result<i32> {
+    let detail = ((sharpness(image)?.max(1.0).log10() - 1.0) / 2.0).clamp(0.0, 1.0);
+    let megapixels = image.cols() as f64 * image.rows() as f64 / 1e6;
+    let size = (megapixels / LARGE_MEGAPIXELS).clamp(0.0, 1.0);
+    let (low, high) = ADAPTIVE_QUALITY;
+    Ok((low + (high - low) * (0.75 * detail + 0.25 * size)).round() as i32)
+}
+
+/// Whether `image` is effectively black & white.
+///
+/// Looks at how much the color (Lab a/b channels) varies across the crop rather
+/// than its average, so sepia-toned or yellowed B&W prints still count.
+pub fn is_monochrome(image: &Mat, max_chroma_stddev: f64) -> Result<bool> {
+    if image.channels() == 1 {
+        return Ok(true);
+    }
+
+    let mut lab = Mat::default();
+    imgproc::cvt_color(
+        image,
+        &mut lab,
+        imgproc::COLOR_BGR2Lab,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+
+    let mut mean = Mat::default();
+    let mut stddev = Mat::default();
+    core::mean_std_dev(&lab, &mut mean, &mut stddev, &core::no_array())?;
+    let a = *stddev.at_2d::<f64>(1, 0)?;
+    let b = *stddev.at_2d::<f64>(2, 0)?;
+    Ok(a.max(b) <= max_chroma_stddev)
+}
+
+/// Converts `image` to a single-channel grayscale image.
+pub fn to_gray(image: &Mat) -> Result<Mat> {
+    if image.channels() == 1 {
+        return Ok(image.clone());
+    }
+    let mut gray = Mat::default();
+    imgproc::cvt_color(
+        image,
+        &mut gray,
+        imgproc::COLOR_BGR2GRAY,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    Ok(gray)
+}
+
+/// Steps of the table [`from_linear`] looks values up in; fine enough that
+/// neighbouring steps never round to 8-bit values more than one apart.
+const LINEAR_STEPS: usize = 4096;
+
+/// 8-bit sRGB `image` as 32-bit float linear light from 0 to 1, so that
+/// resampling averages light rather than gamma-encoded values.
+pub fn to_linear(image: &Mat) -> Result<Mat> {
+    ensure!(
+        image.depth() == core::CV_8U,
+        "Linear light needs an 8-bit image"
+    );
+    let table: Vec<f32> = (0..256).map(|v| srgb_to_linear(v as f32 / 255.0)).collect();
+    let mut linear = Mat::default();
+    core::lut(image, &Mat::from_slice(&table)?.try_clone()?, &mut linear)?;
+    Ok(linear)
+}
+
+/// Float linear-light `image` from [`to_linear`] back to 8-bit sRGB,
+/// clipping what resampling overshot.
+pub fn from_linear(image: &Mat) -> Result<Mat> {
+    let table: Vec<u8> = (0..=LINEAR_STEPS)
+        .map(|i| (linear_to_srgb(i as f32 / LINEAR_STEPS as f32) * 255.0).round() as u8)
+        .collect();
+    let typ = match image.channels() {
+        1 => core::CV_8UC1,
+        3 => core::CV_8UC3,
+        4 => core::CV_8UC4,
+        n => anyhow::bail!("Unexpected {n}-channel image"),
+    };
+    // Pixel data is only addressable as one slice when continuous.
+    let owned;
+    let image = if image.is_continuous() {
+        image
+    } else {
+        owned = image.try_clone()?;
+        &owned
+    };
+    let mut srgb =
+        Mat::new_rows_cols_with_default(image.rows(), image.cols(), typ, Scalar::all(0.0))?;
+    for (out, v) in srgb
+        .data_bytes_mut()?
+        .iter_mut()
+        .zip(image.data_typed::<f32>()?)
+    {
+        *out = table[(v.clamp(0.0, 1.0) * LINEAR_STEPS as f32).round() as usize];
+    }
+    Ok(srgb)
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Share of the darkest and of the brightest pixels clipped by auto-levels.
+const LEVELS_CLIP: f64 = 0.005;
+
+/// Per-channel input range that auto-levels stretches to the full 0..255.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Levels {
+    ranges: Vec<(u8, u8)>,
+}
+
+impl Levels {
+    /// Measures the tonal range of an 8-bit `image`, only where `mask` is set
+    /// when given, so several photos can share one correction.
+    pub fn measure(image: &Mat, mask: Option<&Mat>) -> Result<Self> {
+        // Pixel data is only addressable as one slice when continuous.
+        let (owned_image, owned_mask);
+        let image = if image.is_continuous() {
+            image
+        } else {
+            owned_image = image.try_clone()?;
+            &owned_image
+        };
+        let mask = match mask {
+            Some(mask) if !mask.is_continuous() => {
+                owned_mask = mask.try_clone()?;
+                Some(&owned_mask)
+            }
+            mask => mask,
+        };
+        let channels = image.channels().max(1) as usize;
+        let mut histograms = vec![[0u64; 256]; channels];
+        let pixels = image.data_bytes()?.chunks_exact(channels);
+        match mask {
+            Some(mask) => {
+                for (pixel, _) in pixels.zip(mask.data_bytes()?).filter(|(_, m)| **m != 0) {
+                    for (histogram, &value) in histograms.iter_mut().zip(pixel) {
+                        histogram[value as usize] += 1;
+                    }
+                }
+            }
+            None => {
+                for pixel in pixels {
+                    for (histogram, &value) in histograms.iter_mut().zip(pixel) {
+                        histogram[value as usize] += 1;
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            ranges: histograms.iter().map(clipped_range).collect(),
+        })
+    }
+
+    /// Stretches each channel of `image` by the measured ranges.
+    pub fn apply(&self, image: &Mat) -> Result<Mat> {
+        let mut channels: Vector<Mat> = Vector::new();
+        core::split(image, &mut channels)?;
+        ensure!(
+            channels.len() == self.ranges.len(),
+            "Levels measured for {} channels, image has {}",
+            self.ranges.len(),
+            channels.len()
+        );
+        let mut stretched: Vector<Mat> = Vector::new();
+        for (channel, &(low, high)) in channels.iter().zip(&self.ranges) {
+            let scale = 255.0 / (high - low) as f64;
+            let mut out = Mat::default();
+            channel.convert_to(&mut out, -1, scale, -(low as f64) * scale)?;
+            stretched.push(out);
+        }
+        let mut merged = Mat::default();
+        core::merge(&stretched, &mut merged)?;
+        Ok(merged)
+    }
+}
+
+/// Darkest and brightest values after clipping [`LEVELS_CLIP`] at each end;
+/// the identity range when too little contrast is left to stretch.
+fn clipped_range(histogram: &[u64; 256]) -> (u8, u8) {
+    let total: u64 = histogram.iter().sum();
+    let clip = (total as f64 * LEVELS_CLIP) as u64;
+    let low = first_beyond(histogram.iter().enumerate(), clip).unwrap_or(0);
+    let high = first_beyond(histogram.iter().enumerate().rev(), clip).unwrap_or(255);
+    if high <= low { (0, 255) } else { (low, high) }
+}
+
+/// First value at which the running count of `bins` exceeds `clip`.
+fn first_beyond<'a>(bins: impl Iterator<Item = (usize, &'a u64)>, clip: u64) -> Option<u8> {
+    let mut seen = 0;
+    for (value, count) in bins {
+        seen += count;
+        if seen > clip {
+            return Some(value as u8);
+        }
+    }
+    None
+}
+
+/// What to put outside a photo's outline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MaskFill {
+    /// A solid BGR color.
+    Color(Scalar),
+    /// Fully transparent pixels; the crop gets an alpha channel.
+    Transparent,
+}
+
+/// Fills everything outside a photo's outline.
+///
+/// `outline` is in source coordinates and is mapped into the crop with the same
+/// transform as `quad`; see [`photo_mask`].
+pub fn mask_outside(image: &Mat, quad: &Quad, outline: &[Point2f], fill: MaskFill) -> Result<Mat> {
+    if outline.len() < 3 {
+        return Ok(image.clone());
+    }
+    let mut outside = Mat::default();
+    core::bitwise_not(&photo_mask(quad, outline)?, &mut outside, &core::no_array())?;
+
+    let (mut masked, color) = match fill {
+        MaskFill::Color(color) if image.channels() == 1 => {
+            let luma = 0.114 * color[0] + 0.587 * color[1] + 0.299 * color[2];
+            (image.clone(), Scalar::all(luma))
+        }
+        MaskFill::Color(color) => (image.clone(), color),
+        MaskFill::Transparent => {
+            let code = if image.channels() == 1 {
+                imgproc::COLOR_GRAY2BGRA
+            } else {
+                imgproc::COLOR_BGR2BGRA
+            };
+            let mut bgra = Mat::default();
+            imgproc::cvt_color(image, &mut bgra, code, 0, AlgorithmHint::ALGO_HINT_DEFAULT)?;
+            (bgra, Scalar::all(0.0))
+        }
+    };
+    masked.set_to(&color, &outside)?;
+    Ok(masked)
+}
+
+/// 8-bit mask in the coordinates of the crop warped from `quad`, 255 on the
+/// photo and 0 on the background around it. `outline` is in source
+/// coordinates and is mapped into the crop with the same transform as
+/// `quad`; without one the whole crop is photo.
+///
+/// The mask is pulled in slightly so the scan background caught by the
+/// dilated contour does not count as photo along rounded or deckled edges.
+pub fn photo_mask(quad: &Quad, outline: &[Point2f]) -> Result<Mat> {
+    let (m, size) = detect::warp_transform(quad)?;
+    if outline.len() < 3 {
+        return Ok(Mat::new_size_with_default(
+            size,
+            core::CV_8UC1,
+            Scalar::all(255.0),
+        )?);
+    }
+    let src: Vector<Point2f> = outline.iter().copied().collect();
+    let mut dst: Vector<Point2f> = Vector::new();
+    core::perspective_transform(&src, &mut dst, &m)?;
+    let polygon: Vector<Point> = dst
+        .iter()
+        .map(|p| Point::new(p.x.round() as i32, p.y.round() as i32))
+        .collect();
+
+    let mut mask = Mat::zeros(size.height, size.width, core::CV_8UC1)?.to_mat()?;
+    imgproc::fill_poly(
+        &mut mask,
+        &Vector::<Vector<Point>>::from(vec![polygon]),
+        Scalar::all(255.0),
+        imgproc::LINE_8,
+        0,
+        Point::new(0, 0),
+    )?;
+    let kernel = imgproc::get_structuring_element(
+        imgproc::MORPH_ELLIPSE,
+        Size::new(9, 9),
+        Point::new(-1, -1),
+    )?;
+    let mut eroded = Mat::default();
+    imgproc::erode(
+        &mask,
+        &mut eroded,
+        &kernel,
+        Point::new(-1, -1),
+        1,
+        core::BORDER_CONSTANT,
+        Scalar::all(0.0),
+    )?;
+    Ok(eroded)
+}
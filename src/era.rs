@@ -0,0 +1,106 @@
+use anyhow::Result;
+use opencv::core::{self, AlgorithmHint, Mat, Size};
+use opencv::imgproc;
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::enhance;
+
+/// Longest side crops are shrunk to before being looked at.
+const SAMPLE_SIDE: f64 = 256.0;
+/// Mean saturation (0-255) below which colours count as faded.
+const FADED_SATURATION: f64 = 60.0;
+/// Brightness (0-255) the darkest 5 % of a crop stays above when its blacks
+/// have faded to brown or grey.
+const FADED_BLACKS: f64 = 40.0;
+/// Share of the darkest pixels whose brightness is taken as the black point.
+const BLACK_SHARE: f64 = 0.05;
+
+/// Rough era of a photo, told from its colours alone: a head start for
+/// sorting an unsorted box of prints, not a date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Era {
+    /// Black and white, toned or not.
+    BlackWhite,
+    /// Colour gone pale or with lifted, brownish blacks, as prints of the
+    /// 1960s and 70s have.
+    FadedColor,
+    /// Saturated colour with deep blacks, as the glossy prints of the 1980s
+    /// and 90s.
+    GlossyColor,
+}
+
+impl Era {
+    /// Name of the era as a folder, keyword and manifest value.
+    pub fn name(self) -> &'static str {
+        match self {
+            Era::BlackWhite => "black-white",
+            Era::FadedColor => "faded-color",
+            Era::GlossyColor => "glossy-color",
+        }
+    }
+
+    /// Era of the 8-bit BGR crop `image`; black and white when its colour
+    /// varies no more than `bw_tolerance`, as `--bw-as-gray` decides.
+    pub fn of(image: &Mat, bw_tolerance: f64) -> Result<Self> {
+        let small = shrink(image)?;
+        if enhance::is_monochrome(&small, bw_tolerance)? {
+            return Ok(Era::BlackWhite);
+        }
+        let mut hsv = Mat::default();
+        imgproc::cvt_color(
+            &small,
+            &mut hsv,
+            imgproc::COLOR_BGR2HSV,
+            0,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        let saturation = core::mean(&hsv, &core::no_array())?[1];
+        let blacks = black_point(&hsv)?;
+        tracing::debug!(saturation, blacks, "era");
+        Ok(if saturation < FADED_SATURATION || blacks > FADED_BLACKS {
+            Era::FadedColor
+        } else {
+            Era::GlossyColor
+        })
+    }
+}
+
+/// Brightness below which the darkest [`BLACK_SHARE`] of the HSV `image`
+/// lies.
+fn black_point(hsv: &Mat) -> Result<f64> {
+    let mut value = Mat::default();
+    core::extract_channel(hsv, &mut value, 2)?;
+    let mut histogram = [0usize; 256];
+    for &v in value.data_bytes()? {
+        histogram[v as usize] += 1;
+    }
+    let wanted = (value.total() as f64 * BLACK_SHARE).ceil() as usize;
+    let mut seen = 0;
+    for (v, count) in histogram.iter().enumerate() {
+        seen += count;
+        if seen >= wanted {
+            return Ok(v as f64);
+        }
+    }
+    Ok(255.0)
+}
+
+/// `image` with its longest side at most [`SAMPLE_SIDE`].
+fn shrink(image: &Mat) -> Result<Mat> {
+    let scale = SAMPLE_SIDE / image.cols().max(image.rows()).max(1) as f64;
+    if scale >= 1.0 {
+        return Ok(image.try_clone()?);
+    }
+    let mut small = Mat::default();
+    imgproc::resize(
+        image,
+        &mut small,
+        Size::new(0, 0),
+        scale,
+        scale,
+        imgproc::INTER_AREA,
+    )?;
+    Ok(small)
+}
@@ -0,0 +1,458 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use img_parts::{Bytes, DynImage, ImageEXIF};
+
+/// EXIF `Orientation` tag in IFD0.
+pub const TAG_ORIENTATION: u16 = 0x0112;
+/// `ImageDescription` (IFD0): a caption of the picture.
+pub const TAG_IMAGE_DESCRIPTION: u16 = 0x010E;
+/// `DocumentName` (IFD0): name of the document the image was scanned from.
+pub const TAG_DOCUMENT_NAME: u16 = 0x010D;
+/// `PageName` (IFD0): name of the page the image was scanned from.
+pub const TAG_PAGE_NAME: u16 = 0x011D;
+/// `XResolution` / `YResolution` (IFD0): pixels per `ResolutionUnit`.
+pub const TAG_X_RESOLUTION: u16 = 0x011A;
+pub const TAG_Y_RESOLUTION: u16 = 0x011B;
+/// `ResolutionUnit` (IFD0); 2 means inches.
+pub const TAG_RESOLUTION_UNIT: u16 = 0x0128;
+/// `UserComment` (Exif IFD): free text prefixed with its character code.
+pub const TAG_USER_COMMENT: u16 = 0x9286;
+/// `DateTimeOriginal` (Exif IFD): when the picture was taken, as `YYYY:MM:DD HH:MM:SS`.
+pub const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+/// `ExifIFDPointer` (IFD0): offset of the Exif IFD.
+const TAG_EXIF_IFD: u16 = 0x8769;
+/// `Compression` (IFD1); 6 marks a JPEG thumbnail.
+const TAG_COMPRESSION: u16 = 0x0103;
+/// `JPEGInterchangeFormat` (IFD1): offset of the thumbnail.
+const TAG_THUMBNAIL_OFFSET: u16 = 0x0201;
+/// `JPEGInterchangeFormatLength` (IFD1): byte size of the thumbnail.
+const TAG_THUMBNAIL_LENGTH: u16 = 0x0202;
+
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+const TYPE_UNDEFINED: u16 = 7;
+
+/// A single EXIF field value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Byte(Vec<u8>),
+    Ascii(String),
+    Short(u16),
+    Long(u32),
+    Rational(u32, u32),
+    Undefined(Vec<u8>),
+}
+
+impl Value {
+    fn type_id(&self) -> u16 {
+        match self {
+            Value::Byte(_) => TYPE_BYTE,
+            Value::Ascii(_) => TYPE_ASCII,
+            Value::Short(_) => TYPE_SHORT,
+            Value::Long(_) => TYPE_LONG,
+            Value::Rational(..) => TYPE_RATIONAL,
+            Value::Undefined(_) => TYPE_UNDEFINED,
+        }
+    }
+
+    /// Number of components, as stored in the entry's count field.
+    fn count(&self) -> u32 {
+        match self {
+            Value::Byte(b) | Value::Undefined(b) => b.len() as u32,
+            // ASCII values are NUL terminated.
+            Value::Ascii(s) => s.len() as u32 + 1,
+            Value::Short(_) | Value::Long(_) | Value::Rational(..) => 1,
+        }
+    }
+
+    /// Big-endian encoding of the value.
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Value::Byte(b) | Value::Undefined(b) => b.clone(),
+            Value::Ascii(s) => {
+                let mut bytes = s.as_bytes().to_vec();
+                bytes.push(0);
+                bytes
+            }
+            Value::Short(v) => v.to_be_bytes().to_vec(),
+            Value::Long(v) => v.to_be_bytes().to_vec(),
+            Value::Rational(n, d) => [n.to_be_bytes(), d.to_be_bytes()].concat(),
+        }
+    }
+
+    /// A `UserComment` value: ASCII when possible, otherwise big-endian UCS-2
+    /// to match the block's byte order.
+    pub fn user_comment(text: &str) -> Self {
+        let mut bytes = Vec::new();
+        if text.is_ascii() {
+            bytes.extend_from_slice(b"ASCII\0\0\0");
+            bytes.extend_from_slice(text.as_bytes());
+        } else {
+            bytes.extend_from_slice(b"UNICODE\0");
+            bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        }
+        Value::Undefined(bytes)
+    }
+
+    /// The text of an ASCII value or a `UserComment`, if it holds any.
+    pub fn text(&self) -> Option<String> {
+        let text = match self {
+            Value::Ascii(s) => s.clone(),
+            Value::Undefined(bytes) if bytes.starts_with(b"ASCII\0\0\0") => {
+                String::from_utf8_lossy(&bytes[8..]).into_owned()
+            }
+            Value::Undefined(bytes) if bytes.starts_with(b"UNICODE\0") => {
+                let units: Vec<u16> = bytes[8..]
+                    .chunks_exact(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            _ => return None,
+        };
+        let text = text.trim_end_matches('\0').trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
+
+/// Builds a minimal big-endian EXIF (TIFF) block from scratch.
+///
+/// Crops are freshly encoded by OpenCV and carry no metadata of their own, so
+/// there is nothing to preserve; the block only contains the fields set here.
+#[derive(Clone, Debug, Default)]
+pub struct ExifBuilder {
+    ifd0: Vec<(u16, Value)>,
+    /// Fields of the Exif sub-IFD, linked from IFD0 when present.
+    exif_ifd: Vec<(u16, Value)>,
+    /// JPEG preview stored in IFD1.
+    thumbnail: Option<Vec<u8>>,
+}
+
+impl ExifBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets an IFD0 field, replacing any previous value for `tag`.
+    pub fn set(&mut self, tag: u16, value: Value) -> &mut Self {
+        self.ifd0.retain(|(t, _)| *t != tag);
+        self.ifd0.push((tag, value));
+        self
+    }
+
+    /// Sets an Exif sub-IFD field such as [`TAG_USER_COMMENT`].
+    pub fn set_exif(&mut self, tag: u16, value: Value) -> &mut Self {
+        self.exif_ifd.retain(|(t, _)| *t != tag);
+        self.exif_ifd.push((tag, value));
+        self
+    }
+
+    /// Removes an IFD0 field, if set.
+    pub fn remove(&mut self, tag: u16) -> &mut Self {
+        self.ifd0.retain(|(t, _)| *t != tag);
+        self
+    }
+
+    /// Removes an Exif sub-IFD field, if set.
+    pub fn remove_exif(&mut self, tag: u16) -> &mut Self {
+        self.exif_ifd.retain(|(t, _)| *t != tag);
+        self
+    }
+
+    /// Embeds a JPEG-encoded preview. The whole EXIF block must stay under
+    /// 64 KB, so keep it small (around 160 pixels on the long side).
+    pub fn set_thumbnail(&mut self, jpeg: Vec<u8>) -> &mut Self {
+        self.thumbnail = Some(jpeg);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ifd0.is_empty() && self.exif_ifd.is_empty() && self.thumbnail.is_none()
+    }
+
+    /// Serializes the TIFF header, IFD0, the Exif IFD and the thumbnail IFD1
+    /// if any (without the `Exif\0\0` prefix).
+    pub fn build(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"MM");
+        out.extend_from_slice(&42u16.to_be_bytes());
+        out.extend_from_slice(&8u32.to_be_bytes());
+        let mut ifd0 = self.ifd0.clone();
+        if !self.exif_ifd.is_empty() {
+            // Patched below once the Exif IFD's position is known.
+            ifd0.push((TAG_EXIF_IFD, Value::Long(0)));
+        }
+        let next_field = write_ifd(&mut out, &ifd0, 0);
+
+        if !self.exif_ifd.is_empty() {
+            let exif_start = out.len() as u32;
+            let pointer = entry_value_position(&out, 8, TAG_EXIF_IFD);
+            out[pointer..pointer + 4].copy_from_slice(&exif_start.to_be_bytes());
+            write_ifd(&mut out, &self.exif_ifd, 0);
+        }
+
+        if let Some(thumbnail) = &self.thumbnail {
+            let ifd1_start = out.len();
+            out[next_field..next_field + 4].copy_from_slice(&(ifd1_start as u32).to_be_bytes());
+            // Three inline entries, so the thumbnail follows the IFD directly.
+            let thumbnail_start = ifd1_start + 2 + 3 * 12 + 4;
+            let ifd1 = [
+                (TAG_COMPRESSION, Value::Short(6)),
+                (TAG_THUMBNAIL_OFFSET, Value::Long(thumbnail_start as u32)),
+                (TAG_THUMBNAIL_LENGTH, Value::Long(thumbnail.len() as u32)),
+            ];
+            write_ifd(&mut out, &ifd1, 0);
+            out.extend_from_slice(thumbnail);
+        }
+        out
+    }
+}
+
+/// Appends an IFD at the end of `out`, followed by the values that do not fit
+/// inline. `next` is the offset of the following IFD, or 0 for none.
+///
+/// Returns the position of the next-IFD field so it can be patched later.
+fn write_ifd(out: &mut Vec<u8>, entries: &[(u16, Value)], next: u32) -> usize {
+    let mut entries: Vec<&(u16, Value)> = entries.iter().collect();
+    entries.sort_by_key(|(tag, _)| *tag);
+
+    let ifd_start = out.len();
+    let ifd_len = 2 + entries.len() * 12 + 4;
+    let mut data_offset = ifd_start + ifd_len;
+    let mut data = Vec::new();
+
+    out.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+    for (tag, value) in entries {
+        let encoded = value.encode();
+        out.extend_from_slice(&tag.to_be_bytes());
+        out.extend_from_slice(&value.type_id().to_be_bytes());
+        out.extend_from_slice(&value.count().to_be_bytes());
+        if encoded.len() <= 4 {
+            // Small values are stored left-aligned in the offset field itself.
+            let mut inline = [0u8; 4];
+            inline[..encoded.len()].copy_from_slice(&encoded);
+            out.extend_from_slice(&inline);
+        } else {
+            out.extend_from_slice(&(data_offset as u32).to_be_bytes());
+            data_offset += encoded.len();
+            data.extend_from_slice(&encoded);
+            // Values must start on word boundaries.
+            if encoded.len() % 2 == 1 {
+                data.push(0);
+                data_offset += 1;
+            }
+        }
+    }
+    let next_field = out.len();
+    out.extend_from_slice(&next.to_be_bytes());
+    out.extend_from_slice(&data);
+    next_field
+}
+
+/// Position of the value field of the entry for `tag` in the IFD at `ifd_start`.
+fn entry_value_position(out: &[u8], ifd_start: usize, tag: u16) -> usize {
+    let count = u16::from_be_bytes([out[ifd_start], out[ifd_start + 1]]) as usize;
+    (0..count)
+        .map(|i| ifd_start + 2 + i * 12)
+        .find(|&entry| out[entry..entry + 2] == tag.to_be_bytes())
+        .map(|entry| entry + 8)
+        .expect("tag was written to the IFD")
+}
+
+/// Embeds `exif` into the image file at `path`, replacing any existing block.
+///
+/// Returns `false` without touching the file when its format cannot carry
+/// EXIF through this path (only JPEG, PNG and WebP can).
+pub fn write_to_file(path: &Path, exif: &ExifBuilder) -> Result<bool> {
+    let bytes = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+    let Some(bytes) =
+        embed(bytes, exif).with_context(|| format!("Could not parse {}", path.display()))?
+    else {
+        return Ok(false);
+    };
+    fs::write(path, bytes).with_context(|| format!("Could not write {}", path.display()))?;
+    Ok(true)
+}
+
+/// The encoded image `bytes` with `exif` embedded, replacing any existing
+/// block, or `None` when the format cannot carry EXIF this way.
+pub fn embed(bytes: Vec<u8>, exif: &ExifBuilder) -> Result<Option<Vec<u8>>> {
+    let Some(mut image) = DynImage::from_bytes(Bytes::from(bytes))? else {
+        return Ok(None);
+    };
+    image.set_exif(Some(Bytes::from(exif.build())));
+    Ok(Some(image.encoder().bytes().to_vec()))
+}
+
+/// A field read from an existing EXIF block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub tag: u16,
+    /// Whether it sits in the Exif sub-IFD rather than IFD0.
+    pub exif_ifd: bool,
+    pub value: Value,
+}
+
+/// The IFD0 and Exif sub-IFD fields of the image file `bytes`: a TIFF, or a
+/// JPEG, PNG or WebP carrying an EXIF block. Fields of types [`Value`] cannot
+/// hold, and multi-component shorts, longs and rationals, are left out.
+pub fn read(bytes: &[u8]) -> Result<Vec<Entry>> {
+    if bytes.starts_with(b"MM\0*") || bytes.starts_with(b"II*\0") {
+        return Ok(Tiff::new(bytes).entries());
+    }
+    let Some(image) = DynImage::from_bytes(Bytes::copy_from_slice(bytes))? else {
+        return Ok(Vec::new());
+    };
+    Ok(image
+        .exif()
+        .map(|exif| Tiff::new(&exif).entries())
+        .unwrap_or_default())
+}
+
+/// The encoded image `bytes` with its EXIF block changed by `change`, keeping
+/// the fields [`read`] can read and the thumbnail, or `None` when the format
+/// cannot carry EXIF this way.
+pub fn update(bytes: Vec<u8>, change: impl FnOnce(&mut ExifBuilder)) -> Result<Option<Vec<u8>>> {
+    let Some(mut image) = DynImage::from_bytes(Bytes::from(bytes))? else {
+        return Ok(None);
+    };
+    let mut exif = ExifBuilder::new();
+    if let Some(block) = image.exif() {
+        let tiff = Tiff::new(&block);
+        for entry in tiff.entries() {
+            if entry.exif_ifd {
+                exif.set_exif(entry.tag, entry.value);
+            } else {
+                exif.set(entry.tag, entry.value);
+            }
+        }
+        if let Some(thumbnail) = tiff.thumbnail() {
+            exif.set_thumbnail(thumbnail);
+        }
+    }
+    change(&mut exif);
+    image.set_exif(Some(Bytes::from(exif.build())));
+    Ok(Some(image.encoder().bytes().to_vec()))
+}
+
+/// A TIFF structure being read, in either byte order.
+struct Tiff<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            big_endian: bytes.starts_with(b"MM"),
+        }
+    }
+
+    fn u16_at(&self, at: usize) -> Option<u16> {
+        let b: [u8; 2] = self.bytes.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+
+    fn u32_at(&self, at: usize) -> Option<u32> {
+        let b: [u8; 4] = self.bytes.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    /// Fields of IFD0 and the Exif sub-IFD.
+    fn entries(&self) -> Vec<Entry> {
+        let Some(ifd0) = self.u32_at(4) else {
+            return Vec::new();
+        };
+        let mut entries = self.ifd(ifd0 as usize, false);
+        let exif_pointer = entries
+            .iter()
+            .position(|e| e.tag == TAG_EXIF_IFD)
+            .map(|i| entries.remove(i).value);
+        if let Some(Value::Long(at)) = exif_pointer {
+            entries.extend(self.ifd(at as usize, true));
+        }
+        entries
+    }
+
+    /// The JPEG thumbnail IFD1 points to, if any.
+    fn thumbnail(&self) -> Option<Vec<u8>> {
+        let ifd0 = self.u32_at(4)? as usize;
+        let ifd1 = self.u32_at(ifd0 + 2 + self.u16_at(ifd0)? as usize * 12)? as usize;
+        if ifd1 == 0 {
+            return None;
+        }
+        let entries = self.ifd(ifd1, false);
+        let long = |tag: u16| {
+            entries
+                .iter()
+                .find(|e| e.tag == tag)
+                .and_then(|e| match e.value {
+                    Value::Long(v) => Some(v as usize),
+                    Value::Short(v) => Some(v as usize),
+                    _ => None,
+                })
+        };
+        let (at, len) = (long(TAG_THUMBNAIL_OFFSET)?, long(TAG_THUMBNAIL_LENGTH)?);
+        Some(self.bytes.get(at..at.checked_add(len)?)?.to_vec())
+    }
+
+    /// Fields of the IFD at `start` that [`Value`] can hold.
+    fn ifd(&self, start: usize, exif_ifd: bool) -> Vec<Entry> {
+        let count = self.u16_at(start).unwrap_or(0) as usize;
+        (0..count)
+            .filter_map(|i| self.entry(start + 2 + i * 12, exif_ifd))
+            .collect()
+    }
+
+    fn entry(&self, entry: usize, exif_ifd: bool) -> Option<Entry> {
+        let tag = self.u16_at(entry)?;
+        let kind = self.u16_at(entry + 2)?;
+        let count = self.u32_at(entry + 4)? as usize;
+        let size = match kind {
+            TYPE_BYTE | TYPE_ASCII | TYPE_UNDEFINED => 1,
+            TYPE_SHORT => 2,
+            TYPE_LONG => 4,
+            TYPE_RATIONAL => 8,
+            _ => return None,
+        };
+        let len = count.checked_mul(size)?;
+        let at = if len <= 4 {
+            entry + 8
+        } else {
+            self.u32_at(entry + 8)? as usize
+        };
+        let data = self.bytes.get(at..at.checked_add(len)?)?;
+        let value = match (kind, count) {
+            (TYPE_BYTE, _) => Value::Byte(data.to_vec()),
+            (TYPE_UNDEFINED, _) => Value::Undefined(data.to_vec()),
+            (TYPE_ASCII, _) => Value::Ascii(
+                String::from_utf8_lossy(data)
+                    .trim_end_matches('\0')
+                    .to_string(),
+            ),
+            (TYPE_SHORT, 1) => Value::Short(self.u16_at(at)?),
+            (TYPE_LONG, 1) => Value::Long(self.u32_at(at)?),
+            (TYPE_RATIONAL, 1) => Value::Rational(self.u32_at(at)?, self.u32_at(at + 4)?),
+            _ => return None,
+        };
+        Some(Entry {
+            tag,
+            exif_ifd,
+            value,
+        })
+    }
+}
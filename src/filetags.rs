@@ -0,0 +1,141 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::albums::Placement;
+use crate::xmp;
+
+/// Extended attribute Finder keeps a file's tags in.
+const FINDER_TAGS: &str = "com.apple.metadata:_kMDItemUserTags";
+
+/// Shows the album, collections and colour label of `placement` in the
+/// file manager of the platform: as Finder tags on macOS, and as the Tags of
+/// Explorer's Details pane on Windows, which it reads from the keywords of a
+/// JPEG's embedded XMP. Returns whether anything was written; other
+/// platforms and formats are left alone.
+pub fn apply(path: &Path, placement: &Placement) -> Result<bool> {
+    let mut keywords = vec![placement.album.clone()];
+    keywords.extend(placement.collections.iter().cloned());
+    if cfg!(target_os = "macos") {
+        finder_tags(path, &keywords, placement.label.as_deref())
+    } else if cfg!(windows) {
+        embed_keywords(path, &keywords)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Sets the Finder tags of `path` to `keywords`, plus the colour tag
+/// matching a Lightroom colour `label`.
+fn finder_tags(path: &Path, keywords: &[String], label: Option<&str>) -> Result<bool> {
+    let mut tags: Vec<String> = keywords.iter().map(|k| format!("{k}\n0")).collect();
+    if let Some((name, colour)) = label.and_then(finder_colour) {
+        tags.push(format!("{name}\n{colour}"));
+    }
+    set_xattr(path, FINDER_TAGS, &binary_plist(&tags))
+        .with_context(|| format!("Could not set Finder tags on {}", path.display()))?;
+    Ok(true)
+}
+
+/// Finder's name and colour number for a Lightroom colour label.
+fn finder_colour(label: &str) -> Option<(&'static str, u8)> {
+    let colour = match label.to_lowercase().as_str() {
+        "gray" | "grey" => ("Gray", 1),
+        "green" => ("Green", 2),
+        "purple" => ("Purple", 3),
+        "blue" => ("Blue", 4),
+        "yellow" => ("Yellow", 5),
+        "red" => ("Red", 6),
+        "orange" => ("Orange", 7),
+        _ => return None,
+    };
+    Some(colour)
+}
+
+/// `strings` as a binary property list holding one array, the form Finder
+/// stores tags in.
+fn binary_plist(strings: &[String]) -> Vec<u8> {
+    // One-byte object references hold 255 strings besides the array.
+    let strings = &strings[..strings.len().min(255)];
+    let mut plist = b"bplist00".to_vec();
+    let mut offsets = vec![plist.len()];
+    push_marker(&mut plist, 0xa0, strings.len());
+    plist.extend((1..=strings.len()).map(|i| i as u8));
+    for s in strings {
+        offsets.push(plist.len());
+        if s.is_ascii() {
+            push_marker(&mut plist, 0x50, s.len());
+            plist.extend_from_slice(s.as_bytes());
+        } else {
+            let units: Vec<u16> = s.encode_utf16().collect();
+            push_marker(&mut plist, 0x60, units.len());
+            plist.extend(units.iter().flat_map(|u| u.to_be_bytes()));
+        }
+    }
+    let table = plist.len();
+    for offset in &offsets {
+        plist.extend_from_slice(&(*offset as u64).to_be_bytes());
+    }
+    // Trailer: unused, offset and reference sizes, object count, top
+    // object and where the offset table starts.
+    plist.extend_from_slice(&[0; 6]);
+    plist.extend_from_slice(&[8, 1]);
+    plist.extend_from_slice(&(offsets.len() as u64).to_be_bytes());
+    plist.extend_from_slice(&0u64.to_be_bytes());
+    plist.extend_from_slice(&(table as u64).to_be_bytes());
+    plist
+}
+
+/// An object marker with its length, which from 15 on follows as an integer.
+fn push_marker(plist: &mut Vec<u8>, marker: u8, len: usize) {
+    if len < 15 {
+        plist.push(marker | len as u8);
+    } else if len < 256 {
+        plist.extend_from_slice(&[marker | 0x0f, 0x10, len as u8]);
+    } else {
+        plist.extend_from_slice(&[marker | 0x0f, 0x11]);
+        plist.extend_from_slice(&(len as u16).to_be_bytes());
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn set_xattr(path: &Path, name: &str, value: &[u8]) -> Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new(name)?;
+    // SAFETY: both strings are NUL terminated and `value` outlives the call.
+    let status = unsafe {
+        libc::setxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+            0,
+        )
+    };
+    if status != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn set_xattr(_path: &Path, _name: &str, _value: &[u8]) -> Result<()> {
+    anyhow::bail!("Finder tags are only supported on macOS")
+}
+
+/// Writes `keywords` into the XMP of the JPEG or PNG at `path`, keeping the
+/// rest of its metadata. Other formats are left alone.
+fn embed_keywords(path: &Path, keywords: &[String]) -> Result<bool> {
+    let bytes = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+    let packet = xmp::with_keywords(xmp::extract(&bytes)?.as_deref(), keywords);
+    let Some(tagged) = xmp::embed(bytes, &packet)? else {
+        return Ok(false);
+    };
+    fs::write(path, tagged).with_context(|| format!("Could not write {}", path.display()))?;
+    Ok(true)
+}
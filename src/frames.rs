@@ -0,0 +1,231 @@
+use std::fmt;
+
+/// Most pages of a TIFF followed, so a looping chain of directories in a
+/// broken file cannot keep the count going.
+const MAX_PAGES: usize = 1024;
+
+/// How many pictures an image file holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frames {
+    /// One picture, or a format this does not look into.
+    Single,
+    /// A multi-page TIFF, of which OpenCV reads the first page.
+    Pages(usize),
+    /// An animated GIF or WebP, whose frames are no sheet to crop.
+    Animation(usize),
+}
+
+/// The error a source fails with when it turns out to be an animation, so
+/// batches can report it as skipped rather than failed.
+#[derive(Debug)]
+pub struct Animated {
+    pub frames: usize,
+}
+
+impl fmt::Display for Animated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "animated image with {} frames", self.frames)
+    }
+}
+
+impl std::error::Error for Animated {}
+
+/// Counts the frames of the GIF, WebP or TIFF in `bytes` from their block
+/// structure, without decoding any pixels.
+pub fn inspect(bytes: &[u8]) -> Frames {
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        match gif_frames(bytes) {
+            n if n > 1 => Frames::Animation(n),
+            _ => Frames::Single,
+        }
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        match webp_frames(bytes) {
+            n if n > 1 => Frames::Animation(n),
+            _ => Frames::Single,
+        }
+    } else if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        match tiff_pages(bytes) {
+            n if n > 1 => Frames::Pages(n),
+            _ => Frames::Single,
+        }
+    } else {
+        Frames::Single
+    }
+}
+
+/// Image descriptors in a GIF, up to the trailer or where the file breaks off.
+fn gif_frames(bytes: &[u8]) -> usize {
+    let mut frames = 0;
+    let Some(&packed) = bytes.get(10) else {
+        return frames;
+    };
+    let mut at = Some(13 + colour_table(packed));
+    while let Some(block) = at.and_then(|at| bytes.get(at).map(|b| (at, *b))) {
+        at = match block {
+            // Image descriptor, an optional local colour table, the LZW
+            // code size and the image data.
+            (at, 0x2c) => {
+                frames += 1;
+                bytes
+                    .get(at + 9)
+                    .and_then(|&packed| skip_sub_blocks(bytes, at + 11 + colour_table(packed)))
+            }
+            // Extension: its label, then data sub-blocks.
+            (at, 0x21) => skip_sub_blocks(bytes, at + 2),
+            // The trailer, or something that is no GIF block.
+            _ => None,
+        };
+    }
+    frames
+}
+
+/// Bytes of the colour table a GIF descriptor's `packed` field announces.
+fn colour_table(packed: u8) -> usize {
+    if packed & 0x80 == 0 {
+        0
+    } else {
+        3 << ((packed & 0x07) + 1)
+    }
+}
+
+/// Offset past a run of GIF data sub-blocks starting at `at`.
+fn skip_sub_blocks(bytes: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        let len = *bytes.get(at)? as usize;
+        at += 1 + len;
+        if len == 0 {
+            return Some(at);
+        }
+    }
+}
+
+/// `ANMF` chunks of an animated WebP; a still one has none.
+fn webp_frames(bytes: &[u8]) -> usize {
+    let mut at = 12;
+    let mut frames = 0;
+    while let Some(header) = bytes.get(at..at + 8) {
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        if &header[..4] == b"ANMF" {
+            frames += 1;
+        }
+        // Chunks are padded to an even length.
+        at += 8 + len + len % 2;
+    }
+    frames
+}
+
+/// Image file directories chained in a TIFF.
+fn tiff_pages(bytes: &[u8]) -> usize {
+    let big_endian = bytes.starts_with(b"MM");
+    let u16_at = |at: usize| -> Option<usize> {
+        let b: [u8; 2] = bytes.get(at..at + 2)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        } as usize)
+    };
+    let u32_at = |at: usize| -> Option<usize> {
+        let b: [u8; 4] = bytes.get(at..at + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        } as usize)
+    };
+    let mut pages = 0;
+    let mut ifd = u32_at(4);
+    // A directory the file breaks off before is no page.
+    while let Some(at) = ifd.filter(|&at| at != 0)
+        && let Some(entries) = u16_at(at)
+        && pages < MAX_PAGES
+    {
+        pages += 1;
+        ifd = u32_at(at + 2 + entries * 12);
+    }
+    pages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A GIF89a of 1×1 frames without colour tables, each after a graphic
+    /// control extension.
+    fn gif(frames: usize) -> Vec<u8> {
+        let mut bytes = b"GIF89a".to_vec();
+        bytes.extend([1, 0, 1, 0, 0, 0, 0]);
+        for _ in 0..frames {
+            bytes.extend([0x21, 0xf9, 4, 0, 10, 0, 0, 0]);
+            bytes.extend([0x2c, 0, 0, 0, 0, 1, 0, 1, 0, 0]);
+            bytes.extend([2, 2, 0x44, 0x01, 0]);
+        }
+        bytes.push(0x3b);
+        bytes
+    }
+
+    /// A WebP whose chunks are named `chunks`, each with `len` bytes of
+    /// content.
+    fn webp(chunks: &[(&[u8; 4], u32)]) -> Vec<u8> {
+        let mut body = b"WEBP".to_vec();
+        for (name, len) in chunks {
+            body.extend(*name);
+            body.extend(len.to_le_bytes());
+            body.extend(vec![0; (*len + *len % 2) as usize]);
+        }
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend((body.len() as u32).to_le_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    /// A little-endian TIFF of one-entry directories, each pointing to the
+    /// next as `next` says: `None` ends the chain.
+    fn tiff(next: &[Option<u32>]) -> Vec<u8> {
+        let mut bytes = b"II*\0".to_vec();
+        bytes.extend(8u32.to_le_bytes());
+        for next in next {
+            bytes.extend(1u16.to_le_bytes());
+            bytes.extend([0; 12]);
+            bytes.extend(next.unwrap_or(0).to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn animated_gif() {
+        assert_eq!(inspect(&gif(2)), Frames::Animation(2));
+    }
+
+    #[test]
+    fn still_gif() {
+        assert_eq!(inspect(&gif(1)), Frames::Single);
+    }
+
+    #[test]
+    fn animated_webp() {
+        let bytes = webp(&[(b"VP8X", 10), (b"ANIM", 6), (b"ANMF", 17), (b"ANMF", 16)]);
+        assert_eq!(inspect(&bytes), Frames::Animation(2));
+    }
+
+    #[test]
+    fn still_webp() {
+        assert_eq!(inspect(&webp(&[(b"VP8 ", 20)])), Frames::Single);
+    }
+
+    #[test]
+    fn multi_page_tiff() {
+        // Directories are 18 bytes, so the second starts at 26.
+        assert_eq!(inspect(&tiff(&[Some(26), None])), Frames::Pages(2));
+    }
+
+    #[test]
+    fn truncated_tiff_chain() {
+        assert_eq!(inspect(&tiff(&[Some(4000)])), Frames::Single);
+    }
+
+    #[test]
+    fn looping_tiff_chain() {
+        assert_eq!(inspect(&tiff(&[Some(8)])), Frames::Pages(MAX_PAGES));
+    }
+}
@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use opencv::core::{self, AlgorithmHint, Mat, Point, Point2f, Scalar, Size};
+use opencv::imgproc;
+use opencv::prelude::*;
+
+/// Brightness (HSV value) from which a pixel may be glare: a sleeve's
+/// reflection drives the sensor to clipping.
+const MIN_VALUE: f64 = 250.0;
+/// Highest saturation of glare, which washes out whatever colour is below.
+const MAX_SATURATION: f64 = 30.0;
+/// Share of each side left out when measuring a crop, where white print
+/// borders would pass for glare.
+const BORDER: f32 = 0.1;
+/// Share of a crop's inside that must be glare before it is reported.
+const MIN_SHARE: f32 = 0.005;
+/// Longest side the scans are shrunk to for aligning them.
+const ALIGN_SIDE: f64 = 1024.0;
+/// Pixels the glare mask grows by before blending, to cover its fringe.
+const FEATHER: i32 = 15;
+
+/// Share of the inside of the crop `image` (8-bit BGR) covered by glare,
+/// or `None` when it is too little to matter.
+///
+/// Glare is told by being clipped bright and washed out; a white area in the
+/// picture itself, such as a sky or a wedding dress, may pass for it too.
+pub fn measure(image: &Mat) -> Result<Option<f32>> {
+    let (w, h) = (image.cols(), image.rows());
+    let (dx, dy) = ((w as f32 * BORDER) as i32, (h as f32 * BORDER) as i32);
+    if w - 2 * dx <= 0 || h - 2 * dy <= 0 {
+        return Ok(None);
+    }
+    let inside = Mat::roi(image, core::Rect::new(dx, dy, w - 2 * dx, h - 2 * dy))?;
+    let mask = mask(&inside)?;
+    let share = core::count_non_zero(&mask)? as f32 / mask.total() as f32;
+    Ok((share >= MIN_SHARE).then_some(share))
+}
+
+/// 8-bit mask of the glare in the BGR `image`.
+fn mask(image: &Mat) -> Result<Mat> {
+    let mut hsv = Mat::default();
+    imgproc::cvt_color(
+        image,
+        &mut hsv,
+        imgproc::COLOR_BGR2HSV,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    let mut mask = Mat::default();
+    core::in_range(
+        &hsv,
+        &Scalar::new(0.0, 0.0, MIN_VALUE, 0.0),
+        &Scalar::new(180.0, MAX_SATURATION, 255.0, 0.0),
+        &mut mask,
+    )?;
+    // Single clipped pixels are highlights, not a reflection.
+    let kernel =
+        imgproc::get_structuring_element(imgproc::MORPH_RECT, Size::new(5, 5), Point::new(-1, -1))?;
+    let mut opened = Mat::default();
+    imgproc::morphology_ex(
+        &mask,
+        &mut opened,
+        imgproc::MORPH_OPEN,
+        &kernel,
+        Point::new(-1, -1),
+        1,
+        core::BORDER_CONSTANT,
+        Scalar::all(0.0),
+    )?;
+    Ok(opened)
+}
+
+/// The second scan of the sheet at `source`: `scan_b.tif` for `scan.tif`
+/// with the suffix `_b`.
+pub fn second_scan_path(source: &Path, suffix: &str) -> PathBuf {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{stem}{suffix}");
+    if let Some(extension) = source.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    source.with_file_name(name)
+}
+
+/// Whether `file` is the second scan of another file beside it.
+pub fn is_second_scan(file: &Path, suffix: &str) -> bool {
+    let Some(stem) = file.file_stem().and_then(|s| s.to_str()) else {
+        return false;
+    };
+    let Some(first) = stem.strip_suffix(suffix).filter(|s| !s.is_empty()) else {
+        return false;
+    };
+    let mut name = first.to_string();
+    if let Some(extension) = file.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    file.with_file_name(name).is_file()
+}
+
+/// Merges two scans of the same sheet so the glare of `first` is filled
+/// in from `second`, e.g. a rescan with the sheet turned around or the lid
+/// lit from the other side, where the reflections fall elsewhere.
+///
+/// `second` may be shifted or turned half a turn against `first`; it is
+/// aligned by phase correlation, trying both ways round. Where `first` is
+/// clear it is kept as it is, and the seams are feathered.
+pub fn fuse(first: &Mat, second: &Mat) -> Result<Mat> {
+    let aligned = align(first, second)?;
+    let glare = mask(first)?;
+    let kernel = imgproc::get_structuring_element(
+        imgproc::MORPH_ELLIPSE,
+        Size::new(2 * FEATHER + 1, 2 * FEATHER + 1),
+        Point::new(-1, -1),
+    )?;
+    let mut grown = Mat::default();
+    imgproc::dilate(
+        &glare,
+        &mut grown,
+        &kernel,
+        Point::new(-1, -1),
+        1,
+        core::BORDER_CONSTANT,
+        Scalar::all(0.0),
+    )?;
+    let mut weight = Mat::default();
+    grown.convert_to(&mut weight, core::CV_32F, 1.0 / 255.0, 0.0)?;
+    let mut soft = Mat::default();
+    imgproc::gaussian_blur(
+        &weight,
+        &mut soft,
+        Size::new(2 * FEATHER + 1, 2 * FEATHER + 1),
+        0.0,
+        0.0,
+        core::BORDER_DEFAULT,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    let mut keep = Mat::default();
+    core::subtract(&Scalar::all(1.0), &soft, &mut keep, &core::no_array(), -1)?;
+    let mut fused = Mat::default();
+    imgproc::blend_linear(first, &aligned, &keep, &soft, &mut fused)?;
+    Ok(fused)
+}
+
+/// `second` moved onto `first`'s pixel grid.
+fn align(first: &Mat, second: &Mat) -> Result<Mat> {
+    let scale = (ALIGN_SIDE / first.cols().max(first.rows()) as f64).min(1.0);
+    let size = Size::new(
+        ((first.cols() as f64 * scale).round() as i32).max(1),
+        ((first.rows() as f64 * scale).round() as i32).max(1),
+    );
+    let small_first = small_gray(first, size)?;
+    let small_second = small_gray(second, size)?;
+    let mut turned = Mat::default();
+    core::rotate(&small_second, &mut turned, core::ROTATE_180)?;
+
+    let (mut straight, mut around) = (0.0, 0.0);
+    let shift_straight = imgproc::phase_correlate(
+        &small_first,
+        &small_second,
+        &core::no_array(),
+        &mut straight,
+    )?;
+    let shift_around =
+        imgproc::phase_correlate(&small_first, &turned, &core::no_array(), &mut around)?;
+    let (angle, shift) = if around > straight {
+        (180.0, shift_around)
+    } else {
+        (0.0, shift_straight)
+    };
+    tracing::debug!(angle, dx = shift.x, dy = shift.y, "aligned second scan");
+
+    // Turn about the second scan's centre, place that on the first's and
+    // undo the measured shift, scaled back up from the shrunk scans.
+    let from = Point2f::new(
+        (second.cols() - 1) as f32 / 2.0,
+        (second.rows() - 1) as f32 / 2.0,
+    );
+    let to = Point2f::new(
+        (first.cols() - 1) as f32 / 2.0,
+        (first.rows() - 1) as f32 / 2.0,
+    );
+    let mut transform = imgproc::get_rotation_matrix_2d(from, angle, 1.0)?;
+    *transform.at_2d_mut::<f64>(0, 2)? += (to.x - from.x) as f64 - shift.x / scale;
+    *transform.at_2d_mut::<f64>(1, 2)? += (to.y - from.y) as f64 - shift.y / scale;
+    let mut aligned = Mat::default();
+    imgproc::warp_affine(
+        second,
+        &mut aligned,
+        &transform,
+        first.size()?,
+        imgproc::INTER_LINEAR,
+        core::BORDER_REPLICATE,
+        Scalar::all(0.0),
+    )?;
+    Ok(aligned)
+}
+
+/// `image` as 32-bit grey of `size`, as phase correlation takes it.
+fn small_gray(image: &Mat, size: Size) -> Result<Mat> {
+    let mut gray = Mat::default();
+    imgproc::cvt_color(
+        image,
+        &mut gray,
+        imgproc::COLOR_BGR2GRAY,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    let mut small = Mat::default();
+    imgproc::resize(&gray, &mut small, size, 0.0, 0.0, imgproc::INTER_AREA)?;
+    let mut float = Mat::default();
+    small.convert_to(&mut float, core::CV_32F, 1.0, 0.0)?;
+    Ok(float)
+}
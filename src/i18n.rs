@@ -0,0 +1,113 @@
+use std::env;
+use std::sync::OnceLock;
+
+use fluent::concurrent::FluentBundle;
+use fluent::{FluentArgs, FluentResource};
+use unic_langid::LanguageIdentifier;
+
+pub use fluent::FluentValue;
+
+/// Language of console messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Language {
+    #[default]
+    En,
+    Sv,
+}
+
+impl Language {
+    /// Language from `LC_ALL`, `LC_MESSAGES` or `LANG`, English when none
+    /// names a supported one.
+    pub fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        if locale.to_lowercase().starts_with("sv") {
+            Language::Sv
+        } else {
+            Language::En
+        }
+    }
+
+    fn source(self) -> &'static str {
+        match self {
+            Language::En => include_str!("../locales/en.ftl"),
+            Language::Sv => include_str!("../locales/sv.ftl"),
+        }
+    }
+
+    fn id(self) -> LanguageIdentifier {
+        let tag = match self {
+            Language::En => "en",
+            Language::Sv => "sv",
+        };
+        tag.parse().expect("built-in language tags are valid")
+    }
+}
+
+struct Bundles {
+    selected: FluentBundle<FluentResource>,
+    /// English, for messages the selected language lacks.
+    fallback: FluentBundle<FluentResource>,
+}
+
+impl Bundles {
+    fn load(language: Language) -> Self {
+        Self {
+            selected: bundle(language),
+            fallback: bundle(Language::En),
+        }
+    }
+}
+
+static BUNDLES: OnceLock<Bundles> = OnceLock::new();
+
+/// Selects the language of [`message`]. Only the first call has an effect;
+/// without one the language is taken from the environment.
+pub fn init(language: Language) {
+    BUNDLES.get_or_init(|| Bundles::load(language));
+}
+
+fn bundle(language: Language) -> FluentBundle<FluentResource> {
+    let resource = FluentResource::try_new(language.source().to_string())
+        .unwrap_or_else(|(resource, _)| resource);
+    let mut bundle = FluentBundle::new_concurrent(vec![language.id()]);
+    // Console output has no use for bidi isolation marks.
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .expect("built-in messages have unique ids");
+    bundle
+}
+
+/// Message `id` in the selected language, with `args` filled in. Falls back
+/// to English, and to the id itself for unknown messages.
+pub fn message(id: &str, args: &[(&str, FluentValue)]) -> String {
+    let bundles = BUNDLES.get_or_init(|| Bundles::load(Language::from_env()));
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.clone());
+    }
+    for bundle in [&bundles.selected, &bundles.fallback] {
+        if let Some(pattern) = bundle.get_message(id).and_then(|m| m.value()) {
+            let mut errors = Vec::new();
+            return bundle
+                .format_pattern(pattern, Some(&fluent_args), &mut errors)
+                .into_owned();
+        }
+    }
+    id.to_string()
+}
+
+/// Localized console text: `tr!("found-photos", count = 3)`.
+#[macro_export]
+macro_rules! tr {
+    ($id:literal $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::message(
+            $id,
+            &[$((stringify!($name), $crate::i18n::FluentValue::from($value))),*],
+        )
+    };
+}
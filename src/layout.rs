@@ -0,0 +1,248 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail, ensure};
+use opencv::core::{Point2f, Rect, Size};
+use serde::{Deserialize, Serialize};
+
+use crate::detect::Quad;
+
+/// Fixed arrangement of equally sized photos, written `<columns>x<rows>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Grid {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+/// Part of a sheet, written `x,y,w,h` with each value in pixels or, with a
+/// `%` suffix, relative to the sheet's width or height.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Region {
+    pub x: Extent,
+    pub y: Extent,
+    pub width: Extent,
+    pub height: Extent,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Extent {
+    Pixels(f32),
+    Percent(f32),
+}
+
+impl Extent {
+    fn resolve(self, length: i32) -> i32 {
+        match self {
+            Extent::Pixels(px) => px.round() as i32,
+            Extent::Percent(pct) => (length as f32 * pct / 100.0).round() as i32,
+        }
+    }
+
+    fn scaled(self, scale: f32) -> Self {
+        match self {
+            Extent::Pixels(px) => Extent::Pixels(px * scale),
+            percent => percent,
+        }
+    }
+}
+
+/// Named page design with the photo slots every page of an album shares.
+///
+/// Slots are quads in coordinates relative to the sheet (0 to 1 on both axes),
+/// ordered top-left, top-right, bottom-right, bottom-left like [`Quad`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Layout {
+    pub name: String,
+    pub slots: Vec<[[f32; 2]; 4]>,
+}
+
+impl Layout {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read layout {}", path.display()))?;
+        let layout: Layout = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid layout {}", path.display()))?;
+        ensure!(
+            !layout.slots.is_empty(),
+            "Layout {} has no slots",
+            path.display()
+        );
+        for slot in &layout.slots {
+            ensure!(
+                slot.iter().flatten().all(|v| (0.0..=1.0).contains(v)),
+                "Layout {} has slot coordinates outside 0..1",
+                path.display()
+            );
+        }
+        Ok(layout)
+    }
+
+    /// Slot quads in pixels for an image of `size`.
+    pub fn slots(&self, size: Size) -> Vec<Quad> {
+        let (w, h) = (size.width as f32, size.height as f32);
+        self.slots
+            .iter()
+            .map(|slot| slot.map(|[x, y]| Point2f::new(x * w, y * h)))
+            .collect()
+    }
+}
+
+impl Grid {
+    /// Cell quads for an image of `size`, row by row from the top left, each
+    /// shrunk by `margin` pixels on every side.
+    pub fn cells(&self, size: Size, margin: i32) -> Vec<Quad> {
+        let cell_w = size.width as f32 / self.columns as f32;
+        let cell_h = size.height as f32 / self.rows as f32;
+        // Never shrink a cell below a few pixels.
+        let margin = (margin.max(0) as f32)
+            .min(cell_w.min(cell_h) / 2.0 - 1.0)
+            .max(0.0);
+
+        let mut cells = Vec::with_capacity((self.columns * self.rows) as usize);
+        for row in 0..self.rows {
+            for col in 0..self.columns {
+                let x0 = col as f32 * cell_w + margin;
+                let y0 = row as f32 * cell_h + margin;
+                let x1 = (col + 1) as f32 * cell_w - margin;
+                let y1 = (row + 1) as f32 * cell_h - margin;
+                cells.push([
+                    Point2f::new(x0, y0),
+                    Point2f::new(x1, y0),
+                    Point2f::new(x1, y1),
+                    Point2f::new(x0, y1),
+                ]);
+            }
+        }
+        cells
+    }
+}
+
+impl Region {
+    /// Pixel rectangle for an image of `size`, clipped to the image and at
+    /// least one pixel large.
+    pub fn rect(&self, size: Size) -> Rect {
+        let x = self.x.resolve(size.width).clamp(0, size.width - 1);
+        let y = self.y.resolve(size.height).clamp(0, size.height - 1);
+        let width = self.width.resolve(size.width).clamp(1, size.width - x);
+        let height = self.height.resolve(size.height).clamp(1, size.height - y);
+        Rect::new(x, y, width, height)
+    }
+
+    /// The same region on a copy of the image resized by `scale`.
+    pub fn scaled(&self, scale: f32) -> Self {
+        Self {
+            x: self.x.scaled(scale),
+            y: self.y.scaled(scale),
+            width: self.width.scaled(scale),
+            height: self.height.scaled(scale),
+        }
+    }
+}
+
+impl FromStr for Extent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (number, percent) = match s.strip_suffix('%') {
+            Some(number) => (number, true),
+            None => (s, false),
+        };
+        let value: f32 = number
+            .trim()
+            .parse()
+            .ok()
+            .filter(|v: &f32| v.is_finite() && *v >= 0.0)
+            .with_context(|| format!("Invalid value \"{s}\""))?;
+        Ok(if percent {
+            Extent::Percent(value)
+        } else {
+            Extent::Pixels(value)
+        })
+    }
+}
+
+impl fmt::Display for Extent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Extent::Pixels(px) => write!(f, "{px}"),
+            Extent::Percent(pct) => write!(f, "{pct}%"),
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let values: Vec<&str> = s.split(',').collect();
+        let [x, y, width, height] = values[..] else {
+            bail!("Invalid region \"{s}\" (expected x,y,w,h, e.g. 0,0,90%,100%)");
+        };
+        Ok(Self {
+            x: x.parse()?,
+            y: y.parse()?,
+            width: width.parse()?,
+            height: height.parse()?,
+        })
+    }
+}
+
+impl TryFrom<String> for Region {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Region> for String {
+    fn from(region: Region) -> Self {
+        region.to_string()
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl FromStr for Grid {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parsed = s
+            .trim()
+            .split_once(['x', 'X', '×'])
+            .and_then(|(c, r)| Some((c.trim().parse().ok()?, r.trim().parse().ok()?)));
+        match parsed {
+            Some((columns, rows)) if columns > 0 && rows > 0 => Ok(Self { columns, rows }),
+            _ => bail!("Invalid grid \"{s}\" (expected <columns>x<rows>, e.g. 2x2)"),
+        }
+    }
+}
+
+impl TryFrom<String> for Grid {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Grid> for String {
+    fn from(grid: Grid) -> Self {
+        grid.to_string()
+    }
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.columns, self.rows)
+    }
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, ensure};
+use opencv::calib3d;
+use opencv::core::{Mat, Size};
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Lens model of the camera that photographed the pages, used to straighten
+/// barrel or pincushion distortion before detection.
+///
+/// Coefficients follow OpenCV's order `k1, k2[, p1, p2[, k3]]`. Without a
+/// camera matrix the optical centre is assumed in the middle of the image
+/// with a focal length equal to its longer side, which suits plain `k1, k2`
+/// values such as those published in lens profiles.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LensProfile {
+    /// 3x3 intrinsic matrix from a camera calibration, row by row, in pixels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera_matrix: Option<[[f64; 3]; 3]>,
+    pub dist_coeffs: Vec<f64>,
+}
+
+impl LensProfile {
+    /// Reads a calibration file: JSON with `camera_matrix` and `dist_coeffs`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read lens profile {}", path.display()))?;
+        let profile: LensProfile = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid lens profile {}", path.display()))?;
+        profile
+            .validate()
+            .with_context(|| format!("Invalid lens profile {}", path.display()))?;
+        Ok(profile)
+    }
+
+    /// Profile from bare radial coefficients, e.g. `--lens-k -0.12,0.03`.
+    pub fn from_coefficients(coefficients: Vec<f64>) -> Self {
+        Self {
+            camera_matrix: None,
+            dist_coeffs: coefficients,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            matches!(self.dist_coeffs.len(), 1 | 2 | 4 | 5),
+            "Expected 1, 2, 4 or 5 distortion coefficients (k1, k2[, p1, p2[, k3]]), got {}",
+            self.dist_coeffs.len()
+        );
+        Ok(())
+    }
+
+    /// Returns `image` with the lens distortion removed, at the same size.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub fn undistort(&self, image: &Mat) -> Result<Mat> {
+        self.validate()?;
+        let camera = Mat::from_slice_2d(&self.camera_matrix_for(image.size()?))?;
+        // OpenCV wants at least four coefficients; missing ones are zero.
+        let mut coefficients = self.dist_coeffs.clone();
+        coefficients.resize(coefficients.len().max(4), 0.0);
+        let coefficients = Mat::from_slice(&coefficients)?;
+
+        let mut undistorted = Mat::default();
+        calib3d::undistort(image, &mut undistorted, &camera, &coefficients, &camera)?;
+        Ok(undistorted)
+    }
+
+    fn camera_matrix_for(&self, size: Size) -> [[f64; 3]; 3] {
+        self.camera_matrix.unwrap_or_else(|| {
+            let (w, h) = (size.width as f64, size.height as f64);
+            let focal = w.max(h);
+            [
+                [focal, 0.0, w / 2.0],
+                [0.0, focal, h / 2.0],
+                [0.0, 0.0, 1.0],
+            ]
+        })
+    }
+}
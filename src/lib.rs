@@ -0,0 +1,61 @@
+//! Finds individual photos on scanned sheets and straightens them.
+//!
+//! The CLI is a thin layer over [`PhotoCropper`]; the [`manifest`] module holds
+//! the geometry format shared between its pipeline stages.
+
+pub mod advice;
+pub mod albums;
+pub mod alpha;
+pub mod batches;
+pub mod bench;
+pub mod cancel;
+pub mod colorspace;
+pub mod config;
+pub mod dating;
+pub mod dedup;
+pub mod detcache;
+pub mod detect;
+pub mod deterministic;
+pub mod diskspace;
+pub mod enhance;
+pub mod era;
+pub mod exif;
+pub mod filetags;
+pub mod frames;
+pub mod glare;
+pub mod hash;
+pub mod i18n;
+pub mod layout;
+pub mod lens;
+pub mod logfile;
+pub mod manifest;
+pub mod montage;
+pub mod names;
+pub mod notify;
+pub mod orientation;
+pub mod paths;
+pub mod pipeline;
+pub mod prescale;
+pub mod probe;
+pub mod progress;
+pub mod reject;
+pub mod ruler;
+pub mod server;
+pub mod settle;
+pub mod sizes;
+pub mod skew;
+pub mod tagmap;
+pub mod telemetry;
+mod textlike;
+#[cfg(feature = "tune")]
+pub mod tune;
+pub mod video;
+pub mod xmp;
+
+mod cropper;
+mod pairs;
+mod quadfit;
+
+pub use cancel::{CancelToken, Cancelled};
+pub use cropper::{DetectIter, DetectedPhoto, LazyWarp, PhotoCropper};
+pub use detect::{DetectParams, Interpolation, Quad};
@@ -0,0 +1,110 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use crate::manifest;
+
+/// Start of the name of every log file, so only those are ever pruned.
+const PREFIX: &str = "photo-cropper-";
+/// Size a log file grows to before the run continues in a new part.
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+/// Log files kept in the directory; the oldest are deleted as runs start.
+const KEEP_FILES: usize = 50;
+
+/// The log file of one run in `--log-dir`, named after its start time (and
+/// the run's name, when it has one) and rolled over to a new part once it
+/// reaches [`MAX_FILE_SIZE`].
+pub struct LogFile {
+    dir: PathBuf,
+    stem: String,
+    part: usize,
+    file: File,
+    written: u64,
+}
+
+impl LogFile {
+    /// Opens the log of a run starting now in `dir`, first deleting all but
+    /// the newest [`KEEP_FILES`] earlier logs.
+    pub fn create(dir: &Path, run_name: Option<&str>) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Could not create the log directory {}", dir.display()))?;
+        prune(dir)?;
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut stem = format!("{PREFIX}{}", manifest::timestamp_id(secs));
+        if let Some(name) = run_name {
+            stem = format!("{stem}-{name}");
+        }
+        let (file, written) = open(&dir.join(format!("{stem}.log")))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            stem,
+            part: 1,
+            file,
+            written,
+        })
+    }
+
+    fn roll_over(&mut self) -> io::Result<()> {
+        self.part += 1;
+        let path = self.dir.join(format!("{}.{}.log", self.stem, self.part));
+        (self.file, self.written) = open(&path).map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+impl Write for LogFile {
+    /// Writes one formatted event; an event never straddles two parts.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > MAX_FILE_SIZE {
+            self.roll_over()?;
+        }
+        self.file.write_all(buf)?;
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// `path` opened for appending, with its current length.
+fn open(path: &Path) -> Result<(File, u64)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Could not open the log file {}", path.display()))?;
+    let len = file.metadata()?.len();
+    Ok((file, len))
+}
+
+/// Deletes the oldest log files in `dir` so that, with the one about to be
+/// opened, at most [`KEEP_FILES`] remain.
+fn prune(dir: &Path) -> Result<()> {
+    let mut logs: Vec<(SystemTime, PathBuf)> = fs::read_dir(dir)
+        .with_context(|| format!("Could not read the log directory {}", dir.display()))?
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with(PREFIX) && name.ends_with(".log")
+        })
+        .filter_map(|e| {
+            let modified = e.metadata().ok()?.modified().ok()?;
+            Some((modified, e.path()))
+        })
+        .collect();
+    logs.sort();
+    let excess = (logs.len() + 1).saturating_sub(KEEP_FILES);
+    for (_, path) in logs.into_iter().take(excess) {
+        fs::remove_file(&path)
+            .with_context(|| format!("Could not delete the old log file {}", path.display()))?;
+    }
+    Ok(())
+}
@@ -1,344 +1,4776 @@
-use std::fs;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use std::{env, fs, io, mem, thread};
 
 use anyhow::{Context, Result};
-use clap::Parser;
-use opencv::core::{
-    self, AlgorithmHint, BORDER_REPLICATE, Mat, Point, Point2f, Scalar, Size, Vector,
-};
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use opencv::core::{Mat, Point, Point2f, Scalar, Size, Vector};
 use opencv::imgcodecs;
-use opencv::imgproc::{self, InterpolationFlags};
+use opencv::imgproc;
 use opencv::prelude::*;
 use walkdir::WalkDir;
 
+use photo_cropper::advice::{self, Advice};
+use photo_cropper::albums::{self, AlbumMapping};
+use photo_cropper::alpha;
+use photo_cropper::batches;
+use photo_cropper::bench::{self, Sample};
+use photo_cropper::colorspace::{self, Conversion};
+use photo_cropper::config::{self, ParamOverrides, ParamResolver};
+use photo_cropper::dating::{DateEstimate, DateModel};
+use photo_cropper::dedup;
+use photo_cropper::detcache::DetectionCache;
+use photo_cropper::detect;
+use photo_cropper::detect::{
+    CannyMode, ContourMode, Interpolation, PhotoShape, PrintShape, RejectReason,
+};
+use photo_cropper::deterministic;
+use photo_cropper::diskspace;
+use photo_cropper::enhance::{self, Levels, MaskFill};
+use photo_cropper::era::Era;
+use photo_cropper::exif::{self, ExifBuilder};
+use photo_cropper::filetags;
+use photo_cropper::frames::{self, Animated, Frames};
+use photo_cropper::glare;
+use photo_cropper::hash;
+use photo_cropper::i18n::{self, Language};
+use photo_cropper::layout::{Grid, Layout, Region};
+use photo_cropper::lens::LensProfile;
+use photo_cropper::logfile::LogFile;
+use photo_cropper::manifest::{
+    self, DetectPass, Manifest, Photo, Rejected, ReviewStatus, RunRecord, Sheet,
+};
+use photo_cropper::montage::{self, PageLayout, Paper};
+use photo_cropper::names;
+use photo_cropper::notify;
+use photo_cropper::orientation::{self, Rotation};
+use photo_cropper::paths;
+use photo_cropper::pipeline::{self, Pipeline};
+use photo_cropper::prescale::PrescaleCache;
+use photo_cropper::probe;
+use photo_cropper::progress::Progress;
+use photo_cropper::reject::RejectList;
+use photo_cropper::ruler;
+use photo_cropper::server;
+use photo_cropper::settle::{self, NotReady, Readiness};
+use photo_cropper::sizes::{PrintSize, SizeAction};
+use photo_cropper::skew;
+use photo_cropper::tagmap::{MappedTags, TagMap};
+use photo_cropper::telemetry;
+use photo_cropper::tr;
+use photo_cropper::video;
+use photo_cropper::xmp::{self, Triage};
+use photo_cropper::{DetectIter, DetectParams, PhotoCropper, Quad};
+
+/// File name of the manifest written by `run` into the output directory.
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Grid swept by `bench` when no values are given.
+const DEFAULT_MIN_AREAS: [f64; 3] = [10_000.0, 20_000.0, 40_000.0];
+const DEFAULT_CANNY_LOWS: [f64; 3] = [30.0, 50.0, 80.0];
+
 #[derive(Parser, Debug)]
 #[command(
     version,
-    about = "Extracts individual photos from scanned sheets and saves them straightened."
+    about = "Extracts individual photos from scanned sheets and saves them straightened.",
+    after_help = "Arguments can also be read from a file with @path (one argument per line)."
 )]
-struct Args {
-    /// Directory containing input images
-    input_dir: PathBuf,
-    /// Directory where cropped images will be written
-    output_dir: PathBuf,
-    /// Minimum contour area to consider as a photo (in pixels)
-    #[arg(long, default_value_t = 20_000.0)]
-    min_area: f64,
-    /// Padding (in pixels) added around the image before detection to catch edge-touching photos
-    #[arg(long, default_value_t = 12)]
-    pad: i32,
-    /// Canny low threshold (raise to be less sensitive, lower to be more sensitive)
-    #[arg(long, default_value_t = 50.0)]
-    canny_low: f64,
-    /// Canny high threshold (must be > low; defaults to 3x low if not set)
-    #[arg(long, default_value_t = 150.0)]
-    canny_high: f64,
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Export tracing spans to this OpenTelemetry collector (OTLP/HTTP, e.g. http://localhost:4318)
+    #[arg(long, global = true, value_name = "URL")]
+    otlp_endpoint: Option<String>,
+    /// Keep a JSON file with the progress of the batch up to date (also printed on SIGUSR1)
+    #[arg(long, global = true, value_name = "PATH")]
+    status_file: Option<PathBuf>,
+    /// Language of console messages [default: from LANG]
+    #[arg(long, global = true, value_enum)]
+    lang: Option<Language>,
+    /// POST a JSON summary of the batch to this URL when it finishes or fails
+    #[arg(long, global = true, value_name = "URL")]
+    notify_webhook: Option<String>,
+    /// Id of this run in the manifest and for --run-subfolder [default: start time, e.g. 20260314-091500]
+    #[arg(long, global = true, value_name = "NAME", value_parser = parse_run_name)]
+    run_name: Option<String>,
+    /// Produce byte-identical output on every run, for golden-file tests: one worker per stage, OpenCV on one thread without its CPU-specific optimizations, and the run recorded as starting at the Unix epoch
+    #[arg(long, global = true)]
+    deterministic: bool,
+    /// Also write a debug-level log of each run to a timestamped file in this directory, keeping the newest 50
+    #[arg(long, global = true, value_name = "DIR")]
+    log_dir: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Detect and crop in one pass, writing crops and a manifest to the output directory
+    Run {
+        /// Directory containing input images
+        #[arg(required_unless_present = "tar")]
+        input_dir: Option<PathBuf>,
+        /// Directory where cropped images will be written
+        #[arg(required_unless_present_any = ["in_place", "tar"])]
+        output_dir: Option<PathBuf>,
+        /// Write each sheet's crops next to it and the manifest into input_dir; crops from earlier runs are never taken for sheets
+        #[arg(long, conflicts_with = "output_dir")]
+        in_place: bool,
+        /// Read the sheets as a tar stream on stdin and write the crops and manifest as a tar stream to stdout, keeping nothing on disk afterwards
+        #[arg(long, conflicts_with_all = ["input_dir", "output_dir", "in_place", "incremental"])]
+        tar: bool,
+        /// Skip sources whose content and detection parameters match the previous run's manifest
+        #[arg(long)]
+        incremental: bool,
+        /// Write crops and manifest into a subfolder of output_dir named after the run id, so experimental runs never collide
+        #[arg(long)]
+        run_subfolder: bool,
+        /// Split the sheets into numbered review batches of N, each with its own manifest, overlays and HTML page, so several people can review in parallel
+        #[arg(long, value_name = "N", conflicts_with = "tar", value_parser = clap::value_parser!(u32).range(1..))]
+        review_batch_size: Option<u32>,
+        #[command(flatten)]
+        detect: DetectArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        pipeline: PipelineArgs,
+    },
+    /// Take one sharp, still frame per page from a video (or a directory of frames) of an album leafed through under a camera, then detect and crop those pages like `run`
+    Video {
+        /// Video file, or a directory of frames named so they sort in order
+        input: PathBuf,
+        /// Directory where the chosen pages (under pages/), crops and manifest will be written
+        output_dir: PathBuf,
+        /// Only look at every Nth frame, for speed on long or high frame rate captures
+        #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        frame_step: u32,
+        #[command(flatten)]
+        detect: DetectArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        pipeline: PipelineArgs,
+    },
+    /// Detect photos and write their geometry to a manifest without cropping
+    Detect {
+        /// Directory containing input images
+        input_dir: PathBuf,
+        /// Manifest file to write
+        manifest: PathBuf,
+        #[command(flatten)]
+        detect: DetectArgs,
+    },
+    /// Crop photos using the geometry stored in a manifest
+    Crop {
+        /// Manifest produced by `detect`
+        manifest: PathBuf,
+        /// Directory where cropped images will be written
+        output_dir: PathBuf,
+        /// When nearly all photos lean by the same small angle (e.g. a misaligned scanning jig), turn them all straight by it
+        #[arg(long)]
+        correct_systematic_skew: bool,
+        /// Write crops into a subfolder of output_dir named after the run id
+        #[arg(long)]
+        run_subfolder: bool,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Draw the detected photos onto each sheet for visual inspection, or record the review's outcome
+    Review {
+        /// Manifest produced by `detect` or `run`
+        manifest: PathBuf,
+        /// Directory where overlay images will be written
+        #[arg(required_unless_present = "mark")]
+        review_dir: Option<PathBuf>,
+        /// Only draw sheets with this review status
+        #[arg(long, value_enum)]
+        only: Option<ReviewStatus>,
+        /// Set the review status of the --sheets in the manifest instead of drawing
+        #[arg(long, value_enum, requires = "sheets", conflicts_with = "review_dir")]
+        mark: Option<ReviewStatus>,
+        /// Source images to mark (as listed in the manifest)
+        #[arg(long, num_args = 1..)]
+        sheets: Vec<PathBuf>,
+    },
+    /// Re-detect selected sheets with new parameters and replace their crops
+    Recrop {
+        /// Manifest produced by `run` or `crop`
+        manifest: PathBuf,
+        /// Source images to redo (as listed in the manifest)
+        #[arg(required = true)]
+        sheets: Vec<PathBuf>,
+        /// Crop a new scan of the one sheet given, e.g. at a higher resolution than the preview it was detected on, with the recorded geometry scaled to it instead of detecting again
+        #[arg(long, value_name = "FILE")]
+        source_rescan: Option<PathBuf>,
+        #[command(flatten)]
+        detect: DetectArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Copy the cropped photos listed in a manifest to a destination directory
+    Export {
+        /// Manifest produced by `run` or `crop`
+        manifest: PathBuf,
+        /// Destination directory
+        dest_dir: PathBuf,
+        /// TOML mapping of source folders to albums: sort crops into Year/Album
+        /// folders and write XMP sidecars for digiKam or Lightroom
+        #[arg(long, value_name = "FILE")]
+        albums: Option<PathBuf>,
+        /// Also show the album, collections and label in the file manager: as Finder tags on macOS, as Explorer's Details tags on Windows (JPEG and PNG)
+        #[arg(long, requires = "albums")]
+        file_tags: bool,
+        /// Start a new numbered subfolder when a part reaches this size (e.g. 4.7G) or number of photos (e.g. 500)
+        #[arg(long, value_name = "SIZE|COUNT", value_parser = parse_split)]
+        split_output_every: Option<SplitEvery>,
+        /// CSV of `sheet,photo,caption` rows (sheet by file name or path as listed in the manifest, photo numbered from 1); each caption is written into the exported crop's description
+        #[arg(long, value_name = "FILE")]
+        captions: Option<PathBuf>,
+    },
+    /// Rename crops after the titles given them in the manifest or a CSV, and write the titles into their metadata
+    ApplyNames {
+        /// Manifest produced by `run` or `crop`
+        manifest: PathBuf,
+        /// CSV of `crop,title` rows (crop by file name or path as listed in the manifest); its titles are stored in the manifest
+        #[arg(long)]
+        csv: Option<PathBuf>,
+        /// Prefix names with a running number, in CSV order or else manifest order
+        #[arg(long)]
+        number: bool,
+        /// Only print the renames
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Arrange chosen crops on printable collage pages for reprints
+    Montage {
+        /// Manifest produced by `run` or `crop`
+        manifest: PathBuf,
+        /// Directory the pages are written to
+        output_dir: PathBuf,
+        /// Crops to include, by file name or path as listed in the manifest [default: all]
+        crops: Vec<PathBuf>,
+        /// Paper size of the pages
+        #[arg(long, value_enum, default_value_t = Paper::A4)]
+        paper: Paper,
+        /// Lay the pages out in landscape instead of portrait
+        #[arg(long)]
+        landscape: bool,
+        /// Photos per page as <columns>x<rows>
+        #[arg(long, default_value = "2x3", value_parser = parse_grid)]
+        grid: Grid,
+        /// Blank border around each page, in millimetres
+        #[arg(long, value_name = "MM", default_value_t = 10.0)]
+        margin: f64,
+        /// Space between photos, in millimetres
+        #[arg(long, value_name = "MM", default_value_t = 5.0)]
+        gap: f64,
+        /// Print resolution of the pages
+        #[arg(long, default_value_t = 300.0)]
+        dpi: f64,
+        /// Order the photos are placed in
+        #[arg(long, value_enum, default_value_t = MontageOrder::Manifest)]
+        order: MontageOrder,
+        /// Turn photos a quarter when that lets them fill more of their cell
+        #[arg(long)]
+        rotate_to_fit: bool,
+    },
+    /// Re-hash the crops listed in a manifest to detect missing or modified files
+    Verify {
+        /// Manifest produced by `run` or `crop`
+        manifest: PathBuf,
+    },
+    /// Run a grid of detection parameters over a sample set and report how each combination does
+    Bench {
+        /// Directory with a representative sample of sheets
+        #[arg(long)]
+        sample: PathBuf,
+        /// Reviewed manifest listing the photos really on each sample sheet (matched by file name)
+        #[arg(long)]
+        truth: Option<PathBuf>,
+        /// Write the best combination as a TOML config usable with --config
+        #[arg(long)]
+        preset: Option<PathBuf>,
+        /// Minimum areas to try, comma separated
+        #[arg(long, value_delimiter = ',')]
+        min_areas: Vec<f64>,
+        /// Canny low thresholds to try, comma separated
+        #[arg(long, value_delimiter = ',')]
+        canny_lows: Vec<f64>,
+        /// Canny high thresholds to try, comma separated
+        #[arg(long, value_delimiter = ',')]
+        canny_highs: Vec<f64>,
+        /// Keep downscaled grayscale copies of the sample here and reuse them on later runs
+        #[arg(long, value_name = "DIR")]
+        prescale_cache: Option<PathBuf>,
+        /// Factor the cached copies are downscaled by
+        #[arg(
+            long,
+            value_name = "FACTOR",
+            default_value_t = 0.5,
+            requires = "prescale_cache"
+        )]
+        prescale: f64,
+        #[command(flatten)]
+        detect: DetectArgs,
+    },
+    /// Serve detection over HTTP: POST an image to /detect to get its quads as JSON
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// Manifest whose review status to share with `review` over /review
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+        /// File holding a token that requests must send as `Authorization: Bearer <token>`
+        #[arg(long)]
+        token_file: Option<PathBuf>,
+        /// PEM certificate (chain) to serve HTTPS with; needs a build with the `tls` feature
+        #[arg(long, requires = "tls_key")]
+        tls_cert: Option<PathBuf>,
+        /// PEM private key for --tls-cert
+        #[arg(long, requires = "tls_cert")]
+        tls_key: Option<PathBuf>,
+        #[command(flatten)]
+        detect: DetectArgs,
+    },
+    /// Tune detection on one sheet with sliders and a live overlay, then print the chosen parameters
+    Tune {
+        /// Sheet to tune on
+        image: PathBuf,
+        /// Also write the chosen parameters as a TOML config usable with --config
+        #[arg(long)]
+        preset: Option<PathBuf>,
+        #[command(flatten)]
+        detect: DetectArgs,
+    },
+    /// List what `run` would process in a directory, with sizes, resolutions and an estimated runtime, without writing anything
+    Plan {
+        /// Directory containing input images
+        input_dir: PathBuf,
+        /// Directory `run` would write to, whose manifest tells earlier crops and finished sources apart
+        output_dir: Option<PathBuf>,
+        /// Plan a `run --in-place`
+        #[arg(long, conflicts_with = "output_dir")]
+        in_place: bool,
+        /// Leave out sources the previous run already cropped, as `run --incremental` skips them
+        #[arg(long)]
+        incremental: bool,
+        /// Sheets read and detected to time the estimate (0 skips it)
+        #[arg(long, value_name = "N", default_value_t = 3)]
+        samples: usize,
+        #[command(flatten)]
+        detect: DetectArgs,
+    },
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+impl Command {
+    fn detect_args(&self) -> Option<&DetectArgs> {
+        match self {
+            Command::Run { detect, .. }
+            | Command::Video { detect, .. }
+            | Command::Detect { detect, .. }
+            | Command::Recrop { detect, .. }
+            | Command::Bench { detect, .. }
+            | Command::Serve { detect, .. }
+            | Command::Tune { detect, .. }
+            | Command::Plan { detect, .. } => Some(detect),
+            _ => None,
+        }
+    }
+
+    fn output_args(&self) -> Option<&OutputArgs> {
+        match self {
+            Command::Run { output, .. }
+            | Command::Video { output, .. }
+            | Command::Crop { output, .. }
+            | Command::Recrop { output, .. } => Some(output),
+            _ => None,
+        }
+    }
+
+    fn detect_args_mut(&mut self) -> Option<&mut DetectArgs> {
+        match self {
+            Command::Run { detect, .. }
+            | Command::Video { detect, .. }
+            | Command::Detect { detect, .. }
+            | Command::Recrop { detect, .. }
+            | Command::Bench { detect, .. }
+            | Command::Serve { detect, .. }
+            | Command::Tune { detect, .. }
+            | Command::Plan { detect, .. } => Some(detect),
+            _ => None,
+        }
+    }
+
+    fn pipeline_args_mut(&mut self) -> Option<&mut PipelineArgs> {
+        match self {
+            Command::Run { pipeline, .. } | Command::Video { pipeline, .. } => Some(pipeline),
+            _ => None,
+        }
+    }
 }
 
-struct DetectedPhoto {
-    warped: Mat,
+#[derive(Args, Debug)]
+struct DetectArgs {
+    /// TOML file with detection parameters; flags given on the command line take precedence, and it takes precedence over PHOTO_CROPPER_* variables
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Minimum contour area to consider as a photo (in pixels) [default: 20000]
+    #[arg(long)]
+    min_area: Option<f64>,
+    /// Minimum contour area as a share of the sheet (e.g. 0.01), so one setting suits any scan resolution
+    #[arg(long, value_name = "FRACTION", conflicts_with = "min_area")]
+    min_area_frac: Option<f64>,
+    /// Padding (in pixels) added around the image before detection to catch edge-touching photos [default: 12]
+    #[arg(long)]
+    pad: Option<i32>,
+    /// Canny low threshold (raise to be less sensitive, lower to be more sensitive) [default: 50]
+    #[arg(long)]
+    canny_low: Option<f64>,
+    /// Canny high threshold (must be > low; defaults to 3x low if not set) [default: 150]
+    #[arg(long)]
+    canny_high: Option<f64>,
+    /// `auto` derives the Canny thresholds per sheet from its median gray value (0.66× and 1.33×) and finds edges in the gray sheet, for batches of very different exposure [default: fixed]
+    #[arg(long, value_enum)]
+    canny: Option<CannyMode>,
+    /// Side of the Gaussian blur before thresholding: 3, 5, 7 … (odd), or off; larger smooths away the paper grain of high-DPI scans, smaller keeps faint edges of low-DPI ones [default: 5]
+    #[arg(long, value_name = "SIZE", value_parser = parse_blur)]
+    blur: Option<i32>,
+    /// Standard deviation of the blur in pixels [default: derived from --blur]
+    #[arg(long, value_name = "SIGMA")]
+    blur_sigma: Option<f64>,
+    /// Fit corners from the straight edges so torn or folded corners don't skew the crop
+    #[arg(long)]
+    robust_corners: bool,
+    /// Edge style of the prints; rounded and deckle shapes are fitted from their straight runs [default: rect]
+    #[arg(long, value_enum)]
+    print_shape: Option<PrintShape>,
+    /// Photo outlines to detect, comma separated (e.g. rect,ellipse for oval portraits) [default: rect]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    shapes: Option<Vec<PhotoShape>>,
+    /// Which nested outlines to search: the outermost only, or the innermost one above the minimum area (a photo inside a frame inside a page) [default: external]
+    #[arg(long, value_enum)]
+    contours: Option<ContourMode>,
+    /// Print sizes present in the batch, comma separated (e.g. 9x13,10x15 in cm; also mm or in); needs --dpi
+    #[arg(long, value_delimiter = ',', value_parser = parse_print_size)]
+    known_sizes: Option<Vec<PrintSize>>,
+    /// Scan resolution in dots per inch, used to measure candidates against --known-sizes
+    #[arg(long)]
+    dpi: Option<f64>,
+    /// Share each side may deviate from a known size [default: 0.08]
+    #[arg(long)]
+    size_tolerance: Option<f64>,
+    /// What to do with candidates matching none of --known-sizes [default: reject]
+    #[arg(long, value_enum)]
+    off_size: Option<SizeAction>,
+    /// Flatten the soft shadow a lifted scanner lid leaves around thick albums so crops hug the print, not its shadow
+    #[arg(long)]
+    compensate_shadows: bool,
+    /// Join partial contours of one photo (e.g. split by a strong horizon line) when together they form a photo-sized rectangle
+    #[arg(long)]
+    merge_fragments: bool,
+    /// Keep adhesive corner mounts inside the crop instead of fitting the photo between them
+    #[arg(long)]
+    keep_corner_mounts: bool,
+    /// Keep regions that look like captions or handwritten notes instead of rejecting them
+    #[arg(long)]
+    keep_text_regions: bool,
+    /// Find photos lying flush against the scanner bed edge and crop them at the edge instead of smearing its pixels outwards
+    #[arg(long)]
+    recover_edge_photos: bool,
+    /// Split candidates of about 2:1 with a seam down the middle into the two photos lying edge to edge
+    #[arg(long)]
+    split_pairs: bool,
+    /// Also search the sheet at this many halved scales in total, so tiny and large prints on one sheet are both found [default: 1]
+    #[arg(long, value_name = "LEVELS", value_parser = clap::value_parser!(u32).range(1..=6))]
+    pyramid: Option<u32>,
+    /// Search huge stitched scans in overlapping tiles of SIZE pixels a side, joining photos cut by the seams, to keep memory bounded
+    #[arg(long, value_name = "SIZE", value_parser = clap::value_parser!(u32).range(512..))]
+    tile: Option<u32>,
+    /// Pages were photographed with a camera: find and straighten the page first, then detect the photos on it
+    #[arg(long)]
+    rectify_page: bool,
+    /// Turn each sheet straight by the angle its edges share before detecting, for scans all off by a small tilt
+    #[arg(long)]
+    deskew_sheet: bool,
+    /// Keep at most N photos per sheet, the most confident ones (guards against busy pages exploding into junk crops)
+    #[arg(long, value_name = "N")]
+    max_photos_per_sheet: Option<usize>,
+    /// Split every sheet into a fixed grid of photos (e.g. 2x2 for a scanning jig) instead of detecting them
+    #[arg(long, value_name = "COLSxROWS", value_parser = parse_grid)]
+    grid: Option<Grid>,
+    /// Pixels trimmed from each side of every grid cell [default: 0]
+    #[arg(long, value_name = "N")]
+    cell_margin: Option<i32>,
+    /// JSON page template with the photo slots shared by every page; each slot is refined locally
+    #[arg(long, value_name = "FILE", value_parser = parse_layout)]
+    layout: Option<Layout>,
+    /// JSON camera calibration (camera_matrix, dist_coeffs) used to undistort camera-captured pages before detection
+    #[arg(long, value_name = "FILE", value_parser = parse_lens, conflicts_with = "lens_k")]
+    lens: Option<LensProfile>,
+    /// Radial distortion coefficients k1,k2 to undistort camera-captured pages (negative for barrel distortion)
+    #[arg(
+        long,
+        value_name = "K1,K2",
+        value_delimiter = ',',
+        allow_hyphen_values = true
+    )]
+    lens_k: Option<Vec<f64>>,
+    /// Color that transparent parts of PNG sources with alpha are laid over before detection and cropping: "#RRGGBB" or "R,G,B" [default: #FFFFFF]
+    #[arg(long, value_name = "COLOR", value_parser = parse_rgb)]
+    alpha_background: Option<[u8; 3]>,
+    /// Fuse each sheet with a second scan of it named with this suffix (scan_b.tif beside scan.tif for _b), filling in the glare of plastic album sleeves
+    #[arg(long, value_name = "SUFFIX")]
+    second_scan: Option<String>,
+    /// Measure the scan resolution from a ruler on the bed (ticks MM apart, default 1) and record each photo's size in millimetres
+    #[arg(long, value_name = "MM", num_args = 0..=1, default_missing_value = "1")]
+    ruler: Option<f64>,
+    /// Only search this part of each sheet, as x,y,w,h in pixels or percent (e.g. 0,0,92%,100% to skip a ruler strip)
+    #[arg(long, value_name = "X,Y,W,H", value_parser = parse_region)]
+    roi: Option<Region>,
+    /// JSON file of regions per source file name ("*" for every sheet) that must never yield a photo, e.g. a logo on every page
+    #[arg(long, value_name = "FILE", value_parser = parse_reject_list)]
+    reject_list: Option<RejectList>,
+    /// Number of photos expected per sheet; sheets with fewer are retried with relaxed thresholds
+    #[arg(long, value_name = "N")]
+    expect: Option<usize>,
+    /// Retry sheets that produced fewer photos than the batch average, with relaxed thresholds
+    #[arg(long)]
+    retry: bool,
+    /// Check whether each sheet lies rotated (from any text on it) and turn it upright before detection
+    #[arg(long, value_enum, default_value_t = SheetOrientation::Off)]
+    sheet_orientation: SheetOrientation,
+    /// Write each sheet's contours, candidates before overlap suppression and rejections as DIR/<name>.detection.json
+    #[arg(long, value_name = "DIR")]
+    dump_detection: Option<PathBuf>,
+    /// Keep detected geometry in DIR, keyed by each source's content and parameters, and reuse it instead of detecting a sheet again
+    #[arg(long, value_name = "DIR")]
+    detection_cache: Option<PathBuf>,
+    /// Process inputs with the same content as an earlier one too, instead of skipping them as duplicates
+    #[arg(long)]
+    keep_duplicates: bool,
+    /// Ids of the options above that were read from `PHOTO_CROPPER_*`
+    /// variables rather than given as flags.
+    #[arg(skip)]
+    from_env: HashSet<String>,
 }
 
-struct RectCandidate {
-    rect: core::RotatedRect,
-    area: f64,
+/// Photo count below which a sheet is queued for a relaxed retry.
+#[derive(Clone, Copy, Debug)]
+enum RetryTarget {
+    /// A fixed number of photos per sheet (`--expect`).
+    Expect(usize),
+    /// The average count over the batch's main pass.
+    Average,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum SheetOrientation {
+    Off,
+    /// Built-in check for upside-down captions (180° only)
+    Text,
+    /// Tesseract orientation detection (90°, 180° and 270°; needs `tesseract` on PATH)
+    Tesseract,
+}
 
-    fs::create_dir_all(&args.output_dir)
-        .with_context(|| format!("Failed to create output dir {:?}", args.output_dir))?;
+#[derive(Args, Debug)]
+struct OutputArgs {
+    /// Output format for cropped photos ("same" keeps the format of each source image)
+    #[arg(long, value_enum, default_value_t = OutputFormat::Jpg)]
+    format: OutputFormat,
+    /// Store needed rotations as an EXIF Orientation tag instead of rotating the pixels (JPEG, PNG, WebP)
+    #[arg(long)]
+    orient_via_exif: bool,
+    /// Sharpen crops that measure softer than --sharpen-threshold
+    #[arg(long)]
+    auto_sharpen: bool,
+    /// Variance of the Laplacian below which a crop counts as soft
+    #[arg(long, default_value_t = 100.0)]
+    sharpen_threshold: f64,
+    /// Save crops that are effectively black & white as single-channel grayscale
+    #[arg(long)]
+    bw_as_gray: bool,
+    /// Largest spread in color (Lab a/b standard deviation) still treated as black & white
+    #[arg(long, default_value_t = 6.0)]
+    bw_tolerance: f64,
+    /// Trim every crop by this much on each side, in pixels or percent (e.g. 4 or 1.5%)
+    #[arg(long, value_name = "N[%]", value_parser = parse_margin)]
+    inset: Option<Margin>,
+    /// Widen every crop by this much on each side, in pixels or percent, as a safety margin
+    #[arg(long, value_name = "N[%]", value_parser = parse_margin)]
+    outset: Option<Margin>,
+    /// Give each crop the modification time of its source scan
+    #[arg(long)]
+    keep_times: bool,
+    /// Record the absolute source path and its SHA-256 in each crop's EXIF (DocumentName/PageName) and the manifest
+    #[arg(long)]
+    link_source: bool,
+    /// Only warn, instead of aborting, when the output volume looks too small for the crops
+    #[arg(long)]
+    ignore_space_check: bool,
+    /// Fill the area outside oval, rounded or deckle-edged prints: "#RRGGBB", "R,G,B" or "transparent" (PNG/TIFF)
+    #[arg(long, value_name = "COLOR", value_parser = parse_mask_fill)]
+    mask_outside: Option<MaskFill>,
+    /// Resampling for the perspective warp: lanczos4 for archival copies, nearest or linear for quick previews
+    #[arg(long, value_enum, default_value_t = Interpolation::Cubic)]
+    interpolation: Interpolation,
+    /// Colour space the warp resamples in: linear light avoids dark halos at high-contrast edges
+    #[arg(long, value_enum, default_value_t = WorkingColorspace::Srgb)]
+    working_colorspace: WorkingColorspace,
+    /// Warn about crops whose shorter side has fewer pixels than this
+    #[arg(long, value_name = "PIXELS")]
+    warn_min_side: Option<i32>,
+    /// TIFF compression scheme [default: OpenCV's, LZW]
+    #[arg(long, value_enum)]
+    tiff_compression: Option<TiffCompression>,
+    /// TIFF predictor applied before compression
+    #[arg(long, value_enum)]
+    tiff_predictor: Option<TiffPredictor>,
+    /// Bits per channel of the crops (16 needs PNG or TIFF output and a 16-bit source)
+    #[arg(long, value_enum, default_value_t = BitDepth::Eight)]
+    bit_depth: BitDepth,
+    /// Embed a small preview in each JPEG crop's EXIF so file managers show it instantly
+    #[arg(long)]
+    exif_thumbnail: bool,
+    /// Stretch each crop's tonal range per channel (clipping 0.5% at both ends)
+    #[arg(long)]
+    auto_levels: bool,
+    /// Compute the auto-levels correction per crop or once per sheet for consistent tones (implies --auto-levels)
+    #[arg(long, value_enum, value_name = "SCOPE")]
+    match_histograms: Option<LevelsScope>,
+    /// Mark each crop's detection confidence with a filename suffix (_A, _B, _C) or high/medium/low subfolders
+    #[arg(long, value_enum)]
+    confidence_grades: Option<GradeStyle>,
+    /// Guess each crop's rough era from its colours (black & white, faded colour, glossy colour) and sort the crops into era subfolders or tag them with it
+    #[arg(long, value_enum, value_name = "STYLE")]
+    sort_by_era: Option<EraStyle>,
+    /// Keep the alpha channel of PNG sources in the crops (png or tiff output)
+    #[arg(long)]
+    keep_alpha: bool,
+    /// Also store the run record (command line, config hash, versions) in each crop's EXIF comment
+    #[arg(long)]
+    embed_run_record: bool,
+    /// Choose each JPEG crop's quality (75-95) from its detail and size instead of one fixed quality
+    #[arg(long)]
+    adaptive_quality: bool,
+    /// ONNX model estimating when each photo was taken; needs a build with the `date-model` feature
+    #[arg(long, value_name = "FILE")]
+    date_model: Option<PathBuf>,
+    /// When a sheet is a single photo filling the frame, copy the original file as its crop instead of re-encoding it
+    #[arg(long)]
+    copy_identical: bool,
+    /// For about one photo in N, also save the plain bounding-box cut next to the straightened crop as `<crop>.bbox.<ext>`, to check the warp does not distort content
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "10", value_parser = clap::value_parser!(u32).range(1..))]
+    compare_warp: Option<u32>,
+    /// Also save a black & white mask per crop as `<crop>.mask.png`, white on the photo and black on the background around it, in the crop's pixel grid
+    #[arg(long)]
+    save_masks: bool,
+    /// XMP label for crops that need a second look (low confidence or softer than --sharpen-threshold), e.g. "Red" or "review"
+    #[arg(long)]
+    label: Option<String>,
+    /// XMP star rating (0-5) for crops that need a second look
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=5))]
+    rating: Option<u8>,
+    /// TOML file of exiftool-style rules for which EXIF fields of the source the crops copy, rename or drop
+    #[arg(long, value_name = "FILE")]
+    tag_map: Option<PathBuf>,
+}
 
-    for entry in WalkDir::new(&args.input_dir)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-    {
-        let path = entry.path();
-        if !is_image_file(path) {
-            continue;
+/// Values the perspective warp interpolates between.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum WorkingColorspace {
+    /// The gamma-encoded sRGB values as stored
+    Srgb,
+    /// Linear light, converted back to sRGB for output
+    Linear,
+}
+
+/// Order of the photos on `montage` pages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum MontageOrder {
+    /// As listed in the manifest, sheet by sheet
+    Manifest,
+    /// By crop file name
+    Name,
+    /// Largest first
+    Size,
+}
+
+/// Worker threads per stage of `run` (decode, detect, warp, encode, write)
+/// and how many sheets may wait between two stages.
+#[derive(Args, Debug)]
+struct PipelineArgs {
+    /// Threads reading and decoding source images
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    decode_workers: usize,
+    /// Threads detecting photos [default: number of CPUs]
+    #[arg(long, value_name = "N")]
+    detect_workers: Option<usize>,
+    /// Threads straightening and enhancing crops [default: number of CPUs]
+    #[arg(long, value_name = "N")]
+    warp_workers: Option<usize>,
+    /// Threads encoding crops [default: number of CPUs]
+    #[arg(long, value_name = "N")]
+    encode_workers: Option<usize>,
+    /// Threads writing crops to disk
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    write_workers: usize,
+    /// Sheets that may wait between two stages; bounds how many decoded sheets are held in memory
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    queue_depth: usize,
+    /// Threads each worker's OpenCV calls may use [default: the CPUs shared among the largest stage's workers]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    opencv_threads: Option<u32>,
+}
+
+impl PipelineArgs {
+    /// One worker per stage and one OpenCV thread, for `--deterministic`.
+    fn make_sequential(&mut self) {
+        self.decode_workers = 1;
+        self.detect_workers = Some(1);
+        self.warp_workers = Some(1);
+        self.encode_workers = Some(1);
+        self.write_workers = 1;
+        self.opencv_threads = Some(1);
+    }
+
+    /// OpenCV's thread count while the pipeline runs. Left at its default,
+    /// every worker of a full CPU-bound stage would start a pool as large as
+    /// the machine and the workers would fight over the cores.
+    fn opencv_threads(&self) -> i32 {
+        let workers = [self.detect_workers, self.warp_workers, self.encode_workers]
+            .into_iter()
+            .map(|workers| workers.unwrap_or_else(pipeline::cpu_workers))
+            .max()
+            .unwrap_or(1);
+        self.opencv_threads
+            .map_or_else(|| pipeline::opencv_threads(workers), |n| n as usize) as i32
+    }
+}
+
+/// Crop adjustment per side, absolute or relative to the photo's size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Margin {
+    Pixels(f32),
+    Percent(f32),
+}
+
+impl Margin {
+    fn resolve(self, length: i32) -> f32 {
+        match self {
+            Margin::Pixels(px) => px,
+            Margin::Percent(pct) => length as f32 * pct / 100.0,
         }
+    }
+}
 
-        println!("Processing {}...", path.display());
-        match process_image(
-            path,
-            &args.output_dir,
-            args.min_area,
-            args.pad,
-            args.canny_low,
-            args.canny_high,
-        ) {
-            Ok(count) => {
-                if count == 0 {
-                    println!("  No photos found");
-                } else {
-                    println!("  Saved {count} cropped photos");
-                }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Jpg,
+    Png,
+    Tiff,
+    /// Same format (and extension) as the source image
+    Same,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum LevelsScope {
+    /// Each crop gets its own correction
+    Crop,
+    /// One correction measured over all photos on the sheet
+    Sheet,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum GradeStyle {
+    /// Append _A, _B or _C to the file name
+    Suffix,
+    /// Sort crops into high/, medium/ and low/ subfolders
+    Folders,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum EraStyle {
+    /// Sort crops into black-white/, faded-color/ and glossy-color/ subfolders
+    Folders,
+    /// Add the era as an XMP keyword, e.g. "Era|faded-color"
+    Keywords,
+}
+
+/// Confidence of a crop in three steps, so reviewers can check the dubious ones first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Grade {
+    High,
+    Medium,
+    Low,
+}
+
+impl Grade {
+    fn of(confidence: f32) -> Self {
+        if confidence >= 0.9 {
+            Grade::High
+        } else if confidence >= 0.75 {
+            Grade::Medium
+        } else {
+            Grade::Low
+        }
+    }
+
+    fn letter(self) -> &'static str {
+        match self {
+            Grade::High => "A",
+            Grade::Medium => "B",
+            Grade::Low => "C",
+        }
+    }
+
+    fn folder(self) -> &'static str {
+        match self {
+            Grade::High => "high",
+            Grade::Medium => "medium",
+            Grade::Low => "low",
+        }
+    }
+}
+
+/// When `export --split-output-every` starts the next numbered part.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SplitEvery {
+    Bytes(u64),
+    Photos(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum BitDepth {
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum TiffCompression {
+    None,
+    Lzw,
+    Deflate,
+    Zstd,
+}
+
+impl TiffCompression {
+    /// libtiff compression scheme number.
+    fn code(self) -> i32 {
+        match self {
+            TiffCompression::None => 1,
+            TiffCompression::Lzw => 5,
+            TiffCompression::Deflate => 8,
+            TiffCompression::Zstd => 50000,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum TiffPredictor {
+    None,
+    /// Horizontal differencing, which helps LZW and deflate on photos
+    Horizontal,
+}
+
+impl OutputArgs {
+    /// What `--label` and `--rating` mark crops needing review with.
+    fn triage(&self) -> Triage {
+        Triage {
+            rating: self.rating,
+            label: self.label.clone(),
+        }
+    }
+
+    /// Whether crops get anything a plain copy of the source would lack:
+    /// changed tones or pixels, a mask, or metadata of their own. Then
+    /// `--copy-identical` re-encodes rather than copying.
+    fn alters_crops(&self) -> bool {
+        self.auto_levels
+            || self.match_histograms.is_some()
+            || self.bw_as_gray
+            || self.auto_sharpen
+            || self.inset.is_some()
+            || self.outset.is_some()
+            || self.mask_outside.is_some()
+            || self.tag_map.is_some()
+            || self.date_model.is_some()
+            || self.link_source
+            || self.embed_run_record
+            || self.exif_thumbnail
+            || self.label.is_some()
+            || self.rating.is_some()
+            || self.sort_by_era == Some(EraStyle::Keywords)
+    }
+
+    /// Encoder settings for a crop written with `extension`.
+    fn encode_params(&self, extension: &str) -> Vector<i32> {
+        let mut params = Vector::new();
+        if matches!(extension.to_lowercase().as_str(), "tif" | "tiff") {
+            if let Some(compression) = self.tiff_compression {
+                params.push(imgcodecs::IMWRITE_TIFF_COMPRESSION);
+                params.push(compression.code());
             }
-            Err(err) => {
-                eprintln!("  Failed: {err:?}");
+            if let Some(predictor) = self.tiff_predictor {
+                params.push(imgcodecs::IMWRITE_TIFF_PREDICTOR);
+                params.push(match predictor {
+                    TiffPredictor::None => 1,
+                    TiffPredictor::Horizontal => 2,
+                });
             }
         }
+        params
     }
-
-    Ok(())
 }
 
-fn process_image(
-    path: &Path,
-    output_dir: &Path,
-    min_area: f64,
-    pad: i32,
-    canny_low: f64,
-    canny_high: f64,
-) -> Result<usize> {
-    let img = imgcodecs::imread(path.to_str().unwrap_or_default(), imgcodecs::IMREAD_COLOR)
-        .with_context(|| format!("Could not read image {}", path.display()))?;
+impl DetectArgs {
+    /// Parameters from `PHOTO_CROPPER_*` variables with those of the
+    /// `--config` file, if one was given, over them.
+    fn global(&self) -> Result<ParamOverrides> {
+        let mut global = self.options(true);
+        if let Some(path) = &self.config {
+            global.merge(&ParamOverrides::load(path)?);
+        }
+        Ok(global)
+    }
+
+    /// Parameters given as flags.
+    fn overrides(&self) -> ParamOverrides {
+        self.options(false)
+    }
 
-    let crops = detect_photos(&img, min_area, pad, canny_low, canny_high)
-        .with_context(|| format!("Failed to analyze {}", path.display()))?;
+    /// Parameters read from `PHOTO_CROPPER_*` variables, or those given as
+    /// flags.
+    fn options(&self, from_env: bool) -> ParamOverrides {
+        let pick = |id: &str| self.from_env.contains(id) == from_env;
+        ParamOverrides {
+            min_area: self.min_area.filter(|_| pick("min_area")),
+            min_area_frac: self.min_area_frac.filter(|_| pick("min_area_frac")),
+            pad: self.pad.filter(|_| pick("pad")),
+            canny_low: self.canny_low.filter(|_| pick("canny_low")),
+            canny_high: self.canny_high.filter(|_| pick("canny_high")),
+            canny: self.canny.filter(|_| pick("canny")),
+            blur_kernel: self.blur.filter(|_| pick("blur")),
+            blur_sigma: self.blur_sigma.filter(|_| pick("blur_sigma")),
+            threshold_block: None,
+            robust_corners: (self.robust_corners && pick("robust_corners")).then_some(true),
+            print_shape: self.print_shape.filter(|_| pick("print_shape")),
+            contours: self.contours.filter(|_| pick("contours")),
+            shapes: self.shapes.clone().filter(|_| pick("shapes")),
+            known_sizes: self.known_sizes.clone().filter(|_| pick("known_sizes")),
+            dpi: self.dpi.filter(|_| pick("dpi")),
+            size_tolerance: self.size_tolerance.filter(|_| pick("size_tolerance")),
+            off_size: self.off_size.filter(|_| pick("off_size")),
+            grid: self.grid.filter(|_| pick("grid")),
+            cell_margin: self.cell_margin.filter(|_| pick("cell_margin")),
+            compensate_shadows: (self.compensate_shadows && pick("compensate_shadows"))
+                .then_some(true),
+            merge_fragments: (self.merge_fragments && pick("merge_fragments")).then_some(true),
+            keep_corner_mounts: (self.keep_corner_mounts && pick("keep_corner_mounts"))
+                .then_some(true),
+            keep_text_regions: (self.keep_text_regions && pick("keep_text_regions"))
+                .then_some(true),
+            recover_edge_photos: (self.recover_edge_photos && pick("recover_edge_photos"))
+                .then_some(true),
+            split_pairs: (self.split_pairs && pick("split_pairs")).then_some(true),
+            pyramid_levels: self.pyramid.filter(|_| pick("pyramid")),
+            tile: self.tile.filter(|_| pick("tile")),
+            rectify_page: (self.rectify_page && pick("rectify_page")).then_some(true),
+            deskew_sheet: (self.deskew_sheet && pick("deskew_sheet")).then_some(true),
+            max_photos: self
+                .max_photos_per_sheet
+                .filter(|_| pick("max_photos_per_sheet")),
+            layout: self.layout.clone().filter(|_| pick("layout")),
+            ruler_tick_mm: self.ruler.filter(|_| pick("ruler")),
+            roi: self.roi.filter(|_| pick("roi")),
+            lens: self.lens.clone().filter(|_| pick("lens")).or_else(|| {
+                self.lens_k
+                    .clone()
+                    .filter(|_| pick("lens_k"))
+                    .map(LensProfile::from_coefficients)
+            }),
+            alpha_background: self.alpha_background.filter(|_| pick("alpha_background")),
+            second_scan: self.second_scan.clone().filter(|_| pick("second_scan")),
+        }
+    }
 
-    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+    fn retry_target(&self) -> Option<RetryTarget> {
+        match (self.expect, self.retry) {
+            (Some(expect), _) => Some(RetryTarget::Expect(expect)),
+            (None, true) => Some(RetryTarget::Average),
+            (None, false) => None,
+        }
+    }
 
-    for (idx, crop) in crops.iter().enumerate() {
-        let filename = format!("{}_{}.jpg", stem, idx + 1);
-        let mut out_path = output_dir.to_path_buf();
-        out_path.push(filename);
+    /// Parameters for images that arrive without a path, such as those posted
+    /// to `serve`: the config file and flags, plus the reject list's `*` regions.
+    fn params(&self) -> Result<DetectParams> {
+        let mut layered = self.global()?;
+        layered.merge(&self.overrides());
+        let mut params = DetectParams::default();
+        layered.apply_to(&mut params);
+        if let Some(list) = &self.reject_list {
+            params.exclude = list.for_source(Path::new(""));
+        }
+        Ok(params)
+    }
 
-        imgcodecs::imwrite(
-            out_path.to_str().unwrap_or_default(),
-            &crop.warped,
-            &Vector::new(),
+    fn resolver(&self, input_dir: &Path) -> Result<ParamResolver> {
+        Ok(
+            ParamResolver::new(input_dir, self.global()?, self.overrides())
+                .with_reject_list(self.reject_list.clone()),
         )
-        .with_context(|| format!("Failed to save cropped photo to {}", out_path.display()))?;
     }
+}
+
+fn main() -> Result<()> {
+    load_dotenv(Path::new(DOTENV_FILE))?;
+    let args = expand_response_files(env::args_os())?;
+    let matches = command_with_env().get_matches_from(&args);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if let Some(detect) = cli.command.detect_args_mut() {
+        detect.from_env = from_env(&matches);
+    }
+    let config = cli.command.detect_args().and_then(|d| d.config.as_deref());
+    let mut record = RunRecord::new(&args, config, cli.run_name.as_deref())?;
+    if cli.deterministic {
+        deterministic::enable()?;
+        record.pin_time(cli.run_name.is_some());
+        if let Some(pipeline) = cli.command.pipeline_args_mut() {
+            pipeline.make_sequential();
+        }
+    }
+    record.env = env::vars()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+        .map(|(name, value)| recorded_env(&name, &value))
+        .collect();
+    record.env.sort();
+    RUN_RECORD.set(record).expect("the run record is set once");
+    if let Some(model) = cli
+        .command
+        .output_args()
+        .and_then(|o| o.date_model.as_deref())
+    {
+        DATE_MODEL
+            .set(Mutex::new(DateModel::load(model)?))
+            .ok()
+            .expect("the date model is set once");
+    }
+    if let Some(map) = cli.command.output_args().and_then(|o| o.tag_map.as_deref()) {
+        TAG_MAP
+            .set(TagMap::load(map)?)
+            .expect("the tag map is set once");
+    }
+    let log_file = cli
+        .log_dir
+        .as_deref()
+        .map(|dir| LogFile::create(dir, cli.run_name.as_deref()))
+        .transpose()?;
+    let _telemetry = telemetry::init(cli.otlp_endpoint.as_deref(), log_file)?;
+    if let Some(record) = RUN_RECORD.get() {
+        tracing::info!(run = %record.id, args = ?record.args, version = %record.version, "run started");
+    }
+    i18n::init(cli.lang.unwrap_or_else(Language::from_env));
 
-    Ok(crops.len())
+    // The batch command's progress, for the completion notification.
+    let mut batch = None;
+    let status_file = cli.status_file.as_deref();
+    let mut progress = |command: &str| -> Result<Progress> {
+        let progress = progress(command, status_file)?;
+        batch = Some(progress.clone());
+        Ok(progress)
+    };
+
+    let result = match cli.command {
+        Command::Run {
+            tar: true,
+            run_subfolder,
+            detect,
+            output,
+            pipeline,
+            ..
+        } => run_tar(
+            run_subfolder,
+            &detect,
+            &output,
+            &pipeline,
+            &progress("run")?,
+        ),
+        Command::Run {
+            input_dir,
+            output_dir,
+            in_place: _,
+            tar: _,
+            incremental,
+            run_subfolder,
+            review_batch_size,
+            detect,
+            output,
+            pipeline,
+        } => {
+            // Without --tar, clap requires it.
+            let input_dir = input_dir.expect("input_dir is given");
+            // Without one, --in-place is given.
+            let output_dir = run_output_dir(
+                output_dir.unwrap_or_else(|| input_dir.clone()),
+                run_subfolder,
+            );
+            run(
+                &input_dir,
+                &output_dir,
+                &detect,
+                incremental,
+                &output,
+                &pipeline,
+                &progress("run")?,
+            )
+            .and_then(|()| match review_batch_size {
+                Some(size) => write_review_batches(&output_dir, size as usize),
+                None => Ok(()),
+            })
+        }
+        Command::Video {
+            input,
+            output_dir,
+            frame_step,
+            detect,
+            output,
+            pipeline,
+        } => video(
+            &input,
+            &output_dir,
+            frame_step as usize,
+            &detect,
+            &output,
+            &pipeline,
+            &progress("video")?,
+        ),
+        Command::Detect {
+            input_dir,
+            manifest,
+            detect,
+        } => detect_dir(
+            &input_dir,
+            &manifest,
+            &mut detect.resolver(&input_dir)?,
+            &detect,
+            &progress("detect")?,
+        ),
+        Command::Crop {
+            manifest,
+            output_dir,
+            correct_systematic_skew,
+            run_subfolder,
+            output,
+        } => crop(
+            &manifest,
+            &run_output_dir(output_dir, run_subfolder),
+            correct_systematic_skew,
+            &output,
+            &progress("crop")?,
+        ),
+        Command::Review {
+            manifest,
+            review_dir,
+            only,
+            mark,
+            sheets,
+        } => match (mark, review_dir) {
+            (Some(status), _) => mark_reviewed(&manifest, &sheets, status),
+            (None, Some(review_dir)) => review(&manifest, &review_dir, only),
+            (None, None) => unreachable!("clap requires a review_dir without --mark"),
+        },
+        Command::Recrop {
+            manifest,
+            sheets,
+            source_rescan,
+            detect,
+            output,
+        } => recrop(
+            &manifest,
+            &sheets,
+            source_rescan.as_deref(),
+            &detect,
+            &output,
+            &progress("recrop")?,
+        ),
+        Command::Export {
+            manifest,
+            dest_dir,
+            albums,
+            file_tags,
+            split_output_every,
+            captions,
+        } => export(
+            &manifest,
+            &dest_dir,
+            albums.as_deref(),
+            file_tags,
+            split_output_every,
+            captions.as_deref(),
+        ),
+        Command::ApplyNames {
+            manifest,
+            csv,
+            number,
+            dry_run,
+        } => apply_names(&manifest, csv.as_deref(), number, dry_run),
+        Command::Montage {
+            manifest,
+            output_dir,
+            crops,
+            paper,
+            landscape,
+            grid,
+            margin,
+            gap,
+            dpi,
+            order,
+            rotate_to_fit,
+        } => montage(
+            &manifest,
+            &output_dir,
+            &crops,
+            order,
+            &PageLayout {
+                paper,
+                landscape,
+                dpi,
+                margin_mm: margin,
+                gap_mm: gap,
+                grid,
+                rotate_to_fit,
+            },
+        ),
+        Command::Verify { manifest } => verify(&manifest),
+        Command::Bench {
+            sample,
+            truth,
+            preset,
+            min_areas,
+            canny_lows,
+            canny_highs,
+            prescale_cache,
+            prescale,
+            detect,
+        } => {
+            let mut grid = bench::grid(&min_areas, &canny_lows, &canny_highs);
+            if grid.len() == 1 && grid[0] == ParamOverrides::default() {
+                grid = bench::grid(&DEFAULT_MIN_AREAS, &DEFAULT_CANNY_LOWS, &[]);
+            }
+            let cache = prescale_cache
+                .map(|dir| PrescaleCache::new(&dir, prescale))
+                .transpose()?;
+            bench_sample(
+                &sample,
+                truth.as_deref(),
+                preset.as_deref(),
+                &mut detect.resolver(&sample)?,
+                &grid,
+                cache.as_ref(),
+            )
+        }
+        Command::Serve {
+            listen,
+            manifest,
+            token_file,
+            tls_cert,
+            tls_key,
+            detect,
+        } => {
+            let access = server::Access {
+                token: token_file.as_deref().map(read_token).transpose()?,
+                tls: tls_cert
+                    .zip(tls_key)
+                    .map(|(certificate, private_key)| server::Tls {
+                        certificate,
+                        private_key,
+                    }),
+            };
+            server::serve(&listen, &detect.params()?, manifest.as_deref(), &access)
+        }
+        Command::Tune {
+            image,
+            preset,
+            detect,
+        } => {
+            let params = detect
+                .resolver(image.parent().unwrap_or(Path::new(".")))?
+                .params_for(&image)?;
+            let sheet = read_source(&image, &params)?;
+            tune_sheet(&sheet, &params, preset.as_deref())
+        }
+        Command::Plan {
+            input_dir,
+            output_dir,
+            in_place,
+            incremental,
+            samples,
+            detect,
+        } => {
+            let output_dir = output_dir.or_else(|| in_place.then(|| input_dir.clone()));
+            plan(
+                &input_dir,
+                output_dir.as_deref(),
+                incremental,
+                samples,
+                &detect,
+            )
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                env!("CARGO_PKG_NAME"),
+                &mut io::stdout(),
+            );
+            Ok(())
+        }
+    };
+    if let Err(err) = &result {
+        tracing::error!("{err:#}");
+    }
+
+    if let (Some(url), Some(batch)) = (&cli.notify_webhook, batch)
+        && let Err(err) = notify::post(url, &notify::Summary::new(batch.snapshot(), &result))
+    {
+        eprintln!("{err:#}");
+    }
+    result
 }
 
-fn detect_photos(
-    image: &Mat,
-    min_area: f64,
-    pad: i32,
-    canny_low: f64,
-    canny_high: f64,
-) -> Result<Vec<DetectedPhoto>> {
-    let pad = pad.max(0);
-    let mut padded = Mat::default();
-    core::copy_make_border(
-        image,
-        &mut padded,
-        pad,
-        pad,
-        pad,
-        pad,
-        BORDER_REPLICATE,
-        Scalar::all(0.0),
-    )?;
+/// How this process was invoked, recorded in the manifests it writes.
+static RUN_RECORD: OnceLock<RunRecord> = OnceLock::new();
 
-    let mut gray = Mat::default();
-    imgproc::cvt_color(
-        &padded,
-        &mut gray,
-        imgproc::COLOR_BGR2GRAY,
-        0,
-        AlgorithmHint::ALGO_HINT_DEFAULT,
-    )?;
+/// `--date-model`, loaded once and shared by the warp workers.
+static DATE_MODEL: OnceLock<Mutex<DateModel>> = OnceLock::new();
 
-    let mut blurred = Mat::default();
-    imgproc::gaussian_blur(
-        &gray,
-        &mut blurred,
-        Size::new(5, 5),
-        0.0,
-        0.0,
-        core::BORDER_DEFAULT,
-        AlgorithmHint::ALGO_HINT_DEFAULT,
-    )?;
+/// `--tag-map`, loaded once before any sheet is read.
+static TAG_MAP: OnceLock<TagMap> = OnceLock::new();
 
-    // Adaptive threshold handles both light and dark backgrounds.
-    let mut binary = Mat::default();
-    imgproc::adaptive_threshold(
-        &blurred,
-        &mut binary,
-        255.0,
-        imgproc::ADAPTIVE_THRESH_GAUSSIAN_C,
-        imgproc::THRESH_BINARY,
-        25,
-        10.0,
-    )?;
+fn run_record() -> RunRecord {
+    RUN_RECORD.get().cloned().expect("main sets the run record")
+}
+
+/// `output_dir`, or its subfolder for this run with `--run-subfolder`.
+fn run_output_dir(output_dir: PathBuf, subfolder: bool) -> PathBuf {
+    if subfolder {
+        output_dir.join(run_record().id)
+    } else {
+        output_dir
+    }
+}
+
+/// The token in `path`, without surrounding whitespace such as the final newline.
+fn read_token(path: &Path) -> Result<String> {
+    let token = fs::read_to_string(path)
+        .with_context(|| format!("Could not read token file {}", path.display()))?;
+    let token = token.trim();
+    anyhow::ensure!(!token.is_empty(), "Token file {} is empty", path.display());
+    Ok(token.to_string())
+}
+
+/// Accepts a run name that can double as a folder name.
+fn parse_run_name(value: &str) -> Result<String, String> {
+    let invalid = value.is_empty()
+        || value == "."
+        || value == ".."
+        || value.chars().any(|c| {
+            matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') || c.is_control()
+        });
+    if invalid {
+        return Err(format!("\"{value}\" cannot be used as a folder name"));
+    }
+    Ok(value.to_string())
+}
+
+/// Progress tracking for a batch command, reported on SIGUSR1 and to `status_file`.
+fn progress(command: &str, status_file: Option<&Path>) -> Result<Progress> {
+    let progress = Progress::new(command, status_file);
+    progress.report_on_sigusr1()?;
+    Ok(progress)
+}
+
+/// Replaces each `@file` argument with the arguments listed in that file.
+///
+/// Every non-empty line is one argument, so paths with spaces need no quoting;
+/// lines starting with `#` are comments.
+fn expand_response_files(args: impl IntoIterator<Item = OsString>) -> Result<Vec<OsString>> {
+    let mut expanded = Vec::new();
+    for arg in args {
+        match arg.to_str().and_then(|a| a.strip_prefix('@')) {
+            Some(path) if !path.is_empty() => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("Could not read argument file {path}"))?;
+                expanded.extend(
+                    text.lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(OsString::from),
+                );
+            }
+            _ => expanded.push(arg),
+        }
+    }
+    Ok(expanded)
+}
 
-    let mut inverted = Mat::default();
-    core::bitwise_not(&binary, &mut inverted, &core::no_array())?;
+/// Prefix of the environment variables every option can also be set with.
+const ENV_PREFIX: &str = "PHOTO_CROPPER_";
+/// Words in the names of variables whose values can hold credentials, such
+/// as a webhook or collector URL with a key in it; the run record only
+/// notes that they were set.
+const SECRET_ENV_WORDS: [&str; 6] = ["TOKEN", "WEBHOOK", "ENDPOINT", "SECRET", "PASSWORD", "KEY"];
 
-    let (low, high) = if canny_high <= canny_low {
-        let high = (canny_low * 3.0).max(canny_low + 1.0);
-        (canny_low, high)
+/// `name=value` as the run record lists it, with secret values left out.
+fn recorded_env(name: &str, value: &str) -> String {
+    if SECRET_ENV_WORDS.iter().any(|word| name.contains(word)) {
+        format!("{name}=<redacted>")
     } else {
-        (canny_low, canny_high)
-    };
-
-    let mut edges = Mat::default();
-    imgproc::canny(&inverted, &mut edges, low, high, 3, false)?;
-
-    let kernel =
-        imgproc::get_structuring_element(imgproc::MORPH_RECT, Size::new(5, 5), Point::new(-1, -1))?;
-    let mut dilated = Mat::default();
-    imgproc::dilate(
-        &edges,
-        &mut dilated,
-        &kernel,
-        Point::new(-1, -1),
-        2,
-        core::BORDER_CONSTANT,
-        Scalar::all(0.0),
-    )?;
-    edges = dilated;
-
-    let mut contours: Vector<Vector<Point>> = Vector::new();
-    imgproc::find_contours(
-        &edges,
-        &mut contours,
-        imgproc::RETR_EXTERNAL,
-        imgproc::CHAIN_APPROX_SIMPLE,
-        Point::new(0, 0),
-    )?;
+        format!("{name}={value}")
+    }
+}
 
-    let mut rects = Vec::new();
+/// File in the working directory whose `PHOTO_CROPPER_*` lines are read
+/// into the environment at startup.
+const DOTENV_FILE: &str = ".env";
 
-    for contour in contours {
-        let area = imgproc::contour_area(&contour, false)?;
-        if area < min_area {
-            continue;
+/// The command line parser with every option also read from a variable
+/// named after its long flag, `--min-area` from `PHOTO_CROPPER_MIN_AREA`.
+///
+/// Detection parameters are layered as flags given on the command line over
+/// the `--config` file (and directory configs and sidecars) over the
+/// variables over the built-in defaults; clap only sees flags and variables,
+/// so [`DetectArgs::global`] puts those from variables below the config
+/// file. Other options have no config file, so the flag wins over the
+/// variable.
+fn command_with_env() -> clap::Command {
+    with_env(Cli::command())
+}
+
+/// Ids of the subcommand's options in `matches` that were read from
+/// variables rather than given as flags.
+fn from_env(matches: &clap::ArgMatches) -> HashSet<String> {
+    let Some((_, matches)) = matches.subcommand() else {
+        return HashSet::new();
+    };
+    matches
+        .ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::EnvVariable))
+        .map(|id| id.as_str().to_string())
+        .collect()
+}
+
+fn with_env(command: clap::Command) -> clap::Command {
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|s| s.get_name().to_string())
+        .collect();
+    let mut command = command.mut_args(|arg| match arg.get_long() {
+        Some(long) if !matches!(long, "help" | "version") => {
+            let name = format!("{ENV_PREFIX}{}", long.replace('-', "_").to_uppercase());
+            arg.env(name)
         }
+        _ => arg,
+    });
+    for name in subcommands {
+        command = command.mut_subcommand(name, with_env);
+    }
+    command
+}
 
-        let rect = imgproc::min_area_rect(&contour)?;
-        let size = rect.size;
-        if size.width <= 1.0 || size.height <= 1.0 {
+/// Sets the `PHOTO_CROPPER_*` variables listed in the dotenv file at `path`
+/// that the environment does not already have, so a container or CI job can
+/// keep its settings next to the data. Lines are `NAME=value`, optionally
+/// after `export ` and with the value in quotes; other variables and lines
+/// starting with `#` are ignored. A missing file is no error.
+fn load_dotenv(path: &Path) -> Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err).with_context(|| format!("Could not read {}", path.display())),
+    };
+    for line in text.lines().map(str::trim) {
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((name, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if !name.starts_with(ENV_PREFIX) || env::var_os(name).is_some() {
             continue;
         }
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|q| value.strip_prefix(*q)?.strip_suffix(*q))
+            .unwrap_or(value);
+        // SAFETY: called first thing in `main`, before any other thread exists.
+        unsafe { env::set_var(name, value) };
+    }
+    Ok(())
+}
 
-        rects.push(RectCandidate { rect, area });
+/// `run --tar`: unpacks the tar stream on stdin into a scratch directory,
+/// runs there and streams the output directory back as a tar on stdout, so a
+/// container needs no mounted volumes: `tar c scans | docker run -i … run
+/// --tar | tar x`. Paths in the streamed manifest are relative to the root
+/// of the archives. The scratch directory is removed afterwards.
+fn run_tar(
+    run_subfolder: bool,
+    detect: &DetectArgs,
+    output: &OutputArgs,
+    pipeline: &PipelineArgs,
+    progress: &Progress,
+) -> Result<()> {
+    let archive = tar_stdout()?;
+    let work = env::temp_dir().join(format!("photo-cropper-tar-{}", std::process::id()));
+    let (input_dir, output_root) = (work.join("in"), work.join("out"));
+    let result = (|| {
+        fs::create_dir_all(&input_dir)
+            .with_context(|| format!("Could not create {}", input_dir.display()))?;
+        fs::create_dir_all(&output_root)
+            .with_context(|| format!("Could not create {}", output_root.display()))?;
+        // Entries reaching outside the directory, as with `..`, are refused.
+        tar::Archive::new(io::stdin().lock())
+            .unpack(&input_dir)
+            .context("Could not read the tar stream on stdin")?;
+        let output_dir = run_output_dir(output_root.clone(), run_subfolder);
+        run(
+            &input_dir,
+            &output_dir,
+            detect,
+            false,
+            output,
+            pipeline,
+            progress,
+        )?;
+        relative_manifest(&output_dir.join(MANIFEST_FILE), &input_dir, &output_root)?;
+        let mut builder = tar::Builder::new(archive);
+        builder
+            .append_dir_all(".", &output_root)
+            .context("Could not write the tar stream")?;
+        builder.finish().context("Could not write the tar stream")?;
+        Ok(())
+    })();
+    if let Err(err) = fs::remove_dir_all(&work) {
+        eprintln!(
+            "{}",
+            tr!(
+                "remove-failed",
+                path = work.display().to_string(),
+                error = err.to_string()
+            )
+        );
+    }
+    result
+}
+
+/// Stdout for the tar stream alone: everything printed from now on goes to
+/// stderr instead, so progress and messages cannot corrupt the archive.
+#[cfg(unix)]
+fn tar_stdout() -> Result<fs::File> {
+    let archive = nix::unistd::dup(io::stdout()).context("Could not duplicate stdout")?;
+    nix::unistd::dup2_stdout(io::stderr()).context("Could not redirect stdout")?;
+    Ok(fs::File::from(archive))
+}
+
+#[cfg(not(unix))]
+fn tar_stdout() -> Result<fs::File> {
+    anyhow::bail!("--tar is only supported on Unix-like systems")
+}
+
+/// Rewrites the manifest at `path` so sources are relative to `input_dir`
+/// and crops to `output_root`, as the paths appear in the two archives.
+fn relative_manifest(path: &Path, input_dir: &Path, output_root: &Path) -> Result<()> {
+    if !path.is_file() {
+        return Ok(());
+    }
+    let relative = |path: &Path, base: &Path| {
+        path.strip_prefix(base)
+            .map_or_else(|_| path.to_path_buf(), Path::to_path_buf)
+    };
+    let mut manifest = Manifest::load(path)?;
+    manifest.output_dir = None;
+    for sheet in &mut manifest.sheets {
+        sheet.source = relative(&sheet.source, input_dir);
+        for photo in &mut sheet.photos {
+            photo.output = photo.output.as_deref().map(|o| relative(o, output_root));
+        }
     }
+    manifest.save(path)
+}
+
+fn run(
+    input_dir: &Path,
+    output_dir: &Path,
+    detect: &DetectArgs,
+    incremental: bool,
+    output: &OutputArgs,
+    pipeline: &PipelineArgs,
+    progress: &Progress,
+) -> Result<()> {
+    let mut resolver = detect.resolver(input_dir)?;
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("Failed to create output dir {:?}", output_dir))?;
+
+    // The previous run's manifest records what is already done.
+    let manifest_path = output_dir.join(MANIFEST_FILE);
+    let mut manifest = if incremental && manifest_path.is_file() {
+        Manifest::load(&manifest_path)?
+    } else {
+        Manifest::default()
+    };
+    manifest.output_dir = Some(output_dir.to_path_buf());
+    let previous = incremental.then_some(&manifest.sheets);
 
-    // Keep only the largest rectangle when overlapping occurs (nested or partial overlap).
-    rects.sort_by(|a, b| b.area.partial_cmp(&a.area).unwrap());
-    let mut filtered: Vec<RectCandidate> = Vec::new();
-    'outer: for candidate in rects {
-        for kept in &filtered {
-            if rects_overlap(&kept.rect, &candidate.rect)? {
-                continue 'outer;
+    // Listed before anything is written, so this run's own crops are never
+    // picked up; earlier runs' crops are told apart from the sheets.
+    let in_place = same_dir(input_dir, output_dir);
+    let loaded;
+    let earlier = if incremental {
+        Some(&manifest)
+    } else if in_place {
+        loaded = Manifest::load(&manifest_path).ok();
+        loaded.as_ref()
+    } else {
+        None
+    };
+    let files = sheet_files(
+        input_dir,
+        in_place,
+        earlier,
+        &mut resolver,
+        detect,
+        progress,
+    )?;
+    let sheets: Vec<(&Path, &Path)> = files
+        .iter()
+        .map(|f| (f.as_path(), crop_dir(f, output_dir, in_place)))
+        .collect();
+    let stems = crop_stems(&sheets);
+    check_space(output_dir, files.iter().map(PathBuf::as_path), output)?;
+    let jobs = files
+        .into_iter()
+        .map(|path| {
+            let params = resolver.params_for(&path)?;
+            Ok(SheetJob {
+                source: path,
+                state: Ok(params),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    progress.add_total(jobs.len());
+    let mut skipped = HashSet::new();
+    let mut done = Vec::new();
+    // OpenCV shares one thread setting across the process, so it holds for
+    // every worker while the pipeline runs; the sequential retries after it
+    // get the whole machine again.
+    let previous_threads = opencv::core::get_num_threads()?;
+    let threads = pipeline.opencv_threads();
+    tracing::debug!(threads, "OpenCV threads per worker");
+    opencv::core::set_num_threads(threads)?;
+    thread::scope(|scope| {
+        let finished = Pipeline::new(scope, jobs.into_iter().enumerate(), pipeline.queue_depth)
+            .stage(
+                pipeline.decode_workers,
+                |(index, job): (usize, SheetJob<_>)| {
+                    println!(
+                        "{}",
+                        tr!("processing", path = job.source.display().to_string())
+                    );
+                    progress.start_file(&job.source);
+                    let job = job.then(|source, params| {
+                        settle::wait(source)?;
+                        let mut digest = None;
+                        if let Some(previous) = &previous {
+                            let d = hash::sha256_file(source)?;
+                            if previous.iter().any(|s| unchanged(s, source, &d, &params)) {
+                                return Err(Stop::Unchanged);
+                            }
+                            digest = Some(d);
+                        }
+                        let image = read_source(source, &params)?;
+                        Ok((image, params, digest))
+                    });
+                    (index, job)
+                },
+            )
+            .stage(
+                pipeline
+                    .detect_workers
+                    .unwrap_or_else(pipeline::cpu_workers),
+                |(index, job)| {
+                    let job = job.then(|source, (image, params, digest)| {
+                        let (image, rotation) = orient_sheet(image, detect.sheet_orientation)?;
+                        let mut sheet =
+                            detect_sheet_cached(&image, source, &params, rotation, detect)?;
+                        sheet.source_sha256 = digest;
+                        Ok((image, sheet))
+                    });
+                    (index, job)
+                },
+            )
+            .stage(
+                pipeline.warp_workers.unwrap_or_else(pipeline::cpu_workers),
+                |(index, job)| {
+                    let job = job.then(|source, (image, mut sheet)| {
+                        let dir = crop_dir(source, output_dir, in_place);
+                        let plan = plan_crops(&mut sheet, dir, &stems[source], output)?;
+                        let crops = warp_crops(&image, &sheet, &plan, output)?;
+                        Ok((sheet, plan, crops))
+                    });
+                    (index, job)
+                },
+            )
+            .stage(
+                pipeline
+                    .encode_workers
+                    .unwrap_or_else(pipeline::cpu_workers),
+                |(index, job)| {
+                    let job = job.then(|_, (sheet, plan, crops)| {
+                        let encoded = encode_crops(crops, &sheet, &plan, output)?;
+                        Ok((sheet, plan, encoded))
+                    });
+                    (index, job)
+                },
+            )
+            .stage(pipeline.write_workers, |(index, job)| {
+                let job = job.then(|_, (mut sheet, plan, encoded)| {
+                    let count = write_crops(encoded, &mut sheet, &plan)?;
+                    Ok((sheet, count))
+                });
+                (index, job)
+            });
+
+        for (index, job) in finished {
+            // Sheets overlap in the stages, so name the one each outcome is for.
+            println!(
+                "{}",
+                tr!("finished", path = job.source.display().to_string())
+            );
+            match job.state {
+                Ok((sheet, count)) => {
+                    done.push((index, sheet));
+                    progress.finish_file(report_saved(Ok(count)));
+                }
+                Err(Stop::Unchanged) => {
+                    println!("{}", tr!("unchanged-skipped"));
+                    skipped.insert(job.source);
+                    progress.finish_file(Some(0));
+                }
+                Err(Stop::Failed(err)) => report_failed(err, progress),
             }
         }
-        filtered.push(candidate);
+    });
+    opencv::core::set_num_threads(previous_threads)?;
+    // Sheets finish out of order; record them in input order so the
+    // manifest does not depend on timing.
+    done.sort_by_key(|(index, _)| *index);
+    for (_, sheet) in done {
+        manifest.upsert(sheet);
     }
 
-    let mut photos = Vec::new();
-    for r in filtered {
-        let warped = warp_photo(&padded, &r.rect)?;
-        photos.push(DetectedPhoto { warped });
+    let mut queue = retry_queue(&manifest, detect.retry_target());
+    queue.retain(|source| !skipped.contains(source));
+    progress.add_total(queue.len());
+    for source in queue {
+        let Some(sheet) = manifest.sheets.iter_mut().find(|s| s.source == source) else {
+            continue;
+        };
+        let _sheet = tracing::info_span!("sheet", path = %source.display()).entered();
+        println!("{}", tr!("retrying", path = source.display().to_string()));
+        progress.start_file(&source);
+        let before = sheet.photos.len();
+        let result = read_sheet(sheet).and_then(|img| {
+            if retry_sheet(&img, sheet)? == 0 {
+                return Ok(0);
+            }
+            let dir = crop_dir(&source, output_dir, in_place);
+            let stem = stems
+                .get(&source)
+                .cloned()
+                .unwrap_or_else(|| file_stem(&source));
+            crop_sheet(&img, sheet, dir, &stem, output)
+        });
+        // Only the photos the retry added are new crops.
+        let added = report_saved(result).map(|_| sheet.photos.len() - before);
+        progress.finish_file(added);
     }
 
-    photos.sort_by(|a, b| b.warped.total().cmp(&a.warped.total()));
-    Ok(photos)
+    manifest.runs.push(run_record());
+    manifest.save(&manifest_path)?;
+    print_duplicates(progress);
+    print_advice(&manifest);
+    Ok(())
 }
 
-fn warp_photo(image: &Mat, rect: &core::RotatedRect) -> Result<Mat> {
-    let mut box_points = [Point2f::default(); 4];
-    rect.points(&mut box_points)?;
-    let ordered = order_points(&box_points);
-
-    let width_top = distance(&ordered[0], &ordered[1]);
-    let width_bottom = distance(&ordered[3], &ordered[2]);
-    let max_width = width_top.max(width_bottom).round() as i32;
+/// Picks the pages of the album capture at `input` into `output_dir/pages`
+/// and crops them into `output_dir` as `run` does.
+fn video(
+    input: &Path,
+    output_dir: &Path,
+    step: usize,
+    detect: &DetectArgs,
+    output: &OutputArgs,
+    pipeline: &PipelineArgs,
+    progress: &Progress,
+) -> Result<()> {
+    let pages_dir = output_dir.join(PAGES_DIR);
+    fs::create_dir_all(&pages_dir)
+        .with_context(|| format!("Failed to create {}", pages_dir.display()))?;
+    println!(
+        "{}",
+        tr!("selecting-pages", path = input.display().to_string())
+    );
+    let count = video::select_pages(input, step, |number, frame| {
+        // Lossless, so the page is detected and cropped from the frame as shot.
+        let path = pages_dir.join(format!("page_{number:03}.png"));
+        println!("{}", tr!("page-taken", path = path.display().to_string()));
+        write_image(&path, frame, &Vector::new())
+    })?;
+    anyhow::ensure!(count > 0, "Found no page held still in {}", input.display());
+    run(
+        &pages_dir, output_dir, detect, false, output, pipeline, progress,
+    )
+}
 
-    let height_left = distance(&ordered[0], &ordered[3]);
-    let height_right = distance(&ordered[1], &ordered[2]);
-    let max_height = height_left.max(height_right).round() as i32;
+/// Folder below the output directory `video` writes the chosen pages to.
+const PAGES_DIR: &str = "pages";
 
-    let max_width = max_width.max(1);
-    let max_height = max_height.max(1);
+/// Repeats at the end of a batch how many inputs were left out as duplicates.
+fn print_duplicates(progress: &Progress) {
+    let count = progress.snapshot().duplicates;
+    if count > 0 {
+        println!("{}", tr!("duplicates-summary", count = count));
+    }
+}
 
-    let dst: Vector<Point2f> = Vector::from(vec![
-        Point2f::new(0.0, 0.0),
-        Point2f::new((max_width - 1) as f32, 0.0),
-        Point2f::new((max_width - 1) as f32, (max_height - 1) as f32),
-        Point2f::new(0.0, (max_height - 1) as f32),
-    ]);
+/// Prints the parameter changes the batch's results point to, if any.
+fn print_advice(manifest: &Manifest) {
+    let advice = advice::recommend(manifest);
+    if advice.is_empty() {
+        return;
+    }
+    println!("{}", tr!("advice-heading"));
+    for advice in advice {
+        let line = match advice {
+            Advice::LowerMinArea {
+                suggested,
+                empty_sheets,
+                ..
+            } if empty_sheets > 0 => {
+                tr!(
+                    "advice-min-area-empty",
+                    suggested = suggested,
+                    count = empty_sheets
+                )
+            }
+            Advice::LowerMinArea {
+                suggested,
+                borderline_sheets,
+                ..
+            } => tr!(
+                "advice-min-area-borderline",
+                suggested = suggested,
+                count = borderline_sheets
+            ),
+            Advice::LoosenSizes {
+                rejected,
+                suggested,
+            } => tr!(
+                "advice-size-tolerance",
+                suggested = suggested,
+                count = rejected
+            ),
+            Advice::RaiseMaxPhotos { max_photos, sheets } => {
+                tr!("advice-max-photos", max = max_photos, count = sheets)
+            }
+            Advice::AddShapes { rejected } => tr!("advice-shapes", count = rejected),
+            Advice::RobustCorners { median_confidence } => tr!(
+                "advice-robust-corners",
+                confidence = format!("{median_confidence:.2}")
+            ),
+        };
+        println!("{line}");
+    }
+}
 
-    let src: Vector<Point2f> = Vector::from(ordered.to_vec());
-    let m = imgproc::get_perspective_transform(&src, &dst, core::DECOMP_LU)?;
+/// Whether `a` and `b` name the same directory, however they are spelled.
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
 
-    let mut warped = Mat::default();
-    imgproc::warp_perspective(
-        image,
-        &mut warped,
-        &m,
-        Size::new(max_width, max_height),
-        InterpolationFlags::INTER_CUBIC as i32,
-        core::BORDER_REPLICATE,
-        Scalar::all(0.0),
-    )?;
+/// What a folder below the input holds, told from its manifest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ManifestDir {
+    /// The output of a run from elsewhere, as when a run's output or
+    /// `--run-subfolder` sits inside its input.
+    Output,
+    /// Scans an earlier `run --in-place` cropped, with their crops beside them.
+    InPlace,
+}
 
-    Ok(warped)
+impl ManifestDir {
+    /// What `dir` holds, or `None` when it has no manifest naming it as
+    /// where crops went.
+    fn of(dir: &Path) -> Option<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.is_file() {
+            return None;
+        }
+        let manifest = match Manifest::load(&path) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                // Most likely a run's own, broken off while it was written.
+                tracing::debug!(path = %path.display(), "unreadable manifest: {err:#}");
+                return Some(ManifestDir::Output);
+            }
+        };
+        if !manifest
+            .output_dir
+            .as_deref()
+            .is_some_and(|output| same_dir(output, dir))
+        {
+            return None;
+        }
+        let inside = |source: &Path| {
+            source
+                .ancestors()
+                .skip(1)
+                .any(|parent| !parent.as_os_str().is_empty() && same_dir(parent, dir))
+        };
+        Some(if manifest.sheets.iter().all(|s| inside(&s.source)) {
+            ManifestDir::InPlace
+        } else {
+            ManifestDir::Output
+        })
+    }
 }
 
-fn order_points(points: &[Point2f; 4]) -> [Point2f; 4] {
-    let mut ordered = [Point2f::default(); 4];
+/// The sheets `run` reads from `input_dir`, in the order it reads them:
+/// without crops of earlier runs, second scans and duplicates, and with
+/// files still being written last. `earlier` is the manifest of the
+/// previous run, which lists its crops when cropping `in_place`.
+fn sheet_files(
+    input_dir: &Path,
+    in_place: bool,
+    earlier: Option<&Manifest>,
+    resolver: &mut ParamResolver,
+    detect: &DetectArgs,
+    progress: &Progress,
+) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = image_files(input_dir).collect();
+    if in_place {
+        files = without_own_crops(files, earlier);
+    }
+    let files = without_output_dirs(files, input_dir);
+    let files = without_second_scans(files, resolver)?;
+    let files = without_duplicates(files, detect, progress);
+    Ok(unfinished_last(files))
+}
 
-    // top-left has smallest sum, bottom-right largest sum
-    ordered[0] = *points
+/// `files` without those in an output directory below `input_dir`. A
+/// folder an earlier `run --in-place` cropped keeps its scans and only
+/// loses its crops, told apart by their names, so new scans put there are
+/// still picked up.
+fn without_output_dirs(files: Vec<PathBuf>, input_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: HashMap<PathBuf, Option<ManifestDir>> = HashMap::new();
+    let mut kind_of = |dir: &Path| {
+        *dirs.entry(dir.to_path_buf()).or_insert_with(|| {
+            let kind = ManifestDir::of(dir);
+            match kind {
+                Some(ManifestDir::Output) => {
+                    tracing::debug!(dir = %dir.display(), "skipping an output folder in the input");
+                }
+                Some(ManifestDir::InPlace) => {
+                    tracing::debug!(dir = %dir.display(), "skipping the crops of an in-place folder");
+                }
+                None => {}
+            }
+            kind
+        })
+    };
+    let kinds: Vec<Option<ManifestDir>> = files
         .iter()
-        .min_by(|a, b| (a.x + a.y).partial_cmp(&(b.x + b.y)).unwrap())
-        .unwrap();
-    ordered[2] = *points
+        .map(|file| {
+            file.ancestors()
+                .skip(1)
+                .take_while(|dir| *dir != input_dir)
+                .find_map(&mut kind_of)
+        })
+        .collect();
+    let in_place = files
         .iter()
-        .max_by(|a, b| (a.x + a.y).partial_cmp(&(b.x + b.y)).unwrap())
-        .unwrap();
+        .zip(&kinds)
+        .filter(|(_, kind)| **kind == Some(ManifestDir::InPlace))
+        .map(|(file, _)| file.clone())
+        .collect();
+    let scans: HashSet<PathBuf> = without_own_crops(in_place, None).into_iter().collect();
+    files
+        .into_iter()
+        .zip(kinds)
+        .filter(|(file, kind)| match kind {
+            Some(ManifestDir::Output) => false,
+            Some(ManifestDir::InPlace) => scans.contains(file),
+            None => true,
+        })
+        .map(|(file, _)| file)
+        .collect()
+}
+
+/// `files` without exact copies of an earlier one, unless
+/// `--keep-duplicates` asks for them, naming each copy left out.
+fn without_duplicates(
+    files: Vec<PathBuf>,
+    detect: &DetectArgs,
+    progress: &Progress,
+) -> Vec<PathBuf> {
+    if detect.keep_duplicates {
+        return files;
+    }
+    let (files, duplicates) = dedup::split(files);
+    for duplicate in &duplicates {
+        println!(
+            "{}",
+            tr!(
+                "duplicate-skipped",
+                path = duplicate.path.display().to_string(),
+                original = duplicate.original.display().to_string()
+            )
+        );
+    }
+    progress.add_duplicates(duplicates.len());
+    files
+}
+
+/// `files` with those that are empty or still being written, as a scanner
+/// leaves a large TIFF for a while, moved to the end, giving them the rest
+/// of the batch to be finished before they are read.
+fn unfinished_last(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let (mut ready, unfinished): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|f| !matches!(settle::check(f), Ok(Readiness::Empty | Readiness::Growing)));
+    for file in &unfinished {
+        println!(
+            "{}",
+            tr!("input-deferred", path = file.display().to_string())
+        );
+    }
+    ready.extend(unfinished);
+    ready
+}
+
+/// `files` without the second scans named by `--second-scan`, which are
+/// read together with their first scan rather than as sheets of their own.
+fn without_second_scans(files: Vec<PathBuf>, resolver: &mut ParamResolver) -> Result<Vec<PathBuf>> {
+    let mut sheets = Vec::with_capacity(files.len());
+    for file in files {
+        let params = resolver.params_for(&file)?;
+        if !params
+            .second_scan
+            .as_deref()
+            .is_some_and(|suffix| glare::is_second_scan(&file, suffix))
+        {
+            sheets.push(file);
+        }
+    }
+    Ok(sheets)
+}
+
+/// Where `run` writes the crops of `source`: next to it when cropping in place.
+fn crop_dir<'a>(source: &'a Path, output_dir: &'a Path, in_place: bool) -> &'a Path {
+    match source.parent() {
+        Some(dir) if in_place => dir,
+        _ => output_dir,
+    }
+}
 
-    // top-right has smallest difference, bottom-left largest difference
-    ordered[1] = *points
+/// `files` without the crops earlier in-place runs wrote among them: those
+/// the `earlier` manifest lists and, should it be gone, those named like a
+/// crop of an image beside them (`scan_2.jpg` or `scan_2_A.jpg` next to
+/// `scan.tif`, or in a confidence grade or era folder below it). `--compare-warp`
+/// cuts and `--save-masks` masks are left out either way.
+fn without_own_crops(files: Vec<PathBuf>, earlier: Option<&Manifest>) -> Vec<PathBuf> {
+    let listed: HashSet<&Path> = earlier
         .iter()
-        .min_by(|a, b| (a.x - a.y).partial_cmp(&(b.x - b.y)).unwrap())
-        .unwrap();
-    ordered[3] = *points
+        .flat_map(|m| &m.sheets)
+        .flat_map(|s| &s.photos)
+        .filter_map(|p| p.output.as_deref())
+        .collect();
+    let stems: HashSet<(PathBuf, String)> = files
         .iter()
-        .max_by(|a, b| (a.x - a.y).partial_cmp(&(b.x - b.y)).unwrap())
-        .unwrap();
+        .filter_map(|f| Some((f.parent()?.to_path_buf(), file_stem(f))))
+        .collect();
+    let is_crop = |file: &Path| {
+        let (Some(dir), Some(stem)) = (file.parent(), file.file_stem().and_then(|s| s.to_str()))
+        else {
+            return false;
+        };
+        // A grade folder may sit inside an era folder.
+        let mut sheet_dirs = vec![dir];
+        for _ in 0..2 {
+            let last = sheet_dirs[sheet_dirs.len() - 1];
+            if let Some(parent) = last.parent()
+                && SORTING_FOLDERS
+                    .iter()
+                    .any(|f| last.file_name() == Some(f.as_ref()))
+            {
+                sheet_dirs.push(parent);
+            }
+        }
+        crop_bases(stem).iter().any(|base| {
+            sheet_dirs
+                .iter()
+                .any(|d| stems.contains(&(d.to_path_buf(), base.to_string())))
+        })
+    };
+    let is_comparison = |file: &Path| {
+        file.file_stem()
+            .and_then(|s| s.to_str())
+            .is_some_and(|s| s.ends_with(COMPARE_SUFFIX) || s.ends_with(MASK_SUFFIX))
+    };
+    files
+        .into_iter()
+        .filter(|f| !is_comparison(f))
+        .filter(|f| match earlier {
+            Some(_) => !listed.contains(f.as_path()),
+            None => !is_crop(f),
+        })
+        .collect()
+}
 
-    ordered
+/// Folders `--confidence-grades folders` and `--sort-by-era folders` sort
+/// crops into.
+const SORTING_FOLDERS: [&str; 6] = [
+    "high",
+    "medium",
+    "low",
+    "black-white",
+    "faded-color",
+    "glossy-color",
+];
+
+/// The sheet stems a crop named `stem` could have come from: `scan` for
+/// both `scan_2` and `scan_2_A`.
+fn crop_bases(stem: &str) -> Vec<&str> {
+    fn numbered(stem: &str) -> Option<&str> {
+        let (base, index) = stem.rsplit_once('_')?;
+        (!index.is_empty() && index.bytes().all(|b| b.is_ascii_digit())).then_some(base)
+    }
+    let mut bases: Vec<&str> = numbered(stem).into_iter().collect();
+    if let Some((rest, grade)) = stem.rsplit_once('_')
+        && matches!(grade, "A" | "B" | "C")
+        && let Some(base) = numbered(rest)
+    {
+        bases.push(base);
+    }
+    bases
 }
 
-fn distance(a: &Point2f, b: &Point2f) -> f32 {
-    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+/// A sheet on its way through `run`'s pipeline: what the last stage made of
+/// it, or why it left early.
+struct SheetJob<T> {
+    source: PathBuf,
+    state: Result<T, Stop>,
 }
 
-fn rects_overlap(a: &core::RotatedRect, b: &core::RotatedRect) -> Result<bool> {
-    let (ax1, ay1, ax2, ay2) = rect_bbox(a)?;
-    let (bx1, by1, bx2, by2) = rect_bbox(b)?;
+/// Why a sheet left `run`'s pipeline before being written.
+enum Stop {
+    /// `--incremental` found it cropped before.
+    Unchanged,
+    Failed(anyhow::Error),
+}
 
-    let intersect_w = (ax2.min(bx2) - ax1.max(bx1)).max(0.0);
-    let intersect_h = (ay2.min(by2) - ay1.max(by1)).max(0.0);
+impl From<anyhow::Error> for Stop {
+    fn from(err: anyhow::Error) -> Self {
+        Stop::Failed(err)
+    }
+}
 
-    Ok(intersect_w > 0.0 && intersect_h > 0.0)
+impl<T> SheetJob<T> {
+    /// Runs the next stage's `step`, unless an earlier one stopped the sheet.
+    fn then<U>(self, step: impl FnOnce(&Path, T) -> Result<U, Stop>) -> SheetJob<U> {
+        let _sheet = tracing::info_span!("sheet", path = %self.source.display()).entered();
+        let state = self.state.and_then(|value| step(&self.source, value));
+        SheetJob {
+            source: self.source,
+            state,
+        }
+    }
 }
 
-fn rect_bbox(rect: &core::RotatedRect) -> Result<(f32, f32, f32, f32)> {
-    let mut pts = [Point2f::default(); 4];
-    rect.points(&mut pts)?;
-    let min_x = pts.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
-    let max_x = pts.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
-    let min_y = pts.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
-    let max_y = pts.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
-    Ok((min_x, min_y, max_x, max_y))
+/// Whether `sheet` is `source` as cropped before from the same content with
+/// the same detection parameters, and all of its crops are still in place.
+fn unchanged(sheet: &Sheet, source: &Path, digest: &str, params: &DetectParams) -> bool {
+    let mut recorded = sheet.params.clone();
+    if params.ruler_tick_mm.is_some() {
+        // The recorded DPI is the one the ruler measured.
+        recorded.dpi = params.dpi;
+    }
+    sheet.source == source
+        && sheet.source_sha256.as_deref() == Some(digest)
+        && recorded == *params
+        && sheet
+            .photos
+            .iter()
+            .all(|p| p.output.as_deref().is_some_and(Path::is_file))
 }
 
-fn is_image_file(path: &Path) -> bool {
-    const EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "bmp", "tif", "tiff"];
-    path.extension()
+fn detect_dir(
+    input_dir: &Path,
+    manifest_path: &Path,
+    resolver: &mut ParamResolver,
+    detect: &DetectArgs,
+    progress: &Progress,
+) -> Result<()> {
+    let mut manifest = Manifest::default();
+
+    let files = without_second_scans(image_files(input_dir).collect(), resolver)?;
+    let files = without_duplicates(files, detect, progress);
+    let files = unfinished_last(files);
+    progress.add_total(files.len());
+    for path in files {
+        let _sheet = tracing::info_span!("sheet", path = %path.display()).entered();
+        println!("{}", tr!("detecting", path = path.display().to_string()));
+        progress.start_file(&path);
+        let params = resolver.params_for(&path)?;
+        let result = settle::wait(&path)
+            .and_then(|()| read_source(&path, &params))
+            .and_then(|img| {
+                let (img, rotation) = orient_sheet(img, detect.sheet_orientation)?;
+                detect_sheet_cached(&img, &path, &params, rotation, detect)
+            });
+        match result {
+            Ok(sheet) => {
+                if sheet.photos.is_empty() {
+                    println!("{}", tr!("no-photos"));
+                } else {
+                    println!("{}", tr!("found-photos", count = sheet.photos.len()));
+                }
+                manifest.upsert(sheet);
+                progress.finish_file(Some(0));
+            }
+            Err(err) => report_failed(err, progress),
+        }
+    }
+
+    let queue = retry_queue(&manifest, detect.retry_target());
+    progress.add_total(queue.len());
+    for source in queue {
+        let Some(sheet) = manifest.sheets.iter_mut().find(|s| s.source == source) else {
+            continue;
+        };
+        let _sheet = tracing::info_span!("sheet", path = %source.display()).entered();
+        println!("{}", tr!("retrying", path = source.display().to_string()));
+        progress.start_file(&source);
+        match read_sheet(sheet).and_then(|img| retry_sheet(&img, sheet)) {
+            Ok(added) => {
+                if added == 0 {
+                    println!("{}", tr!("no-more-photos"));
+                } else {
+                    println!("{}", tr!("found-more-photos", count = added));
+                }
+                progress.finish_file(Some(0));
+            }
+            Err(err) => {
+                eprintln!("{}", tr!("failed", error = format!("{err:?}")));
+                progress.finish_file(None);
+            }
+        }
+    }
+
+    manifest.runs.push(run_record());
+    manifest.save(manifest_path)?;
+    print_duplicates(progress);
+    print_advice(&manifest);
+    Ok(())
+}
+
+/// Sheets whose main pass found fewer photos than `target` asks for.
+fn retry_queue(manifest: &Manifest, target: Option<RetryTarget>) -> Vec<PathBuf> {
+    let minimum = match target {
+        None => return Vec::new(),
+        Some(RetryTarget::Expect(expect)) => expect,
+        Some(RetryTarget::Average) if manifest.sheets.is_empty() => return Vec::new(),
+        Some(RetryTarget::Average) => {
+            let total: usize = manifest.sheets.iter().map(|s| s.photos.len()).sum();
+            total / manifest.sheets.len()
+        }
+    };
+    manifest
+        .sheets
+        .iter()
+        .filter(|s| s.photos.len() < minimum)
+        .map(|s| s.source.clone())
+        .collect()
+}
+
+/// Re-detects `image` with relaxed parameters and appends photos the main pass
+/// missed, marked as coming from the retry. Returns how many were added.
+#[tracing::instrument(skip_all, err)]
+fn retry_sheet(image: &Mat, sheet: &mut Sheet) -> Result<usize> {
+    let relaxed = sheet.params.relaxed();
+    let retried = detect_sheet(image, &sheet.source, &relaxed, None)?;
+    let mut added = 0;
+    for mut photo in retried.photos {
+        if sheet
+            .params
+            .max_photos
+            .is_some_and(|max| sheet.photos.len() >= max)
+        {
+            break;
+        }
+        let quad = photo.quad();
+        if sheet
+            .photos
+            .iter()
+            .any(|p| detect::quads_overlap(&p.quad(), &quad))
+        {
+            continue;
+        }
+        photo.pass = DetectPass::Retry;
+        sheet.photos.push(photo);
+        added += 1;
+    }
+    Ok(added)
+}
+
+fn crop(
+    manifest_path: &Path,
+    output_dir: &Path,
+    correct_skew: bool,
+    output: &OutputArgs,
+    progress: &Progress,
+) -> Result<()> {
+    let mut manifest = Manifest::load(manifest_path)?;
+    if correct_skew {
+        correct_systematic_skew(&mut manifest);
+    }
+
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("Failed to create output dir {:?}", output_dir))?;
+
+    check_space(
+        output_dir,
+        manifest.sheets.iter().map(|s| s.source.as_path()),
+        output,
+    )?;
+    let stems = manifest_stems(&manifest, output_dir);
+    progress.add_total(manifest.sheets.len());
+    for sheet in &mut manifest.sheets {
+        let _sheet = tracing::info_span!("sheet", path = %sheet.source.display()).entered();
+        println!(
+            "{}",
+            tr!("cropping", path = sheet.source.display().to_string())
+        );
+        progress.start_file(&sheet.source);
+        let stem = &stems[&sheet.source];
+        let result =
+            read_sheet(sheet).and_then(|img| crop_sheet(&img, sheet, output_dir, stem, output));
+        progress.finish_file(report_saved(result));
+    }
+
+    manifest.output_dir = Some(output_dir.to_path_buf());
+    manifest.runs.push(run_record());
+    manifest.save(manifest_path)
+}
+
+/// Measures how the content of every photo leans and, when nearly all lean
+/// alike, turns every quad by that angle and records it in the manifest.
+fn correct_systematic_skew(manifest: &mut Manifest) {
+    println!("{}", tr!("measuring-skew"));
+    let mut skews = Vec::new();
+    for sheet in &manifest.sheets {
+        let _sheet = tracing::info_span!("sheet", path = %sheet.source.display()).entered();
+        let measured = read_sheet(sheet).and_then(|image| {
+            sheet
+                .photos
+                .iter()
+                .map(|photo| {
+                    let warped = detect::warp_quad(&image, &photo.quad(), Interpolation::Linear)?;
+                    skew::measure(&warped)
+                })
+                .collect::<Result<Vec<_>>>()
+        });
+        match measured {
+            Ok(measured) => skews.extend(measured.into_iter().flatten()),
+            Err(err) => eprintln!("{}", tr!("failed", error = format!("{err:?}"))),
+        }
+    }
+    let Some(angle) = skew::systematic(&skews) else {
+        println!("{}", tr!("no-systematic-skew", count = skews.len()));
+        return;
+    };
+    println!(
+        "{}",
+        tr!(
+            "systematic-skew",
+            degrees = format!("{angle:.2}"),
+            count = skews.len()
+        )
+    );
+    for photo in manifest.sheets.iter_mut().flat_map(|s| &mut s.photos) {
+        photo.quad = skew::rotate_quad(&photo.quad(), angle).map(|p| [p.x, p.y]);
+    }
+    manifest.skew_correction = Some(manifest.skew_correction.unwrap_or(0.0) + angle);
+}
+
+/// Lists every file `run` would process with its size and resolution,
+/// reports image files it would skip, and estimates the runtime from
+/// reading and detecting `samples` sheets spread over the batch.
+fn plan(
+    input_dir: &Path,
+    output_dir: Option<&Path>,
+    incremental: bool,
+    samples: usize,
+    detect: &DetectArgs,
+) -> Result<()> {
+    let mut resolver = detect.resolver(input_dir)?;
+    let in_place = output_dir.is_some_and(|dir| same_dir(input_dir, dir));
+    let earlier = output_dir.and_then(|dir| Manifest::load(&dir.join(MANIFEST_FILE)).ok());
+    let progress = Progress::new("plan", None);
+    let mut files = sheet_files(
+        input_dir,
+        in_place,
+        earlier.as_ref(),
+        &mut resolver,
+        detect,
+        &progress,
+    )?;
+    // Left out as `run --incremental` leaves them out once it has read them.
+    if incremental && let Some(earlier) = &earlier {
+        let mut unfinished = Vec::with_capacity(files.len());
+        for path in files {
+            let params = resolver.params_for(&path)?;
+            let digest = hash::sha256_file(&path)?;
+            if earlier
+                .sheets
+                .iter()
+                .any(|s| unchanged(s, &path, &digest, &params))
+            {
+                tracing::debug!(path = %path.display(), "already cropped");
+            } else {
+                unfinished.push(path);
+            }
+        }
+        files = unfinished;
+    }
+    let unsupported: Vec<PathBuf> = WalkDir::new(input_dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| is_unsupported_image(p))
+        .collect();
+
+    let (mut total_bytes, mut total_pixels, mut measured) = (0, 0.0, 0);
+    let mut pixels = Vec::with_capacity(files.len());
+    for path in &files {
+        let bytes = fs::metadata(path).map_or(0, |m| m.len());
+        total_bytes += bytes;
+        let size = probe::dimensions(path).unwrap_or(None);
+        let line = match size {
+            Some((width, height)) => {
+                total_pixels += width as f64 * height as f64;
+                measured += 1;
+                tr!(
+                    "plan-file",
+                    path = path.display().to_string(),
+                    size = human_size(bytes),
+                    width = width,
+                    height = height
+                )
+            }
+            None => tr!(
+                "plan-file-unknown",
+                path = path.display().to_string(),
+                size = human_size(bytes)
+            ),
+        };
+        println!("{line}");
+        pixels.push(size.map(|(w, h)| w as f64 * h as f64));
+    }
+    for path in &unsupported {
+        let extension = path
+            .extension()
+            .map_or(String::new(), |e| e.to_string_lossy().to_lowercase());
+        println!(
+            "{}",
+            tr!(
+                "plan-unsupported",
+                path = path.display().to_string(),
+                extension = extension
+            )
+        );
+    }
+    println!(
+        "{}",
+        tr!(
+            "plan-total",
+            count = files.len(),
+            size = human_size(total_bytes),
+            megapixels = format!("{:.0}", total_pixels / 1e6),
+            unsupported = unsupported.len()
+        )
+    );
+
+    // Sheets of unknown size count as the average of the others.
+    let average = if measured > 0 {
+        total_pixels / measured as f64
+    } else {
+        0.0
+    };
+    let batch_pixels: f64 = pixels.iter().map(|p| p.unwrap_or(average)).sum();
+    let timed: Vec<usize> = (0..samples.min(files.len()))
+        .map(|i| i * files.len() / samples.min(files.len()))
+        .filter(|&i| pixels[i].is_some())
+        .collect();
+    let (mut elapsed, mut timed_pixels) = (Duration::ZERO, 0.0);
+    for &i in &timed {
+        let path = &files[i];
+        let params = resolver.params_for(path)?;
+        let start = Instant::now();
+        let result = read_source(path, &params)
+            .and_then(|image| detect_sheet(&image, path, &params, None).map(|_| ()));
+        match result {
+            Ok(()) => {
+                elapsed += start.elapsed();
+                timed_pixels += pixels[i].unwrap_or(0.0);
+            }
+            Err(err) => eprintln!(
+                "{}",
+                tr!(
+                    "skipping",
+                    path = path.display().to_string(),
+                    error = format!("{err:?}")
+                )
+            ),
+        }
+    }
+    if timed_pixels > 0.0 {
+        let serial = elapsed.mul_f64(batch_pixels / timed_pixels);
+        let workers = pipeline::cpu_workers().min(files.len()).max(1);
+        println!(
+            "{}",
+            tr!(
+                "plan-estimate",
+                serial = human_duration(serial),
+                parallel = human_duration(serial / workers as u32),
+                workers = workers,
+                samples = timed.len()
+            )
+        );
+    }
+    Ok(())
+}
+
+/// `bytes` in the largest binary unit that keeps a leading digit, e.g. "4.7 GB".
+fn human_size(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    for unit in ["B", "KB", "MB", "GB"] {
+        if value < 1024.0 {
+            return if unit == "B" {
+                format!("{bytes} B")
+            } else {
+                format!("{value:.1} {unit}")
+            };
+        }
+        value /= 1024.0;
+    }
+    format!("{value:.1} TB")
+}
+
+/// `duration` to the nearest minute once it exceeds one, e.g. "3 h 12 min".
+fn human_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{secs} s"),
+        60..3600 => format!("{} min", (secs + 30) / 60),
+        _ => {
+            let minutes = (secs + 30) / 60;
+            format!("{} h {} min", minutes / 60, minutes % 60)
+        }
+    }
+}
+
+fn review(manifest_path: &Path, review_dir: &Path, only: Option<ReviewStatus>) -> Result<()> {
+    let manifest = Manifest::load(manifest_path)?;
+
+    fs::create_dir_all(review_dir)
+        .with_context(|| format!("Failed to create review dir {:?}", review_dir))?;
+
+    let count = |status| {
+        manifest
+            .sheets
+            .iter()
+            .filter(|s| s.review == status)
+            .count()
+    };
+    println!(
+        "{}",
+        tr!(
+            "review-state",
+            approved = count(ReviewStatus::Approved),
+            needs_fix = count(ReviewStatus::NeedsFix),
+            unreviewed = count(ReviewStatus::Unreviewed)
+        )
+    );
+    for sheet in &manifest.sheets {
+        if only.is_some_and(|status| sheet.review != status) {
+            continue;
+        }
+        println!(
+            "{}",
+            tr!(
+                "sheet-photos",
+                path = sheet.source.display().to_string(),
+                count = sheet.photos.len()
+            )
+        );
+        let stem = file_stem(&sheet.source);
+        let out_path = review_dir.join(format!("{stem}_review.jpg"));
+        if let Err(err) = read_sheet(sheet)
+            .and_then(|img| draw_overlay(&img, sheet))
+            .and_then(|overlay| write_image(&out_path, &overlay, &Vector::new()))
+        {
+            eprintln!("{}", tr!("failed", error = format!("{err:?}")));
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits the sheets of the manifest in `output_dir` into numbered batches
+/// of `size` under [`batches::BATCHES_DIR`], each with a manifest of its
+/// sheets, their overlays and an HTML page to review them from. Batches of
+/// an earlier run are replaced.
+fn write_review_batches(output_dir: &Path, size: usize) -> Result<()> {
+    let manifest_path = output_dir.join(MANIFEST_FILE);
+    let manifest = Manifest::load(&manifest_path)?;
+    let root = output_dir.join(batches::BATCHES_DIR);
+    if root.is_dir() {
+        fs::remove_dir_all(&root)
+            .with_context(|| format!("Could not remove old batches {}", root.display()))?;
+    }
+    let stems = manifest_stems(&manifest, output_dir);
+    let total = manifest.sheets.len().div_ceil(size);
+    for (i, chunk) in manifest.sheets.chunks(size).enumerate() {
+        let dir = root.join(batches::dir_name(i + 1));
+        fs::create_dir_all(&dir).with_context(|| format!("Could not create {}", dir.display()))?;
+        let mut sheets = Vec::with_capacity(chunk.len());
+        for sheet in chunk {
+            let stem = stems
+                .get(&sheet.source)
+                .cloned()
+                .unwrap_or_else(|| file_stem(&sheet.source));
+            let name = format!("{stem}_review.jpg");
+            let overlay = read_sheet(sheet)
+                .and_then(|img| draw_overlay(&img, sheet))
+                .and_then(|overlay| shrink(&overlay, REVIEW_PAGE_SIZE))
+                .and_then(|overlay| write_image(&dir.join(&name), &overlay, &Vector::new()));
+            if let Err(err) = &overlay {
+                eprintln!("{}", tr!("failed", error = format!("{err:?}")));
+            }
+            sheets.push(batches::BatchSheet {
+                sheet,
+                overlay: overlay.ok().map(|()| name),
+            });
+        }
+        let page = batches::page(i + 1, total, &sheets, &manifest_path, &dir);
+        let page_path = dir.join("index.html");
+        fs::write(&page_path, page)
+            .with_context(|| format!("Could not write {}", page_path.display()))?;
+
+        let mut batch = Manifest {
+            output_dir: manifest.output_dir.clone(),
+            sheets: chunk.to_vec(),
+            skew_correction: manifest.skew_correction,
+            ..Manifest::default()
+        };
+        batch.save(&dir.join(MANIFEST_FILE))?;
+    }
+    println!(
+        "{}",
+        tr!(
+            "batches-written",
+            count = total,
+            path = root.display().to_string()
+        )
+    );
+    Ok(())
+}
+
+/// Longest side of the overlays on review batch pages.
+const REVIEW_PAGE_SIZE: f64 = 1600.0;
+
+/// `image` scaled down so its longest side is at most `side`.
+fn shrink(image: &Mat, side: f64) -> Result<Mat> {
+    let scale = side / image.cols().max(image.rows()) as f64;
+    if scale >= 1.0 {
+        return Ok(image.try_clone()?);
+    }
+    let mut small = Mat::default();
+    imgproc::resize(
+        image,
+        &mut small,
+        Size::new(0, 0),
+        scale,
+        scale,
+        imgproc::INTER_AREA,
+    )?;
+    Ok(small)
+}
+
+fn mark_reviewed(manifest_path: &Path, sources: &[PathBuf], status: ReviewStatus) -> Result<()> {
+    let marked = manifest::set_review(manifest_path, sources, status)?;
+    let status = status
+        .to_possible_value()
+        .map_or_else(String::new, |v| v.get_name().to_string());
+    println!(
+        "{}",
+        tr!("marked-reviewed", count = marked, status = status)
+    );
+    if marked < sources.len() {
+        eprintln!("{}", tr!("marked-missing", count = sources.len() - marked));
+    }
+    Ok(())
+}
+
+fn recrop(
+    manifest_path: &Path,
+    sources: &[PathBuf],
+    rescan: Option<&Path>,
+    detect: &DetectArgs,
+    output: &OutputArgs,
+    progress: &Progress,
+) -> Result<()> {
+    anyhow::ensure!(
+        rescan.is_none() || sources.len() == 1,
+        "--source-rescan replaces one sheet at a time"
+    );
+    let mut manifest = Manifest::load(manifest_path)?;
+    // Start from each sheet's previous parameters so only the given ones change.
+    let mut overrides = detect.global()?;
+    overrides.merge(&detect.overrides());
+    let output_dir = manifest.output_dir.clone().with_context(|| {
+        format!(
+            "Manifest {} has no crops yet; run `crop` first",
+            manifest_path.display()
+        )
+    })?;
+
+    let skew_correction = manifest.skew_correction;
+    let stems = manifest_stems(&manifest, &output_dir);
+
+    check_space(&output_dir, sources.iter().map(PathBuf::as_path), output)?;
+    progress.add_total(sources.len());
+    for source in sources {
+        let Some(sheet) = manifest.sheets.iter_mut().find(|s| &s.source == source) else {
+            eprintln!(
+                "{}",
+                tr!("not-in-manifest", path = source.display().to_string())
+            );
+            progress.start_file(source);
+            progress.finish_file(None);
+            continue;
+        };
+
+        let _sheet = tracing::info_span!("sheet", path = %source.display()).entered();
+        println!("{}", tr!("recropping", path = source.display().to_string()));
+        progress.start_file(source);
+
+        let mut params = sheet.params.clone();
+        // A sidecar written since detection tunes this sheet alone.
+        let sidecar = config::sidecar_path(source);
+        if sidecar.is_file() {
+            match ParamOverrides::load(&sidecar) {
+                Ok(sidecar) => sidecar.apply_to(&mut params),
+                Err(err) => {
+                    eprintln!("{}", tr!("failed", error = format!("{err:?}")));
+                    progress.finish_file(None);
+                    continue;
+                }
+            }
+        }
+        overrides.apply_to(&mut params);
+
+        if let Some(list) = &detect.reject_list {
+            params.exclude = list.for_source(source);
+        }
+        // The sheet is only replaced, and its old crops only deleted, once
+        // the new pass has written its crops.
+        let mut updated = sheet.clone();
+        let previous_rotation = sheet.rotation;
+        let result = if let Some(rescan) = rescan {
+            read_source(rescan, &params).and_then(|img| {
+                let img = previous_rotation.apply(&img)?;
+                updated.rescale([img.cols() as u32, img.rows() as u32])?;
+                if updated.source_sha256.is_some() {
+                    updated.source_sha256 = Some(hash::sha256_file(rescan)?);
+                }
+                updated.source = rescan.to_path_buf();
+                updated.params = params;
+                crop_sheet(&img, &mut updated, &output_dir, &stems[source], output)
+            })
+        } else {
+            read_source(source, &params).and_then(|img| {
+                // Without a new orientation check, keep the rotation found earlier.
+                let (img, rotation) = match detect.sheet_orientation {
+                    SheetOrientation::Off => (previous_rotation.apply(&img)?, previous_rotation),
+                    mode => orient_sheet(img, mode)?,
+                };
+                updated = detect_sheet_cached(&img, source, &params, rotation, detect)?;
+                // Keep the batch's skew correction, which a fresh detection knows nothing of.
+                if let Some(angle) = skew_correction {
+                    for photo in &mut updated.photos {
+                        photo.quad = skew::rotate_quad(&photo.quad(), angle).map(|p| [p.x, p.y]);
+                    }
+                }
+                crop_sheet(&img, &mut updated, &output_dir, &stems[source], output)
+            })
+        };
+        if result.is_ok() {
+            let old = mem::replace(sheet, updated);
+            // Stale crops would otherwise linger when the new pass finds fewer photos.
+            for stale in old.photos.iter().filter_map(|p| p.output.as_ref()) {
+                if !sheet
+                    .photos
+                    .iter()
+                    .any(|p| p.output.as_ref() == Some(stale))
+                {
+                    let _ = fs::remove_file(stale);
+                }
+            }
+        }
+        progress.finish_file(report_saved(result));
+    }
+
+    manifest.runs.push(run_record());
+    manifest.save(manifest_path)
+}
+
+fn export(
+    manifest_path: &Path,
+    dest_dir: &Path,
+    albums: Option<&Path>,
+    file_tags: bool,
+    split: Option<SplitEvery>,
+    captions: Option<&Path>,
+) -> Result<()> {
+    let manifest = Manifest::load(manifest_path)?;
+    let albums = albums.map(AlbumMapping::load).transpose()?;
+    let captions = match captions {
+        Some(csv) => photo_captions(&manifest, csv)?,
+        None => HashMap::new(),
+    };
+
+    fs::create_dir_all(dest_dir)
+        .with_context(|| format!("Failed to create destination dir {:?}", dest_dir))?;
+
+    let mut copied = 0;
+    // Current numbered part and what has gone into it so far.
+    let (mut part, mut part_photos, mut part_bytes) = (1, 0, 0);
+    for (s, sheet) in manifest.sheets.iter().enumerate() {
+        let placement = albums.as_ref().map(|a| a.place(&sheet.source));
+        let xmp = placement.as_ref().map(|p| p.xmp());
+        for (p, photo) in sheet.photos.iter().enumerate() {
+            let Some(src) = &photo.output else {
+                continue;
+            };
+            let Some(name) = src.file_name() else {
+                continue;
+            };
+            let size = fs::metadata(src).map_or(0, |m| m.len())
+                + xmp.as_ref().map_or(0, |x| x.len() as u64);
+            let full = match split {
+                Some(SplitEvery::Bytes(limit)) => part_bytes > 0 && part_bytes + size > limit,
+                Some(SplitEvery::Photos(limit)) => part_photos >= limit,
+                None => false,
+            };
+            if full {
+                (part, part_photos, part_bytes) = (part + 1, 0, 0);
+            }
+
+            let mut dir = dest_dir.to_path_buf();
+            if split.is_some() {
+                dir.push(format!("{part:03}"));
+            }
+            if let Some(placement) = &placement {
+                dir.push(placement.dir());
+            }
+            if let Err(err) = fs::create_dir_all(paths::long_path_safe(&dir)) {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "create-failed",
+                        path = dir.display().to_string(),
+                        error = err.to_string()
+                    )
+                );
+                continue;
+            }
+            let dest = paths::long_path_safe(&dir.join(name));
+            if let Err(err) = fs::copy(src, &dest) {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "copy-failed",
+                        path = src.display().to_string(),
+                        error = err.to_string()
+                    )
+                );
+                continue;
+            }
+            copied += 1;
+            part_photos += 1;
+            part_bytes += size;
+            if let Some(placement) = placement.as_ref().filter(|_| file_tags)
+                && let Err(err) = filetags::apply(&dest, placement)
+            {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "write-failed",
+                        path = dest.display().to_string(),
+                        error = format!("{err:#}")
+                    )
+                );
+            }
+            let mut xmp = xmp.clone();
+            if let Some(caption) = captions.get(&(s, p))
+                && let Err(err) = write_caption(&dest, caption, &mut xmp)
+            {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "write-failed",
+                        path = dest.display().to_string(),
+                        error = format!("{err:#}")
+                    )
+                );
+            }
+            if let Some(xmp) = &xmp {
+                let sidecar = albums::sidecar_path(&dest);
+                if let Err(err) = fs::write(&sidecar, xmp) {
+                    eprintln!(
+                        "{}",
+                        tr!(
+                            "write-failed",
+                            path = sidecar.display().to_string(),
+                            error = err.to_string()
+                        )
+                    );
+                }
+            }
+        }
+    }
+
+    if split.is_some() {
+        println!(
+            "{}",
+            tr!(
+                "exported-parts",
+                count = copied,
+                path = dest_dir.display().to_string(),
+                parts = part
+            )
+        );
+    } else {
+        println!(
+            "{}",
+            tr!(
+                "exported",
+                count = copied,
+                path = dest_dir.display().to_string()
+            )
+        );
+    }
+    Ok(())
+}
+
+/// The captions of `export --captions` by (sheet, photo) index in `manifest`.
+fn photo_captions(manifest: &Manifest, csv: &Path) -> Result<HashMap<(usize, usize), String>> {
+    let mut captions = HashMap::new();
+    for caption in names::read_captions(csv)? {
+        let wanted = Path::new(&caption.sheet);
+        let found = manifest
+            .sheets
+            .iter()
+            .position(|sheet| picks(wanted, &sheet.source))
+            .filter(|&s| caption.photo <= manifest.sheets[s].photos.len());
+        match found {
+            Some(s) => {
+                captions.insert((s, caption.photo - 1), caption.caption);
+            }
+            None => eprintln!(
+                "{}",
+                tr!(
+                    "caption-not-in-manifest",
+                    sheet = caption.sheet,
+                    photo = caption.photo
+                )
+            ),
+        }
+    }
+    Ok(captions)
+}
+
+/// Writes `caption` into the exported crop at `dest`: as EXIF
+/// `ImageDescription` where the format carries EXIF, and as XMP
+/// `dc:description` in the album `sidecar` when there is one, else in the
+/// file or in a new sidecar.
+fn write_caption(dest: &Path, caption: &str, sidecar: &mut Option<String>) -> Result<()> {
+    let bytes = fs::read(dest).with_context(|| format!("Could not read {}", dest.display()))?;
+    let mut bytes = exif::update(bytes.clone(), |tags| {
+        tags.set(
+            exif::TAG_IMAGE_DESCRIPTION,
+            exif::Value::Ascii(caption.to_string()),
+        );
+    })
+    .with_context(|| format!("Could not parse {}", dest.display()))?
+    .unwrap_or(bytes);
+    if let Some(packet) = sidecar {
+        *packet = xmp::with_dc_text(Some(packet), "dc:description", caption);
+    } else {
+        let packet = xmp::with_dc_text(xmp::extract(&bytes)?.as_deref(), "dc:description", caption);
+        match xmp::embed(bytes.clone(), &packet)? {
+            Some(tagged) => bytes = tagged,
+            None => *sidecar = Some(packet),
+        }
+    }
+    fs::write(dest, bytes).with_context(|| format!("Could not write {}", dest.display()))
+}
+
+/// Whether `wanted`, as given on the command line, names `crop`; a bare
+/// file name picks the crop of that name wherever it was written.
+fn picks(wanted: &Path, crop: &Path) -> bool {
+    wanted == crop
+        || (wanted.parent() == Some(Path::new("")) && Some(wanted.as_os_str()) == crop.file_name())
+}
+
+fn apply_names(
+    manifest_path: &Path,
+    csv: Option<&Path>,
+    number: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let mut manifest = Manifest::load(manifest_path)?;
+    // Photos to name as (sheet, photo) indices, in numbering order.
+    let mut order = Vec::new();
+    match csv {
+        Some(csv) => {
+            for naming in names::read_csv(csv)? {
+                let wanted = Path::new(&naming.crop);
+                let found = manifest.sheets.iter().enumerate().find_map(|(s, sheet)| {
+                    let p = sheet.photos.iter().position(|photo| {
+                        photo.output.as_deref().is_some_and(|o| picks(wanted, o))
+                    })?;
+                    Some((s, p))
+                });
+                match found {
+                    Some((s, p)) => {
+                        manifest.sheets[s].photos[p].title = Some(naming.title);
+                        order.push((s, p));
+                    }
+                    None => eprintln!("{}", tr!("not-in-manifest", path = naming.crop)),
+                }
+            }
+        }
+        None => {
+            for (s, sheet) in manifest.sheets.iter().enumerate() {
+                for (p, photo) in sheet.photos.iter().enumerate() {
+                    if photo.title.is_some() && photo.output.is_some() {
+                        order.push((s, p));
+                    }
+                }
+            }
+        }
+    }
+
+    let total = order.len();
+    let mut claimed = HashSet::new();
+    for (n, (s, p)) in order.into_iter().enumerate() {
+        let photo = &mut manifest.sheets[s].photos[p];
+        let (Some(current), Some(title)) = (photo.output.clone(), photo.title.clone()) else {
+            continue;
+        };
+        let stem = names::stem(&title, number.then_some((n + 1, total)));
+        let target = free_name(&current, &stem, &claimed);
+        claimed.insert(target.clone());
+        println!(
+            "{}",
+            tr!(
+                "renaming",
+                from = current.display().to_string(),
+                to = target.display().to_string()
+            )
+        );
+        if dry_run {
+            continue;
+        }
+        if target != current {
+            fs::rename(
+                paths::long_path_safe(&current),
+                paths::long_path_safe(&target),
+            )
+            .with_context(|| format!("Could not rename {}", current.display()))?;
+            let sidecar = albums::sidecar_path(&current);
+            if sidecar.is_file() {
+                fs::rename(&sidecar, albums::sidecar_path(&target))
+                    .with_context(|| format!("Could not rename {}", sidecar.display()))?;
+            }
+        }
+        write_title(&target, &title)?;
+        if photo.sha256.is_some() {
+            photo.sha256 = Some(hash::sha256_file(&target)?);
+        }
+        photo.output = Some(target);
+    }
+    if !dry_run {
+        manifest.save(manifest_path)?;
+    }
+    Ok(())
+}
+
+/// `current` renamed to `stem`, with `_2`, `_3` … added when another file
+/// or another crop of this batch has that name already.
+fn free_name(current: &Path, stem: &str, claimed: &HashSet<PathBuf>) -> PathBuf {
+    let extension = current
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("jpg");
+    let candidate = |suffix: String| current.with_file_name(format!("{stem}{suffix}.{extension}"));
+    (1..)
+        .map(|n| {
+            candidate(if n == 1 {
+                String::new()
+            } else {
+                format!("_{n}")
+            })
+        })
+        .find(|path| !claimed.contains(path) && (path == current || !path.exists()))
+        .expect("some suffix is free")
+}
+
+/// Stores `title` as the XMP `dc:title` of the crop at `path`, in the file
+/// where its format allows and in its sidecar otherwise.
+fn write_title(path: &Path, title: &str) -> Result<()> {
+    let bytes = fs::read(paths::long_path_safe(path))
+        .with_context(|| format!("Could not read {}", path.display()))?;
+    let existing =
+        xmp::extract(&bytes).with_context(|| format!("Could not parse {}", path.display()))?;
+    let packet = xmp::with_title(existing.as_deref(), title);
+    if let Some(tagged) = xmp::embed(bytes, &packet)? {
+        return fs::write(paths::long_path_safe(path), tagged)
+            .with_context(|| format!("Could not write {}", path.display()));
+    }
+    let sidecar = albums::sidecar_path(path);
+    let existing = fs::read_to_string(&sidecar).ok();
+    fs::write(&sidecar, xmp::with_title(existing.as_deref(), title))
+        .with_context(|| format!("Failed to write {}", sidecar.display()))
+}
+
+fn montage(
+    manifest_path: &Path,
+    output_dir: &Path,
+    selection: &[PathBuf],
+    order: MontageOrder,
+    layout: &PageLayout,
+) -> Result<()> {
+    let manifest = Manifest::load(manifest_path)?;
+    let mut crops: Vec<(&Path, Size)> = manifest
+        .sheets
+        .iter()
+        .flat_map(|sheet| &sheet.photos)
+        .filter_map(|photo| Some((photo.output.as_deref()?, detect::warp_size(&photo.quad()))))
+        .filter(|(crop, _)| selection.is_empty() || selection.iter().any(|w| picks(w, crop)))
+        .collect();
+    for wanted in selection {
+        if !crops.iter().any(|(crop, _)| picks(wanted, crop)) {
+            eprintln!(
+                "{}",
+                tr!("not-in-manifest", path = wanted.display().to_string())
+            );
+        }
+    }
+    match order {
+        MontageOrder::Manifest => {}
+        MontageOrder::Name => crops.sort_by(|a, b| a.0.file_name().cmp(&b.0.file_name())),
+        MontageOrder::Size => crops.sort_by_key(|(_, size)| std::cmp::Reverse(size.area())),
+    }
+    anyhow::ensure!(
+        !crops.is_empty(),
+        "No crops to arrange in {}; run `crop` first",
+        manifest_path.display()
+    );
+
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("Failed to create output dir {:?}", output_dir))?;
+    // Lets print dialogs pick the true page size.
+    let mut tags = ExifBuilder::new();
+    let dpi = exif::Value::Rational((layout.dpi * 100.0).round() as u32, 100);
+    tags.set(exif::TAG_X_RESOLUTION, dpi.clone());
+    tags.set(exif::TAG_Y_RESOLUTION, dpi);
+    tags.set(exif::TAG_RESOLUTION_UNIT, exif::Value::Short(2));
+    let encode_params = Vector::from(vec![imgcodecs::IMWRITE_JPEG_QUALITY, 95]);
+    for (index, page) in crops.chunks(layout.per_page()).enumerate() {
+        let photos = page
+            .iter()
+            .map(|(crop, _)| read_source(crop, &DetectParams::default()))
+            .collect::<Result<Vec<_>>>()?;
+        let path = output_dir.join(format!("montage_{:03}.jpg", index + 1));
+        write_image(&path, &montage::compose(&photos, layout)?, &encode_params)?;
+        exif::write_to_file(&paths::long_path_safe(&path), &tags)?;
+        println!(
+            "{}",
+            tr!(
+                "montage-page",
+                path = path.display().to_string(),
+                count = page.len()
+            )
+        );
+    }
+    Ok(())
+}
+
+fn verify(manifest_path: &Path) -> Result<()> {
+    let manifest = Manifest::load(manifest_path)?;
+
+    let (mut ok, mut unhashed, mut problems) = (0, 0, 0);
+    let mut listed = HashSet::new();
+    for photo in manifest.sheets.iter().flat_map(|s| &s.photos) {
+        let Some(path) = &photo.output else {
+            continue;
+        };
+        listed.insert(path.clone());
+        let Some(expected) = &photo.sha256 else {
+            unhashed += 1;
+            continue;
+        };
+        match hash::sha256_file(&paths::long_path_safe(path)) {
+            Ok(actual) if &actual == expected => ok += 1,
+            Ok(_) => {
+                println!(
+                    "{}",
+                    tr!("verify-modified", path = path.display().to_string())
+                );
+                problems += 1;
+            }
+            Err(_) if !path.exists() => {
+                println!(
+                    "{}",
+                    tr!("verify-missing", path = path.display().to_string())
+                );
+                problems += 1;
+            }
+            Err(err) => {
+                println!(
+                    "{}",
+                    tr!(
+                        "verify-unreadable",
+                        path = path.display().to_string(),
+                        error = format!("{err:#}")
+                    )
+                );
+                problems += 1;
+            }
+        }
+    }
+
+    // Images in the output directory that the manifest does not know about.
+    if let Some(output_dir) = &manifest.output_dir {
+        for path in image_files(output_dir) {
+            if !listed.contains(&path) {
+                println!(
+                    "{}",
+                    tr!("verify-untracked", path = path.display().to_string())
+                );
+            }
+        }
+    }
+
+    println!(
+        "{}",
+        tr!(
+            "verified",
+            ok = ok,
+            problems = problems,
+            unhashed = unhashed
+        )
+    );
+    anyhow::ensure!(problems == 0, "{problems} crops are missing or modified");
+    Ok(())
+}
+
+fn bench_sample(
+    sample_dir: &Path,
+    truth: Option<&Path>,
+    preset: Option<&Path>,
+    resolver: &mut ParamResolver,
+    grid: &[ParamOverrides],
+    cache: Option<&PrescaleCache>,
+) -> Result<()> {
+    let truth = truth.map(Manifest::load).transpose()?;
+    let mut samples = Vec::new();
+    for path in image_files(sample_dir) {
+        let params = resolver.params_for(&path)?;
+        let image = match cache {
+            Some(cache) => cache.load(&path, &params, || read_source(&path, &params)),
+            None => read_source(&path, &params),
+        };
+        let image = match image {
+            Ok(image) => image,
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "skipping",
+                        path = path.display().to_string(),
+                        error = format!("{err:?}")
+                    )
+                );
+                continue;
+            }
+        };
+        let truth = truth.as_ref().map(|manifest| {
+            manifest
+                .sheets
+                .iter()
+                .find(|s| s.source.file_name() == path.file_name())
+                .map(|s| s.photos.iter().map(Photo::quad).collect())
+                .unwrap_or_default()
+        });
+        samples.push(Sample {
+            image,
+            params,
+            truth,
+            scale: cache.map_or(1.0, PrescaleCache::scale),
+        });
+    }
+    anyhow::ensure!(
+        !samples.is_empty(),
+        "No images found in {}",
+        sample_dir.display()
+    );
+
+    println!(
+        "{}",
+        tr!(
+            "bench-start",
+            combinations = grid.len(),
+            sheets = samples.len()
+        )
+    );
+    let describe = |v: Option<f64>| v.map_or("-".to_string(), |v| v.to_string());
+    let mut scores = Vec::new();
+    for overrides in grid {
+        let score = bench::evaluate(&samples, overrides)?;
+        let accuracy = score.accuracy.map_or(String::new(), |acc| {
+            tr!(
+                "bench-accuracy",
+                precision = format!("{:.2}", acc.precision),
+                recall = format!("{:.2}", acc.recall),
+                f1 = format!("{:.2}", acc.f1())
+            )
+        });
+        println!(
+            "{}",
+            tr!(
+                "bench-row",
+                min_area = format!("{:>7}", describe(overrides.min_area)),
+                low = format!("{:>4}", describe(overrides.canny_low)),
+                high = format!("{:<4}", describe(overrides.canny_high)),
+                crops = format!("{:>4}", score.crops),
+                ms = format!("{:>6}", score.elapsed.as_millis()),
+                accuracy = accuracy
+            )
+        );
+        scores.push(score);
+    }
+
+    let Some(best) = bench::best(&scores) else {
+        return Ok(());
+    };
+    println!(
+        "{}",
+        tr!(
+            "bench-best",
+            min_area = describe(best.overrides.min_area),
+            low = describe(best.overrides.canny_low),
+            high = describe(best.overrides.canny_high)
+        )
+    );
+    if let Some(preset) = preset {
+        let text = toml::to_string(&best.overrides)?;
+        fs::write(preset, text)
+            .with_context(|| format!("Failed to write preset {}", preset.display()))?;
+        println!(
+            "{}",
+            tr!("preset-written", path = preset.display().to_string())
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tune")]
+fn tune_sheet(image: &Mat, params: &DetectParams, preset: Option<&Path>) -> Result<()> {
+    let Some(chosen) = photo_cropper::tune::tune(image, params)? else {
+        println!("{}", tr!("tune-cancelled"));
+        return Ok(());
+    };
+    let text = toml::to_string(&chosen)?;
+    print!("{text}");
+    if let Some(preset) = preset {
+        fs::write(preset, text)
+            .with_context(|| format!("Failed to write preset {}", preset.display()))?;
+        println!(
+            "{}",
+            tr!("preset-written", path = preset.display().to_string())
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "tune"))]
+fn tune_sheet(_image: &Mat, _params: &DetectParams, _preset: Option<&Path>) -> Result<()> {
+    anyhow::bail!("tune needs a build with the `tune` feature")
+}
+
+/// [`detect_sheet`] on `image`, the sheet at `source` turned by
+/// `rotation`, or the geometry `--detection-cache` kept from detecting the
+/// same scan the same way before. Dumping the detection always detects.
+#[tracing::instrument(skip_all, err)]
+fn detect_sheet_cached(
+    image: &Mat,
+    source: &Path,
+    params: &DetectParams,
+    rotation: Rotation,
+    detect: &DetectArgs,
+) -> Result<Sheet> {
+    let dump = detect.dump_detection.as_deref();
+    let Some(dir) = detect.detection_cache.as_deref().filter(|_| dump.is_none()) else {
+        let mut sheet = detect_sheet(image, source, params, dump)?;
+        sheet.rotation = rotation;
+        return Ok(sheet);
+    };
+    let cache = DetectionCache::new(dir);
+    let key = DetectionCache::key(&hash::sha256_file(source)?, params, rotation)?;
+    if let Some(mut sheet) = cache.get(&key) {
+        println!("{}", tr!("detection-cached"));
+        sheet.source = source.to_path_buf();
+        return Ok(sheet);
+    }
+    let mut sheet = detect_sheet(image, source, params, dump)?;
+    sheet.rotation = rotation;
+    cache.put(&key, &sheet)?;
+    Ok(sheet)
+}
+
+fn detect_sheet(
+    image: &Mat,
+    source: &Path,
+    params: &DetectParams,
+    dump: Option<&Path>,
+) -> Result<Sheet> {
+    let mut params = params.clone();
+    if let Some(tick_mm) = params.ruler_tick_mm {
+        match ruler::measure_dpi(image, tick_mm)? {
+            Some(dpi) => {
+                println!("{}", tr!("ruler-found", dpi = dpi.round()));
+                params.dpi = Some(dpi);
+            }
+            None => println!("{}", tr!("no-ruler")),
+        }
+    }
+    let mm_per_px = params.dpi.map(|dpi| (25.4 / dpi) as f32);
+
+    let mut detected = PhotoCropper::new(params.clone())
+        .detect_iter(image)
+        .with_context(|| format!("Failed to analyze {}", source.display()))?;
+    let rejected: Vec<Rejected> = detected
+        .rejected()
+        .iter()
+        .filter(|r| r.reason == RejectReason::Text)
+        .map(Rejected::from)
+        .collect();
+    if !rejected.is_empty() {
+        println!("{}", tr!("text-rejected", count = rejected.len()));
+    }
+    let photos: Vec<Photo> = detected
+        .by_ref()
+        .map(|p| {
+            let mut photo = Photo::new(&p.quad, p.confidence);
+            photo.shape = p.shape;
+            photo.off_size = p.off_size;
+            photo.set_outline(&p.outline);
+            let size = p.warped.size();
+            photo.size_mm = mm_per_px.map(|mm| [size.width as f32 * mm, size.height as f32 * mm]);
+            photo
+        })
+        .collect();
+    if let Some(dir) = dump {
+        dump_detection(dir, source, &photos, &detected)?;
+    }
+
+    Ok(Sheet {
+        source: source.to_path_buf(),
+        params,
+        rotation: Rotation::None,
+        source_sha256: None,
+        size: Some([image.cols() as u32, image.rows() as u32]),
+        photos,
+        rejected,
+        review: ReviewStatus::Unreviewed,
+    })
+}
+
+/// Writes the intermediate results of detecting `source` to
+/// `dir/<name>.detection.json`, for tuning parameters and debugging misses.
+fn dump_detection(
+    dir: &Path,
+    source: &Path,
+    photos: &[Photo],
+    detected: &DetectIter,
+) -> Result<()> {
+    let points = |points: &[Point2f]| points.iter().map(|p| [p.x, p.y]).collect::<Vec<_>>();
+    let dump = serde_json::json!({
+        "source": source,
+        "photos": photos,
+        "contours": detected.contours().iter().map(|c| points(c)).collect::<Vec<_>>(),
+        "candidates": detected.candidates().iter().map(|q| points(q)).collect::<Vec<_>>(),
+        "rejected": detected.rejected().iter().map(|r| serde_json::json!({
+            "quad": points(&r.quad),
+            "reason": r.reason,
+        })).collect::<Vec<_>>(),
+    });
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    let name = source.file_name().unwrap_or(source.as_os_str());
+    let path = dir.join(format!("{}.detection.json", name.to_string_lossy()));
+    fs::write(&path, serde_json::to_string_pretty(&dump)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Turns the sheet upright according to `mode`, returning the rotation applied.
+#[tracing::instrument(skip(image), err)]
+fn orient_sheet(image: Mat, mode: SheetOrientation) -> Result<(Mat, Rotation)> {
+    let rotation = match mode {
+        SheetOrientation::Off => None,
+        SheetOrientation::Text => orientation::upside_down_by_text(&image)?
+            .filter(|&upside_down| upside_down)
+            .map(|_| Rotation::Rot180),
+        SheetOrientation::Tesseract => orientation::tesseract_osd(&image)?,
+    };
+
+    match rotation {
+        Some(rotation) if !rotation.is_none() => {
+            println!("{}", tr!("sheet-turned", degrees = u16::from(rotation)));
+            Ok((rotation.apply(&image)?, rotation))
+        }
+        _ => Ok((image, Rotation::None)),
+    }
+}
+
+#[tracing::instrument(skip_all, err)]
+fn crop_sheet(
+    image: &Mat,
+    sheet: &mut Sheet,
+    output_dir: &Path,
+    stem: &str,
+    output: &OutputArgs,
+) -> Result<usize> {
+    let plan = plan_crops(sheet, output_dir, stem, output)?;
+    let crops = warp_crops(image, sheet, &plan, output)?;
+    let encoded = encode_crops(crops, sheet, &plan, output)?;
+    write_crops(encoded, sheet, &plan)
+}
+
+/// What the crops of one sheet share, worked out before any is warped.
+struct CropPlan {
+    output_dir: PathBuf,
+    stem: String,
+    extension: String,
+    exif_capable: bool,
+    source_alpha: Option<Mat>,
+    /// The sheet at its native 16 bits per channel, for `--bit-depth 16`.
+    source_deep: Option<Mat>,
+    encode_params: Vector<i32>,
+    provenance: ExifBuilder,
+    /// Absolute path of the source, for the crops' provenance comment.
+    source: PathBuf,
+    source_sha256: Option<String>,
+    params_sha256: String,
+    source_mtime: Option<SystemTime>,
+    /// The source's fields picked by `--tag-map`.
+    mapped_tags: Option<MappedTags>,
+}
+
+/// A crop's final pixels and where they go.
+struct Crop {
+    index: usize,
+    pixels: Mat,
+    path: PathBuf,
+    /// The rotation is left to the EXIF Orientation tag.
+    via_exif: bool,
+    /// Low confidence or soft, so it gets the `--label` and `--rating`.
+    needs_review: bool,
+    /// The whole sheet as it is, so `--copy-identical` writes the source's own bytes.
+    identical: bool,
+    date: Option<DateEstimate>,
+    /// Share of the crop washed out by glare, when noticeable.
+    glare: Option<f32>,
+    era: Option<Era>,
+    /// Images saved beside the crop: its `--save-masks` mask and its
+    /// `--compare-warp` cut, each with its path.
+    side_files: Vec<(PathBuf, Mat)>,
+}
+
+/// A crop encoded with its metadata, ready to be written.
+struct EncodedCrop {
+    index: usize,
+    path: PathBuf,
+    bytes: Vec<u8>,
+    /// XMP packet for a sidecar, for formats that cannot embed one.
+    sidecar: Option<String>,
+    date: Option<DateEstimate>,
+    glare: Option<f32>,
+    era: Option<Era>,
+    side_files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+fn plan_crops(
+    sheet: &mut Sheet,
+    output_dir: &Path,
+    stem: &str,
+    output: &OutputArgs,
+) -> Result<CropPlan> {
+    let extension = output_extension(output.format, &sheet.source).to_string();
+    let exif_capable = supports_exif(&extension);
+    anyhow::ensure!(
+        output.mask_outside != Some(MaskFill::Transparent) || supports_alpha(&extension),
+        "--mask-outside transparent needs an output format with alpha (png or tiff), not {extension}"
+    );
+    anyhow::ensure!(
+        output.bit_depth == BitDepth::Eight || supports_alpha(&extension),
+        "--bit-depth 16 needs png or tiff output, not {extension}"
+    );
+    anyhow::ensure!(
+        !output.keep_alpha || supports_alpha(&extension),
+        "--keep-alpha needs an output format with alpha (png or tiff), not {extension}"
+    );
+    let source_alpha = if output.keep_alpha {
+        read_sheet_alpha(sheet)?
+    } else {
+        None
+    };
+    let source_deep = match output.bit_depth {
+        BitDepth::Sixteen => {
+            // These adjust tones at 8 bits, which 16-bit crops would lose.
+            anyhow::ensure!(
+                !output.auto_levels
+                    && output.match_histograms.is_none()
+                    && !output.auto_sharpen
+                    && output.working_colorspace == WorkingColorspace::Srgb,
+                "--bit-depth 16 cannot be combined with --auto-levels, --match-histograms, --auto-sharpen or --working-colorspace linear"
+            );
+            Some(read_sheet_deep(sheet)?)
+        }
+        BitDepth::Eight => None,
+    };
+
+    let mut provenance = ExifBuilder::new();
+    if let Some(dpi) = sheet.params.dpi {
+        // Lets viewers print or measure the crop at its true size.
+        let dpi = exif::Value::Rational((dpi * 100.0).round() as u32, 100);
+        provenance.set(exif::TAG_X_RESOLUTION, dpi.clone());
+        provenance.set(exif::TAG_Y_RESOLUTION, dpi);
+        provenance.set(exif::TAG_RESOLUTION_UNIT, exif::Value::Short(2));
+    }
+    let source = fs::canonicalize(&sheet.source).unwrap_or_else(|_| sheet.source.clone());
+    if output.link_source {
+        let digest = match &sheet.source_sha256 {
+            Some(digest) => digest.clone(),
+            None => hash::sha256_file(&sheet.source)?,
+        };
+        provenance.set(
+            exif::TAG_DOCUMENT_NAME,
+            exif::Value::Ascii(source.display().to_string()),
+        );
+        provenance.set(
+            exif::TAG_PAGE_NAME,
+            exif::Value::Ascii(format!("sha256:{digest}")),
+        );
+        if !exif_capable {
+            println!("{}", tr!("no-exif", format = extension.as_str()));
+        }
+        sheet.source_sha256 = Some(digest);
+    }
+    let source_mtime = output
+        .keep_times
+        .then(|| fs::metadata(&sheet.source).and_then(|m| m.modified()))
+        .transpose()
+        .with_context(|| format!("Could not read mtime of {}", sheet.source.display()))?;
+    let mapped_tags = match TAG_MAP.get() {
+        Some(map) => {
+            let bytes = fs::read(paths::long_path_safe(&sheet.source))
+                .with_context(|| format!("Could not read {}", sheet.source.display()))?;
+            let mapped = map.apply_to(&bytes).with_context(|| {
+                format!("Could not read the metadata of {}", sheet.source.display())
+            })?;
+            Some(mapped)
+        }
+        None => None,
+    };
+
+    Ok(CropPlan {
+        output_dir: output_dir.to_path_buf(),
+        stem: stem.to_string(),
+        encode_params: output.encode_params(&extension),
+        extension,
+        exif_capable,
+        source_alpha,
+        source_deep,
+        provenance,
+        source,
+        source_sha256: sheet.source_sha256.clone(),
+        params_sha256: hash::sha256_hex(serde_json::to_string(&sheet.params)?.as_bytes()),
+        source_mtime,
+        mapped_tags,
+    })
+}
+
+/// Straightens and finishes every photo of `sheet`, ready for encoding.
+#[tracing::instrument(skip_all, err)]
+fn warp_crops(
+    image: &Mat,
+    sheet: &Sheet,
+    plan: &CropPlan,
+    output: &OutputArgs,
+) -> Result<Vec<Crop>> {
+    let sheet_levels = match output.match_histograms {
+        Some(LevelsScope::Sheet) => Some(sheet_levels(image, sheet, output)?),
+        _ => None,
+    };
+    let auto_levels = output.auto_levels || output.match_histograms.is_some();
+    let linear = match output.working_colorspace {
+        WorkingColorspace::Linear => Some(enhance::to_linear(image)?),
+        WorkingColorspace::Srgb => None,
+    };
+
+    let mut crops = Vec::with_capacity(sheet.photos.len());
+    for (idx, photo) in sheet.photos.iter().enumerate() {
+        let quad = adjusted_quad(&photo.quad(), output)?;
+        let mut warped = match &linear {
+            Some(linear) => {
+                enhance::from_linear(&detect::warp_quad(linear, &quad, output.interpolation)?)?
+            }
+            None => detect::warp_quad(image, &quad, output.interpolation)?,
+        };
+        if auto_levels {
+            warped = match &sheet_levels {
+                Some(levels) => levels.apply(&warped)?,
+                None => Levels::measure(&warped, None)?.apply(&warped)?,
+            };
+        }
+        if let Some(min_side) = output.warn_min_side
+            && warped.cols().min(warped.rows()) < min_side
+        {
+            println!(
+                "{}",
+                tr!(
+                    "photo-too-small",
+                    index = idx + 1,
+                    width = warped.cols(),
+                    height = warped.rows(),
+                    min = min_side
+                )
+            );
+        }
+        let glare = glare::measure(&warped)?;
+        if let Some(share) = glare {
+            println!(
+                "{}",
+                tr!(
+                    "glare-found",
+                    index = idx + 1,
+                    percent = format!("{:.0}", share * 100.0)
+                )
+            );
+        }
+        let needs_review = (output.label.is_some() || output.rating.is_some())
+            && (Grade::of(photo.confidence) == Grade::Low
+                || glare.is_some()
+                || enhance::sharpness(&warped)? < output.sharpen_threshold);
+        if needs_review {
+            println!("{}", tr!("needs-review", index = idx + 1));
+        }
+        let identical = output.copy_identical
+            && !output.alters_crops()
+            && sheet.photos.len() == 1
+            && sheet.rotation.is_none()
+            && photo.rotation.is_none()
+            && same_format(&plan.extension, &sheet.source)
+            && fills_frame(&photo.quad(), image.size()?);
+        if identical {
+            println!("{}", tr!("copied-identical", index = idx + 1));
+        }
+        if output.auto_sharpen
+            && let Some(sharpened) = enhance::auto_sharpen(&warped, output.sharpen_threshold)?
+        {
+            println!("{}", tr!("sharpened", index = idx + 1));
+            warped = sharpened;
+        }
+        let date = match DATE_MODEL.get() {
+            Some(model) => {
+                let upright = photo.rotation.apply(&warped)?;
+                let estimate = model
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .estimate(&upright)?;
+                println!(
+                    "{}",
+                    tr!(
+                        "date-estimated",
+                        index = idx + 1,
+                        from = estimate.from,
+                        to = estimate.to,
+                        confidence = format!("{:.0}", estimate.confidence * 100.0)
+                    )
+                );
+                Some(estimate)
+            }
+            None => None,
+        };
+        let era = match output.sort_by_era {
+            Some(_) => {
+                let era = Era::of(&warped, output.bw_tolerance)?;
+                println!("{}", tr!("era-found", index = idx + 1, era = era.name()));
+                Some(era)
+            }
+            None => None,
+        };
+        let gray = output.bw_as_gray && enhance::is_monochrome(&warped, output.bw_tolerance)?;
+        // Everything above looks at the 8-bit crop; a 16-bit one is warped
+        // anew from the source at its own depth and finished the same way.
+        let (mut warped, scale) = match &plan.source_deep {
+            Some(deep) => (detect::warp_quad(deep, &quad, output.interpolation)?, 257.0),
+            None => (warped, 1.0),
+        };
+        if gray {
+            warped = enhance::to_gray(&warped)?;
+        }
+        if let Some(fill) = output.mask_outside
+            && !photo.outline.is_empty()
+        {
+            let fill = match fill {
+                MaskFill::Color(color) => {
+                    MaskFill::Color(Scalar::from_array(color.0.map(|c| c * scale)))
+                }
+                MaskFill::Transparent => MaskFill::Transparent,
+            };
+            warped = enhance::mask_outside(&warped, &quad, &photo.outline(), fill)?;
+        }
+        if let Some(alpha) = &plan.source_alpha {
+            let mut alpha = detect::warp_quad(alpha, &quad, output.interpolation)?;
+            if warped.depth() != alpha.depth() {
+                let mut deep = Mat::default();
+                alpha.convert_to(&mut deep, warped.depth(), scale, 0.0)?;
+                alpha = deep;
+            }
+            warped = alpha::attach(&warped, &alpha)?;
+        }
+        let via_exif = output.orient_via_exif && plan.exif_capable && !photo.rotation.is_none();
+        let pixels = if via_exif {
+            warped
+        } else {
+            photo.rotation.apply(&warped)?
+        };
+
+        let grade = Grade::of(photo.confidence);
+        let filename = match output.confidence_grades {
+            Some(GradeStyle::Suffix) => {
+                format!(
+                    "{}_{}_{}.{}",
+                    plan.stem,
+                    idx + 1,
+                    grade.letter(),
+                    plan.extension
+                )
+            }
+            _ => format!("{}_{}.{}", plan.stem, idx + 1, plan.extension),
+        };
+        let mut path = plan.output_dir.clone();
+        if let Some(era) = era.filter(|_| output.sort_by_era == Some(EraStyle::Folders)) {
+            path.push(era.name());
+        }
+        if output.confidence_grades == Some(GradeStyle::Folders) {
+            path.push(grade.folder());
+        }
+        path.push(filename);
+        let mut side_files = Vec::new();
+        if output.save_masks {
+            let mask = enhance::photo_mask(&quad, &photo.outline())?;
+            let mask = if via_exif {
+                mask
+            } else {
+                photo.rotation.apply(&mask)?
+            };
+            side_files.push((mask_path(&path), mask));
+        }
+        if let Some(every) = output.compare_warp
+            && sampled(&path, every)
+            && let Some(cut) = comparison_cut(image, &quad, photo.rotation)?
+        {
+            side_files.push((comparison_path(&path), cut));
+        }
+        crops.push(Crop {
+            index: idx,
+            pixels,
+            path,
+            via_exif,
+            needs_review,
+            identical,
+            date,
+            glare,
+            era,
+            side_files,
+        });
+    }
+    Ok(crops)
+}
+
+/// Encodes `crops` in memory with their EXIF block.
+#[tracing::instrument(skip_all, err)]
+fn encode_crops(
+    crops: Vec<Crop>,
+    sheet: &Sheet,
+    plan: &CropPlan,
+    output: &OutputArgs,
+) -> Result<Vec<EncodedCrop>> {
+    let triage = output.triage();
+    crops
+        .into_iter()
+        .map(|crop| {
+            let side_files = crop
+                .side_files
+                .iter()
+                .map(|(path, image)| Ok((path.clone(), encode_image(path, image, &Vector::new())?)))
+                .collect::<Result<Vec<_>>>()?;
+            if crop.identical {
+                // Re-encoding would only lose quality; the source's metadata stays as it was.
+                let bytes = fs::read(paths::long_path_safe(&sheet.source))
+                    .with_context(|| format!("Could not read {}", sheet.source.display()))?;
+                return Ok(EncodedCrop {
+                    index: crop.index,
+                    path: crop.path,
+                    bytes,
+                    sidecar: None,
+                    date: None,
+                    glare: crop.glare,
+                    era: crop.era,
+                    side_files,
+                });
+            }
+            let mut encode_params = plan.encode_params.clone();
+            if output.adaptive_quality && is_jpeg(&plan.extension) {
+                let quality = enhance::adaptive_jpeg_quality(&crop.pixels)?;
+                tracing::debug!(crop = crop.index + 1, quality, "adaptive JPEG quality");
+                encode_params.push(imgcodecs::IMWRITE_JPEG_QUALITY);
+                encode_params.push(quality);
+            }
+            let mut bytes = encode_image(&crop.path, &crop.pixels, &encode_params)?;
+            let mut tags = plan.provenance.clone();
+            // Lets a crop found on its own be traced back to its sheet.
+            let mut comment = serde_json::json!({
+                "tool": concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")),
+                "source": plan.source,
+                "source_sha256": plan.source_sha256,
+                "crop": crop.index + 1,
+                "params_sha256": plan.params_sha256,
+            });
+            if output.embed_run_record {
+                comment["run"] = serde_json::to_value(run_record())?;
+            }
+            if let Some(date) = &crop.date {
+                // Only an estimate, so it stays out of the EXIF date fields,
+                // which photo managers take for the real capture time.
+                comment["date_estimate"] = serde_json::to_value(date)?;
+            }
+            tags.set_exif(
+                exif::TAG_USER_COMMENT,
+                exif::Value::user_comment(&comment.to_string()),
+            );
+            if crop.via_exif {
+                tags.set(
+                    exif::TAG_ORIENTATION,
+                    exif::Value::Short(sheet.photos[crop.index].rotation.exif_orientation()),
+                );
+            }
+            if let Some(mapped) = &plan.mapped_tags {
+                mapped.apply(&mut tags);
+            }
+            if output.exif_thumbnail && is_jpeg(&plan.extension) {
+                tags.set_thumbnail(thumbnail_jpeg(&crop.pixels)?);
+            }
+            if plan.exif_capable
+                && !tags.is_empty()
+                && let Some(tagged) = exif::embed(bytes.clone(), &tags)
+                    .with_context(|| format!("Could not parse {}", crop.path.display()))?
+            {
+                bytes = tagged;
+            }
+            let mut sidecar = None;
+            let mut packet = (crop.needs_review && !triage.is_empty()).then(|| triage.packet());
+            if let Some(era) = crop
+                .era
+                .filter(|_| output.sort_by_era == Some(EraStyle::Keywords))
+            {
+                packet = Some(xmp::with_keywords(
+                    packet.as_deref(),
+                    &[format!("Era|{}", era.name())],
+                ));
+            }
+            if let Some(mapped) = &plan.mapped_tags {
+                packet = mapped.xmp(packet);
+            }
+            if let Some(packet) = packet {
+                match xmp::embed(bytes.clone(), &packet)
+                    .with_context(|| format!("Could not parse {}", crop.path.display()))?
+                {
+                    Some(tagged) => bytes = tagged,
+                    None => sidecar = Some(packet),
+                }
+            }
+            Ok(EncodedCrop {
+                index: crop.index,
+                path: crop.path,
+                bytes,
+                sidecar,
+                date: crop.date,
+                glare: crop.glare,
+                era: crop.era,
+                side_files,
+            })
+        })
+        .collect()
+}
+
+/// Marks the stem of a `--compare-warp` bounding-box cut.
+const COMPARE_SUFFIX: &str = ".bbox";
+
+/// Whether the crop at `path` is one of about one in `every` picked for
+/// `--compare-warp`; the pick follows from the name, so reruns agree.
+fn sampled(path: &Path, every: u32) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let digest = hash::sha256_hex(name.as_bytes());
+    u64::from_str_radix(&digest[..15], 16).is_ok_and(|n| n % u64::from(every) == 0)
+}
+
+/// The axis-aligned bounding box of `quad`, cut from `image` without any
+/// warp but turned like the crop; `None` when it lies off the image.
+fn comparison_cut(image: &Mat, quad: &Quad, rotation: Rotation) -> Result<Option<Mat>> {
+    let xs = quad.iter().map(|p| p.x);
+    let ys = quad.iter().map(|p| p.y);
+    let (x1, x2) = (
+        xs.clone().fold(f32::INFINITY, f32::min).floor().max(0.0) as i32,
+        (xs.fold(f32::NEG_INFINITY, f32::max).ceil() as i32).min(image.cols()),
+    );
+    let (y1, y2) = (
+        ys.clone().fold(f32::INFINITY, f32::min).floor().max(0.0) as i32,
+        (ys.fold(f32::NEG_INFINITY, f32::max).ceil() as i32).min(image.rows()),
+    );
+    if x2 <= x1 || y2 <= y1 {
+        return Ok(None);
+    }
+    let cut = Mat::roi(image, opencv::core::Rect::new(x1, y1, x2 - x1, y2 - y1))?.try_clone()?;
+    Ok(Some(rotation.apply(&cut)?))
+}
+
+/// Where the `--compare-warp` cut of the crop at `crop_path` goes:
+/// `<crop>.bbox.<ext>` beside it.
+fn comparison_path(crop_path: &Path) -> PathBuf {
+    let stem = crop_path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = crop_path.extension().unwrap_or_default().to_string_lossy();
+    crop_path.with_file_name(format!("{stem}{COMPARE_SUFFIX}.{extension}"))
+}
+
+/// Marks the stem of a `--save-masks` mask.
+const MASK_SUFFIX: &str = ".mask";
+
+/// Where the mask of the crop at `crop_path` goes: `<crop>.mask.png` beside
+/// it, lossless whatever the crops' format.
+fn mask_path(crop_path: &Path) -> PathBuf {
+    let stem = crop_path.file_stem().unwrap_or_default().to_string_lossy();
+    crop_path.with_file_name(format!("{stem}{MASK_SUFFIX}.png"))
+}
+
+/// Writes encoded crops to disk and records them in `sheet`.
+#[tracing::instrument(skip_all, err)]
+fn write_crops(crops: Vec<EncodedCrop>, sheet: &mut Sheet, plan: &CropPlan) -> Result<usize> {
+    for crop in crops {
+        if let Some(dir) = crop.path.parent()
+            && dir != plan.output_dir
+        {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create output dir {:?}", dir))?;
+        }
+        diskspace::write_waiting_for_space(&paths::long_path_safe(&crop.path), &crop.bytes)
+            .with_context(|| format!("Failed to write image {}", crop.path.display()))?;
+        if let Some(packet) = &crop.sidecar {
+            let sidecar = albums::sidecar_path(&crop.path);
+            fs::write(paths::long_path_safe(&sidecar), packet)
+                .with_context(|| format!("Failed to write {}", sidecar.display()))?;
+        }
+        if let Some(mtime) = plan.source_mtime {
+            set_mtime(&crop.path, mtime)?;
+        }
+        for (path, bytes) in &crop.side_files {
+            diskspace::write_waiting_for_space(&paths::long_path_safe(path), bytes)
+                .with_context(|| format!("Failed to write image {}", path.display()))?;
+        }
+        let photo = &mut sheet.photos[crop.index];
+        photo.sha256 = Some(hash::sha256_hex(&crop.bytes));
+        photo.output = Some(crop.path);
+        photo.run = Some(run_record().id);
+        photo.date_estimate = crop.date;
+        photo.glare = crop.glare;
+        photo.era = crop.era;
+    }
+    Ok(sheet.photos.len())
+}
+
+/// Applies `--inset` / `--outset` to a detected quad; the manifest keeps the original.
+fn adjusted_quad(quad: &Quad, output: &OutputArgs) -> Result<Quad> {
+    if output.inset.is_none() && output.outset.is_none() {
+        return Ok(*quad);
+    }
+    let size = detect::warp_size(quad);
+    let per_side = |length: i32| {
+        output.outset.map_or(0.0, |m| m.resolve(length))
+            - output.inset.map_or(0.0, |m| m.resolve(length))
+    };
+    detect::adjust_quad(quad, per_side(size.width), per_side(size.height))
+}
+
+fn set_mtime(path: &Path, mtime: SystemTime) -> Result<()> {
+    fs::File::options()
+        .write(true)
+        .open(paths::long_path_safe(path))
+        .and_then(|file| file.set_modified(mtime))
+        .with_context(|| format!("Could not set mtime of {}", path.display()))
+}
+
+/// Parses "N" (pixels) or "N%" into a [`Margin`].
+fn parse_margin(value: &str) -> Result<Margin, String> {
+    let (number, percent) = match value.trim().strip_suffix('%') {
+        Some(number) => (number, true),
+        None => (
+            value.trim().strip_suffix("px").unwrap_or(value.trim()),
+            false,
+        ),
+    };
+    let amount: f32 = number
+        .trim()
+        .parse()
+        .ok()
+        .filter(|v: &f32| v.is_finite() && *v >= 0.0)
+        .ok_or_else(|| {
+            format!("expected a non-negative number of pixels or a percentage, got \"{value}\"")
+        })?;
+    Ok(if percent {
+        Margin::Percent(amount)
+    } else {
+        Margin::Pixels(amount)
+    })
+}
+
+/// Parses a size in bytes with an optional K, M, G or T prefix (powers of
+/// 1024) and "B", such as "4.7G" or "700MB", into [`SplitEvery::Bytes`], or a
+/// bare count into [`SplitEvery::Photos`].
+fn parse_split(value: &str) -> Result<SplitEvery, String> {
+    let upper = value.trim().to_uppercase();
+    let (number, in_bytes) = match upper.strip_suffix('B') {
+        Some(number) => (number, true),
+        None => (upper.as_str(), false),
+    };
+    let unit = ["K", "M", "G", "T"]
+        .iter()
+        .position(|suffix| number.ends_with(suffix));
+    let invalid = || format!("expected a size like 4.7G or a number of photos, got \"{value}\"");
+    let split = match unit {
+        Some(unit) => {
+            let amount: f64 = number[..number.len() - 1]
+                .trim()
+                .parse()
+                .map_err(|_| invalid())?;
+            SplitEvery::Bytes((amount * 1024f64.powi(unit as i32 + 1)) as u64)
+        }
+        None if in_bytes => SplitEvery::Bytes(number.trim().parse().map_err(|_| invalid())?),
+        None => SplitEvery::Photos(number.trim().parse().map_err(|_| invalid())?),
+    };
+    match split {
+        SplitEvery::Bytes(0) | SplitEvery::Photos(0) => Err(invalid()),
+        split => Ok(split),
+    }
+}
+
+/// Parses "off" as 0 or an odd kernel side of at least 3.
+fn parse_blur(value: &str) -> Result<i32, String> {
+    if value.eq_ignore_ascii_case("off") {
+        return Ok(0);
+    }
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|side: &i32| *side >= 3 && side % 2 == 1)
+        .ok_or_else(|| {
+            format!("expected an odd kernel side such as 3, 5 or 7, or off, got \"{value}\"")
+        })
+}
+
+fn parse_layout(value: &str) -> Result<Layout, String> {
+    Layout::load(Path::new(value)).map_err(|err| format!("{err:#}"))
+}
+
+fn parse_lens(value: &str) -> Result<LensProfile, String> {
+    LensProfile::load(Path::new(value)).map_err(|err| format!("{err:#}"))
+}
+
+fn parse_reject_list(value: &str) -> Result<RejectList, String> {
+    RejectList::load(Path::new(value)).map_err(|err| format!("{err:#}"))
+}
+
+fn parse_region(value: &str) -> Result<Region, String> {
+    value.parse().map_err(|err: anyhow::Error| err.to_string())
+}
+
+fn parse_grid(value: &str) -> Result<Grid, String> {
+    value.parse().map_err(|err: anyhow::Error| err.to_string())
+}
+
+fn parse_print_size(value: &str) -> Result<PrintSize, String> {
+    value.parse().map_err(|err: anyhow::Error| err.to_string())
+}
+
+/// Parses "transparent" or a color accepted by [`parse_color`].
+fn parse_mask_fill(value: &str) -> Result<MaskFill, String> {
+    if value.eq_ignore_ascii_case("transparent") {
+        return Ok(MaskFill::Transparent);
+    }
+    parse_color(value).map(MaskFill::Color)
+}
+
+/// Parses "#RRGGBB" or "R,G,B" into an OpenCV (BGR) color.
+fn parse_color(value: &str) -> Result<Scalar, String> {
+    let [r, g, b] = parse_rgb(value)?;
+    Ok(Scalar::new(b as f64, g as f64, r as f64, 0.0))
+}
+
+/// Parses "#RRGGBB" or "R,G,B".
+fn parse_rgb(value: &str) -> Result<[u8; 3], String> {
+    let rgb = if let Some(hex) = value.strip_prefix('#') {
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+        };
+        match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Some([r, g, b]),
+            _ => None,
+        }
+    } else {
+        let parts: Vec<Option<u8>> = value.split(',').map(|c| c.trim().parse().ok()).collect();
+        match parts.as_slice() {
+            [Some(r), Some(g), Some(b)] => Some([*r, *g, *b]),
+            _ => None,
+        }
+    };
+    rgb.ok_or_else(|| format!("expected \"#RRGGBB\" or \"R,G,B\", got \"{value}\""))
+}
+
+fn draw_overlay(image: &Mat, sheet: &Sheet) -> Result<Mat> {
+    let mut overlay = image.try_clone()?;
+    let thickness = (image.cols().max(image.rows()) / 400).max(2);
+    let matched = Scalar::new(0.0, 0.0, 255.0, 0.0);
+    // Photos matching no known print size stand out in orange.
+    let off_size = Scalar::new(0.0, 165.0, 255.0, 0.0);
+
+    for (idx, photo) in sheet.photos.iter().enumerate() {
+        let color = if photo.off_size { off_size } else { matched };
+        let quad = photo.quad();
+        // Ovals are shown by their own outline rather than the bounding box.
+        let corners = if photo.shape == PhotoShape::Ellipse && !photo.outline.is_empty() {
+            photo.outline()
+        } else {
+            quad.to_vec()
+        };
+        let points: Vector<Point> = corners
+            .iter()
+            .map(|p| Point::new(p.x.round() as i32, p.y.round() as i32))
+            .collect();
+        let outline: Vector<Vector<Point>> = Vector::from(vec![points]);
+        imgproc::polylines(
+            &mut overlay,
+            &outline,
+            true,
+            color,
+            thickness,
+            imgproc::LINE_AA,
+            0,
+        )?;
+        imgproc::put_text(
+            &mut overlay,
+            &(idx + 1).to_string(),
+            Point::new(
+                quad[0].x as i32 + thickness * 4,
+                quad[0].y as i32 + thickness * 12,
+            ),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            thickness as f64 / 2.0,
+            color,
+            thickness,
+            imgproc::LINE_AA,
+            false,
+        )?;
+    }
+
+    Ok(overlay)
+}
+
+/// Prints the outcome of cropping one sheet and returns the number of crops saved.
+fn report_saved(result: Result<usize>) -> Option<usize> {
+    match result {
+        Ok(count) => {
+            if count == 0 {
+                println!("{}", tr!("no-photos"));
+            } else {
+                println!("{}", tr!("saved-photos", count = count));
+            }
+            Some(count)
+        }
+        Err(err) => {
+            eprintln!("{}", tr!("failed", error = format!("{err:?}")));
+            None
+        }
+    }
+}
+
+/// Reports a sheet that could not be cropped: as skipped when it is an
+/// animation or not written yet, as failed otherwise.
+fn report_failed(err: anyhow::Error, progress: &Progress) {
+    if let Some(animated) = err.downcast_ref::<Animated>() {
+        println!("{}", tr!("animated-skipped", frames = animated.frames));
+        progress.skip_file();
+        return;
+    }
+    match err.downcast_ref::<NotReady>() {
+        Some(not_ready) => {
+            let empty = not_ready.readiness == Readiness::Empty;
+            println!("{}", tr!("unfinished-skipped", empty = empty.to_string()));
+            progress.skip_file();
+        }
+        None => {
+            eprintln!("{}", tr!("failed", error = format!("{err:?}")));
+            progress.finish_file(None);
+        }
+    }
+}
+
+fn image_files(input_dir: &Path) -> impl Iterator<Item = PathBuf> {
+    WalkDir::new(input_dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| is_image_file(p))
+}
+
+/// Reads an image in sRGB, saying so when its colours had to be converted
+/// or it has more pages than the first, which is the one read. Animations
+/// fail with [`Animated`].
+#[tracing::instrument(err)]
+fn read_image(path: &Path) -> Result<Mat> {
+    let bytes = fs::read(paths::long_path_safe(path))
+        .with_context(|| format!("Could not read image {}", path.display()))?;
+    match frames::inspect(&bytes) {
+        Frames::Animation(frames) => return Err(Animated { frames }.into()),
+        Frames::Pages(pages) => println!("{}", tr!("first-page-only", pages = pages)),
+        Frames::Single => {}
+    }
+    let decoded = colorspace::decode(&bytes)
+        .with_context(|| format!("Could not read image {}", path.display()))?;
+    match decoded.conversion {
+        Some(Conversion::CmykProfile) => println!("{}", tr!("cmyk-converted")),
+        Some(Conversion::CmykApproximate) => println!("{}", tr!("cmyk-approximate")),
+        Some(Conversion::Profile(profile)) => {
+            println!("{}", tr!("profile-converted", profile = profile))
+        }
+        None => {}
+    }
+    Ok(decoded.image)
+}
+
+/// Reads a source image, with lens distortion removed when `params` has a
+/// profile, any transparency laid over `params.alpha_background` and the
+/// glare filled in from a second scan when `params` names one.
+fn read_source(path: &Path, params: &DetectParams) -> Result<Mat> {
+    let mut image = read_image(path)?;
+    if let Some(alpha) = alpha::read_alpha(path)? {
+        image = alpha::composite(&image, &alpha, params.alpha_background)?;
+    }
+    if let Some(suffix) = &params.second_scan {
+        let second = glare::second_scan_path(path, suffix);
+        if second.is_file() {
+            println!(
+                "{}",
+                tr!("fusing-scans", path = second.display().to_string())
+            );
+            image = glare::fuse(&image, &read_image(&second)?)
+                .with_context(|| format!("Could not fuse {}", second.display()))?;
+        }
+    }
+    match &params.lens {
+        Some(lens) => lens
+            .undistort(&image)
+            .with_context(|| format!("Could not undistort {}", path.display())),
+        None => Ok(image),
+    }
+}
+
+/// Reads a sheet's source image, undistorted and turned the way it was during detection.
+fn read_sheet(sheet: &Sheet) -> Result<Mat> {
+    let image = read_source(&sheet.source, &sheet.params)?;
+    sheet.rotation.apply(&image)
+}
+
+/// Whether reading the source at `path` fuses a second scan into it.
+fn fuses_second_scan(path: &Path, params: &DetectParams) -> bool {
+    params
+        .second_scan
+        .as_ref()
+        .is_some_and(|suffix| glare::second_scan_path(path, suffix).is_file())
+}
+
+/// A sheet's source at its native 16 bits per channel, undistorted and
+/// turned like [`read_sheet`].
+fn read_sheet_deep(sheet: &Sheet) -> Result<Mat> {
+    let path = &sheet.source;
+    anyhow::ensure!(
+        !fuses_second_scan(path, &sheet.params) && alpha::read_alpha(path)?.is_none(),
+        "--bit-depth 16 cannot be used for {}, as fusing a second scan or laying transparency over a background is only done at 8 bits",
+        path.display()
+    );
+    let bytes = fs::read(paths::long_path_safe(path))
+        .with_context(|| format!("Could not read image {}", path.display()))?;
+    let mut image = colorspace::decode_deep(&bytes)
+        .with_context(|| format!("Could not read image {} at 16 bits", path.display()))?;
+    if let Some(lens) = &sheet.params.lens {
+        image = lens.undistort(&image)?;
+    }
+    sheet.rotation.apply(&image)
+}
+
+/// Alpha channel of a sheet's source, undistorted and turned like [`read_sheet`].
+fn read_sheet_alpha(sheet: &Sheet) -> Result<Option<Mat>> {
+    let Some(mut alpha) = alpha::read_alpha(&sheet.source)? else {
+        return Ok(None);
+    };
+    if let Some(lens) = &sheet.params.lens {
+        alpha = lens.undistort(&alpha)?;
+    }
+    Ok(Some(sheet.rotation.apply(&alpha)?))
+}
+
+#[tracing::instrument(skip(image), err)]
+fn write_image(path: &Path, image: &Mat, params: &Vector<i32>) -> Result<()> {
+    // Encode in memory so a full disk surfaces as an I/O error we can wait out.
+    let encoded = encode_image(path, image, params)?;
+    diskspace::write_waiting_for_space(&paths::long_path_safe(path), &encoded)
+        .with_context(|| format!("Failed to write image {}", path.display()))
+}
+
+/// `image` encoded in the format `path`'s extension names.
+fn encode_image(path: &Path, image: &Mat, params: &Vector<i32>) -> Result<Vec<u8>> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("jpg");
+    let mut encoded = Vector::new();
+    let ok = imgcodecs::imencode(&format!(".{extension}"), image, &mut encoded, params)
+        .with_context(|| format!("Failed to encode image {}", path.display()))?;
+    anyhow::ensure!(ok, "Failed to encode image {}", path.display());
+    Ok(encoded.to_vec())
+}
+
+/// Auto-levels measured over every photo on the sheet at once, so crops
+/// scanned together keep matching tones.
+fn sheet_levels(image: &Mat, sheet: &Sheet, output: &OutputArgs) -> Result<Levels> {
+    let mut polygons: Vector<Vector<Point>> = Vector::new();
+    for photo in &sheet.photos {
+        let quad = adjusted_quad(&photo.quad(), output)?;
+        polygons.push(
+            quad.iter()
+                .map(|p| Point::new(p.x.round() as i32, p.y.round() as i32))
+                .collect(),
+        );
+    }
+    let mut mask = Mat::zeros(image.rows(), image.cols(), opencv::core::CV_8UC1)?.to_mat()?;
+    imgproc::fill_poly(
+        &mut mask,
+        &polygons,
+        Scalar::all(255.0),
+        imgproc::LINE_8,
+        0,
+        Point::new(0, 0),
+    )?;
+    Levels::measure(image, Some(&mask))
+}
+
+/// Longest side of embedded EXIF thumbnails, as in camera JPEGs.
+const THUMBNAIL_SIZE: f64 = 160.0;
+
+/// Small JPEG preview of `image` for the EXIF thumbnail.
+fn thumbnail_jpeg(image: &Mat) -> Result<Vec<u8>> {
+    let scale = (THUMBNAIL_SIZE / image.cols().max(image.rows()) as f64).min(1.0);
+    let mut small = Mat::default();
+    imgproc::resize(
+        image,
+        &mut small,
+        Size::new(0, 0),
+        scale,
+        scale,
+        imgproc::INTER_AREA,
+    )?;
+    let mut encoded = Vector::new();
+    let params = Vector::from(vec![imgcodecs::IMWRITE_JPEG_QUALITY, 80]);
+    let ok = imgcodecs::imencode(".jpg", &small, &mut encoded, &params)?;
+    anyhow::ensure!(ok, "Failed to encode thumbnail");
+    Ok(encoded.to_vec())
+}
+
+/// Aborts (or only warns, with `--ignore-space-check`) when the output volume
+/// looks too small for the crops of `sources`.
+fn check_space<'a>(
+    output_dir: &Path,
+    sources: impl IntoIterator<Item = &'a Path>,
+    output: &OutputArgs,
+) -> Result<()> {
+    let estimate: u64 = sources
+        .into_iter()
+        .filter_map(|source| {
+            let bytes = fs::metadata(source).ok()?.len();
+            let source_ext = source.extension()?.to_str()?;
+            let output_ext = output_extension(output.format, source);
+            Some(diskspace::estimate_output(bytes, source_ext, output_ext))
+        })
+        .sum();
+    let Some(shortfall) = diskspace::preflight(output_dir, estimate)? else {
+        return Ok(());
+    };
+    if output.ignore_space_check {
+        eprintln!("{}", tr!("space-warning", message = shortfall));
+        return Ok(());
+    }
+    anyhow::bail!("{shortfall}; free up space or pass --ignore-space-check")
+}
+
+/// [`crop_stems`] for every sheet of `manifest`, cropped into `output_dir`.
+fn manifest_stems(manifest: &Manifest, output_dir: &Path) -> HashMap<PathBuf, String> {
+    let sheets: Vec<(&Path, &Path)> = manifest
+        .sheets
+        .iter()
+        .map(|s| (s.source.as_path(), output_dir))
+        .collect();
+    crop_stems(&sheets)
+}
+
+/// Stems for the crops of `sheets`, each given as its source and the
+/// directory its crops go to, that stay distinct within every directory,
+/// even on case-insensitive file systems, so no sheet's crops overwrite
+/// another's.
+///
+/// Sheets whose stems clash (`a/scan.tif` and `b/scan.tif`, or `Scan.jpg`
+/// and `scan.tif` side by side) get their folder's name in front; any that
+/// still clash get a short hash of their path added.
+fn crop_stems(sheets: &[(&Path, &Path)]) -> HashMap<PathBuf, String> {
+    let clashing = |stems: &[String]| -> Vec<usize> {
+        let mut seen: HashMap<(String, String), Vec<usize>> = HashMap::new();
+        for (i, ((_, dir), stem)) in sheets.iter().zip(stems).enumerate() {
+            let key = (dir.to_string_lossy().to_lowercase(), stem.to_lowercase());
+            seen.entry(key).or_default().push(i);
+        }
+        seen.into_values()
+            .filter(|v| v.len() > 1)
+            .flatten()
+            .collect()
+    };
+    let mut stems: Vec<String> = sheets.iter().map(|(source, _)| file_stem(source)).collect();
+    for i in clashing(&stems) {
+        if let Some(folder) = sheets[i].0.parent().and_then(|p| p.file_name()) {
+            stems[i] = paths::sanitize_stem(&format!("{}_{}", folder.to_string_lossy(), stems[i]));
+        }
+    }
+    for i in clashing(&stems) {
+        let digest = hash::sha256_hex(sheets[i].0.to_string_lossy().as_bytes());
+        stems[i] = format!("{}_{}", stems[i], &digest[..8]);
+    }
+    sheets
+        .iter()
+        .zip(stems)
+        .map(|((source, _), stem)| {
+            if stem != file_stem(source) {
+                println!(
+                    "{}",
+                    tr!(
+                        "stem-disambiguated",
+                        path = source.display().to_string(),
+                        stem = stem.clone()
+                    )
+                );
+            }
+            (source.to_path_buf(), stem)
+        })
+        .collect()
+}
+
+/// Stem of `path`, made safe for use in output file names.
+fn file_stem(path: &Path) -> String {
+    paths::sanitize_stem(path.file_stem().and_then(|s| s.to_str()).unwrap_or("image"))
+}
+
+/// Source formats whose crops could not carry the EXIF provenance and XMP
+/// fields, so `--format same` writes their crops as JPEG instead.
+const NO_METADATA_FORMATS: [&str; 3] = ["gif", "webp", "bmp"];
+
+fn output_extension(format: OutputFormat, source: &Path) -> &str {
+    match format {
+        OutputFormat::Jpg => "jpg",
+        OutputFormat::Png => "png",
+        OutputFormat::Tiff => "tif",
+        // Keep the source spelling (.jpeg vs .jpg, .tif vs .tiff) so crops follow the archive's conventions.
+        OutputFormat::Same => source
+            .extension()
+            .and_then(|e| e.to_str())
+            .filter(|e| !NO_METADATA_FORMATS.contains(&e.to_lowercase().as_str()))
+            .unwrap_or("jpg"),
+    }
+}
+
+/// Whether crops with this extension can carry an EXIF block.
+fn supports_exif(extension: &str) -> bool {
+    const EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+    EXTENSIONS.contains(&extension.to_lowercase().as_str())
+}
+
+/// Whether crops written as `extension` share the format of `source`.
+fn same_format(extension: &str, source: &Path) -> bool {
+    let normalize = |e: &str| match e.to_lowercase().as_str() {
+        "jpeg" => "jpg".to_string(),
+        "tiff" => "tif".to_string(),
+        other => other.to_string(),
+    };
+    source
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| normalize(e) == normalize(extension))
+}
+
+/// Whether every corner of `quad` lies within 1% of the frame's matching corner.
+fn fills_frame(quad: &Quad, size: Size) -> bool {
+    let (w, h) = (size.width as f32, size.height as f32);
+    let frame = [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)];
+    quad.iter().zip(frame).all(|(corner, (x, y))| {
+        (corner.x - x).abs() <= 0.01 * w && (corner.y - y).abs() <= 0.01 * h
+    })
+}
+
+fn is_jpeg(extension: &str) -> bool {
+    matches!(extension.to_lowercase().as_str(), "jpg" | "jpeg")
+}
+
+fn supports_alpha(extension: &str) -> bool {
+    const EXTENSIONS: [&str; 3] = ["png", "tif", "tiff"];
+    EXTENSIONS.contains(&extension.to_lowercase().as_str())
+}
+
+/// Extensions of image formats the walker skips, reported by `plan` so
+/// they are not silently left out of a batch.
+fn is_unsupported_image(path: &Path) -> bool {
+    const EXTENSIONS: [&str; 14] = [
+        "heic", "heif", "avif", "jxl", "jp2", "psd", "dng", "cr2", "cr3", "nef", "arw", "orf",
+        "rw2", "raf",
+    ];
+    path.extension()
         .and_then(|e| e.to_str())
         .map(|e| EXTENSIONS.contains(&e.to_lowercase().as_str()))
         .unwrap_or(false)
 }
+
+fn is_image_file(path: &Path) -> bool {
+    const EXTENSIONS: [&str; 8] = ["jpg", "jpeg", "png", "bmp", "tif", "tiff", "webp", "gif"];
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animation_is_skipped_rather_than_failed() {
+        let progress = Progress::new("run", None);
+        report_failed(Animated { frames: 2 }.into(), &progress);
+        let status = progress.snapshot();
+        assert_eq!((status.skipped, status.errors), (1, 0));
+    }
+
+    #[test]
+    fn other_errors_fail() {
+        let progress = Progress::new("run", None);
+        report_failed(anyhow::anyhow!("Could not decode image"), &progress);
+        let status = progress.snapshot();
+        assert_eq!((status.skipped, status.errors), (0, 1));
+    }
+
+    #[test]
+    fn same_format_falls_back_to_jpeg_for_gif() {
+        assert_eq!(
+            output_extension(OutputFormat::Same, Path::new("scan.gif")),
+            "jpg"
+        );
+        assert_eq!(
+            output_extension(OutputFormat::Same, Path::new("scan.GIF")),
+            "jpg"
+        );
+        assert_eq!(
+            output_extension(OutputFormat::Same, Path::new("scan.tiff")),
+            "tiff"
+        );
+    }
+
+    #[test]
+    fn secret_variables_are_redacted() {
+        assert_eq!(
+            recorded_env("PHOTO_CROPPER_NOTIFY_WEBHOOK", "https://hooks.example/abc"),
+            "PHOTO_CROPPER_NOTIFY_WEBHOOK=<redacted>"
+        );
+        assert_eq!(
+            recorded_env("PHOTO_CROPPER_MIN_AREA", "12000"),
+            "PHOTO_CROPPER_MIN_AREA=12000"
+        );
+    }
+}
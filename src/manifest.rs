@@ -0,0 +1,435 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use opencv::core::Point2f;
+use serde::{Deserialize, Serialize};
+
+use crate::dating::DateEstimate;
+use crate::detect::{DetectParams, PhotoShape, Quad, RejectReason, Rejection};
+use crate::era::Era;
+use crate::hash;
+use crate::orientation::Rotation;
+
+/// Bumped whenever the manifest layout changes incompatibly.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// How long [`set_review`] waits for another writer's lock before giving up.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Geometry produced by `detect` and consumed by the later pipeline stages.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Directory the crops were written to, once `crop` has run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<PathBuf>,
+    pub sheets: Vec<Sheet>,
+    /// Clockwise turn in degrees applied to every quad to undo a skew the
+    /// whole batch shared; re-detected sheets get it too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skew_correction: Option<f32>,
+    /// Every run that wrote the manifest, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub runs: Vec<RunRecord>,
+}
+
+/// How one run was invoked, so an archive can audit or repeat a
+/// digitization batch years later.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// `--run-name`, or the start time as `YYYYMMDD-HHMMSS` in UTC.
+    #[serde(default)]
+    pub id: String,
+    /// Unix timestamp in seconds.
+    pub started_at: u64,
+    /// The command line, program name included and response files expanded.
+    pub args: Vec<String>,
+    /// Options set through `PHOTO_CROPPER_*` variables, as `NAME=value`, with
+    /// `<redacted>` for values that may hold credentials.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<String>,
+    /// SHA-256 of the `--config` file, when one was given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_sha256: Option<String>,
+    /// Version of photo-cropper.
+    pub version: String,
+    /// Version of the OpenCV library detection ran on.
+    pub opencv: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sheet {
+    pub source: PathBuf,
+    pub params: DetectParams,
+    /// Clockwise rotation applied to the source before detection; quads refer to the rotated sheet.
+    #[serde(default, skip_serializing_if = "Rotation::is_none")]
+    pub rotation: Rotation,
+    /// SHA-256 of the source file, recorded when crops link back to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_sha256: Option<String>,
+    /// Width and height in pixels of the sheet the quads refer to, rotation
+    /// included.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<[u32; 2]>,
+    pub photos: Vec<Photo>,
+    /// Regions found on the sheet but not taken for photos, and why.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<Rejected>,
+    /// How far a person has got checking the sheet's crops.
+    #[serde(default, skip_serializing_if = "ReviewStatus::is_unreviewed")]
+    pub review: ReviewStatus,
+}
+
+/// Where a sheet stands in review, shared by `review` and the server so a
+/// review can be continued on another machine against the same output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReviewStatus {
+    /// Not looked at yet, or re-detected since.
+    #[default]
+    Unreviewed,
+    /// The crops are good.
+    Approved,
+    /// The crops need `recrop` or manual fixing.
+    NeedsFix,
+}
+
+impl ReviewStatus {
+    pub fn is_unreviewed(&self) -> bool {
+        *self == ReviewStatus::Unreviewed
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rejected {
+    /// Corners in source pixels, ordered like [`Photo::quad`].
+    pub quad: [[f32; 2]; 4],
+    pub reason: RejectReason,
+}
+
+impl From<&Rejection> for Rejected {
+    fn from(rejection: &Rejection) -> Self {
+        Self {
+            quad: rejection.quad.map(|p| [p.x, p.y]),
+            reason: rejection.reason,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Photo {
+    /// Corners as `[x, y]` pairs in source pixels: top-left, top-right, bottom-right, bottom-left.
+    pub quad: [[f32; 2]; 4],
+    /// The corners as shares (0–1) of the sheet's width and height, which
+    /// hold for any scan of the sheet whatever its resolution. Written from
+    /// `quad` when the sheet's size is known; `quad` is what is read back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quad_normalized: Option<[[f32; 2]; 4]>,
+    #[serde(default)]
+    pub confidence: f32,
+    #[serde(default, skip_serializing_if = "PhotoShape::is_rect")]
+    pub shape: PhotoShape,
+    /// Detection pass that found the photo.
+    #[serde(default, skip_serializing_if = "DetectPass::is_main")]
+    pub pass: DetectPass,
+    /// Set when the photo matches none of the batch's known print sizes.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub off_size: bool,
+    /// Outline of an oval, rounded or deckle-edged photo as `[x, y]` source pixels.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outline: Vec<[f32; 2]>,
+    /// Physical width and height in millimetres of the straightened photo,
+    /// known when the scan resolution is (from `--dpi` or a ruler).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_mm: Option<[f32; 2]>,
+    /// Clockwise rotation (0, 90, 180 or 270 degrees) applied when cropping.
+    #[serde(default, skip_serializing_if = "Rotation::is_none")]
+    pub rotation: Rotation,
+    /// Path of the cropped file, once written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    /// SHA-256 of the cropped file as written, for `verify`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Id of the run that wrote the cropped file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<String>,
+    /// Title given after review; `apply-names` names the cropped file after it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// When the photo was probably taken, from `--date-model`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_estimate: Option<DateEstimate>,
+    /// Share of the photo's inside washed out by sleeve glare, when noticeable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glare: Option<f32>,
+    /// Rough era told from the photo's colours, from `--sort-by-era`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub era: Option<Era>,
+}
+
+/// Which detection pass produced a photo.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DetectPass {
+    /// The regular pass with the sheet's parameters.
+    #[default]
+    Main,
+    /// The retry with relaxed parameters for under-detected sheets.
+    Retry,
+}
+
+impl DetectPass {
+    pub fn is_main(&self) -> bool {
+        *self == DetectPass::Main
+    }
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            output_dir: None,
+            sheets: Vec::new(),
+            skew_correction: None,
+            runs: Vec::new(),
+        }
+    }
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read manifest {}", path.display()))?;
+        let manifest: Manifest = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid manifest {}", path.display()))?;
+        anyhow::ensure!(
+            manifest.version == MANIFEST_VERSION,
+            "Unsupported manifest version {} in {} (expected {})",
+            manifest.version,
+            path.display(),
+            MANIFEST_VERSION
+        );
+        Ok(manifest)
+    }
+
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        for sheet in &mut self.sheets {
+            sheet.normalize_quads();
+        }
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text)
+            .with_context(|| format!("Failed to write manifest {}", path.display()))
+    }
+
+    /// Writes the manifest through a temporary file, so that a reader never
+    /// sees it half written.
+    fn save_atomically(&mut self, path: &Path) -> Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        self.save(&temp)?;
+        fs::rename(&temp, path)
+            .with_context(|| format!("Failed to write manifest {}", path.display()))
+    }
+
+    /// Replaces the entry for `sheet.source`, or appends it if the sheet is new.
+    pub fn upsert(&mut self, sheet: Sheet) {
+        match self.sheets.iter_mut().find(|s| s.source == sheet.source) {
+            Some(existing) => *existing = sheet,
+            None => self.sheets.push(sheet),
+        }
+    }
+}
+
+/// Sets the review status of the `sources` in the manifest at `path` and
+/// returns how many of them it lists.
+///
+/// The manifest is read afresh and written back under a lock file next to
+/// it, so the CLI and the server, or two people on a shared folder, can mark
+/// sheets at the same time without undoing each other's marks.
+pub fn set_review(path: &Path, sources: &[PathBuf], status: ReviewStatus) -> Result<usize> {
+    let _lock = ManifestLock::acquire(path)?;
+    let mut manifest = Manifest::load(path)?;
+    let mut marked = 0;
+    for sheet in &mut manifest.sheets {
+        if sources.contains(&sheet.source) {
+            sheet.review = status;
+            marked += 1;
+        }
+    }
+    manifest.save_atomically(path)?;
+    Ok(marked)
+}
+
+/// A `<manifest>.lock` file held while the manifest is read and rewritten.
+struct ManifestLock(PathBuf);
+
+impl ManifestLock {
+    fn acquire(manifest: &Path) -> Result<Self> {
+        let mut path = manifest.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+        let started = Instant::now();
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(_) => return Ok(Self(path)),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    anyhow::ensure!(
+                        started.elapsed() < LOCK_TIMEOUT,
+                        "{} is locked by another writer; delete it if none is running",
+                        path.display()
+                    );
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Err(err) => {
+                    return Err(err).with_context(|| format!("Could not lock {}", path.display()));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ManifestLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+impl RunRecord {
+    /// Record of the running process, invoked with `args` and named `name`
+    /// or after its start time.
+    pub fn new(args: &[OsString], config: Option<&Path>, name: Option<&str>) -> Result<Self> {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Ok(Self {
+            id: name.map_or_else(|| timestamp_id(started_at), str::to_string),
+            started_at,
+            args: args
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            env: Vec::new(),
+            config_sha256: config.map(hash::sha256_file).transpose()?,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            opencv: opencv::core::get_version_string()?,
+        })
+    }
+
+    /// Pins the start time to the Unix epoch, so runs of the same command
+    /// write the same record; the id follows unless the run was `named`.
+    pub fn pin_time(&mut self, named: bool) {
+        self.started_at = 0;
+        if !named {
+            self.id = timestamp_id(0);
+        }
+    }
+}
+
+/// `secs` since the Unix epoch as `YYYYMMDD-HHMMSS` in UTC, which sorts
+/// in time order and is safe in file names.
+pub(crate) fn timestamp_id(secs: u64) -> String {
+    let (days, time) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+impl Sheet {
+    /// Brings every photo's `quad_normalized` in line with its `quad`.
+    fn normalize_quads(&mut self) {
+        let size = self.size.filter(|&[w, h]| w > 0 && h > 0);
+        for photo in &mut self.photos {
+            photo.quad_normalized =
+                size.map(|[w, h]| photo.quad.map(|[x, y]| [x / w as f32, y / h as f32]));
+        }
+    }
+
+    /// Moves the geometry over to a scan of the same sheet that is `size`
+    /// pixels large, e.g. a high-resolution rescan of a preview: every quad,
+    /// outline and rejected region keeps its place relative to the sheet.
+    pub fn rescale(&mut self, size: [u32; 2]) -> Result<()> {
+        let [w, h] = self.size.with_context(|| {
+            format!(
+                "The manifest does not record the size of {}; detect it again first",
+                self.source.display()
+            )
+        })?;
+        let (sx, sy) = (size[0] as f32 / w as f32, size[1] as f32 / h as f32);
+        let scale = |[x, y]: [f32; 2]| [x * sx, y * sy];
+        for photo in &mut self.photos {
+            photo.quad = photo.quad.map(scale);
+            photo.outline.iter_mut().for_each(|p| *p = scale(*p));
+        }
+        for rejected in &mut self.rejected {
+            rejected.quad = rejected.quad.map(scale);
+        }
+        self.size = Some(size);
+        Ok(())
+    }
+}
+
+impl Photo {
+    pub fn new(quad: &Quad, confidence: f32) -> Self {
+        Self {
+            quad: quad.map(|p| [p.x, p.y]),
+            quad_normalized: None,
+            confidence,
+            shape: PhotoShape::Rect,
+            off_size: false,
+            pass: DetectPass::Main,
+            outline: Vec::new(),
+            size_mm: None,
+            rotation: Rotation::None,
+            output: None,
+            sha256: None,
+            run: None,
+            title: None,
+            date_estimate: None,
+            glare: None,
+            era: None,
+        }
+    }
+
+    pub fn quad(&self) -> Quad {
+        self.quad.map(|[x, y]| Point2f::new(x, y))
+    }
+
+    pub fn set_outline(&mut self, outline: &[Point2f]) {
+        self.outline = outline.iter().map(|p| [p.x, p.y]).collect();
+    }
+
+    pub fn outline(&self) -> Vec<Point2f> {
+        self.outline
+            .iter()
+            .map(|&[x, y]| Point2f::new(x, y))
+            .collect()
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::{Result, ensure};
+use opencv::core::{self, AlgorithmHint, Mat, Rect, Scalar, Size};
+use opencv::imgproc;
+use opencv::prelude::*;
+
+use crate::alpha;
+use crate::layout::Grid;
+
+const MM_PER_INCH: f64 = 25.4;
+
+/// Paper size of collage pages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Paper {
+    #[default]
+    A4,
+    Letter,
+}
+
+impl Paper {
+    /// Width and height in millimetres, portrait.
+    pub fn size_mm(self) -> (f64, f64) {
+        match self {
+            Paper::A4 => (210.0, 297.0),
+            Paper::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+/// How crops are placed on collage pages: a grid of equal cells inside the
+/// page margins, each photo scaled to fit its cell and centred in it.
+#[derive(Clone, Copy, Debug)]
+pub struct PageLayout {
+    pub paper: Paper,
+    pub landscape: bool,
+    pub dpi: f64,
+    /// Blank border around the page, for printers that cannot print to the edge.
+    pub margin_mm: f64,
+    /// Space between neighbouring cells, to cut along.
+    pub gap_mm: f64,
+    pub grid: Grid,
+    /// Turn photos a quarter when that lets them fill more of their cell.
+    pub rotate_to_fit: bool,
+}
+
+impl PageLayout {
+    pub fn per_page(&self) -> usize {
+        (self.grid.columns * self.grid.rows) as usize
+    }
+
+    /// Page size in pixels at the layout's resolution.
+    pub fn page_size(&self) -> Size {
+        let (mut width, mut height) = self.paper.size_mm();
+        if self.landscape {
+            (width, height) = (height, width);
+        }
+        Size::new(self.px(width), self.px(height))
+    }
+
+    /// Cells in reading order, in page pixels.
+    pub fn cells(&self) -> Result<Vec<Rect>> {
+        let page = self.page_size();
+        let (margin, gap) = (self.px(self.margin_mm), self.px(self.gap_mm));
+        let (columns, rows) = (self.grid.columns as i32, self.grid.rows as i32);
+        let width = (page.width - 2 * margin - (columns - 1) * gap) / columns;
+        let height = (page.height - 2 * margin - (rows - 1) * gap) / rows;
+        ensure!(
+            width > 0 && height > 0,
+            "Margins and gaps leave no room for a {}x{} grid on the page",
+            columns,
+            rows
+        );
+        Ok((0..rows)
+            .flat_map(|row| {
+                (0..columns).map(move |column| {
+                    Rect::new(
+                        margin + column * (width + gap),
+                        margin + row * (height + gap),
+                        width,
+                        height,
+                    )
+                })
+            })
+            .collect())
+    }
+
+    fn px(&self, mm: f64) -> i32 {
+        (mm / MM_PER_INCH * self.dpi).round() as i32
+    }
+}
+
+/// One white page with `photos` (grey, BGR or BGRA) placed in the layout's cells in order.
+pub fn compose(photos: &[Mat], layout: &PageLayout) -> Result<Mat> {
+    let cells = layout.cells()?;
+    ensure!(
+        photos.len() <= cells.len(),
+        "{} photos do not fit on a page of {} cells",
+        photos.len(),
+        cells.len()
+    );
+    let size = layout.page_size();
+    let mut page = Mat::new_rows_cols_with_default(
+        size.height,
+        size.width,
+        core::CV_8UC3,
+        Scalar::all(255.0),
+    )?;
+    for (photo, cell) in photos.iter().zip(cells) {
+        let photo = if layout.rotate_to_fit && is_landscape(photo) != (cell.width > cell.height) {
+            let mut turned = Mat::default();
+            core::rotate(photo, &mut turned, core::ROTATE_90_CLOCKWISE)?;
+            turned
+        } else {
+            photo.clone()
+        };
+        let photo = to_bgr(&photo)?;
+        let scale =
+            (cell.width as f64 / photo.cols() as f64).min(cell.height as f64 / photo.rows() as f64);
+        let fitted = Size::new(
+            ((photo.cols() as f64 * scale).round() as i32).clamp(1, cell.width),
+            ((photo.rows() as f64 * scale).round() as i32).clamp(1, cell.height),
+        );
+        let mut resized = Mat::default();
+        imgproc::resize(
+            &photo,
+            &mut resized,
+            fitted,
+            0.0,
+            0.0,
+            if scale < 1.0 {
+                imgproc::INTER_AREA
+            } else {
+                imgproc::INTER_CUBIC
+            },
+        )?;
+        let target = Rect::new(
+            cell.x + (cell.width - fitted.width) / 2,
+            cell.y + (cell.height - fitted.height) / 2,
+            fitted.width,
+            fitted.height,
+        );
+        let mut roi = Mat::roi_mut(&mut page, target)?;
+        resized.copy_to(&mut roi)?;
+    }
+    Ok(page)
+}
+
+fn is_landscape(image: &Mat) -> bool {
+    image.cols() > image.rows()
+}
+
+/// `image` as 8-bit BGR, whatever crops were written as.
+fn to_bgr(image: &Mat) -> Result<Mat> {
+    let image = if image.depth() == core::CV_8U {
+        image.clone()
+    } else {
+        let mut narrow = Mat::default();
+        image.convert_to(&mut narrow, core::CV_8U, 1.0 / 257.0, 0.0)?;
+        narrow
+    };
+    match image.channels() {
+        1 => convert(&image, imgproc::COLOR_GRAY2BGR),
+        4 => {
+            // Transparent parts show the white paper.
+            let mut alpha = Mat::default();
+            core::extract_channel(&image, &mut alpha, 3)?;
+            let bgr = convert(&image, imgproc::COLOR_BGRA2BGR)?;
+            alpha::composite(&bgr, &alpha, [255; 3])
+        }
+        _ => Ok(image),
+    }
+}
+
+fn convert(image: &Mat, code: i32) -> Result<Mat> {
+    let mut converted = Mat::default();
+    imgproc::cvt_color(
+        image,
+        &mut converted,
+        code,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    Ok(converted)
+}
@@ -0,0 +1,147 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::paths;
+
+/// One row of a names CSV: a crop, by file name or path as listed in the
+/// manifest, and the title to give it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Naming {
+    pub crop: String,
+    pub title: String,
+}
+
+/// Reads a CSV of `crop,title` rows, in the order they should be numbered.
+///
+/// A first row reading `crop,title` is taken for a header. Fields may be
+/// quoted, with `""` for a quote inside. Semicolons separate the fields
+/// instead when the first row has no comma, as spreadsheets save CSV in
+/// locales that write decimal commas. Rows with an empty title are skipped.
+pub fn read_csv(path: &Path) -> Result<Vec<Naming>> {
+    let mut namings = Vec::new();
+    for (number, fields) in read_rows(path, ["crop", "title"])? {
+        anyhow::ensure!(
+            fields.len() >= 2,
+            "{} line {number}: expected a crop and a title",
+            path.display(),
+        );
+        let title = fields[1].trim();
+        if title.is_empty() {
+            continue;
+        }
+        namings.push(Naming {
+            crop: fields[0].trim().to_string(),
+            title: title.to_string(),
+        });
+    }
+    Ok(namings)
+}
+
+/// One row of a captions CSV: a photo, by its sheet and its number on the
+/// sheet, and the caption written on paper for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Caption {
+    /// The sheet's source, by file name or path as listed in the manifest.
+    pub sheet: String,
+    /// The photo's number on the sheet, from 1 as in the crops' names.
+    pub photo: usize,
+    pub caption: String,
+}
+
+/// Reads a CSV of `sheet,photo,caption` rows, as [`read_csv`] reads names.
+pub fn read_captions(path: &Path) -> Result<Vec<Caption>> {
+    let mut captions = Vec::new();
+    for (number, fields) in read_rows(path, ["sheet", "photo"])? {
+        anyhow::ensure!(
+            fields.len() >= 3,
+            "{} line {number}: expected a sheet, a photo number and a caption",
+            path.display(),
+        );
+        let photo = fields[1]
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .with_context(|| {
+                format!(
+                    "{} line {number}: {:?} is no photo number",
+                    path.display(),
+                    fields[1]
+                )
+            })?;
+        let caption = fields[2].trim();
+        if caption.is_empty() {
+            continue;
+        }
+        captions.push(Caption {
+            sheet: fields[0].trim().to_string(),
+            photo,
+            caption: caption.to_string(),
+        });
+    }
+    Ok(captions)
+}
+
+/// The non-empty rows of the CSV at `path` with their line numbers, without
+/// a first row starting with the `header` fields.
+fn read_rows(path: &Path, header: [&str; 2]) -> Result<Vec<(usize, Vec<String>)>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Could not read {}", path.display()))?;
+    let text = text.trim_start_matches('\u{feff}');
+    let first = text.lines().next().unwrap_or_default();
+    let separator = if !first.contains(',') && first.contains(';') {
+        ';'
+    } else {
+        ','
+    };
+    let mut rows = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_row(line, separator);
+        if number == 0
+            && fields.len() >= 2
+            && fields[0].trim().eq_ignore_ascii_case(header[0])
+            && fields[1].trim().eq_ignore_ascii_case(header[1])
+        {
+            continue;
+        }
+        rows.push((number + 1, fields));
+    }
+    Ok(rows)
+}
+
+fn split_row(line: &str, separator: char) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().expect("there is always a field");
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => fields.push(String::new()),
+            c => field.push(c),
+        }
+    }
+    fields
+}
+
+/// File stem for a crop titled `title`, prefixed with its `number` out of
+/// `total` (zero-padded so the files sort in order) when numbering.
+pub fn stem(title: &str, number: Option<(usize, usize)>) -> String {
+    let title = paths::sanitize_stem(title);
+    match number {
+        Some((number, total)) => {
+            let width = total.to_string().len().max(3);
+            format!("{number:0width$} {title}")
+        }
+        None => title,
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::progress::Status;
+
+/// How long to wait for the webhook before giving up.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Posted to `--notify-webhook` when a batch ends.
+#[derive(Clone, Debug, Serialize)]
+pub struct Summary {
+    /// `succeeded` or `failed`.
+    pub outcome: &'static str,
+    /// Error chain of a failed batch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(flatten)]
+    pub status: Status,
+}
+
+impl Summary {
+    pub fn new(status: Status, result: &Result<()>) -> Self {
+        Self {
+            outcome: if result.is_ok() {
+                "succeeded"
+            } else {
+                "failed"
+            },
+            error: result.as_ref().err().map(|err| format!("{err:#}")),
+            status,
+        }
+    }
+}
+
+/// POSTs `summary` as JSON to `url`.
+pub fn post(url: &str, summary: &Summary) -> Result<()> {
+    ureq::post(url)
+        .timeout(TIMEOUT)
+        .send_json(summary)
+        .with_context(|| format!("Could not notify {url}"))?;
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use opencv::core::{self, Mat, Point, Scalar, Size, Vector};
+use opencv::prelude::*;
+use opencv::{imgcodecs, imgproc};
+use serde::{Deserialize, Serialize};
+
+/// Clockwise rotation needed to show a crop upright.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u16", into = "u16")]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Rot180,
+    Cw270,
+}
+
+impl Rotation {
+    pub fn is_none(&self) -> bool {
+        *self == Rotation::None
+    }
+
+    /// Rotates the pixels of `image`.
+    pub fn apply(&self, image: &Mat) -> Result<Mat> {
+        let code = match self {
+            Rotation::None => return Ok(image.clone()),
+            Rotation::Cw90 => core::ROTATE_90_CLOCKWISE,
+            Rotation::Rot180 => core::ROTATE_180,
+            Rotation::Cw270 => core::ROTATE_90_COUNTERCLOCKWISE,
+        };
+        let mut rotated = Mat::default();
+        core::rotate(image, &mut rotated, code)?;
+        Ok(rotated)
+    }
+
+    /// EXIF `Orientation` value that makes viewers apply this rotation.
+    pub fn exif_orientation(&self) -> u16 {
+        match self {
+            Rotation::None => 1,
+            Rotation::Cw90 => 6,
+            Rotation::Rot180 => 3,
+            Rotation::Cw270 => 8,
+        }
+    }
+}
+
+impl TryFrom<u16> for Rotation {
+    type Error = String;
+
+    fn try_from(degrees: u16) -> Result<Self, Self::Error> {
+        match degrees {
+            0 => Ok(Rotation::None),
+            90 => Ok(Rotation::Cw90),
+            180 => Ok(Rotation::Rot180),
+            270 => Ok(Rotation::Cw270),
+            other => Err(format!("rotation must be 0, 90, 180 or 270, got {other}")),
+        }
+    }
+}
+
+impl From<Rotation> for u16 {
+    fn from(rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::None => 0,
+            Rotation::Cw90 => 90,
+            Rotation::Rot180 => 180,
+            Rotation::Cw270 => 270,
+        }
+    }
+}
+
+/// Longest side the sheet is scaled down to before looking for text.
+const TEXT_ANALYSIS_SIZE: f64 = 2000.0;
+
+/// Guesses whether a sheet lies upside down from any captions printed on it.
+///
+/// Latin script has more ascenders (b, d, h, k, l, t) than descenders (g, p, q,
+/// y), so upright text carries more ink above its x-height band than below.
+/// Returns `None` when too few text lines are found to decide.
+pub fn upside_down_by_text(image: &Mat) -> Result<Option<bool>> {
+    let gray = crate::enhance::to_gray(image)?;
+    let long_side = gray.cols().max(gray.rows()) as f64;
+    let scale = (TEXT_ANALYSIS_SIZE / long_side).min(1.0);
+    let mut small = Mat::default();
+    imgproc::resize(
+        &gray,
+        &mut small,
+        Size::new(0, 0),
+        scale,
+        scale,
+        imgproc::INTER_AREA,
+    )?;
+
+    // Ink becomes white so row sums measure how much text each row holds.
+    let mut ink = Mat::default();
+    imgproc::adaptive_threshold(
+        &small,
+        &mut ink,
+        255.0,
+        imgproc::ADAPTIVE_THRESH_MEAN_C,
+        imgproc::THRESH_BINARY_INV,
+        31,
+        15.0,
+    )?;
+
+    // Smear letters horizontally so each text line becomes one blob.
+    let kernel = imgproc::get_structuring_element(
+        imgproc::MORPH_RECT,
+        Size::new(25, 3),
+        Point::new(-1, -1),
+    )?;
+    let mut lines = Mat::default();
+    imgproc::morphology_ex(
+        &ink,
+        &mut lines,
+        imgproc::MORPH_CLOSE,
+        &kernel,
+        Point::new(-1, -1),
+        1,
+        core::BORDER_CONSTANT,
+        Scalar::all(0.0),
+    )?;
+
+    let mut contours: Vector<Vector<Point>> = Vector::new();
+    imgproc::find_contours(
+        &lines,
+        &mut contours,
+        imgproc::RETR_EXTERNAL,
+        imgproc::CHAIN_APPROX_SIMPLE,
+        Point::new(0, 0),
+    )?;
+
+    let mut votes = Vec::new();
+    for contour in contours {
+        let bbox = imgproc::bounding_rect(&contour)?;
+        if bbox.height < 8 || bbox.height > 80 || bbox.width < bbox.height * 4 {
+            continue;
+        }
+        let roi = Mat::roi(&ink, bbox)?;
+        let density = core::count_non_zero(&roi)? as f64 / bbox.area() as f64;
+        if !(0.1..=0.7).contains(&density) {
+            continue;
+        }
+        if let Some(vote) = ascender_vote(&roi)? {
+            votes.push(vote);
+        }
+    }
+
+    if votes.len() < 3 {
+        return Ok(None);
+    }
+    let mean = votes.iter().sum::<f64>() / votes.len() as f64;
+    if mean.abs() < 0.15 {
+        return Ok(None);
+    }
+    Ok(Some(mean < 0.0))
+}
+
+/// Scores one text line from -1 (ink hangs below the core band) to 1 (above).
+fn ascender_vote(line: &Mat) -> Result<Option<f64>> {
+    let mut profile = Mat::default();
+    core::reduce(line, &mut profile, 1, core::REDUCE_SUM, core::CV_32F)?;
+    let rows: Vec<f32> = (0..profile.rows())
+        .map(|r| profile.at_2d::<f32>(r, 0).copied())
+        .collect::<opencv::Result<_>>()?;
+
+    let peak = rows.iter().copied().fold(0.0, f32::max);
+    if peak <= 0.0 {
+        return Ok(None);
+    }
+    // The x-height band is where most rows are densely inked.
+    let core_top = rows.iter().position(|&v| v >= peak * 0.5).unwrap_or(0);
+    let core_bottom = rows.iter().rposition(|&v| v >= peak * 0.5).unwrap_or(0);
+    let above: f32 = rows[..core_top].iter().sum();
+    let below: f32 = rows[core_bottom + 1..].iter().sum();
+    if above + below <= 0.0 {
+        return Ok(None);
+    }
+    Ok(Some(((above - below) / (above + below)) as f64))
+}
+
+/// Orientation confidence below which tesseract's answer is ignored; sheets
+/// with only a few words of handwriting score around 1 and are often wrong.
+const MIN_OSD_CONFIDENCE: f64 = 2.0;
+
+/// Asks the `tesseract` CLI (orientation and script detection) how to turn the sheet.
+///
+/// Returns `None` when tesseract finds too little text to decide, or is not
+/// confident of its answer.
+pub fn tesseract_osd(image: &Mat) -> Result<Option<Rotation>> {
+    let gray = crate::enhance::to_gray(image)?;
+    let mut png = Vector::<u8>::new();
+    let ok = imgcodecs::imencode(".png", &gray, &mut png, &Vector::new())?;
+    anyhow::ensure!(ok, "Could not encode the sheet for tesseract");
+
+    // The sheet goes in through stdin, so parallel workers share no files.
+    let mut child = Command::new("tesseract")
+        .args(["stdin", "stdout", "--psm", "0"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Could not run tesseract; is it installed and on PATH?")?;
+    // Tesseract reads all of stdin before it writes anything.
+    child
+        .stdin
+        .take()
+        .context("tesseract has no stdin")?
+        .write_all(&png.to_vec())
+        .context("Could not pass the sheet to tesseract")?;
+    let output = child
+        .wait_with_output()
+        .context("Could not run tesseract")?;
+
+    // "Rotate" is the clockwise turn that makes the text upright; "Orientation
+    // in degrees" is how far the page lies turned counter-clockwise, the
+    // opposite for 90 and 270. Pages with too few characters fail without
+    // printing either.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let field = |name: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .map(str::trim)
+    };
+    let confidence = field("Orientation confidence:").and_then(|v| v.parse::<f64>().ok());
+    if confidence.is_none_or(|c| c < MIN_OSD_CONFIDENCE) {
+        return Ok(None);
+    }
+    let degrees = field("Rotate:").and_then(|v| v.parse::<u16>().ok());
+    Ok(degrees.and_then(|d| Rotation::try_from(d).ok()))
+}
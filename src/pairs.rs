@@ -0,0 +1,171 @@
+use std::ops::RangeInclusive;
+
+use anyhow::Result;
+use opencv::core::{self, AlgorithmHint, Mat, Point2f, Scalar, Size, Vector};
+use opencv::imgproc;
+use opencv::prelude::*;
+
+use crate::detect::{Quad, warp_size};
+
+/// Long-to-short side ratios taken for two equal photos lying side by side.
+const PAIR_ASPECT: RangeInclusive<f32> = 1.75..=2.3;
+/// How far from the middle, as a share of the long side, the seam may lie.
+const SEAM_ZONE: f32 = 0.1;
+/// How many times stronger than a typical column the seam's edges must be.
+const SEAM_CONTRAST: f32 = 2.5;
+/// Long side the candidate is straightened to for the search.
+const WORK_SIDE: f32 = 600.0;
+/// Columns at either end left out of the typical edge strength.
+const BORDER: usize = 3;
+
+/// Splits a candidate that is really two same-size photos placed edge to
+/// edge, which detection sees as one rectangle of about 2:1.
+///
+/// The straightened candidate is searched near the middle of its long side
+/// for a seam: a line across the whole short side with far stronger edges
+/// than the rest, as where two prints meet or a thin gap runs between them.
+/// Returns the two halves in the order they lie along the long side, or
+/// `None` when the shape or the seam does not fit.
+pub fn split(image: &Mat, quad: &Quad) -> Result<Option<[Quad; 2]>> {
+    let size = warp_size(quad);
+    let (long, short) = (
+        size.width.max(size.height) as f32,
+        size.width.min(size.height) as f32,
+    );
+    if !PAIR_ASPECT.contains(&(long / short)) {
+        return Ok(None);
+    }
+    let side_by_side = size.width >= size.height;
+
+    let scale = (WORK_SIDE / long).min(1.0);
+    let (w, h) = (
+        ((size.width as f32 * scale).round() as i32).max(2),
+        ((size.height as f32 * scale).round() as i32).max(2),
+    );
+    let (right, bottom) = ((w - 1) as f32, (h - 1) as f32);
+    let upright = [
+        Point2f::new(0.0, 0.0),
+        Point2f::new(right, 0.0),
+        Point2f::new(right, bottom),
+        Point2f::new(0.0, bottom),
+    ];
+    let src: Vector<Point2f> = Vector::from(quad.to_vec());
+    let dst: Vector<Point2f> = Vector::from(upright.to_vec());
+    let forward = imgproc::get_perspective_transform(&src, &dst, core::DECOMP_LU)?;
+    let mut straight = Mat::default();
+    imgproc::warp_perspective(
+        image,
+        &mut straight,
+        &forward,
+        Size::new(w, h),
+        imgproc::INTER_LINEAR,
+        core::BORDER_REPLICATE,
+        Scalar::all(0.0),
+    )?;
+    let mut gray = Mat::default();
+    imgproc::cvt_color(
+        &straight,
+        &mut gray,
+        imgproc::COLOR_BGR2GRAY,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    // Look for the seam across columns either way.
+    if !side_by_side {
+        let mut turned = Mat::default();
+        core::transpose(&gray, &mut turned)?;
+        gray = turned;
+    }
+
+    let Some(seam) = find_seam(&gray)? else {
+        return Ok(None);
+    };
+    let seam = seam as f32;
+    let halves = if side_by_side {
+        [
+            [
+                upright[0],
+                Point2f::new(seam, 0.0),
+                Point2f::new(seam, bottom),
+                upright[3],
+            ],
+            [
+                Point2f::new(seam, 0.0),
+                upright[1],
+                upright[2],
+                Point2f::new(seam, bottom),
+            ],
+        ]
+    } else {
+        [
+            [
+                upright[0],
+                upright[1],
+                Point2f::new(right, seam),
+                Point2f::new(0.0, seam),
+            ],
+            [
+                Point2f::new(0.0, seam),
+                Point2f::new(right, seam),
+                upright[2],
+                upright[3],
+            ],
+        ]
+    };
+    let back = imgproc::get_perspective_transform(&dst, &src, core::DECOMP_LU)?;
+    let map = |half: [Point2f; 4]| -> Result<Quad> {
+        let points: Vector<Point2f> = Vector::from(half.to_vec());
+        let mut mapped: Vector<Point2f> = Vector::new();
+        core::perspective_transform(&points, &mut mapped, &back)?;
+        let mut quad = half;
+        for (corner, p) in quad.iter_mut().zip(mapped.iter()) {
+            *corner = p;
+        }
+        Ok(quad)
+    };
+    Ok(Some([map(halves[0])?, map(halves[1])?]))
+}
+
+/// Column near the middle of `gray` whose horizontal edges stand out most
+/// from the typical column, if they stand out enough to be a seam.
+fn find_seam(gray: &Mat) -> Result<Option<usize>> {
+    let mut gradient = Mat::default();
+    imgproc::sobel(
+        gray,
+        &mut gradient,
+        core::CV_32F,
+        1,
+        0,
+        3,
+        1.0,
+        0.0,
+        core::BORDER_REPLICATE,
+    )?;
+    let cols = gradient.cols() as usize;
+    if cols <= 2 * BORDER {
+        return Ok(None);
+    }
+    // Mean edge strength per column; a seam runs the full height.
+    let mut profile = vec![0.0f32; cols];
+    for row in gradient.data_typed::<f32>()?.chunks_exact(cols) {
+        for (sum, v) in profile.iter_mut().zip(row) {
+            *sum += v.abs();
+        }
+    }
+    let rows = gradient.rows().max(1) as f32;
+    for sum in &mut profile {
+        *sum /= rows;
+    }
+
+    let mut typical = profile[BORDER..cols - BORDER].to_vec();
+    typical.sort_by(f32::total_cmp);
+    let typical = typical[typical.len() / 2].max(1.0);
+
+    let zone = (cols as f32 * SEAM_ZONE) as usize;
+    let (first, last) = (cols / 2 - zone, (cols / 2 + zone).min(cols - 1));
+    let (seam, strength) = (first..=last)
+        .map(|col| (col, profile[col]))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("the search zone is never empty");
+    Ok((strength >= SEAM_CONTRAST * typical).then_some(seam))
+}
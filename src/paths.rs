@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+
+/// Device names Windows refuses as file names, with or without an extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Longest stem kept for output files, leaving room for index suffixes and
+/// extensions within the 255-character limit on a single path component.
+const MAX_STEM_CHARS: usize = 200;
+
+/// Paths this long or longer need the `\\?\` prefix on Windows.
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// Turns a source file stem into one that is a valid file name on every platform.
+///
+/// Characters Windows rejects are replaced with `_`, trailing dots and spaces are
+/// dropped, overly long stems are shortened, and reserved device names such as
+/// `CON` or `prn.scan` get a `_` appended to their first segment.
+pub fn sanitize_stem(stem: &str) -> String {
+    let mut clean: String = stem
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .take(MAX_STEM_CHARS)
+        .collect();
+
+    let trimmed_len = clean.trim_end_matches(['.', ' ']).len();
+    clean.truncate(trimmed_len);
+    if clean.is_empty() {
+        return "image".to_string();
+    }
+
+    let first_segment = clean.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|name| first_segment.trim_end().eq_ignore_ascii_case(name))
+    {
+        clean.insert(first_segment.len(), '_');
+    }
+
+    clean
+}
+
+/// Returns a path that can be opened even when it exceeds `MAX_PATH` on Windows.
+///
+/// Long paths are made absolute and given the extended-length `\\?\` prefix
+/// (`\\?\UNC\` for network shares). Other platforms get the path unchanged.
+#[cfg(windows)]
+pub fn long_path_safe(path: &Path) -> PathBuf {
+    if path.as_os_str().len() < MAX_PATH || path.as_os_str().to_string_lossy().starts_with(r"\\?\")
+    {
+        return path.to_path_buf();
+    }
+
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    let absolute = absolute.to_string_lossy();
+    match absolute.strip_prefix(r"\\") {
+        Some(unc) => PathBuf::from(format!(r"\\?\UNC\{unc}")),
+        None => PathBuf::from(format!(r"\\?\{absolute}")),
+    }
+}
+
+#[cfg(not(windows))]
+pub fn long_path_safe(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
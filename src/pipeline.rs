@@ -0,0 +1,89 @@
+use std::sync::Arc;
+use std::thread::{self, Scope};
+
+use crossbeam_channel::{Receiver, bounded};
+
+/// Items handed from stage to stage of a processing pipeline, each stage
+/// running on its own scoped threads.
+///
+/// Every channel between two stages holds at most `depth` items, so a slow
+/// stage holds back the ones before it instead of letting decoded images
+/// pile up in memory, while the stages themselves overlap: one sheet can be
+/// read from disk while another is detected and a third is written.
+pub struct Pipeline<'scope, 'env, T> {
+    scope: &'scope Scope<'scope, 'env>,
+    items: Receiver<T>,
+    depth: usize,
+}
+
+impl<'scope, 'env, T: Send + 'scope> Pipeline<'scope, 'env, T> {
+    /// Starts a pipeline fed with `items` from a thread of its own.
+    pub fn new<I>(scope: &'scope Scope<'scope, 'env>, items: I, depth: usize) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'scope,
+    {
+        let (sender, receiver) = bounded(depth);
+        let items = items.into_iter();
+        scope.spawn(move || {
+            for item in items {
+                if sender.send(item).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            scope,
+            items: receiver,
+            depth,
+        }
+    }
+
+    /// Adds a stage that runs `work` on every item with `workers` threads.
+    /// Items may leave a stage with several workers in another order than
+    /// they entered it.
+    pub fn stage<U, F>(self, workers: usize, work: F) -> Pipeline<'scope, 'env, U>
+    where
+        U: Send + 'scope,
+        F: Fn(T) -> U + Send + Sync + 'scope,
+    {
+        let (sender, receiver) = bounded(self.depth);
+        let work = Arc::new(work);
+        for _ in 0..workers.max(1) {
+            let (items, sender, work) = (self.items.clone(), sender.clone(), Arc::clone(&work));
+            self.scope.spawn(move || {
+                for item in items {
+                    if sender.send(work(item)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Pipeline {
+            scope: self.scope,
+            items: receiver,
+            depth: self.depth,
+        }
+    }
+}
+
+impl<T> IntoIterator for Pipeline<'_, '_, T> {
+    type Item = T;
+    type IntoIter = crossbeam_channel::IntoIter<T>;
+
+    /// The results of the last stage, as they come.
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+/// Worker count for CPU-bound stages: one per available core.
+pub fn cpu_workers() -> usize {
+    thread::available_parallelism().map_or(1, usize::from)
+}
+
+/// OpenCV threads for each of `workers` threads running OpenCV at once, so
+/// that together they keep about one thread per core busy.
+pub fn opencv_threads(workers: usize) -> usize {
+    (cpu_workers() / workers.max(1)).max(1)
+}
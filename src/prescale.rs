@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, ensure};
+use opencv::core::{AlgorithmHint, Mat, Size, Vector};
+use opencv::prelude::*;
+use opencv::{imgcodecs, imgproc};
+
+use crate::detect::DetectParams;
+use crate::hash;
+use crate::tr;
+
+/// Downscaled grayscale copies of sheets kept on disk, so repeated tuning
+/// runs over the same sample skip decoding and converting the full scans.
+///
+/// Entries are keyed by the source's content hash, the lens profile and the
+/// scale, so edited scans or a new profile simply miss the cache.
+pub struct PrescaleCache {
+    dir: PathBuf,
+    scale: f64,
+}
+
+impl PrescaleCache {
+    pub fn new(dir: &Path, scale: f64) -> Result<Self> {
+        ensure!(
+            scale > 0.0 && scale <= 1.0,
+            "Prescale factor must be above 0 and at most 1, got {scale}"
+        );
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Could not create cache dir {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            scale,
+        })
+    }
+
+    /// Factor cached images are resized by; detection parameters in pixels
+    /// must be scaled to match (see [`DetectParams::scaled`]).
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// The sheet at `source`, downscaled and in gray (stored as BGR so it can
+    /// go straight into detection). On a miss `decode` reads the full image.
+    #[tracing::instrument(level = "debug", skip(self, params, decode), err)]
+    pub fn load(
+        &self,
+        source: &Path,
+        params: &DetectParams,
+        decode: impl FnOnce() -> Result<Mat>,
+    ) -> Result<Mat> {
+        let path = self.entry_path(source, params)?;
+        let cached = imgcodecs::imread(
+            path.to_str().unwrap_or_default(),
+            imgcodecs::IMREAD_GRAYSCALE,
+        )
+        .ok()
+        .filter(|image| !image.empty());
+        let gray = match cached {
+            Some(gray) => gray,
+            None => {
+                let gray = self.prescale(&decode()?)?;
+                let written =
+                    imgcodecs::imwrite(path.to_str().unwrap_or_default(), &gray, &Vector::new());
+                if !matches!(written, Ok(true)) {
+                    eprintln!(
+                        "{}",
+                        tr!("cache-write-failed", path = path.display().to_string())
+                    );
+                }
+                gray
+            }
+        };
+        let mut bgr = Mat::default();
+        imgproc::cvt_color(
+            &gray,
+            &mut bgr,
+            imgproc::COLOR_GRAY2BGR,
+            0,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        Ok(bgr)
+    }
+
+    fn entry_path(&self, source: &Path, params: &DetectParams) -> Result<PathBuf> {
+        let lens = serde_json::to_string(&params.lens)?;
+        let key = format!(
+            "{}|{lens}|{:?}|{}",
+            hash::sha256_file(source)?,
+            params.alpha_background,
+            self.scale
+        );
+        Ok(self
+            .dir
+            .join(format!("{}.png", hash::sha256_hex(key.as_bytes()))))
+    }
+
+    fn prescale(&self, image: &Mat) -> Result<Mat> {
+        let mut small = Mat::default();
+        imgproc::resize(
+            image,
+            &mut small,
+            Size::new(0, 0),
+            self.scale,
+            self.scale,
+            imgproc::INTER_AREA,
+        )?;
+        let mut gray = Mat::default();
+        imgproc::cvt_color(
+            &small,
+            &mut gray,
+            imgproc::COLOR_BGR2GRAY,
+            0,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        Ok(gray)
+    }
+}
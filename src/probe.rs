@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::paths;
+
+/// Width and height of the image file at `path`, read from its header
+/// without decoding the pixels. `None` for formats or files it cannot make
+/// sense of; JPEG, PNG, BMP and TIFF are understood.
+pub fn dimensions(path: &Path) -> Result<Option<(u32, u32)>> {
+    let file = File::open(paths::long_path_safe(path))
+        .with_context(|| format!("Could not open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut magic = [0u8; 4];
+    if reader.read_exact(&mut magic).is_err() {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(0))?;
+    // A truncated or odd header just leaves the size unknown.
+    Ok(match magic {
+        [0xff, 0xd8, ..] => jpeg(&mut reader).ok().flatten(),
+        [0x89, b'P', b'N', b'G'] => png(&mut reader).ok(),
+        [b'B', b'M', ..] => bmp(&mut reader).ok(),
+        [b'I', b'I', 42, 0] => tiff(&mut reader, false).ok().flatten(),
+        [b'M', b'M', 0, 42] => tiff(&mut reader, true).ok().flatten(),
+        _ => None,
+    })
+}
+
+/// Walks the segments up to the first start-of-frame marker.
+fn jpeg(reader: &mut (impl Read + Seek)) -> std::io::Result<Option<(u32, u32)>> {
+    reader.seek(SeekFrom::Start(2))?;
+    loop {
+        let mut marker = [0u8; 2];
+        reader.read_exact(&mut marker)?;
+        if marker[0] != 0xff {
+            return Ok(None);
+        }
+        // Fill bytes may pad between segments.
+        if marker[1] == 0xff {
+            reader.seek(SeekFrom::Current(-1))?;
+            continue;
+        }
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length)?;
+        let length = u16::from_be_bytes(length) as i64;
+        let is_frame = matches!(marker[1], 0xc0..=0xcf) && !matches!(marker[1], 0xc4 | 0xc8 | 0xcc);
+        if is_frame {
+            let mut frame = [0u8; 5];
+            reader.read_exact(&mut frame)?;
+            let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+            let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+            return Ok(Some((width, height)));
+        }
+        reader.seek(SeekFrom::Current(length - 2))?;
+    }
+}
+
+fn png(reader: &mut impl Read) -> std::io::Result<(u32, u32)> {
+    // Signature, IHDR length and type, then width and height.
+    let mut header = [0u8; 24];
+    reader.read_exact(&mut header)?;
+    Ok((
+        u32::from_be_bytes(header[16..20].try_into().unwrap()),
+        u32::from_be_bytes(header[20..24].try_into().unwrap()),
+    ))
+}
+
+fn bmp(reader: &mut impl Read) -> std::io::Result<(u32, u32)> {
+    let mut header = [0u8; 26];
+    reader.read_exact(&mut header)?;
+    let width = i32::from_le_bytes(header[18..22].try_into().unwrap());
+    // Negative heights mark top-down bitmaps.
+    let height = i32::from_le_bytes(header[22..26].try_into().unwrap());
+    Ok((width.unsigned_abs(), height.unsigned_abs()))
+}
+
+/// ImageWidth and ImageLength from the first image file directory.
+fn tiff(reader: &mut (impl Read + Seek), big_endian: bool) -> std::io::Result<Option<(u32, u32)>> {
+    let u16_of = |b: [u8; 2]| {
+        if big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    };
+    let u32_of = |b: [u8; 4]| {
+        if big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    };
+    let mut word = [0u8; 4];
+    reader.seek(SeekFrom::Start(4))?;
+    reader.read_exact(&mut word)?;
+    reader.seek(SeekFrom::Start(u32_of(word) as u64))?;
+    let mut count = [0u8; 2];
+    reader.read_exact(&mut count)?;
+    let (mut width, mut height) = (None, None);
+    for _ in 0..u16_of(count) {
+        let mut entry = [0u8; 12];
+        reader.read_exact(&mut entry)?;
+        let tag = u16_of([entry[0], entry[1]]);
+        // SHORT or LONG, stored in the first bytes of the value field.
+        let value = match u16_of([entry[2], entry[3]]) {
+            3 => u16_of([entry[8], entry[9]]) as u32,
+            4 => u32_of([entry[8], entry[9], entry[10], entry[11]]),
+            _ => continue,
+        };
+        match tag {
+            256 => width = Some(value),
+            257 => height = Some(value),
+            _ => {}
+        }
+    }
+    Ok(width.zip(height))
+}
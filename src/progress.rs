@@ -0,0 +1,168 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+/// Snapshot of a running batch, as written to the status file.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Status {
+    pub command: String,
+    /// Unix timestamps in seconds.
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub files_total: usize,
+    pub files_processed: usize,
+    pub current_file: Option<PathBuf>,
+    pub crops_written: usize,
+    pub errors: usize,
+    /// Files left out for what they are rather than failed, such as animations.
+    pub skipped: usize,
+    /// Inputs left out before processing as copies of another input.
+    pub duplicates: usize,
+}
+
+/// Shared progress of a batch, readable from a signal handler thread and
+/// mirrored to an optional status file for headless runs.
+#[derive(Clone)]
+pub struct Progress {
+    status: Arc<Mutex<Status>>,
+    status_file: Option<PathBuf>,
+}
+
+impl Progress {
+    pub fn new(command: &str, status_file: Option<&Path>) -> Self {
+        let now = unix_now();
+        Self {
+            status: Arc::new(Mutex::new(Status {
+                command: command.to_string(),
+                started_at: now,
+                updated_at: now,
+                ..Status::default()
+            })),
+            status_file: status_file.map(Path::to_path_buf),
+        }
+    }
+
+    pub fn snapshot(&self) -> Status {
+        self.lock().clone()
+    }
+
+    /// Adds `count` files to the expected total.
+    pub fn add_total(&self, count: usize) {
+        self.update(|s| s.files_total += count);
+    }
+
+    pub fn start_file(&self, path: &Path) {
+        self.update(|s| s.current_file = Some(path.to_path_buf()));
+    }
+
+    /// Marks the current file as done with the number of crops written, or
+    /// `None` if it failed.
+    pub fn finish_file(&self, crops: Option<usize>) {
+        self.update(|s| {
+            s.files_processed += 1;
+            s.current_file = None;
+            match crops {
+                Some(crops) => s.crops_written += crops,
+                None => s.errors += 1,
+            }
+        });
+    }
+
+    /// Counts `count` inputs left out as duplicates; they are not part of
+    /// the total.
+    pub fn add_duplicates(&self, count: usize) {
+        if count > 0 {
+            self.update(|s| s.duplicates += count);
+        }
+    }
+
+    /// Marks the current file as done without cropping it, on purpose.
+    pub fn skip_file(&self) {
+        self.update(|s| {
+            s.files_processed += 1;
+            s.current_file = None;
+            s.skipped += 1;
+        });
+    }
+
+    /// One line summary for humans, e.g. in response to SIGUSR1.
+    pub fn summary(&self) -> String {
+        let s = self.snapshot();
+        let current = s
+            .current_file
+            .as_ref()
+            .map(|p| format!(", working on {}", p.display()))
+            .unwrap_or_default();
+        format!(
+            "{}: {}/{} files, {} crops written, {} errors, {} skipped, {} duplicates, {}s elapsed{current}",
+            s.command,
+            s.files_processed,
+            s.files_total,
+            s.crops_written,
+            s.errors,
+            s.skipped,
+            s.duplicates,
+            unix_now().saturating_sub(s.started_at),
+        )
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Status)) {
+        let snapshot = {
+            let mut status = self.lock();
+            change(&mut status);
+            status.updated_at = unix_now();
+            status.clone()
+        };
+        if let Some(path) = &self.status_file
+            && let Err(err) = write_status(path, &snapshot)
+        {
+            eprintln!("{err:?}");
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Status> {
+        // A panic elsewhere must not stop progress reporting.
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Prints the summary to stderr whenever the process receives SIGUSR1.
+    #[cfg(unix)]
+    pub fn report_on_sigusr1(&self) -> Result<()> {
+        use signal_hook::consts::SIGUSR1;
+        use signal_hook::iterator::Signals;
+
+        let mut signals = Signals::new([SIGUSR1]).context("Could not install SIGUSR1 handler")?;
+        let progress = self.clone();
+        std::thread::spawn(move || {
+            for _ in signals.forever() {
+                eprintln!("{}", progress.summary());
+            }
+        });
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn report_on_sigusr1(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes through a temporary file so readers never see a half-written status.
+fn write_status(path: &Path, status: &Status) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(status)?)
+        .with_context(|| format!("Failed to write status file {}", tmp.display()))?;
+    fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to write status file {}", path.display()))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
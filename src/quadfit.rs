@@ -0,0 +1,271 @@
+use opencv::core::Point2f;
+
+use crate::detect::{PrintShape, Quad};
+
+/// RANSAC iterations per edge.
+const ITERATIONS: usize = 200;
+/// Share of an edge's length that must be backed by straight contour points.
+const MIN_EDGE_SUPPORT: f64 = 0.25;
+/// How far (as a share of the diagonal) a fitted corner may move from the rectangle's.
+const MAX_CORNER_SHIFT: f64 = 0.15;
+/// Share of each side next to a corner that a corner mount may cover.
+const MOUNT_ZONE: f64 = 0.25;
+/// How far (as a share of the short side) the outline must stick out at a
+/// corner to count as a mount.
+const MOUNT_MIN_OVERHANG: f64 = 0.005;
+/// Mounted corners needed before the quad is refitted; one odd corner is
+/// more likely a scrap or a neighbouring print.
+const MIN_MOUNTED_CORNERS: usize = 2;
+
+#[derive(Clone, Copy, Debug)]
+struct Line {
+    point: (f64, f64),
+    dir: (f64, f64),
+}
+
+/// Fits a quad to the straight edges of `contour`, using `rect` (the ordered
+/// corners of its minimum-area rectangle) as the starting guess.
+///
+/// Each contour point is assigned to the nearest rectangle side, a line is fitted
+/// to every side with RANSAC so torn or folded sections are ignored, and the
+/// corners are taken where adjacent lines meet. A missing corner is thereby
+/// extrapolated from the intact edges. Returns `None` if any edge is too poorly
+/// supported, in which case the caller should keep the rectangle.
+///
+/// For rounded prints the corner arcs are left out of the fit so the quad
+/// reaches the virtual square corner; for deckle prints the tolerance is wide
+/// enough to treat a whole row of scallops as one edge.
+pub fn fit_quad(contour: &[Point2f], rect: &Quad, shape: PrintShape) -> Option<Quad> {
+    let (band_share, tolerance_share, corner_margin) = match shape {
+        PrintShape::Rect => (0.08, 0.004, -0.1),
+        PrintShape::Rounded => (0.08, 0.004, 0.12),
+        PrintShape::Deckle => (0.12, 0.015, 0.0),
+    };
+    fit_sides(contour, rect, band_share, tolerance_share, corner_margin)
+}
+
+/// Fits a quad to the photo itself when its corners sit in adhesive corner
+/// mounts, whose triangles otherwise widen the outline at every corner.
+///
+/// The sides are fitted to the middle of each edge only, where the photo's
+/// own edge shows, and a corner counts as mounted when the outline next to
+/// it sticks out past those lines. Returns `None` unless at least
+/// [`MIN_MOUNTED_CORNERS`] corners look mounted.
+pub fn fit_inside_corner_mounts(contour: &[Point2f], guess: &Quad) -> Option<Quad> {
+    let quad = fit_sides(contour, guess, 0.08, 0.004, MOUNT_ZONE)?;
+    let corners: Vec<(f64, f64)> = quad.iter().map(|p| (p.x as f64, p.y as f64)).collect();
+    let short_side = (0..4)
+        .map(|i| dist(corners[i], corners[(i + 1) % 4]))
+        .fold(f64::INFINITY, f64::min);
+    let threshold = (short_side * MOUNT_MIN_OVERHANG).max(2.0);
+    let centre = (
+        corners.iter().map(|c| c.0).sum::<f64>() / 4.0,
+        corners.iter().map(|c| c.1).sum::<f64>() / 4.0,
+    );
+
+    // Outward distance of the outline near each corner, beyond the fitted sides.
+    let mut overhang: [Vec<f64>; 4] = Default::default();
+    for p in contour {
+        let p = (p.x as f64, p.y as f64);
+        let nearest = (0..4)
+            .filter_map(|i| {
+                let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                let t = projection(p, a, b);
+                (0.0..=1.0)
+                    .contains(&t)
+                    .then(|| (i, t, line_distance(p, a, b)))
+            })
+            .min_by(|x, y| x.2.total_cmp(&y.2));
+        let Some((i, t, d)) = nearest else {
+            continue;
+        };
+        let corner = if t < MOUNT_ZONE {
+            i
+        } else if t > 1.0 - MOUNT_ZONE {
+            (i + 1) % 4
+        } else {
+            continue;
+        };
+        let (a, b) = (corners[i], corners[(i + 1) % 4]);
+        let outside = side_of(p, a, b) != side_of(centre, a, b);
+        overhang[corner].push(if outside { d } else { -d });
+    }
+    let mut mounted = 0;
+    for distances in &mut overhang {
+        if distances.len() < 5 {
+            continue;
+        }
+        distances.sort_by(f64::total_cmp);
+        if distances[distances.len() / 2] > threshold {
+            mounted += 1;
+        }
+    }
+    (mounted >= MIN_MOUNTED_CORNERS).then_some(quad)
+}
+
+/// Fits a line to each side of `rect` from the contour points within
+/// `band_share` of the short side, leaving out `corner_margin` of each side
+/// at both ends, and intersects them.
+fn fit_sides(
+    contour: &[Point2f],
+    rect: &Quad,
+    band_share: f64,
+    tolerance_share: f64,
+    corner_margin: f64,
+) -> Option<Quad> {
+    let corners: Vec<(f64, f64)> = rect.iter().map(|p| (p.x as f64, p.y as f64)).collect();
+    let side_len = |i: usize| dist(corners[i], corners[(i + 1) % 4]);
+    let short_side = (0..4).map(side_len).fold(f64::INFINITY, f64::min);
+    let band = (short_side * band_share).max(3.0);
+
+    // Sides: 0 top (tl→tr), 1 right, 2 bottom, 3 left.
+    let mut sides: [Vec<(f64, f64)>; 4] = Default::default();
+    for p in contour {
+        let p = (p.x as f64, p.y as f64);
+        let nearest = (0..4)
+            .filter_map(|i| {
+                let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                let t = projection(p, a, b);
+                (corner_margin..=1.0 - corner_margin)
+                    .contains(&t)
+                    .then(|| (i, line_distance(p, a, b)))
+            })
+            .min_by(|x, y| x.1.total_cmp(&y.1));
+        if let Some((i, d)) = nearest
+            && d <= band
+        {
+            sides[i].push(p);
+        }
+    }
+
+    let mut rng = Lcg(0x5eed);
+    let mut lines = Vec::with_capacity(4);
+    for (i, points) in sides.iter().enumerate() {
+        let len = side_len(i);
+        let tolerance = (len * tolerance_share).max(1.5);
+        let inliers = ransac(points, tolerance, len * 0.2, &mut rng)?;
+        let fitted_len = len * (1.0 - 2.0 * corner_margin.max(0.0));
+        if (inliers.len() as f64) < fitted_len * MIN_EDGE_SUPPORT {
+            return None;
+        }
+        lines.push(least_squares(&inliers)?);
+    }
+
+    let diagonal = dist(corners[0], corners[2]);
+    let mut quad = [Point2f::default(); 4];
+    // Corner i sits between side i-1 and side i (tl = left ∩ top, ...).
+    for i in 0..4 {
+        let corner = intersect(&lines[(i + 3) % 4], &lines[i])?;
+        if dist(corner, corners[i]) > diagonal * MAX_CORNER_SHIFT {
+            return None;
+        }
+        quad[i] = Point2f::new(corner.0 as f32, corner.1 as f32);
+    }
+    Some(quad)
+}
+
+/// Largest set of points within `tolerance` of a line through two sample points.
+fn ransac(
+    points: &[(f64, f64)],
+    tolerance: f64,
+    min_span: f64,
+    rng: &mut Lcg,
+) -> Option<Vec<(f64, f64)>> {
+    if points.len() < 10 {
+        return None;
+    }
+
+    let mut best: Vec<(f64, f64)> = Vec::new();
+    for _ in 0..ITERATIONS {
+        let a = points[rng.next(points.len())];
+        let b = points[rng.next(points.len())];
+        // Samples close together give unstable directions.
+        if dist(a, b) < min_span {
+            continue;
+        }
+        let inliers: Vec<(f64, f64)> = points
+            .iter()
+            .copied()
+            .filter(|&p| line_distance(p, a, b) <= tolerance)
+            .collect();
+        if inliers.len() > best.len() {
+            best = inliers;
+        }
+    }
+    (!best.is_empty()).then_some(best)
+}
+
+/// Total least squares line through `points`.
+fn least_squares(points: &[(f64, f64)]) -> Option<Line> {
+    let n = points.len() as f64;
+    let (mx, my) = points
+        .iter()
+        .fold((0.0, 0.0), |(sx, sy), p| (sx + p.0, sy + p.1));
+    let (mx, my) = (mx / n, my / n);
+    let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+    for p in points {
+        let (dx, dy) = (p.0 - mx, p.1 - my);
+        sxx += dx * dx;
+        syy += dy * dy;
+        sxy += dx * dy;
+    }
+    if sxx + syy <= f64::EPSILON {
+        return None;
+    }
+    let angle = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+    Some(Line {
+        point: (mx, my),
+        dir: (angle.cos(), angle.sin()),
+    })
+}
+
+fn intersect(a: &Line, b: &Line) -> Option<(f64, f64)> {
+    let cross = a.dir.0 * b.dir.1 - a.dir.1 * b.dir.0;
+    if cross.abs() < 1e-6 {
+        return None;
+    }
+    let (qx, qy) = (b.point.0 - a.point.0, b.point.1 - a.point.1);
+    let t = (qx * b.dir.1 - qy * b.dir.0) / cross;
+    Some((a.point.0 + t * a.dir.0, a.point.1 + t * a.dir.1))
+}
+
+/// Position of `p` projected onto segment `a`–`b` (0 at `a`, 1 at `b`).
+fn projection(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    if len2 <= f64::EPSILON {
+        return 0.0;
+    }
+    ((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2
+}
+
+/// Distance from `p` to the infinite line through `a` and `b`.
+fn line_distance(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let len = dist(a, b);
+    if len <= f64::EPSILON {
+        return dist(p, a);
+    }
+    ((b.0 - a.0) * (a.1 - p.1) - (a.0 - p.0) * (b.1 - a.1)).abs() / len
+}
+
+/// Which side of the line through `a` and `b` the point `p` lies on.
+fn side_of(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> bool {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0) >= 0.0
+}
+
+fn dist(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+/// Small deterministic generator so repeated runs fit identical quads.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % bound as u64) as usize
+    }
+}
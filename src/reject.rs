@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Key of the regions excluded on every sheet.
+const ALL_SHEETS: &str = "*";
+
+/// Regions that must never produce a crop, such as a logo printed on every
+/// album page, keyed by source file name (`*` for every sheet).
+///
+/// Regions are quads of `[x, y]` source pixels ordered like a manifest quad.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RejectList {
+    sheets: HashMap<String, Vec<[[f32; 2]; 4]>>,
+}
+
+impl RejectList {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read reject list {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("Invalid reject list {}", path.display()))
+    }
+
+    /// Regions excluded on the sheet at `source`: those listed for its file
+    /// name plus those listed for every sheet.
+    pub fn for_source(&self, source: &Path) -> Vec<[[f32; 2]; 4]> {
+        let name = source
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        [ALL_SHEETS, name.as_str()]
+            .iter()
+            .filter_map(|key| self.sheets.get(*key))
+            .flatten()
+            .copied()
+            .collect()
+    }
+}
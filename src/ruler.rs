@@ -0,0 +1,150 @@
+use anyhow::Result;
+use opencv::core::{AlgorithmHint, Mat, Point, Point2f, Vector};
+use opencv::imgproc;
+
+/// Fewest evenly spaced ticks accepted as a ruler.
+const MIN_TICKS: usize = 20;
+/// Share of tick gaps that must agree with the median gap.
+const MIN_AGREEMENT: f64 = 0.7;
+/// How far a gap may deviate from the median and still agree.
+const GAP_TOLERANCE: f32 = 0.2;
+/// Width of the orientation buckets ticks are grouped in, in degrees.
+const ANGLE_STEP: f32 = 5.0;
+
+/// A thin dark mark that could be a ruler tick.
+struct Tick {
+    center: Point2f,
+    /// Angle of the tick's long side, 0 to 180 degrees.
+    angle: f32,
+    length: f32,
+}
+
+/// Measures the scan resolution from a ruler or scale bar lying on the bed.
+///
+/// Looks for a row of thin, parallel, evenly spaced marks and takes their
+/// median spacing as `tick_mm` millimetres. Returns `None` when no row of at
+/// least [`MIN_TICKS`] consistent ticks is found.
+#[tracing::instrument(level = "debug", skip(image), err)]
+pub fn measure_dpi(image: &Mat, tick_mm: f64) -> Result<Option<f64>> {
+    let mut gray = Mat::default();
+    imgproc::cvt_color(
+        image,
+        &mut gray,
+        imgproc::COLOR_BGR2GRAY,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    // Ink becomes white so each tick is its own small contour.
+    let mut ink = Mat::default();
+    imgproc::adaptive_threshold(
+        &gray,
+        &mut ink,
+        255.0,
+        imgproc::ADAPTIVE_THRESH_MEAN_C,
+        imgproc::THRESH_BINARY_INV,
+        15,
+        10.0,
+    )?;
+    let mut contours: Vector<Vector<Point>> = Vector::new();
+    imgproc::find_contours(
+        &ink,
+        &mut contours,
+        imgproc::RETR_LIST,
+        imgproc::CHAIN_APPROX_SIMPLE,
+        Point::new(0, 0),
+    )?;
+
+    let mut ticks = Vec::new();
+    for contour in contours.iter() {
+        if contour.len() < 4 {
+            continue;
+        }
+        let rect = imgproc::min_area_rect(&contour)?;
+        let (w, h) = (rect.size.width, rect.size.height);
+        let (long, short) = (w.max(h), w.min(h));
+        if short < 1.0 || !(5.0..=300.0).contains(&long) || long / short < 4.0 {
+            continue;
+        }
+        let mut corners = [Point2f::default(); 4];
+        rect.points(&mut corners)?;
+        // The long side decides the tick's direction.
+        let (a, b, c) = (corners[0], corners[1], corners[2]);
+        let (dx, dy) = if (b.x - a.x).hypot(b.y - a.y) >= (c.x - b.x).hypot(c.y - b.y) {
+            (b.x - a.x, b.y - a.y)
+        } else {
+            (c.x - b.x, c.y - b.y)
+        };
+        ticks.push(Tick {
+            center: rect.center,
+            angle: dy.atan2(dx).to_degrees().rem_euclid(180.0),
+            length: long,
+        });
+    }
+
+    Ok(tick_spacing(&ticks).map(|px| px as f64 * 25.4 / tick_mm))
+}
+
+/// Median spacing in pixels of the largest row of parallel, evenly spaced ticks.
+fn tick_spacing(ticks: &[Tick]) -> Option<f32> {
+    let buckets = (180.0 / ANGLE_STEP) as usize;
+    let bucket = |t: &Tick| (t.angle / ANGLE_STEP).round() as usize % buckets;
+    let mut counts = vec![0usize; buckets];
+    for tick in ticks {
+        counts[bucket(tick)] += 1;
+    }
+    let (dominant, _) = counts.iter().enumerate().max_by_key(|(_, c)| **c)?;
+    let parallel: Vec<&Tick> = ticks.iter().filter(|t| bucket(t) == dominant).collect();
+    if parallel.len() < MIN_TICKS {
+        return None;
+    }
+
+    // Along the tick (offset) and across the ticks (position on the ruler).
+    let theta = (dominant as f32 * ANGLE_STEP).to_radians();
+    let (along, across) = ((theta.cos(), theta.sin()), (-theta.sin(), theta.cos()));
+    let project = |p: Point2f, (x, y): (f32, f32)| p.x * x + p.y * y;
+
+    // Ticks of one ruler sit side by side: group them by offset.
+    let mut by_offset: Vec<(f32, f32, f32)> = parallel
+        .iter()
+        .map(|t| {
+            (
+                project(t.center, along),
+                project(t.center, across),
+                t.length,
+            )
+        })
+        .collect();
+    by_offset.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut best: Vec<f32> = Vec::new();
+    let mut row: Vec<f32> = Vec::new();
+    let mut last_offset = f32::NEG_INFINITY;
+    for (offset, position, length) in by_offset {
+        if offset - last_offset > length {
+            if row.len() > best.len() {
+                best = std::mem::take(&mut row);
+            }
+            row.clear();
+        }
+        row.push(position);
+        last_offset = offset;
+    }
+    if row.len() > best.len() {
+        best = row;
+    }
+    if best.len() < MIN_TICKS {
+        return None;
+    }
+
+    best.sort_by(f32::total_cmp);
+    let mut gaps: Vec<f32> = best.windows(2).map(|w| w[1] - w[0]).collect();
+    gaps.sort_by(f32::total_cmp);
+    let spacing = gaps[gaps.len() / 2];
+    if spacing < 2.0 {
+        return None;
+    }
+    let agreeing = gaps
+        .iter()
+        .filter(|g| ((**g - spacing) / spacing).abs() <= GAP_TOLERANCE)
+        .count();
+    (agreeing as f64 >= gaps.len() as f64 * MIN_AGREEMENT).then_some(spacing)
+}
@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+use anyhow::{Context, Result, anyhow};
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::alpha;
+use crate::cancel::{self, CancelToken, Cancelled};
+use crate::colorspace;
+use crate::detect::{self, DetectParams};
+use crate::manifest::{self, Manifest, Photo, ReviewStatus};
+use crate::tr;
+
+/// Largest image accepted in a request body.
+const MAX_BODY: u64 = 512 * 1024 * 1024;
+
+/// Reply of `POST /detect`: the proposed photos in the manifest's format,
+/// so a frontend can send chosen quads back for cropping later.
+#[derive(Debug, Serialize)]
+struct Proposals {
+    width: i32,
+    height: i32,
+    photos: Vec<Photo>,
+}
+
+/// Reply of `GET /review`: where each sheet of the manifest stands.
+#[derive(Debug, Serialize)]
+struct ReviewState {
+    sheets: Vec<SheetReview>,
+}
+
+#[derive(Debug, Serialize)]
+struct SheetReview {
+    source: PathBuf,
+    photos: usize,
+    review: ReviewStatus,
+}
+
+/// Body of `POST /review`.
+#[derive(Debug, Deserialize)]
+struct Mark {
+    sheets: Vec<PathBuf>,
+    status: ReviewStatus,
+}
+
+/// Largest `POST /review` body accepted.
+const MAX_MARK_BODY: u64 = 1024 * 1024;
+
+/// Status answered to a detection abandoned through `POST /cancel`, as
+/// nginx uses for requests the client gave up on.
+const CANCELLED_STATUS: u16 = 499;
+
+/// Tokens of the detections in progress that were given an `id`.
+type Running = Mutex<HashMap<String, CancelToken>>;
+
+/// Who may use the server and how they reach it.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    /// Bearer token every request must carry in its `Authorization` header.
+    pub token: Option<String>,
+    /// Certificate and key to serve HTTPS with instead of plain HTTP.
+    pub tls: Option<Tls>,
+}
+
+/// PEM files for HTTPS.
+#[derive(Clone, Debug)]
+pub struct Tls {
+    pub certificate: PathBuf,
+    pub private_key: PathBuf,
+}
+
+/// Serves detection over HTTP until the process is stopped.
+///
+/// `POST /detect` takes an encoded image as the body and answers with the
+/// quads and confidences found, without warping or encoding anything. With
+/// `?id=<id>` the detection can be abandoned by `POST /cancel?id=<id>`, e.g.
+/// when the user navigates away from a huge sheet.
+///
+/// With a `manifest`, `GET /review` lists its sheets with their review
+/// status and `POST /review` with `{"sheets": [...], "status": "approved"}`
+/// marks them, through the same locked update as the `review` command. Each
+/// request is handled on a thread of its own.
+///
+/// With a token in `access`, requests without `Authorization: Bearer <token>`
+/// are turned away with 401, so the service can run on a shared network.
+pub fn serve(
+    addr: &str,
+    params: &DetectParams,
+    manifest: Option<&Path>,
+    access: &Access,
+) -> Result<()> {
+    let (server, scheme) = match &access.tls {
+        Some(tls) => (https(addr, tls)?, "https"),
+        None => (
+            Server::http(addr).map_err(|err| anyhow!("Could not listen on {addr}: {err}"))?,
+            "http",
+        ),
+    };
+    println!(
+        "{}",
+        tr!(
+            "server-listening",
+            url = format!("{scheme}://{}", server.server_addr())
+        )
+    );
+    if access.token.is_none()
+        && server
+            .server_addr()
+            .to_ip()
+            .is_some_and(|ip| !ip.ip().is_loopback())
+    {
+        eprintln!("{}", tr!("server-open"));
+    }
+    let running = Running::default();
+    thread::scope(|scope| {
+        for request in server.incoming_requests() {
+            let running = &running;
+            scope.spawn(move || {
+                let (path, id) = split_url(request.url());
+                if let Some(token) = &access.token
+                    && !authorized(&request, token)
+                {
+                    respond_unauthorized(request);
+                    return;
+                }
+                let reply = match (request.method(), path) {
+                    (Method::Post, "/detect") => detect(request, params, id, running),
+                    (Method::Post, "/cancel") => Ok(cancel(request, id, running)),
+                    (Method::Get, "/review") => review_state(request, manifest),
+                    (Method::Post, "/review") => mark(request, manifest),
+                    _ => Ok((request, 404, error_json("Not found"))),
+                };
+                match reply {
+                    Ok((request, status, body)) => respond(request, status, body),
+                    Err(err) => eprintln!("{err:#}"),
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+type Reply = (Request, u16, String);
+
+#[cfg(feature = "tls")]
+fn https(addr: &str, tls: &Tls) -> Result<Server> {
+    let read = |path: &Path| {
+        std::fs::read(path).with_context(|| format!("Could not read {}", path.display()))
+    };
+    let config = tiny_http::SslConfig {
+        certificate: read(&tls.certificate)?,
+        private_key: read(&tls.private_key)?,
+    };
+    Server::https(addr, config).map_err(|err| anyhow!("Could not listen on {addr}: {err}"))
+}
+
+#[cfg(not(feature = "tls"))]
+fn https(_addr: &str, _tls: &Tls) -> Result<Server> {
+    anyhow::bail!("HTTPS needs a build with the `tls` feature")
+}
+
+/// Whether `request` carries the bearer `token`.
+fn authorized(request: &Request, token: &str) -> bool {
+    request
+        .headers()
+        .iter()
+        .filter(|h| h.field.equiv("Authorization"))
+        .filter_map(|h| h.value.as_str().strip_prefix("Bearer "))
+        .any(|given| constant_time_eq(given.trim().as_bytes(), token.as_bytes()))
+}
+
+/// Compares without returning early, so response times give nothing away
+/// about how much of a guessed token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn respond_unauthorized(request: Request) {
+    let challenge =
+        Header::from_bytes("WWW-Authenticate", "Bearer").expect("static header is valid");
+    let content_type =
+        Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+    let response = Response::from_string(error_json("Unauthorized"))
+        .with_status_code(401)
+        .with_header(challenge)
+        .with_header(content_type);
+    if let Err(err) = request.respond(response) {
+        eprintln!("{}", tr!("response-failed", error = err.to_string()));
+    }
+}
+
+#[tracing::instrument(skip_all, err)]
+fn detect(
+    mut request: Request,
+    params: &DetectParams,
+    id: Option<String>,
+    running: &Running,
+) -> Result<Reply> {
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY + 1)
+        .read_to_end(&mut body)
+        .context("Could not read request body")?;
+    if body.len() as u64 > MAX_BODY {
+        return Ok((request, 413, error_json("Image too large")));
+    }
+
+    let token = CancelToken::new();
+    if let Some(id) = &id {
+        lock(running).insert(id.clone(), token.clone());
+    }
+    let result = {
+        let (params, worker_token) = (params.clone(), token.clone());
+        cancel::abandonable(&token, move || proposals(&body, &params, &worker_token))
+    };
+    if let Some(id) = &id {
+        lock(running).remove(id);
+    }
+    match result {
+        Ok(proposals) => Ok((request, 200, serde_json::to_string(&proposals)?)),
+        Err(err) if err.is::<Cancelled>() => {
+            Ok((request, CANCELLED_STATUS, error_json("Cancelled")))
+        }
+        Err(err) => Ok((request, 400, error_json(&format!("{err:#}")))),
+    }
+}
+
+fn cancel(request: Request, id: Option<String>, running: &Running) -> Reply {
+    let Some(id) = id else {
+        return (request, 400, error_json("Missing id"));
+    };
+    match lock(running).get(&id) {
+        Some(token) => {
+            token.cancel();
+            (
+                request,
+                200,
+                serde_json::json!({ "cancelled": id }).to_string(),
+            )
+        }
+        None => (
+            request,
+            404,
+            error_json("No detection with that id is running"),
+        ),
+    }
+}
+
+fn review_state(request: Request, manifest: Option<&Path>) -> Result<Reply> {
+    let Some(path) = manifest else {
+        return Ok((request, 404, error_json("Started without --manifest")));
+    };
+    // Read afresh, so marks made elsewhere show up.
+    let manifest = match Manifest::load(path) {
+        Ok(manifest) => manifest,
+        Err(err) => return Ok((request, 500, error_json(&format!("{err:#}")))),
+    };
+    let state = ReviewState {
+        sheets: manifest
+            .sheets
+            .into_iter()
+            .map(|sheet| SheetReview {
+                photos: sheet.photos.len(),
+                source: sheet.source,
+                review: sheet.review,
+            })
+            .collect(),
+    };
+    Ok((request, 200, serde_json::to_string(&state)?))
+}
+
+fn mark(mut request: Request, manifest: Option<&Path>) -> Result<Reply> {
+    let Some(path) = manifest else {
+        return Ok((request, 404, error_json("Started without --manifest")));
+    };
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_MARK_BODY)
+        .read_to_end(&mut body)
+        .context("Could not read request body")?;
+    let mark: Mark = match serde_json::from_slice(&body) {
+        Ok(mark) => mark,
+        Err(err) => return Ok((request, 400, error_json(&err.to_string()))),
+    };
+    match manifest::set_review(path, &mark.sheets, mark.status) {
+        Ok(marked) => Ok((
+            request,
+            200,
+            serde_json::json!({ "marked": marked }).to_string(),
+        )),
+        Err(err) => Ok((request, 500, error_json(&format!("{err:#}")))),
+    }
+}
+
+fn proposals(body: &[u8], params: &DetectParams, cancel: &CancelToken) -> Result<Proposals> {
+    let image = colorspace::decode(body)?.image;
+    cancel.check()?;
+    let image = match alpha::decode_alpha(body)? {
+        Some(alpha) => alpha::composite(&image, &alpha, params.alpha_background)?,
+        None => image,
+    };
+    let image = match &params.lens {
+        Some(lens) => lens.undistort(&image)?,
+        None => image,
+    };
+    let photos = detect::detect_cancellable(&image, params, cancel)?
+        .photos
+        .into_iter()
+        .map(|candidate| {
+            let mut photo = Photo::new(&candidate.quad, candidate.confidence);
+            photo.shape = candidate.shape;
+            photo.off_size = candidate.off_size;
+            photo.set_outline(&candidate.outline);
+            photo
+        })
+        .collect();
+    Ok(Proposals {
+        width: image.cols(),
+        height: image.rows(),
+        photos,
+    })
+}
+
+/// The path of a request URL and its `id` query parameter, if any.
+fn split_url(url: &str) -> (&str, Option<String>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let id = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("id="))
+        .filter(|id| !id.is_empty())
+        .map(str::to_string);
+    (path, id)
+}
+
+fn lock(running: &Running) -> MutexGuard<'_, HashMap<String, CancelToken>> {
+    // A panicked request must not take cancellation down with it.
+    running.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn error_json(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn respond(request: Request, status: u16, body: String) {
+    let content_type =
+        Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(content_type);
+    if let Err(err) = request.respond(response) {
+        eprintln!("{}", tr!("response-failed", error = err.to_string()));
+    }
+}
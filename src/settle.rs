@@ -0,0 +1,85 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+/// How long a file must have gone unchanged before it counts as written;
+/// scanners writing a large TIFF touch it far more often than that.
+const QUIET: Duration = Duration::from_secs(2);
+/// First pause before looking at an unfinished file again; each further
+/// pause is twice as long.
+const FIRST_BACKOFF: Duration = Duration::from_millis(500);
+/// Longest wait for one file before it is left for a later run.
+const MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// Whether an input file is ready to be read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Readiness {
+    Ready,
+    /// Nothing written yet.
+    Empty,
+    /// Changed within the last [`QUIET`] period, so probably still being
+    /// written.
+    Growing,
+}
+
+/// Looks at `path` once, without waiting.
+pub fn check(path: &Path) -> Result<Readiness> {
+    let metadata =
+        fs::metadata(path).with_context(|| format!("Could not read {}", path.display()))?;
+    if metadata.len() == 0 {
+        return Ok(Readiness::Empty);
+    }
+    // A modification time ahead of the clock says nothing; take it as done.
+    let recent = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < QUIET);
+    Ok(if recent {
+        Readiness::Growing
+    } else {
+        Readiness::Ready
+    })
+}
+
+/// Waits for `path` to be written, looking again after ever longer pauses,
+/// and fails with [`NotReady`] when it still is not after [`MAX_WAIT`].
+pub fn wait(path: &Path) -> Result<()> {
+    let mut waited = Duration::ZERO;
+    let mut backoff = FIRST_BACKOFF;
+    loop {
+        let readiness = check(path)?;
+        if readiness == Readiness::Ready {
+            return Ok(());
+        }
+        if waited >= MAX_WAIT {
+            return Err(NotReady { readiness }.into());
+        }
+        tracing::debug!(path = %path.display(), ?readiness, ?backoff, "waiting for input");
+        thread::sleep(backoff);
+        waited += backoff;
+        backoff *= 2;
+    }
+}
+
+/// The error an input fails with when it is empty or still being written,
+/// so batches can leave it for a later run rather than report it as broken.
+#[derive(Debug)]
+pub struct NotReady {
+    pub readiness: Readiness,
+}
+
+impl fmt::Display for NotReady {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.readiness {
+            Readiness::Empty => write!(f, "the file is empty"),
+            _ => write!(f, "the file is still being written"),
+        }
+    }
+}
+
+impl std::error::Error for NotReady {}
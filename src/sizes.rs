@@ -0,0 +1,94 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+use opencv::core::Size;
+use serde::{Deserialize, Serialize};
+
+const MM_PER_INCH: f64 = 25.4;
+
+/// Physical print size, orientation-agnostic, stored in millimetres.
+///
+/// Written as `<short>x<long>` with an optional unit (`cm` by default, `mm` or
+/// `in`), e.g. `9x13`, `100x150mm` or `3.5x5in`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PrintSize {
+    short_mm: f64,
+    long_mm: f64,
+}
+
+/// What to do with a candidate that matches none of the known sizes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeAction {
+    /// Drop the candidate
+    #[default]
+    Reject,
+    /// Keep it but mark it in the manifest and review overlay
+    Flag,
+}
+
+impl PrintSize {
+    /// Whether a crop of `size` pixels scanned at `dpi` is this print, with
+    /// each side allowed to deviate by the `tolerance` share.
+    pub fn matches(&self, size: Size, dpi: f64, tolerance: f64) -> bool {
+        let to_mm = |px: i32| px as f64 / dpi * MM_PER_INCH;
+        let (a, b) = (to_mm(size.width), to_mm(size.height));
+        let (short, long) = (a.min(b), a.max(b));
+        (short - self.short_mm).abs() <= self.short_mm * tolerance
+            && (long - self.long_mm).abs() <= self.long_mm * tolerance
+    }
+}
+
+impl FromStr for PrintSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let text = s.trim().to_lowercase();
+        let (dims, mm_per_unit) = if let Some(dims) = text.strip_suffix("mm") {
+            (dims, 1.0)
+        } else if let Some(dims) = text.strip_suffix("cm") {
+            (dims, 10.0)
+        } else if let Some(dims) = text.strip_suffix("in") {
+            (dims, MM_PER_INCH)
+        } else {
+            (text.as_str(), 10.0)
+        };
+        let Some((a, b)) = dims.split_once(['x', '×']) else {
+            bail!("Invalid print size \"{s}\" (expected e.g. 9x13, 100x150mm or 3.5x5in)");
+        };
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|v| *v > 0.0)
+                .with_context(|| format!("Invalid print size \"{s}\""))
+        };
+        let (a, b) = (parse(a)? * mm_per_unit, parse(b)? * mm_per_unit);
+        Ok(Self {
+            short_mm: a.min(b),
+            long_mm: a.max(b),
+        })
+    }
+}
+
+impl TryFrom<String> for PrintSize {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<PrintSize> for String {
+    fn from(size: PrintSize) -> Self {
+        size.to_string()
+    }
+}
+
+impl fmt::Display for PrintSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}mm", self.short_mm, self.long_mm)
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use opencv::core::{AlgorithmHint, Mat, Point2f, Size, Vec4i, Vector};
+use opencv::imgproc;
+use opencv::prelude::*;
+
+use crate::detect::Quad;
+
+/// Longest side images are scaled down to before looking for lines.
+const MAX_SIDE: f64 = 800.0;
+/// Largest tilt away from the axes still taken for skew, in degrees.
+const MAX_SKEW: f32 = 5.0;
+/// Fewest lines an image needs before its skew is trusted.
+const MIN_LINES: usize = 3;
+/// Fewest photos with a measured skew needed to call a skew systematic.
+const MIN_PHOTOS: usize = 4;
+/// Share of the measured photos that must agree with the median skew.
+const MIN_AGREEMENT: f64 = 0.8;
+/// How far, in degrees, a photo's skew may lie from the median and agree.
+const AGREEMENT: f32 = 0.5;
+/// Median skews smaller than this, in degrees, are left alone.
+const MIN_CORRECTION: f32 = 0.1;
+
+/// Clockwise tilt in degrees of the straight lines in `image` (BGR or grey)
+/// that run nearly along its axes: horizons, walls, picture borders.
+///
+/// Each line votes with its length. Returns `None` when too few such lines
+/// are found to tell.
+pub fn measure(image: &Mat) -> Result<Option<f32>> {
+    let mut gray = if image.channels() == 1 {
+        image.clone()
+    } else {
+        let mut gray = Mat::default();
+        imgproc::cvt_color(
+            image,
+            &mut gray,
+            imgproc::COLOR_BGR2GRAY,
+            0,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        gray
+    };
+    let scale = MAX_SIDE / gray.cols().max(gray.rows()) as f64;
+    if scale < 1.0 {
+        let mut small = Mat::default();
+        imgproc::resize(
+            &gray,
+            &mut small,
+            Size::default(),
+            scale,
+            scale,
+            imgproc::INTER_AREA,
+        )?;
+        gray = small;
+    }
+    let mut edges = Mat::default();
+    imgproc::canny(&gray, &mut edges, 50.0, 150.0, 3, false)?;
+    let mut lines: Vector<Vec4i> = Vector::new();
+    imgproc::hough_lines_p(
+        &edges,
+        &mut lines,
+        1.0,
+        // A quarter of a degree.
+        std::f64::consts::PI / 720.0,
+        60,
+        gray.cols().min(gray.rows()) as f64 / 4.0,
+        5.0,
+    )?;
+
+    let mut votes: Vec<(f32, f32)> = lines
+        .iter()
+        .filter_map(|l| {
+            let (dx, dy) = ((l[2] - l[0]) as f32, (l[3] - l[1]) as f32);
+            // Fold onto the nearest axis: horizontal and vertical lines tilt alike.
+            let tilt = (dy.atan2(dx).to_degrees() + 45.0).rem_euclid(90.0) - 45.0;
+            (tilt.abs() <= MAX_SKEW).then_some((tilt, dx.hypot(dy)))
+        })
+        .collect();
+    if votes.len() < MIN_LINES {
+        return Ok(None);
+    }
+    votes.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let half = votes.iter().map(|(_, length)| length).sum::<f32>() / 2.0;
+    let mut running = 0.0;
+    Ok(votes
+        .iter()
+        .find(|(_, length)| {
+            running += length;
+            running >= half
+        })
+        .map(|(tilt, _)| *tilt))
+}
+
+/// The skew most photos of a batch share, from each photo's [`measure`]d
+/// skew, or `None` when the photos disagree or are straight already.
+///
+/// A jig or scanner lid that sits slightly askew tilts every photo alike,
+/// while the odd crooked horizon tilts just one.
+pub fn systematic(skews: &[f32]) -> Option<f32> {
+    if skews.len() < MIN_PHOTOS {
+        return None;
+    }
+    let mut sorted = skews.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    let agreeing = skews
+        .iter()
+        .filter(|s| (*s - median).abs() <= AGREEMENT)
+        .count();
+    (agreeing as f64 >= MIN_AGREEMENT * skews.len() as f64 && median.abs() >= MIN_CORRECTION)
+        .then_some(median)
+}
+
+/// `quad` turned clockwise by `degrees` about its centre, so that a photo
+/// tilted that much inside it comes out straight.
+pub fn rotate_quad(quad: &Quad, degrees: f32) -> Quad {
+    let cx = quad.iter().map(|p| p.x).sum::<f32>() / 4.0;
+    let cy = quad.iter().map(|p| p.y).sum::<f32>() / 4.0;
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    quad.map(|p| {
+        let (dx, dy) = (p.x - cx, p.y - cy);
+        Point2f::new(cx + dx * cos - dy * sin, cy + dx * sin + dy * cos)
+    })
+}
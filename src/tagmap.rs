@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::exif::{self, ExifBuilder, Value};
+use crate::xmp;
+
+/// A metadata field a tag map can name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    /// An IFD0 field.
+    Ifd0(u16),
+    /// A field of the Exif sub-IFD.
+    Exif(u16),
+    /// A Dublin Core element of the crop's XMP; only written, never read.
+    Dc(&'static str),
+}
+
+/// The fields a tag map can name, as exiftool spells them.
+const FIELDS: [(&str, Field); 24] = [
+    ("ImageDescription", Field::Ifd0(exif::TAG_IMAGE_DESCRIPTION)),
+    ("Make", Field::Ifd0(0x010F)),
+    ("Model", Field::Ifd0(0x0110)),
+    ("Software", Field::Ifd0(0x0131)),
+    ("ModifyDate", Field::Ifd0(0x0132)),
+    ("Artist", Field::Ifd0(0x013B)),
+    ("HostComputer", Field::Ifd0(0x013C)),
+    ("Copyright", Field::Ifd0(0x8298)),
+    ("DocumentName", Field::Ifd0(exif::TAG_DOCUMENT_NAME)),
+    ("PageName", Field::Ifd0(exif::TAG_PAGE_NAME)),
+    ("XResolution", Field::Ifd0(exif::TAG_X_RESOLUTION)),
+    ("YResolution", Field::Ifd0(exif::TAG_Y_RESOLUTION)),
+    ("ResolutionUnit", Field::Ifd0(exif::TAG_RESOLUTION_UNIT)),
+    (
+        "DateTimeOriginal",
+        Field::Exif(exif::TAG_DATE_TIME_ORIGINAL),
+    ),
+    ("CreateDate", Field::Exif(0x9004)),
+    ("UserComment", Field::Exif(exif::TAG_USER_COMMENT)),
+    ("ImageUniqueID", Field::Exif(0xA420)),
+    ("OwnerName", Field::Exif(0xA430)),
+    ("SerialNumber", Field::Exif(0xA431)),
+    ("XMP-dc:Title", Field::Dc("dc:title")),
+    ("XMP-dc:Description", Field::Dc("dc:description")),
+    ("XMP-dc:Creator", Field::Dc("dc:creator")),
+    ("XMP-dc:Rights", Field::Dc("dc:rights")),
+    ("XMP-dc:Subject", Field::Dc("dc:subject")),
+];
+
+/// Fields holding text, which can be renamed into one another.
+const TEXT_FIELDS: [Field; 16] = [
+    Field::Ifd0(exif::TAG_IMAGE_DESCRIPTION),
+    Field::Ifd0(0x010F),
+    Field::Ifd0(0x0110),
+    Field::Ifd0(0x0131),
+    Field::Ifd0(0x0132),
+    Field::Ifd0(0x013B),
+    Field::Ifd0(0x013C),
+    Field::Ifd0(0x8298),
+    Field::Ifd0(exif::TAG_DOCUMENT_NAME),
+    Field::Ifd0(exif::TAG_PAGE_NAME),
+    Field::Exif(exif::TAG_DATE_TIME_ORIGINAL),
+    Field::Exif(0x9004),
+    Field::Exif(exif::TAG_USER_COMMENT),
+    Field::Exif(0xA420),
+    Field::Exif(0xA430),
+    Field::Exif(0xA431),
+];
+
+fn field(name: &str) -> Result<Field> {
+    FIELDS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map(|(_, field)| *field)
+        .with_context(|| {
+            let known: Vec<&str> = FIELDS.iter().map(|(known, _)| *known).collect();
+            format!("Unknown field {name:?}; known are {}", known.join(", "))
+        })
+}
+
+fn is_text(field: Field) -> bool {
+    matches!(field, Field::Dc(_)) || TEXT_FIELDS.contains(&field)
+}
+
+/// The tag map file as written.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    copy: Vec<String>,
+    #[serde(default)]
+    drop: Vec<String>,
+    #[serde(default)]
+    rename: BTreeMap<String, String>,
+}
+
+/// Which metadata of a source sheet its crops carry, for `--tag-map`, in
+/// the spirit of exiftool's `-tagsFromFile` rules: fields copied as they
+/// are, fields copied under another name, and fields of the crop's own left
+/// out.
+///
+/// ```toml
+/// copy = ["Artist", "Copyright"]
+/// drop = ["DocumentName", "PageName"]
+///
+/// [rename]
+/// ImageDescription = "XMP-dc:Description"
+/// Make = "Software"
+/// ```
+///
+/// `drop` applies to what the crop would carry otherwise, such as its
+/// provenance comment, before anything is copied, so a field both dropped
+/// and copied ends up with the source's value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TagMap {
+    /// Source field and the crop field it goes to.
+    copy: Vec<(Field, Field)>,
+    drop: Vec<Field>,
+}
+
+impl TagMap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read tag map {}", path.display()))?;
+        let file: File =
+            toml::from_str(&text).with_context(|| format!("Invalid tag map {}", path.display()))?;
+        Self::from_file(file).with_context(|| format!("Invalid tag map {}", path.display()))
+    }
+
+    fn from_file(file: File) -> Result<Self> {
+        let mut map = TagMap::default();
+        let copies = file.copy.iter().map(|name| (name, name));
+        for (from, to) in copies.chain(&file.rename) {
+            let (from, to) = (field(from)?, field(to)?);
+            if matches!(from, Field::Dc(_)) {
+                bail!("XMP fields can only be copied to, not from");
+            }
+            if from != to && !(is_text(from) && is_text(to)) {
+                bail!("Only text fields can be renamed into one another");
+            }
+            map.copy.push((from, to));
+        }
+        for name in &file.drop {
+            let field = field(name)?;
+            if matches!(field, Field::Dc(_)) {
+                bail!("XMP fields cannot be dropped, as crops only get them copied");
+            }
+            map.drop.push(field);
+        }
+        Ok(map)
+    }
+
+    /// What the crops of the sheet in the file `source` (its bytes) carry
+    /// by this map.
+    pub fn apply_to(&self, source: &[u8]) -> Result<MappedTags> {
+        let entries = if self.copy.is_empty() {
+            Vec::new()
+        } else {
+            exif::read(source)?
+        };
+        let mut mapped = MappedTags {
+            drop: self.drop.clone(),
+            ..MappedTags::default()
+        };
+        for &(from, to) in &self.copy {
+            let Some(value) = entries
+                .iter()
+                .find(|e| match from {
+                    Field::Ifd0(tag) => !e.exif_ifd && e.tag == tag,
+                    Field::Exif(tag) => e.exif_ifd && e.tag == tag,
+                    Field::Dc(_) => false,
+                })
+                .map(|e| &e.value)
+            else {
+                continue;
+            };
+            if from == to {
+                mapped.exif.push((to, value.clone()));
+                continue;
+            }
+            let Some(text) = value.text() else {
+                continue;
+            };
+            match to {
+                Field::Dc(element) => mapped.xmp.push((element, text)),
+                Field::Exif(exif::TAG_USER_COMMENT) => {
+                    mapped.exif.push((to, Value::user_comment(&text)))
+                }
+                _ => mapped.exif.push((to, Value::Ascii(text))),
+            }
+        }
+        Ok(mapped)
+    }
+}
+
+/// A tag map applied to one source: the values its crops get and the
+/// fields they lose.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MappedTags {
+    exif: Vec<(Field, Value)>,
+    xmp: Vec<(&'static str, String)>,
+    drop: Vec<Field>,
+}
+
+impl MappedTags {
+    /// Drops and then sets the EXIF fields of a crop's `tags`.
+    pub fn apply(&self, tags: &mut ExifBuilder) {
+        for field in &self.drop {
+            match *field {
+                Field::Ifd0(tag) => tags.remove(tag),
+                Field::Exif(tag) => tags.remove_exif(tag),
+                Field::Dc(_) => tags,
+            };
+        }
+        for (field, value) in &self.exif {
+            match *field {
+                Field::Ifd0(tag) => tags.set(tag, value.clone()),
+                Field::Exif(tag) => tags.set_exif(tag, value.clone()),
+                Field::Dc(_) => tags,
+            };
+        }
+    }
+
+    /// `packet` with the copied Dublin Core elements added, or a new packet
+    /// for them; `None` when there is neither.
+    pub fn xmp(&self, packet: Option<String>) -> Option<String> {
+        self.xmp.iter().fold(packet, |packet, (element, text)| {
+            Some(xmp::with_dc_text(packet.as_deref(), element, text))
+        })
+    }
+}
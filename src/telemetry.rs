@@ -0,0 +1,101 @@
+use anyhow::Result;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::logfile::LogFile;
+#[cfg(feature = "otlp")]
+use crate::tr;
+
+/// What `--log-dir` files record, whatever `RUST_LOG` asks of the console:
+/// everything this tool traces, and what its libraries report at info level.
+const LOG_FILE_FILTER: &str = "info,photo_cropper=debug";
+
+/// Keeps the span exporter alive for the run; pending spans are flushed on drop.
+pub struct Telemetry {
+    #[cfg(feature = "otlp")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+/// Installs the global tracing subscriber.
+///
+/// Progress is still printed directly, so tracing events only reach stderr when
+/// `RUST_LOG` asks for them. With `otlp_endpoint` (the collector's base URL,
+/// e.g. `http://localhost:4318`) spans are also exported over OTLP/HTTP; that
+/// needs a build with the `otlp` feature. With `log_file` every event down
+/// to debug level is also written there, so a batch that went wrong can be
+/// looked into afterwards.
+pub fn init(otlp_endpoint: Option<&str>, log_file: Option<LogFile>) -> Result<Telemetry> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("off"));
+    let console = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_filter(filter);
+    let file = log_file.map(|log_file| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_thread_names(true)
+            .with_writer(std::sync::Mutex::new(log_file))
+            .with_filter(EnvFilter::new(LOG_FILE_FILTER))
+    });
+    let registry = tracing_subscriber::registry().with(console).with(file);
+
+    #[cfg(feature = "otlp")]
+    {
+        use opentelemetry::trace::TracerProvider as _;
+        use opentelemetry_otlp::WithExportConfig;
+        use opentelemetry_sdk::Resource;
+        use opentelemetry_sdk::trace::SdkTracerProvider;
+
+        let Some(endpoint) = otlp_endpoint else {
+            registry.try_init()?;
+            return Ok(Telemetry { provider: None });
+        };
+
+        let endpoint = endpoint.trim_end_matches('/');
+        let url = if endpoint.ends_with("/v1/traces") {
+            endpoint.to_string()
+        } else {
+            format!("{endpoint}/v1/traces")
+        };
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(url)
+            .build()?;
+        let provider = SdkTracerProvider::builder()
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(env!("CARGO_PKG_NAME"))
+                    .build(),
+            )
+            .with_batch_exporter(exporter)
+            .build();
+        let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+        registry
+            .with(tracing_opentelemetry::layer().with_tracer(tracer))
+            .try_init()?;
+        Ok(Telemetry {
+            provider: Some(provider),
+        })
+    }
+
+    #[cfg(not(feature = "otlp"))]
+    {
+        anyhow::ensure!(
+            otlp_endpoint.is_none(),
+            "--otlp-endpoint needs a build with the `otlp` feature"
+        );
+        registry.try_init()?;
+        Ok(Telemetry {})
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = self.provider.take()
+            && let Err(err) = provider.shutdown()
+        {
+            eprintln!("{}", tr!("traces-flush-failed", error = err.to_string()));
+        }
+    }
+}
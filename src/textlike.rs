@@ -0,0 +1,169 @@
+use anyhow::Result;
+use opencv::core::{self, Mat, Point2f, Scalar, Size, Vector};
+use opencv::imgproc;
+use opencv::prelude::*;
+
+use crate::detect::{self, Quad};
+
+/// Long side of the thumbnail the region is measured on.
+const SAMPLE_SIDE: f32 = 256.0;
+/// Colourfulness (Hasler and Süsstrunk) below which a region may be ink on paper.
+const MAX_COLORFULNESS: f64 = 15.0;
+/// Share of the region that must be plain paper.
+const MIN_PAPER_SHARE: f64 = 0.8;
+/// Largest grey-level spread of the paper; photos rarely have such a flat background.
+const MAX_PAPER_SPREAD: f64 = 20.0;
+/// Least share of ink; below it the region is blank rather than written on.
+const MIN_INK_SHARE: f64 = 0.005;
+/// Widest mean stroke, as a share of the thumbnail's short side.
+const MAX_STROKE_SHARE: f64 = 0.02;
+
+/// Whether the region inside `quad` looks like a caption, label or note
+/// rather than a photo: nearly colourless, mostly flat paper, with the rest
+/// made of thin strokes.
+#[tracing::instrument(level = "debug", skip_all, err)]
+pub fn is_text_like(image: &Mat, quad: &Quad) -> Result<bool> {
+    let sample = thumbnail(image, quad)?;
+    let (width, height) = (sample.cols() as usize, sample.rows() as usize);
+    let bytes = sample.data_bytes()?;
+
+    let mut gray = Vec::with_capacity(width * height);
+    let (mut rg, mut yb) = (Stats::default(), Stats::default());
+    for bgr in bytes.chunks_exact(3) {
+        let (b, g, r) = (bgr[0] as f64, bgr[1] as f64, bgr[2] as f64);
+        rg.add(r - g);
+        yb.add(0.5 * (r + g) - b);
+        gray.push((0.114 * b + 0.587 * g + 0.299 * r).round() as u8);
+    }
+    let colorfulness = (rg.variance() + yb.variance()).sqrt()
+        + 0.3 * (rg.mean().powi(2) + yb.mean().powi(2)).sqrt();
+    if colorfulness > MAX_COLORFULNESS {
+        return Ok(false);
+    }
+
+    // Paper is the brighter side of the Otsu split, ink the darker.
+    let split = otsu(&gray);
+    let mut paper = Stats::default();
+    for &v in &gray {
+        if v > split {
+            paper.add(v as f64);
+        }
+    }
+    let total = gray.len() as f64;
+    let ink = total - paper.count;
+    if paper.count / total < MIN_PAPER_SHARE
+        || paper.variance().sqrt() > MAX_PAPER_SPREAD
+        || ink / total < MIN_INK_SHARE
+    {
+        return Ok(false);
+    }
+
+    // Mean stroke width from ink area over ink border length.
+    let is_ink = |x: usize, y: usize| gray[y * width + x] <= split;
+    let mut border = 0usize;
+    for y in 0..height {
+        for x in 0..width {
+            if is_ink(x, y)
+                && ((x > 0 && !is_ink(x - 1, y))
+                    || (x + 1 < width && !is_ink(x + 1, y))
+                    || (y > 0 && !is_ink(x, y - 1))
+                    || (y + 1 < height && !is_ink(x, y + 1)))
+            {
+                border += 1;
+            }
+        }
+    }
+    let stroke = 2.0 * ink / border.max(1) as f64;
+    Ok(stroke <= (width.min(height) as f64 * MAX_STROKE_SHARE).max(3.0))
+}
+
+/// The region straightened at no more than [`SAMPLE_SIDE`] pixels.
+fn thumbnail(image: &Mat, quad: &Quad) -> Result<Mat> {
+    let size = detect::warp_size(quad);
+    let scale = (SAMPLE_SIDE / size.width.max(size.height) as f32).min(1.0);
+    let (w, h) = (
+        ((size.width as f32 * scale) as i32).max(8),
+        ((size.height as f32 * scale) as i32).max(8),
+    );
+    let dst: Vector<Point2f> = Vector::from(vec![
+        Point2f::new(0.0, 0.0),
+        Point2f::new((w - 1) as f32, 0.0),
+        Point2f::new((w - 1) as f32, (h - 1) as f32),
+        Point2f::new(0.0, (h - 1) as f32),
+    ]);
+    let src: Vector<Point2f> = Vector::from(quad.to_vec());
+    let m = imgproc::get_perspective_transform(&src, &dst, core::DECOMP_LU)?;
+    let mut small = Mat::default();
+    imgproc::warp_perspective(
+        image,
+        &mut small,
+        &m,
+        Size::new(w, h),
+        imgproc::INTER_LINEAR,
+        core::BORDER_REPLICATE,
+        Scalar::all(0.0),
+    )?;
+    Ok(small)
+}
+
+/// Threshold that best separates the two classes of a grey-level histogram.
+fn otsu(gray: &[u8]) -> u8 {
+    let mut histogram = [0f64; 256];
+    for &v in gray {
+        histogram[v as usize] += 1.0;
+    }
+    let total = gray.len() as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, n)| i as f64 * n)
+        .sum();
+    let (mut below, mut below_sum) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0u8, 0.0);
+    for (t, n) in histogram.iter().enumerate() {
+        below += n;
+        below_sum += t as f64 * n;
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+        let (mean_below, mean_above) = (below_sum / below, (sum - below_sum) / above);
+        let variance = below * above * (mean_below - mean_above).powi(2);
+        if variance > best_variance {
+            (best, best_variance) = (t as u8, variance);
+        }
+    }
+    best
+}
+
+/// Running mean and variance.
+#[derive(Default)]
+struct Stats {
+    count: f64,
+    sum: f64,
+    sum_sq: f64,
+}
+
+impl Stats {
+    fn add(&mut self, v: f64) {
+        self.count += 1.0;
+        self.sum += v;
+        self.sum_sq += v * v;
+    }
+
+    fn mean(&self) -> f64 {
+        if self.count == 0.0 {
+            0.0
+        } else {
+            self.sum / self.count
+        }
+    }
+
+    fn variance(&self) -> f64 {
+        if self.count == 0.0 {
+            0.0
+        } else {
+            (self.sum_sq / self.count - self.mean().powi(2)).max(0.0)
+        }
+    }
+}
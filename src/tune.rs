@@ -0,0 +1,184 @@
+use anyhow::Result;
+use opencv::core::{Mat, Point, Scalar, Size, Vector};
+use opencv::highgui;
+use opencv::imgproc;
+use opencv::prelude::*;
+
+use crate::config::ParamOverrides;
+use crate::detect::{self, DetectParams};
+
+const WINDOW: &str = "photo-cropper tune";
+/// Long side of the preview; detection runs on it so the sliders respond at once.
+const PREVIEW_SIDE: f64 = 1600.0;
+
+const CANNY_LOW: &str = "Canny low";
+const CANNY_HIGH: &str = "Canny high";
+const BLUR: &str = "Blur";
+const BLOCK: &str = "Block size";
+const MIN_AREA: &str = "Min area (per mille)";
+
+/// Slider positions, in preview pixels where they are sizes.
+#[derive(Clone, Copy, PartialEq)]
+struct Positions {
+    canny_low: i32,
+    canny_high: i32,
+    /// Blur kernel radius; 0 turns blurring off.
+    blur: i32,
+    /// Threshold block radius.
+    block: i32,
+    /// Least photo area in thousandths of the sheet.
+    min_area: i32,
+}
+
+impl Positions {
+    fn read() -> Result<Self> {
+        Ok(Self {
+            canny_low: highgui::get_trackbar_pos(CANNY_LOW, WINDOW)?,
+            canny_high: highgui::get_trackbar_pos(CANNY_HIGH, WINDOW)?,
+            blur: highgui::get_trackbar_pos(BLUR, WINDOW)?,
+            block: highgui::get_trackbar_pos(BLOCK, WINDOW)?,
+            min_area: highgui::get_trackbar_pos(MIN_AREA, WINDOW)?,
+        })
+    }
+
+    /// `params` at preview scale with the sliders applied.
+    fn apply(&self, params: &DetectParams) -> DetectParams {
+        DetectParams {
+            canny_low: self.canny_low as f64,
+            canny_high: self.canny_high as f64,
+            blur_kernel: if self.blur == 0 { 0 } else { 2 * self.blur + 1 },
+            threshold_block: 2 * self.block + 1,
+            min_area_frac: Some(self.min_area as f64 / 1000.0),
+            ..params.clone()
+        }
+    }
+}
+
+/// Opens a window with sliders for the main detection parameters over a
+/// preview of `image`, redrawing the detected photos as they move.
+///
+/// Enter or `s` accepts and returns the chosen values, converted back to
+/// source pixels; Esc or `q` cancels and returns `None`.
+pub fn tune(image: &Mat, params: &DetectParams) -> Result<Option<ParamOverrides>> {
+    let scale = (PREVIEW_SIDE / image.cols().max(image.rows()) as f64).min(1.0);
+    let preview = if scale < 1.0 {
+        let mut small = Mat::default();
+        imgproc::resize(
+            image,
+            &mut small,
+            Size::default(),
+            scale,
+            scale,
+            imgproc::INTER_AREA,
+        )?;
+        small
+    } else {
+        image.clone()
+    };
+    let base = params.scaled(scale);
+    let initial = Positions {
+        canny_low: params.canny_low.round() as i32,
+        canny_high: params.canny_high.round() as i32,
+        blur: preview_radius(params.blur_kernel, scale),
+        block: preview_radius(params.threshold_block, scale).max(1),
+        min_area: (1000.0 * min_area_frac(params, image)).round() as i32,
+    };
+
+    highgui::named_window(WINDOW, highgui::WINDOW_NORMAL)?;
+    for (name, count, pos) in [
+        (CANNY_LOW, 500, initial.canny_low),
+        (CANNY_HIGH, 500, initial.canny_high),
+        (BLUR, 10, initial.blur),
+        (BLOCK, 50, initial.block),
+        (MIN_AREA, 200, initial.min_area),
+    ] {
+        highgui::create_trackbar(name, WINDOW, None, count, None)?;
+        highgui::set_trackbar_pos(name, WINDOW, pos)?;
+    }
+
+    let mut shown = None;
+    let accepted = loop {
+        let mut positions = Positions::read()?;
+        // The adaptive threshold needs a block of at least 3.
+        positions.block = positions.block.max(1);
+        if shown != Some(positions) {
+            let overlay = draw(&preview, &positions.apply(&base))?;
+            highgui::imshow(WINDOW, &overlay)?;
+            shown = Some(positions);
+        }
+        // Some backends report modifier keys in the upper bits.
+        match (highgui::wait_key(30)? & 0xff) as u8 {
+            b'\n' | b'\r' | b's' => break Some(positions),
+            0x1b | b'q' => break None,
+            _ => {}
+        }
+    };
+    highgui::destroy_window(WINDOW)?;
+
+    Ok(accepted.map(|p| ParamOverrides {
+        canny_low: Some(p.canny_low as f64),
+        canny_high: Some(p.canny_high as f64),
+        blur_kernel: Some(if p.blur == 0 {
+            0
+        } else {
+            source_side(p.blur, scale)
+        }),
+        threshold_block: Some(source_side(p.block, scale)),
+        min_area_frac: Some(p.min_area as f64 / 1000.0),
+        ..ParamOverrides::default()
+    }))
+}
+
+/// The preview with the photos found under `params` outlined and counted.
+fn draw(preview: &Mat, params: &DetectParams) -> Result<Mat> {
+    let mut overlay = preview.try_clone()?;
+    let color = Scalar::new(0.0, 0.0, 255.0, 0.0);
+    let status = match detect::detect_quads(preview, params) {
+        Ok(candidates) => {
+            let outlines: Vector<Vector<Point>> = candidates
+                .iter()
+                .map(|c| {
+                    c.quad
+                        .iter()
+                        .map(|p| Point::new(p.x.round() as i32, p.y.round() as i32))
+                        .collect()
+                })
+                .collect();
+            imgproc::polylines(&mut overlay, &outlines, true, color, 2, imgproc::LINE_AA, 0)?;
+            format!("{} photos", candidates.len())
+        }
+        Err(err) => format!("{err:#}"),
+    };
+    imgproc::put_text(
+        &mut overlay,
+        &status,
+        Point::new(12, 32),
+        imgproc::FONT_HERSHEY_SIMPLEX,
+        0.9,
+        color,
+        2,
+        imgproc::LINE_AA,
+        false,
+    )?;
+    Ok(overlay)
+}
+
+/// Slider radius for an odd kernel `side` in source pixels.
+fn preview_radius(side: i32, scale: f64) -> i32 {
+    if side <= 1 {
+        return 0;
+    }
+    ((side as f64 * scale - 1.0) / 2.0).round().max(1.0) as i32
+}
+
+/// Odd kernel side in source pixels for a slider `radius` in preview pixels.
+fn source_side(radius: i32, scale: f64) -> i32 {
+    ((2 * radius + 1) as f64 / scale).round() as i32 | 1
+}
+
+/// The least photo area as a share of `image`, however it was configured.
+fn min_area_frac(params: &DetectParams, image: &Mat) -> f64 {
+    params
+        .min_area_frac
+        .unwrap_or_else(|| params.min_area / (image.cols() as f64 * image.rows() as f64).max(1.0))
+}
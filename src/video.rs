@@ -0,0 +1,248 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use opencv::core::{self, AlgorithmHint, Mat, Size};
+use opencv::imgcodecs;
+use opencv::imgproc;
+use opencv::prelude::*;
+
+use crate::enhance;
+
+/// Width frames are shrunk to for telling movement from stillness.
+const MOTION_WIDTH: i32 = 320;
+/// Mean grey change per pixel between looked-at frames below which the
+/// album is taken to lie still; hands turning a page stay well above it.
+const STILL_MOTION: f64 = 2.0;
+/// Frames in a row that must lie still before they count as a page, so a
+/// page only paused on mid-turn is not taken.
+const MIN_STILL_FRAMES: usize = 3;
+/// Mean grey difference from the last page taken above which a still
+/// stretch shows a new page rather than the same one after a nudge.
+const NEW_PAGE: f64 = 12.0;
+
+/// Where the frames come from: a video file, or a directory of frames
+/// named so they sort in order.
+enum Frames {
+    Video(Capture),
+    Images(std::vec::IntoIter<PathBuf>),
+}
+
+impl Frames {
+    fn open(input: &Path) -> Result<Self> {
+        if !input.is_dir() {
+            return Ok(Frames::Video(open_capture(input)?));
+        }
+        let mut files: Vec<PathBuf> = fs::read_dir(input)
+            .with_context(|| format!("Could not read {}", input.display()))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+                    matches!(
+                        e.to_lowercase().as_str(),
+                        "jpg" | "jpeg" | "png" | "bmp" | "tif" | "tiff"
+                    )
+                })
+            })
+            .collect();
+        files.sort();
+        Ok(Frames::Images(files.into_iter()))
+    }
+
+    fn next(&mut self) -> Result<Option<Mat>> {
+        match self {
+            Frames::Video(capture) => read_frame(capture),
+            Frames::Images(files) => {
+                let Some(file) = files.next() else {
+                    return Ok(None);
+                };
+                let frame = imgcodecs::imread(&file.to_string_lossy(), imgcodecs::IMREAD_COLOR)?;
+                anyhow::ensure!(!frame.empty(), "Could not read frame {}", file.display());
+                Ok(Some(frame))
+            }
+        }
+    }
+}
+
+/// The still stretch being watched and its sharpest frame so far.
+struct Stretch {
+    frames: usize,
+    best: Mat,
+    best_sharpness: f64,
+    small: Mat,
+}
+
+/// Picks one frame per page from a capture of someone leafing through an
+/// album under a camera: the sharpest frame of every stretch where the
+/// picture holds still, skipping stretches that show the page taken last.
+/// Only every `step`th frame is looked at.
+///
+/// Each page is handed to `on_page` with its number, from 1, as soon as
+/// the album moves on, so a long capture never holds more than a few frames.
+/// Returns the number of pages.
+pub fn select_pages(
+    input: &Path,
+    step: usize,
+    on_page: impl FnMut(usize, &Mat) -> Result<()>,
+) -> Result<usize> {
+    let mut frames = Frames::open(input)?;
+    let mut pages = Pages {
+        on_page,
+        count: 0,
+        last: None,
+    };
+    let mut previous: Option<Mat> = None;
+    let mut stretch: Option<Stretch> = None;
+    let mut index = 0;
+    while let Some(frame) = frames.next()? {
+        index += 1;
+        if (index - 1) % step != 0 {
+            continue;
+        }
+        let small = small_gray(&frame)?;
+        let still = match &previous {
+            Some(previous) => difference(previous, &small)? < STILL_MOTION,
+            None => false,
+        };
+        previous = Some(small.try_clone()?);
+        if !still {
+            pages.take(stretch.take())?;
+            continue;
+        }
+        let sharpness = enhance::sharpness(&frame)?;
+        match &mut stretch {
+            Some(s) => {
+                s.frames += 1;
+                if sharpness > s.best_sharpness {
+                    s.best = frame;
+                    s.best_sharpness = sharpness;
+                    s.small = small;
+                }
+            }
+            None => {
+                stretch = Some(Stretch {
+                    frames: 1,
+                    best: frame,
+                    best_sharpness: sharpness,
+                    small,
+                })
+            }
+        }
+    }
+    pages.take(stretch)?;
+    Ok(pages.count)
+}
+
+/// The pages taken so far.
+struct Pages<F> {
+    on_page: F,
+    count: usize,
+    /// The last page taken, shrunk like [`Stretch::small`].
+    last: Option<Mat>,
+}
+
+impl<F: FnMut(usize, &Mat) -> Result<()>> Pages<F> {
+    /// Takes the best frame of a finished `stretch` for a page when it held
+    /// still long enough and shows another page than the last one taken.
+    fn take(&mut self, stretch: Option<Stretch>) -> Result<()> {
+        let Some(stretch) = stretch.filter(|s| s.frames >= MIN_STILL_FRAMES) else {
+            return Ok(());
+        };
+        if let Some(last) = &self.last
+            && difference(last, &stretch.small)? < NEW_PAGE
+        {
+            return Ok(());
+        }
+        self.count += 1;
+        tracing::debug!(
+            page = self.count,
+            frames = stretch.frames,
+            sharpness = stretch.best_sharpness,
+            "took page"
+        );
+        (self.on_page)(self.count, &stretch.best)?;
+        self.last = Some(stretch.small);
+        Ok(())
+    }
+}
+
+/// Mean absolute grey difference per pixel of two equally sized frames.
+fn difference(a: &Mat, b: &Mat) -> Result<f64> {
+    if a.size()? != b.size()? {
+        return Ok(f64::INFINITY);
+    }
+    let mut diff = Mat::default();
+    core::absdiff(a, b, &mut diff)?;
+    Ok(core::mean(&diff, &core::no_array())?[0])
+}
+
+/// `frame` in grey, [`MOTION_WIDTH`] wide and blurred against sensor noise.
+fn small_gray(frame: &Mat) -> Result<Mat> {
+    let mut gray = Mat::default();
+    imgproc::cvt_color(
+        frame,
+        &mut gray,
+        imgproc::COLOR_BGR2GRAY,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    let height = (gray.rows() as f64 * MOTION_WIDTH as f64 / gray.cols().max(1) as f64).round();
+    let mut small = Mat::default();
+    imgproc::resize(
+        &gray,
+        &mut small,
+        Size::new(MOTION_WIDTH, (height as i32).max(1)),
+        0.0,
+        0.0,
+        imgproc::INTER_AREA,
+    )?;
+    let mut smooth = Mat::default();
+    imgproc::gaussian_blur(
+        &small,
+        &mut smooth,
+        Size::new(5, 5),
+        0.0,
+        0.0,
+        core::BORDER_DEFAULT,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    Ok(smooth)
+}
+
+#[cfg(feature = "video")]
+type Capture = opencv::videoio::VideoCapture;
+
+#[cfg(feature = "video")]
+fn open_capture(input: &Path) -> Result<Capture> {
+    use opencv::videoio;
+
+    let capture = videoio::VideoCapture::from_file(&input.to_string_lossy(), videoio::CAP_ANY)
+        .with_context(|| format!("Could not open the video {}", input.display()))?;
+    anyhow::ensure!(
+        capture.is_opened()?,
+        "Could not open the video {}",
+        input.display()
+    );
+    Ok(capture)
+}
+
+#[cfg(feature = "video")]
+fn read_frame(capture: &mut Capture) -> Result<Option<Mat>> {
+    let mut frame = Mat::default();
+    Ok((capture.read(&mut frame)? && !frame.empty()).then_some(frame))
+}
+
+#[cfg(not(feature = "video"))]
+type Capture = ();
+
+#[cfg(not(feature = "video"))]
+fn open_capture(_input: &Path) -> Result<Capture> {
+    anyhow::bail!(
+        "Reading video files needs a build with the `video` feature; a directory of frames works without it"
+    )
+}
+
+#[cfg(not(feature = "video"))]
+fn read_frame(_capture: &mut Capture) -> Result<Option<Mat>> {
+    Ok(None)
+}
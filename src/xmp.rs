@@ -0,0 +1,205 @@
+use anyhow::Result;
+use img_parts::jpeg::{Jpeg, JpegSegment, markers};
+use img_parts::png::{Png, PngChunk};
+use img_parts::{Bytes, DynImage};
+
+/// Identifies an XMP packet among a JPEG's APP1 segments.
+const JPEG_XMP_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Keyword of the PNG `iTXt` chunk holding XMP.
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+const PNG_ITXT: [u8; 4] = *b"iTXt";
+
+/// Triage state for a crop that asks for a second look: a star rating
+/// and a colour label, as Lightroom and digiKam show them after import.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Triage {
+    /// Stars from 0 to 5.
+    pub rating: Option<u8>,
+    /// Label text; Lightroom's colour labels are "Red", "Yellow", "Green",
+    /// "Blue" and "Purple", any other text shows as a custom label.
+    pub label: Option<String>,
+}
+
+impl Triage {
+    pub fn is_empty(&self) -> bool {
+        self.rating.is_none() && self.label.is_none()
+    }
+
+    /// A complete XMP packet with `xmp:Rating` and `xmp:Label`.
+    pub fn packet(&self) -> String {
+        let mut xmp = String::from(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             <rdf:Description rdf:about=\"\"\n \
+             xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"",
+        );
+        if let Some(rating) = self.rating {
+            xmp.push_str(&format!("\n xmp:Rating=\"{}\"", rating.min(5)));
+        }
+        if let Some(label) = &self.label {
+            xmp.push_str(&format!("\n xmp:Label=\"{}\"", escape(label)));
+        }
+        xmp.push_str("/>\n</rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>\n");
+        xmp
+    }
+}
+
+/// The encoded image `bytes` with the XMP `packet` embedded, replacing any
+/// existing one, or `None` when the format cannot carry it this way (only
+/// JPEG and PNG can); such crops need a sidecar instead.
+pub fn embed(bytes: Vec<u8>, packet: &str) -> Result<Option<Vec<u8>>> {
+    let image = match DynImage::from_bytes(Bytes::from(bytes))? {
+        Some(DynImage::Jpeg(jpeg)) => DynImage::Jpeg(embed_jpeg(jpeg, packet)),
+        Some(DynImage::Png(png)) => DynImage::Png(embed_png(png, packet)),
+        _ => return Ok(None),
+    };
+    Ok(Some(image.encoder().bytes().to_vec()))
+}
+
+/// The XMP packet embedded in the JPEG or PNG `bytes`, if there is one.
+pub fn extract(bytes: &[u8]) -> Result<Option<String>> {
+    let packet = match DynImage::from_bytes(Bytes::copy_from_slice(bytes))? {
+        Some(DynImage::Jpeg(jpeg)) => jpeg
+            .segments()
+            .iter()
+            .filter(|s| s.marker() == markers::APP1)
+            .find_map(|s| {
+                s.contents()
+                    .strip_prefix(JPEG_XMP_PREFIX)
+                    .map(<[u8]>::to_vec)
+            }),
+        Some(DynImage::Png(png)) => png
+            .chunks()
+            .iter()
+            .filter(|c| c.kind() == PNG_ITXT)
+            .find_map(|c| {
+                // Skip the keyword and the five bytes after it.
+                c.contents()
+                    .strip_prefix(PNG_XMP_KEYWORD)
+                    .and_then(|rest| rest.get(5..))
+                    .map(<[u8]>::to_vec)
+            }),
+        _ => None,
+    };
+    Ok(packet.map(|p| String::from_utf8_lossy(&p).into_owned()))
+}
+
+/// `packet`, or a new one when there is none, with its `dc:title` set to
+/// `title` and everything else kept, so naming a crop leaves its rating,
+/// label and keywords alone.
+pub fn with_title(packet: Option<&str>, title: &str) -> String {
+    with_dc_text(packet, "dc:title", title)
+}
+
+/// `packet`, or a new one when there is none, with the Dublin Core element
+/// `tag` (such as `dc:description`) set to `text` in the form the standard
+/// gives it: a language alternative for the title, description and rights,
+/// and a list of the `;`-separated parts for the creators and subjects.
+pub fn with_dc_text(packet: Option<&str>, tag: &str, text: &str) -> String {
+    let (container, items) = match tag {
+        "dc:creator" => ("rdf:Seq", split_list(text)),
+        "dc:subject" => ("rdf:Bag", split_list(text)),
+        _ => ("rdf:Alt", vec![text.trim()]),
+    };
+    let language = if container == "rdf:Alt" {
+        " xml:lang=\"x-default\""
+    } else {
+        ""
+    };
+    let items: String = items
+        .iter()
+        .map(|item| format!("   <rdf:li{language}>{}</rdf:li>\n", escape(item)))
+        .collect();
+    let element = format!("<{tag}>\n  <{container}>\n{items}  </{container}>\n </{tag}>");
+    with_dc_element(packet, tag, &element)
+}
+
+fn split_list(text: &str) -> Vec<&str> {
+    text.split(';')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// `packet`, or a new one when there is none, with its `dc:subject` set to
+/// `keywords` and everything else kept.
+pub fn with_keywords(packet: Option<&str>, keywords: &[String]) -> String {
+    let items: String = keywords
+        .iter()
+        .map(|k| format!("   <rdf:li>{}</rdf:li>\n", escape(k)))
+        .collect();
+    let element = format!("<dc:subject>\n  <rdf:Bag>\n{items}  </rdf:Bag>\n </dc:subject>");
+    with_dc_element(packet, "dc:subject", &element)
+}
+
+/// Replaces the `tag` element of `packet` with `element`, or adds it to the
+/// description when there is none.
+fn with_dc_element(packet: Option<&str>, tag: &str, element: &str) -> String {
+    let mut xmp = packet.map_or_else(|| Triage::default().packet(), str::to_string);
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    if let (Some(start), Some(end)) = (xmp.find(&open), xmp.find(&close)) {
+        xmp.replace_range(start..end + close.len(), element);
+        return xmp;
+    }
+    let Some(description) = xmp.find("<rdf:Description") else {
+        return with_dc_element(None, tag, element);
+    };
+    // Attribute values escape `>`, so the first one closes the start tag.
+    let Some(close) = xmp[description..].find('>').map(|i| description + i) else {
+        return with_dc_element(None, tag, element);
+    };
+    if xmp[..close].ends_with('/') {
+        xmp.replace_range(
+            close - 1..=close,
+            &format!(">\n {element}\n</rdf:Description>"),
+        );
+    } else {
+        xmp.insert_str(close + 1, &format!("\n {element}"));
+    }
+    if !xmp.contains("xmlns:dc=") {
+        xmp.insert_str(
+            description + "<rdf:Description".len(),
+            "\n xmlns:dc=\"http://purl.org/dc/elements/1.1/\"",
+        );
+    }
+    xmp
+}
+
+fn embed_jpeg(mut jpeg: Jpeg, packet: &str) -> Jpeg {
+    let segments = jpeg.segments_mut();
+    segments
+        .retain(|s| !(s.marker() == markers::APP1 && s.contents().starts_with(JPEG_XMP_PREFIX)));
+    let mut contents = JPEG_XMP_PREFIX.to_vec();
+    contents.extend_from_slice(packet.as_bytes());
+    // Right after the other application segments, where readers look for it.
+    let at = segments
+        .iter()
+        .position(|s| !(markers::APP0..=markers::APP15).contains(&s.marker()))
+        .unwrap_or(segments.len());
+    segments.insert(
+        at,
+        JpegSegment::new_with_contents(markers::APP1, Bytes::from(contents)),
+    );
+    jpeg
+}
+
+fn embed_png(mut png: Png, packet: &str) -> Png {
+    let chunks = png.chunks_mut();
+    chunks.retain(|c| !(c.kind() == PNG_ITXT && c.contents().starts_with(PNG_XMP_KEYWORD)));
+    // Keyword, then no compression, no language tag and no translated keyword.
+    let mut contents = PNG_XMP_KEYWORD.to_vec();
+    contents.extend_from_slice(&[0, 0, 0, 0, 0]);
+    contents.extend_from_slice(packet.as_bytes());
+    // Before IEND, which always comes last.
+    let at = chunks.len().saturating_sub(1);
+    chunks.insert(at, PngChunk::new(PNG_ITXT, Bytes::from(contents)));
+    png
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}